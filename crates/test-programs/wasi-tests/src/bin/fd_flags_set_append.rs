@@ -0,0 +1,99 @@
+use std::{env, process};
+use wasi_tests::{assert_errno, open_scratch_directory};
+
+unsafe fn write(fd: wasi::Fd, data: &[u8]) {
+    let nwritten = wasi::fd_write(
+        fd,
+        &[wasi::Ciovec {
+            buf: data.as_ptr(),
+            buf_len: data.len(),
+        }],
+    )
+    .expect("writing to a file");
+    assert_eq!(nwritten, data.len(), "should write {} bytes", data.len());
+}
+
+unsafe fn read_all(fd: wasi::Fd) -> Vec<u8> {
+    let mut buffer = [0u8; 64];
+    let nread = wasi::fd_pread(
+        fd,
+        &[wasi::Iovec {
+            buf: buffer.as_mut_ptr(),
+            buf_len: buffer.len(),
+        }],
+        0,
+    )
+    .expect("reading file");
+    buffer[..nread].to_vec()
+}
+
+unsafe fn test_fd_flags_set_append(dir_fd: wasi::Fd) {
+    let file_fd = wasi::path_open(
+        dir_fd,
+        0,
+        "file",
+        wasi::OFLAGS_CREAT,
+        wasi::RIGHTS_FD_READ
+            | wasi::RIGHTS_FD_WRITE
+            | wasi::RIGHTS_FD_SEEK
+            | wasi::RIGHTS_FD_TELL
+            | wasi::RIGHTS_FD_FDSTAT_SET_FLAGS,
+        0,
+        0,
+    )
+    .expect("opening a file");
+
+    write(file_fd, b"0123456789");
+    wasi::fd_seek(file_fd, 2, wasi::WHENCE_SET).expect("seeking file");
+
+    // Turning APPEND on mid-stream sends the next write to the end.
+    wasi::fd_fdstat_set_flags(file_fd, wasi::FDFLAGS_APPEND).expect("enabling append");
+    let stat = wasi::fd_fdstat_get(file_fd).expect("fdstat");
+    assert_eq!(stat.fs_flags, wasi::FDFLAGS_APPEND, "append is set");
+    write(file_fd, b"ab");
+    assert_eq!(read_all(file_fd), b"0123456789ab");
+
+    // Turning it off again writes at the current offset.
+    wasi::fd_fdstat_set_flags(file_fd, 0).expect("disabling append");
+    wasi::fd_seek(file_fd, 4, wasi::WHENCE_SET).expect("seeking file");
+    write(file_fd, b"cd");
+    assert_eq!(read_all(file_fd), b"0123cd6789ab");
+
+    // Synchronous I/O flags can't be set, and a failed attempt changes
+    // nothing.
+    assert_errno!(
+        wasi::fd_fdstat_set_flags(file_fd, wasi::FDFLAGS_SYNC)
+            .expect_err("setting the SYNC flag")
+            .raw_error(),
+        wasi::ERRNO_NOTSUP,
+        wasi::ERRNO_INVAL
+    );
+    let stat = wasi::fd_fdstat_get(file_fd).expect("fdstat");
+    assert_eq!(stat.fs_flags, 0, "no flags are set");
+
+    wasi::fd_close(file_fd).expect("closing a file");
+    wasi::path_unlink_file(dir_fd, "file").expect("removing a file");
+}
+
+fn main() {
+    let mut args = env::args();
+    let prog = args.next().unwrap();
+    let arg = if let Some(arg) = args.next() {
+        arg
+    } else {
+        eprintln!("usage: {} <scratch directory>", prog);
+        process::exit(1);
+    };
+
+    let dir_fd = match open_scratch_directory(&arg) {
+        Ok(dir_fd) => dir_fd,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1)
+        }
+    };
+
+    unsafe {
+        test_fd_flags_set_append(dir_fd);
+    }
+}
@@ -0,0 +1,70 @@
+use std::{env, process};
+use wasi_tests::{assert_errno, open_scratch_directory};
+
+unsafe fn test_file_allocate_too_big(dir_fd: wasi::Fd) {
+    let file_fd = wasi::path_open(
+        dir_fd,
+        0,
+        "file",
+        wasi::OFLAGS_CREAT,
+        wasi::RIGHTS_FD_READ
+            | wasi::RIGHTS_FD_WRITE
+            | wasi::RIGHTS_FD_ALLOCATE
+            | wasi::RIGHTS_FD_FILESTAT_GET
+            | wasi::RIGHTS_FD_FILESTAT_SET_SIZE,
+        0,
+        0,
+    )
+    .expect("opening a file");
+
+    // Sizes far beyond any sane limit fail with EFBIG rather than taking
+    // down the host.
+    for (offset, len) in &[(0, 1 << 40), (1 << 40, 1), (1 << 62, 1 << 62)] {
+        assert_errno!(
+            wasi::fd_allocate(file_fd, *offset, *len)
+                .expect_err("allocating an absurd size")
+                .raw_error(),
+            wasi::ERRNO_FBIG
+        );
+    }
+    assert_errno!(
+        wasi::fd_filestat_set_size(file_fd, 1 << 40)
+            .expect_err("growing to an absurd size")
+            .raw_error(),
+        wasi::ERRNO_FBIG
+    );
+
+    let stat = wasi::fd_filestat_get(file_fd).expect("reading file stats");
+    assert_eq!(stat.size, 0, "file size should remain 0");
+
+    // Reasonable sizes still work afterwards.
+    wasi::fd_allocate(file_fd, 0, 100).expect("allocating size");
+    let stat = wasi::fd_filestat_get(file_fd).expect("reading file stats");
+    assert_eq!(stat.size, 100, "file size should be 100");
+
+    wasi::fd_close(file_fd).expect("closing a file");
+    wasi::path_unlink_file(dir_fd, "file").expect("removing a file");
+}
+
+fn main() {
+    let mut args = env::args();
+    let prog = args.next().unwrap();
+    let arg = if let Some(arg) = args.next() {
+        arg
+    } else {
+        eprintln!("usage: {} <scratch directory>", prog);
+        process::exit(1);
+    };
+
+    // Open scratch directory
+    let dir_fd = match open_scratch_directory(&arg) {
+        Ok(dir_fd) => dir_fd,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1)
+        }
+    };
+
+    // Run the tests.
+    unsafe { test_file_allocate_too_big(dir_fd) }
+}
@@ -0,0 +1,54 @@
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::{env, process};
+
+fn test_file_append_seek(dir: &str) {
+    let path = format!("{}/append_seek.log", dir);
+
+    let mut log = BufWriter::new(
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .expect("opening the log for appending"),
+    );
+    writeln!(log, "first").expect("buffering the first line");
+    log.flush().expect("flushing the first line");
+
+    // `fseek(f, 0, SEEK_END)` and `ftell` on an append stream report the
+    // size of the file.
+    assert_eq!(log.seek(SeekFrom::End(0)).expect("seeking to the end"), 6);
+    assert_eq!(log.stream_position().expect("telling the position"), 6);
+
+    // Buffered appends from a second stream land after the first.
+    let mut other = BufWriter::new(
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .expect("opening the log again"),
+    );
+    writeln!(other, "second").expect("buffering the second line");
+    other.flush().expect("flushing the second line");
+    writeln!(log, "third").expect("buffering the third line");
+    log.flush().expect("flushing the third line");
+    assert_eq!(log.seek(SeekFrom::End(0)).expect("seeking to the end"), 19);
+
+    drop(log);
+    drop(other);
+    let contents = std::fs::read_to_string(&path).expect("reading the log");
+    assert_eq!(contents, "first\nsecond\nthird\n");
+    std::fs::remove_file(&path).expect("removing the log");
+}
+
+fn main() {
+    let mut args = env::args();
+    let prog = args.next().unwrap();
+    let arg = if let Some(arg) = args.next() {
+        arg
+    } else {
+        eprintln!("usage: {} <scratch directory>", prog);
+        process::exit(1);
+    };
+
+    test_file_append_seek(&arg)
+}
@@ -0,0 +1,77 @@
+use std::{env, process};
+use wasi_tests::open_scratch_directory;
+
+unsafe fn test_file_empty_writev(dir_fd: wasi::Fd) {
+    for fdflags in &[0, wasi::FDFLAGS_APPEND] {
+        let fd = wasi::path_open(
+            dir_fd,
+            0,
+            "file",
+            wasi::OFLAGS_CREAT,
+            wasi::RIGHTS_FD_READ | wasi::RIGHTS_FD_WRITE | wasi::RIGHTS_FD_TELL,
+            0,
+            *fdflags,
+        )
+        .expect("opening a file");
+
+        let contents = b"contents";
+        let ciovec = wasi::Ciovec {
+            buf: contents.as_ptr() as *const _,
+            buf_len: contents.len(),
+        };
+        let nwritten = wasi::fd_write(fd, &[ciovec]).expect("writing the contents");
+        assert_eq!(nwritten, contents.len(), "nwritten bytes check");
+        let size = wasi::fd_filestat_get(fd).expect("stat after write").size;
+        let offset = wasi::fd_tell(fd).expect("tell after write");
+
+        // An empty iovec array writes nothing.
+        let nwritten = wasi::fd_write(fd, &[]).expect("writing an empty iovec array");
+        assert_eq!(nwritten, 0, "empty iovec array writes nothing");
+
+        // So do iovecs which are all empty.
+        let empty = wasi::Ciovec {
+            buf: contents.as_ptr() as *const _,
+            buf_len: 0,
+        };
+        let nwritten = wasi::fd_write(fd, &[empty, empty, empty]).expect("writing empty iovecs");
+        assert_eq!(nwritten, 0, "empty iovecs write nothing");
+
+        let stat = wasi::fd_filestat_get(fd).expect("stat after empty writes");
+        assert_eq!(stat.size, size, "empty writes leave the size");
+        assert_eq!(
+            wasi::fd_tell(fd).expect("tell after empty writes"),
+            offset,
+            "empty writes leave the offset"
+        );
+
+        // Empty reads are fine too.
+        let nread = wasi::fd_read(fd, &[]).expect("reading into an empty iovec array");
+        assert_eq!(nread, 0, "empty iovec array reads nothing");
+
+        wasi::fd_close(fd).expect("closing the file");
+        wasi::path_unlink_file(dir_fd, "file").expect("removing the file");
+    }
+}
+
+fn main() {
+    let mut args = env::args();
+    let prog = args.next().unwrap();
+    let arg = if let Some(arg) = args.next() {
+        arg
+    } else {
+        eprintln!("usage: {} <scratch directory>", prog);
+        process::exit(1);
+    };
+
+    // Open scratch directory
+    let dir_fd = match open_scratch_directory(&arg) {
+        Ok(dir_fd) => dir_fd,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1)
+        }
+    };
+
+    // Run the tests.
+    unsafe { test_file_empty_writev(dir_fd) }
+}
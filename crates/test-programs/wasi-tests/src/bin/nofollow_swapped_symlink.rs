@@ -0,0 +1,83 @@
+use std::{env, process};
+use wasi_tests::{assert_errno, create_file, open_scratch_directory};
+
+unsafe fn test_nofollow_swapped_symlink(dir_fd: wasi::Fd) {
+    wasi::path_create_directory(dir_fd, "etc").expect("creating a dir");
+    create_file(dir_fd, "etc/passwd");
+    create_file(dir_fd, "etc/shadow");
+
+    // Opening the real file with O_NOFOLLOW works.
+    let file_fd = wasi::path_open(dir_fd, 0, "etc/passwd", 0, wasi::RIGHTS_FD_READ, 0, 0)
+        .expect("opening a file with O_NOFOLLOW");
+    wasi::fd_close(file_fd).expect("closing a file");
+
+    // Swap the file for a symlink pointing somewhere else.
+    wasi::path_unlink_file(dir_fd, "etc/passwd").expect("removing a file");
+    wasi::path_symlink("shadow", dir_fd, "etc/passwd").expect("creating a symlink");
+
+    // O_NOFOLLOW must refuse to open the symlink.
+    assert_errno!(
+        wasi::path_open(dir_fd, 0, "etc/passwd", 0, wasi::RIGHTS_FD_READ, 0, 0)
+            .expect_err("opening a swapped symlink with O_NOFOLLOW should fail")
+            .raw_error(),
+        wasi::ERRNO_LOOP
+    );
+
+    // Following the symlink lands on its target.
+    let file_fd = wasi::path_open(
+        dir_fd,
+        wasi::LOOKUPFLAGS_SYMLINK_FOLLOW,
+        "etc/passwd",
+        0,
+        wasi::RIGHTS_FD_READ | wasi::RIGHTS_FD_FILESTAT_GET,
+        0,
+        0,
+    )
+    .expect("opening a symlink without O_NOFOLLOW");
+    let link_target = wasi::fd_filestat_get(file_fd).expect("fd_filestat_get");
+    let shadow_fd = wasi::path_open(
+        dir_fd,
+        0,
+        "etc/shadow",
+        0,
+        wasi::RIGHTS_FD_FILESTAT_GET,
+        0,
+        0,
+    )
+    .expect("opening the symlink target");
+    let shadow = wasi::fd_filestat_get(shadow_fd).expect("fd_filestat_get");
+    assert_eq!(
+        link_target.ino, shadow.ino,
+        "symlink resolves to its target"
+    );
+    wasi::fd_close(file_fd).expect("closing a file");
+    wasi::fd_close(shadow_fd).expect("closing a file");
+
+    // Clean up.
+    wasi::path_unlink_file(dir_fd, "etc/passwd").expect("removing a symlink");
+    wasi::path_unlink_file(dir_fd, "etc/shadow").expect("removing a file");
+    wasi::path_remove_directory(dir_fd, "etc").expect("removing a directory");
+}
+
+fn main() {
+    let mut args = env::args();
+    let prog = args.next().unwrap();
+    let arg = if let Some(arg) = args.next() {
+        arg
+    } else {
+        eprintln!("usage: {} <scratch directory>", prog);
+        process::exit(1);
+    };
+
+    // Open scratch directory
+    let dir_fd = match open_scratch_directory(&arg) {
+        Ok(dir_fd) => dir_fd,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1)
+        }
+    };
+
+    // Run the tests.
+    unsafe { test_nofollow_swapped_symlink(dir_fd) }
+}
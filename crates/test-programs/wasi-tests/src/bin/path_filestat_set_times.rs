@@ -0,0 +1,72 @@
+use std::{env, process};
+use wasi_tests::{assert_errno, create_file, open_scratch_directory};
+
+unsafe fn test_path_filestat_set_times(dir_fd: wasi::Fd) {
+    create_file(dir_fd, "file");
+    wasi::path_create_directory(dir_fd, "dir").expect("creating a directory");
+
+    for path in &["file", "dir"] {
+        let stat = wasi::path_filestat_get(dir_fd, 0, path).expect("reading stats");
+
+        // Set both times explicitly.
+        let new_atim = stat.atim - 200;
+        let new_mtim = stat.mtim - 100;
+        wasi::path_filestat_set_times(
+            dir_fd,
+            0,
+            path,
+            new_atim,
+            new_mtim,
+            wasi::FSTFLAGS_ATIM | wasi::FSTFLAGS_MTIM,
+        )
+        .expect("path_filestat_set_times should succeed");
+        let modified = wasi::path_filestat_get(dir_fd, 0, path).expect("reading stats");
+        assert_eq!(modified.atim, new_atim, "{}: atim should change", path);
+        assert_eq!(modified.mtim, new_mtim, "{}: mtim should change", path);
+
+        // Set only the mtime to the current time.
+        wasi::path_filestat_set_times(dir_fd, 0, path, 0, 0, wasi::FSTFLAGS_MTIM_NOW)
+            .expect("path_filestat_set_times with MTIM_NOW should succeed");
+        let modified = wasi::path_filestat_get(dir_fd, 0, path).expect("reading stats");
+        assert_eq!(modified.atim, new_atim, "{}: atim should not change", path);
+        assert!(
+            modified.mtim > new_mtim,
+            "{}: mtim should move forward",
+            path
+        );
+    }
+
+    assert_errno!(
+        wasi::path_filestat_set_times(dir_fd, 0, "missing", 0, 0, wasi::FSTFLAGS_MTIM_NOW)
+            .expect_err("setting times on a missing path should fail")
+            .raw_error(),
+        wasi::ERRNO_NOENT
+    );
+
+    // Clean up.
+    wasi::path_unlink_file(dir_fd, "file").expect("removing a file");
+    wasi::path_remove_directory(dir_fd, "dir").expect("removing a directory");
+}
+
+fn main() {
+    let mut args = env::args();
+    let prog = args.next().unwrap();
+    let arg = if let Some(arg) = args.next() {
+        arg
+    } else {
+        eprintln!("usage: {} <scratch directory>", prog);
+        process::exit(1);
+    };
+
+    // Open scratch directory
+    let dir_fd = match open_scratch_directory(&arg) {
+        Ok(dir_fd) => dir_fd,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1)
+        }
+    };
+
+    // Run the tests.
+    unsafe { test_path_filestat_set_times(dir_fd) }
+}
@@ -0,0 +1,70 @@
+use std::{env, process};
+use wasi_tests::{assert_errno, create_file, open_scratch_directory};
+
+unsafe fn test_path_open_create_over_directory(dir_fd: wasi::Fd) {
+    // Create a directory with a file in it.
+    wasi::path_create_directory(dir_fd, "foo").expect("creating a directory");
+    create_file(dir_fd, "foo/bar.txt");
+
+    // Opening the directory with O_CREAT must not replace it with a file.
+    assert_errno!(
+        wasi::path_open(dir_fd, 0, "foo", wasi::OFLAGS_CREAT, 0, 0, 0)
+            .expect_err("opening a directory with O_CREAT")
+            .raw_error(),
+        wasi::ERRNO_ISDIR
+    );
+    assert_errno!(
+        wasi::path_open(
+            dir_fd,
+            0,
+            "foo",
+            wasi::OFLAGS_CREAT | wasi::OFLAGS_EXCL,
+            0,
+            0,
+            0,
+        )
+        .expect_err("opening a directory with O_CREAT | O_EXCL")
+        .raw_error(),
+        wasi::ERRNO_EXIST
+    );
+
+    // The directory and its contents are still there.
+    let stat = wasi::path_filestat_get(dir_fd, 0, "foo").expect("stat foo");
+    assert_eq!(
+        stat.filetype,
+        wasi::FILETYPE_DIRECTORY,
+        "foo is still a directory"
+    );
+    let stat = wasi::path_filestat_get(dir_fd, 0, "foo/bar.txt").expect("stat foo/bar.txt");
+    assert_eq!(
+        stat.filetype,
+        wasi::FILETYPE_REGULAR_FILE,
+        "foo/bar.txt is still a file"
+    );
+
+    wasi::path_unlink_file(dir_fd, "foo/bar.txt").expect("removing a file");
+    wasi::path_remove_directory(dir_fd, "foo").expect("removing a directory");
+}
+
+fn main() {
+    let mut args = env::args();
+    let prog = args.next().unwrap();
+    let arg = if let Some(arg) = args.next() {
+        arg
+    } else {
+        eprintln!("usage: {} <scratch directory>", prog);
+        process::exit(1);
+    };
+
+    // Open scratch directory
+    let dir_fd = match open_scratch_directory(&arg) {
+        Ok(dir_fd) => dir_fd,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1)
+        }
+    };
+
+    // Run the tests.
+    unsafe { test_path_open_create_over_directory(dir_fd) }
+}
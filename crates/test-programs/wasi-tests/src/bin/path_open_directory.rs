@@ -0,0 +1,69 @@
+use std::{env, process};
+use wasi_tests::{assert_errno, create_file, open_scratch_directory};
+
+unsafe fn test_path_open_directory(dir_fd: wasi::Fd) {
+    // Create a directory and a file in the scratch directory.
+    wasi::path_create_directory(dir_fd, "dir").expect("creating a directory");
+    create_file(dir_fd, "file");
+
+    // Opening a directory with O_DIRECTORY, as `opendir` does, succeeds.
+    let fd = wasi::path_open(dir_fd, 0, "dir", wasi::OFLAGS_DIRECTORY, 0, 0, 0)
+        .expect("opening a directory with O_DIRECTORY");
+    let stat = wasi::fd_filestat_get(fd).expect("fd_filestat_get");
+    assert_eq!(
+        stat.filetype,
+        wasi::FILETYPE_DIRECTORY,
+        "expected a directory"
+    );
+    wasi::fd_close(fd).expect("closing a directory");
+
+    // Opening a file with O_DIRECTORY fails with ENOTDIR.
+    assert_errno!(
+        wasi::path_open(dir_fd, 0, "file", wasi::OFLAGS_DIRECTORY, 0, 0, 0)
+            .expect_err("opening a file with O_DIRECTORY")
+            .raw_error(),
+        wasi::ERRNO_NOTDIR
+    );
+
+    // O_DIRECTORY can't be combined with O_CREAT.
+    assert_errno!(
+        wasi::path_open(
+            dir_fd,
+            0,
+            "dir",
+            wasi::OFLAGS_DIRECTORY | wasi::OFLAGS_CREAT,
+            0,
+            0,
+            0,
+        )
+        .expect_err("opening a directory with O_DIRECTORY | O_CREAT")
+        .raw_error(),
+        wasi::ERRNO_INVAL
+    );
+
+    wasi::path_unlink_file(dir_fd, "file").expect("removing a file");
+    wasi::path_remove_directory(dir_fd, "dir").expect("removing a directory");
+}
+
+fn main() {
+    let mut args = env::args();
+    let prog = args.next().unwrap();
+    let arg = if let Some(arg) = args.next() {
+        arg
+    } else {
+        eprintln!("usage: {} <scratch directory>", prog);
+        process::exit(1);
+    };
+
+    // Open scratch directory
+    let dir_fd = match open_scratch_directory(&arg) {
+        Ok(dir_fd) => dir_fd,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1)
+        }
+    };
+
+    // Run the tests.
+    unsafe { test_path_open_directory(dir_fd) }
+}
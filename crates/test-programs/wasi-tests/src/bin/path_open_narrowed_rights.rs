@@ -0,0 +1,82 @@
+use std::{env, process};
+use wasi_tests::{assert_errno, create_file, open_scratch_directory};
+
+unsafe fn test_path_open_narrowed_rights(dir_fd: wasi::Fd) {
+    create_file(dir_fd, "file");
+
+    // Read access without seeking, telling, or changing the file.
+    let fd = wasi::path_open(dir_fd, 0, "file", 0, wasi::RIGHTS_FD_READ, 0, 0)
+        .expect("opening the file with narrowed rights");
+
+    assert_errno!(
+        wasi::fd_seek(fd, 0, wasi::WHENCE_SET)
+            .expect_err("seeking without FD_SEEK")
+            .raw_error(),
+        wasi::ERRNO_NOTCAPABLE
+    );
+    assert_errno!(
+        wasi::fd_tell(fd)
+            .expect_err("telling without FD_TELL")
+            .raw_error(),
+        wasi::ERRNO_NOTCAPABLE
+    );
+    let mut buf = [0u8; 4];
+    let iovec = wasi::Iovec {
+        buf: buf.as_mut_ptr(),
+        buf_len: buf.len(),
+    };
+    assert_errno!(
+        wasi::fd_pread(fd, &[iovec], 0)
+            .expect_err("pread without FD_SEEK")
+            .raw_error(),
+        wasi::ERRNO_NOTCAPABLE
+    );
+    assert_errno!(
+        wasi::fd_filestat_set_size(fd, 100)
+            .expect_err("set_size without FD_FILESTAT_SET_SIZE")
+            .raw_error(),
+        wasi::ERRNO_NOTCAPABLE
+    );
+    assert_errno!(
+        wasi::fd_allocate(fd, 0, 100)
+            .expect_err("allocate without FD_ALLOCATE")
+            .raw_error(),
+        wasi::ERRNO_NOTCAPABLE
+    );
+    assert_errno!(
+        wasi::fd_filestat_set_times(fd, 0, 0, wasi::FSTFLAGS_MTIM_NOW)
+            .expect_err("set_times without FD_FILESTAT_SET_TIMES")
+            .raw_error(),
+        wasi::ERRNO_NOTCAPABLE
+    );
+
+    // Plain reads are still allowed.
+    let nread = wasi::fd_read(fd, &[iovec]).expect("reading the file");
+    assert_eq!(nread, 0, "the file is empty");
+
+    wasi::fd_close(fd).expect("closing the file");
+    wasi::path_unlink_file(dir_fd, "file").expect("removing the file");
+}
+
+fn main() {
+    let mut args = env::args();
+    let prog = args.next().unwrap();
+    let arg = if let Some(arg) = args.next() {
+        arg
+    } else {
+        eprintln!("usage: {} <scratch directory>", prog);
+        process::exit(1);
+    };
+
+    // Open scratch directory
+    let dir_fd = match open_scratch_directory(&arg) {
+        Ok(dir_fd) => dir_fd,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1)
+        }
+    };
+
+    // Run the tests.
+    unsafe { test_path_open_narrowed_rights(dir_fd) }
+}
@@ -69,6 +69,39 @@ pub enum ErrorKind {
     /// Errno::Spipe: Invalid seek
     #[error("Spipe: Invalid seek")]
     Spipe,
+    /// Errno::Notempty: Directory not empty
+    #[error("Notempty: Directory not empty")]
+    Notempty,
+    /// Errno::Isdir: Is a directory
+    #[error("Isdir: Is a directory")]
+    Isdir,
+    /// Errno::Loop: Too many levels of symbolic links
+    #[error("Loop: Too many levels of symbolic links")]
+    Loop,
+    /// Errno::Xdev: Cross-device link
+    #[error("Xdev: Cross-device link")]
+    Xdev,
+    /// Errno::Nospc: No space left on device
+    #[error("Nospc: No space left on device")]
+    Nospc,
+    /// Errno::Perm: Operation not permitted
+    #[error("Perm: Operation not permitted")]
+    Perm,
+    /// Errno::Fbig: File too large
+    #[error("Fbig: File too large")]
+    Fbig,
+    /// Errno::Rofs: Read-only file system
+    #[error("Rofs: Read-only file system")]
+    Rofs,
+    /// Errno::Again: Resource unavailable, or operation would block
+    #[error("Again: Resource unavailable, or operation would block")]
+    Again,
+    /// Errno::Pipe: Broken pipe
+    #[error("Pipe: Broken pipe")]
+    Pipe,
+    /// Errno::Busy: Device or resource busy
+    #[error("Busy: Device or resource busy")]
+    Busy,
     /// Errno::NotCapable: Not capable
     #[error("Not capable")]
     NotCapable,
@@ -89,6 +122,17 @@ pub trait ErrorExt {
     fn overflow() -> Self;
     fn range() -> Self;
     fn seek_pipe() -> Self;
+    fn not_empty() -> Self;
+    fn is_dir() -> Self;
+    fn loop_() -> Self;
+    fn cross_device() -> Self;
+    fn no_space() -> Self;
+    fn perm() -> Self;
+    fn file_too_big() -> Self;
+    fn read_only() -> Self;
+    fn would_block() -> Self;
+    fn broken_pipe() -> Self;
+    fn busy() -> Self;
     fn not_capable() -> Self;
 }
 
@@ -135,6 +179,39 @@ impl ErrorExt for Error {
     fn seek_pipe() -> Self {
         ErrorKind::Spipe.into()
     }
+    fn not_empty() -> Self {
+        ErrorKind::Notempty.into()
+    }
+    fn is_dir() -> Self {
+        ErrorKind::Isdir.into()
+    }
+    fn loop_() -> Self {
+        ErrorKind::Loop.into()
+    }
+    fn cross_device() -> Self {
+        ErrorKind::Xdev.into()
+    }
+    fn no_space() -> Self {
+        ErrorKind::Nospc.into()
+    }
+    fn perm() -> Self {
+        ErrorKind::Perm.into()
+    }
+    fn file_too_big() -> Self {
+        ErrorKind::Fbig.into()
+    }
+    fn read_only() -> Self {
+        ErrorKind::Rofs.into()
+    }
+    fn would_block() -> Self {
+        ErrorKind::Again.into()
+    }
+    fn broken_pipe() -> Self {
+        ErrorKind::Pipe.into()
+    }
+    fn busy() -> Self {
+        ErrorKind::Busy.into()
+    }
     fn not_capable() -> Self {
         ErrorKind::NotCapable.into()
     }
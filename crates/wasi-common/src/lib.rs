@@ -27,8 +27,9 @@
 //! This design makes it possible for `wasi-common` embedders to statically
 //! reason about access to the local filesystem by examining what impls are
 //! linked into an application. We found that this separation of concerns also
-//! makes it pretty enjoyable to write alternative implementations, e.g. a
-//! virtual filesystem (which will land in a future PR).
+//! makes it pretty enjoyable to write alternative implementations, e.g. the
+//! in-memory virtual filesystem provided by the `wasi-virtfs` crate found at
+//! `crates/wasi-common/virtfs`.
 //!
 //! ## Traits for the rest of WASI's features
 //!
@@ -83,6 +83,17 @@ impl From<ErrorKind> for types::Errno {
             ErrorKind::Overflow => Errno::Overflow,
             ErrorKind::Range => Errno::Range,
             ErrorKind::Spipe => Errno::Spipe,
+            ErrorKind::Notempty => Errno::Notempty,
+            ErrorKind::Isdir => Errno::Isdir,
+            ErrorKind::Loop => Errno::Loop,
+            ErrorKind::Xdev => Errno::Xdev,
+            ErrorKind::Nospc => Errno::Nospc,
+            ErrorKind::Perm => Errno::Perm,
+            ErrorKind::Fbig => Errno::Fbig,
+            ErrorKind::Rofs => Errno::Rofs,
+            ErrorKind::Again => Errno::Again,
+            ErrorKind::Pipe => Errno::Pipe,
+            ErrorKind::Busy => Errno::Busy,
             ErrorKind::NotCapable => Errno::Notcapable,
         }
     }
@@ -0,0 +1,76 @@
+//! Appending to a large file one guest-sized write at a time, as a guest
+//! streaming its output to a file would, and many small writes at a time,
+//! as a guest writing a log line by line would.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::io::IoSlice;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags},
+};
+use wasi_virtfs::Filesystem;
+use wiggle::run_in_dummy_executor as run;
+
+const WRITE_SIZE: usize = 64 * 1024;
+const LINE_SIZE: usize = 100;
+const LINES: usize = 1_000_000;
+
+fn append_to_file(total: usize) {
+    let fs = Filesystem::new(wasi_cap_std_sync::clocks_ctx().system, 0);
+    let root = fs.root();
+    let f =
+        run(root.open_file(false, "file", OFlags::CREATE, false, true, FdFlags::APPEND)).unwrap();
+    let data = vec![1; WRITE_SIZE];
+    for _ in 0..total / WRITE_SIZE {
+        run(f.write_vectored(&[IoSlice::new(&data)])).unwrap();
+    }
+}
+
+/// Contents kept in one contiguous buffer, which copies the whole file each
+/// time it outgrows its capacity.
+fn append_to_vec(total: usize) {
+    let mut contents = Vec::new();
+    let data = vec![1; WRITE_SIZE];
+    for _ in 0..total / WRITE_SIZE {
+        contents.extend_from_slice(&data);
+    }
+    criterion::black_box(contents);
+}
+
+fn append_lines() {
+    let fs = Filesystem::new(wasi_cap_std_sync::clocks_ctx().system, 0);
+    let root = fs.root();
+    let f =
+        run(root.open_file(false, "log", OFlags::CREATE, false, true, FdFlags::APPEND)).unwrap();
+    let line = [b'x'; LINE_SIZE];
+    for _ in 0..LINES {
+        run(f.write_vectored(&[IoSlice::new(&line)])).unwrap();
+    }
+}
+
+fn bench_append(c: &mut Criterion) {
+    let mut group = c.benchmark_group("append");
+    group.sample_size(10);
+    for mib in &[1, 16, 256] {
+        let total = mib << 20;
+        group.throughput(Throughput::Bytes(total as u64));
+        group.bench_with_input(BenchmarkId::new("virtfs", mib), &total, |b, total| {
+            b.iter(|| append_to_file(*total))
+        });
+        group.bench_with_input(BenchmarkId::new("vec", mib), &total, |b, total| {
+            b.iter(|| append_to_vec(*total))
+        });
+    }
+    group.finish();
+}
+
+fn bench_lines(c: &mut Criterion) {
+    let mut group = c.benchmark_group("append_lines");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(LINES as u64));
+    group.bench_function("virtfs", |b| b.iter(append_lines));
+    group.finish();
+}
+
+criterion_group!(benches, bench_append, bench_lines);
+criterion_main!(benches);
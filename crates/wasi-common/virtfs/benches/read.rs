@@ -0,0 +1,67 @@
+//! Reading a large file from start to end in small reads, as a guest
+//! streaming its input would.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::io::{IoSlice, IoSliceMut, SeekFrom};
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags, WasiFile},
+};
+use wasi_virtfs::{AtimeUpdates, Filesystem};
+use wiggle::run_in_dummy_executor as run;
+
+const FILE_SIZE: usize = 256 << 20;
+const READ_SIZE: usize = 4096;
+
+fn file(atime_updates: AtimeUpdates) -> Box<dyn WasiFile> {
+    let fs = Filesystem::new(wasi_cap_std_sync::clocks_ctx().system, 0);
+    fs.set_atime_updates(atime_updates);
+    let root = fs.root();
+    let f =
+        run(root.open_file(false, "file", OFlags::CREATE, true, true, FdFlags::empty())).unwrap();
+    let data = vec![1; 1 << 20];
+    for _ in 0..FILE_SIZE / data.len() {
+        run(f.write_vectored(&[IoSlice::new(&data)])).unwrap();
+    }
+    f
+}
+
+fn read_sequentially(f: &dyn WasiFile) {
+    run(f.seek(SeekFrom::Start(0))).unwrap();
+    let mut buf = [0; READ_SIZE];
+    while run(f.read_vectored(&mut [IoSliceMut::new(&mut buf)])).unwrap() > 0 {}
+}
+
+fn read_sequentially_at(f: &dyn WasiFile) {
+    let mut buf = [0; READ_SIZE];
+    let mut offset = 0;
+    loop {
+        let n = run(f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], offset)).unwrap();
+        if n == 0 {
+            break;
+        }
+        offset += n;
+    }
+}
+
+fn bench_read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read_4k");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+    for (name, atime_updates) in &[
+        ("strictatime", AtimeUpdates::Strict),
+        ("noatime", AtimeUpdates::Never),
+    ] {
+        let f = file(*atime_updates);
+        group.bench_with_input(BenchmarkId::new("read", name), &f, |b, f| {
+            b.iter(|| read_sequentially(&**f))
+        });
+        group.bench_with_input(BenchmarkId::new("pread", name), &f, |b, f| {
+            b.iter(|| read_sequentially_at(&**f))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_read);
+criterion_main!(benches);
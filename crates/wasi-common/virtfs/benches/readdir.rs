@@ -0,0 +1,63 @@
+//! Listing a directory with 100k entries, all at once, and a batch at a
+//! time as a guest with a 4 KiB `fd_readdir` buffer would.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::sync::Arc;
+use wasi_common::{
+    dir::{ReaddirCursor, WasiDir},
+    file::{FdFlags, OFlags},
+};
+use wasi_virtfs::{Dir, Filesystem};
+use wiggle::run_in_dummy_executor as run;
+
+const ENTRIES: usize = 100_000;
+/// Entries with names like these take about 32 bytes each in a guest's
+/// buffer.
+const BATCH: usize = 128;
+
+fn populated() -> (Arc<Filesystem>, Dir) {
+    let fs = Filesystem::new(wasi_cap_std_sync::clocks_ctx().system, 0);
+    let root = fs.root();
+    for i in 0..ENTRIES {
+        let name = format!("file{:06}", i);
+        run(root.open_file(false, &name, OFlags::CREATE, false, true, FdFlags::empty())).unwrap();
+    }
+    (fs, root)
+}
+
+fn list_all(dir: &Dir) -> usize {
+    run(dir.readdir(ReaddirCursor::from(0))).unwrap().count()
+}
+
+fn list_in_batches(dir: &Dir) -> usize {
+    let mut listed = 0;
+    let mut cookie = ReaddirCursor::from(0);
+    loop {
+        let mut batch = 0;
+        for entry in run(dir.readdir(cookie)).unwrap().take(BATCH) {
+            cookie = entry.unwrap().next;
+            batch += 1;
+        }
+        if batch == 0 {
+            return listed;
+        }
+        listed += batch;
+    }
+}
+
+fn bench_readdir(c: &mut Criterion) {
+    let (_fs, root) = populated();
+    let mut group = c.benchmark_group("readdir");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(ENTRIES as u64));
+    group.bench_function("whole", |b| {
+        b.iter(|| assert_eq!(list_all(&root), ENTRIES + 2))
+    });
+    group.bench_function("batches", |b| {
+        b.iter(|| assert_eq!(list_in_batches(&root), ENTRIES + 2))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_readdir);
+criterion_main!(benches);
@@ -0,0 +1,331 @@
+//! Populating a tree from an archive, as `Dir::import_tar` and
+//! `Dir::import_zip` do, and writing one out as `Dir::export_tar` does.
+
+use crate::contents::Contents;
+use crate::dir::Dir;
+use crate::file::FileInode;
+use crate::{Filesystem, Inode, StaticWrites};
+use cap_std::time::{Duration, SystemTime};
+use std::collections::HashMap;
+use std::io::{self, IoSliceMut, Read, Seek, Write};
+use std::sync::Arc;
+use tar::{EntryType, Header};
+use wasi_common::{Error, ErrorExt, SystemTimeSpec};
+use zip::result::ZipError;
+
+/// How `Dir::import_tar` treats the entries of an archive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TarOptions {
+    /// Skip devices, FIFOs, and any other entries which can't be
+    /// represented, rather than failing with ENOTSUP.
+    pub skip_unsupported: bool,
+}
+
+/// How `Dir::import_zip` treats the entries of an archive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ZipOptions {
+    /// Create a symlink for each entry whose Unix mode, as Info-ZIP records
+    /// it, says it's one, with the entry's contents as its target, rather
+    /// than a file holding the target.
+    pub unix_symlinks: bool,
+}
+
+/// Create the entries of the tar archive read from `reader` in `dir`, which
+/// they can't reach above.
+pub(crate) fn import_tar<R: Read>(dir: &Dir, reader: R, options: TarOptions) -> Result<(), Error> {
+    let mut archive = tar::Archive::new(reader);
+    // Directories get their mtims once everything in them is created, which
+    // would otherwise update them.
+    let mut dir_times = Vec::new();
+    for entry in archive.entries().map_err(read_failed)? {
+        let mut entry = entry.map_err(read_failed)?;
+        let header = entry.header();
+        let entry_type = header.entry_type();
+        let mtim = header.mtime().map_err(read_failed)?;
+        let mtim = SystemTime::from_std(std::time::UNIX_EPOCH + Duration::from_secs(mtim));
+        let path = sanitize(&entry.path_bytes())?;
+        let created = match entry_type {
+            EntryType::XGlobalHeader => continue,
+            EntryType::Directory => {
+                dir.create_dir_all(&path)?;
+                dir_times.push((path.clone(), mtim));
+                continue;
+            }
+            EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse => {
+                let contents = read_contents(dir, &mut entry)?;
+                create_parent(dir, &path)?;
+                dir.put_file(&path, contents, StaticWrites::CopyOnWrite)
+            }
+            EntryType::Symlink => {
+                let target = link_name(&entry)?;
+                create_parent(dir, &path)?;
+                dir.put_symlink(&target, &path)
+            }
+            EntryType::Link => {
+                let target = sanitize(link_name(&entry)?.as_bytes())?;
+                create_parent(dir, &path)?;
+                dir.link(&target, dir, &path)
+            }
+            _ if options.skip_unsupported => continue,
+            other => {
+                return Err(Error::not_supported()
+                    .context(format!("{:?} entry {:?} in archive", other, path)))
+            }
+        };
+        created.map_err(|e| e.context(format!("creating {:?} from archive", path)))?;
+        set_mtim(dir, &path, mtim)?;
+    }
+    // Innermost first, though setting times never touches the parent.
+    for (path, mtim) in dir_times.into_iter().rev() {
+        set_mtim(dir, &path, mtim)?;
+    }
+    Ok(())
+}
+
+/// Create the entries of the zip archive read from `reader` in `dir`, which
+/// they can't reach above.
+pub(crate) fn import_zip<R: Read + Seek>(
+    dir: &Dir,
+    reader: R,
+    options: ZipOptions,
+) -> Result<(), Error> {
+    let mut archive = zip::ZipArchive::new(reader).map_err(zip_failed)?;
+    let mut dir_times = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(zip_failed)?;
+        let path = sanitize(file.name_raw())?;
+        let mtim = zip_time(file.last_modified());
+        if file.is_dir() {
+            dir.create_dir_all(&path)?;
+            dir_times.push((path, mtim));
+            continue;
+        }
+        let is_symlink = matches!(file.unix_mode(), Some(mode) if mode & S_IFMT == S_IFLNK);
+        let contents = read_contents(dir, &mut file)?;
+        create_parent(dir, &path)?;
+        let created = if options.unix_symlinks && is_symlink {
+            let mut target = vec![0; contents.len() as usize];
+            contents.read_at(&mut [IoSliceMut::new(&mut target)], 0)?;
+            let target = String::from_utf8(target).map_err(|_| {
+                Error::illegal_byte_sequence().context("archive link target isn't UTF-8")
+            })?;
+            dir.put_symlink(&target, &path)
+        } else {
+            dir.put_file(&path, contents, StaticWrites::CopyOnWrite)
+        };
+        created.map_err(|e| e.context(format!("creating {:?} from archive", path)))?;
+        set_mtim(dir, &path, mtim)?;
+    }
+    for (path, mtim) in dir_times.into_iter().rev() {
+        set_mtim(dir, &path, mtim)?;
+    }
+    Ok(())
+}
+
+/// Write the tree rooted at the directory `root` to `writer` as a tar
+/// archive, with `root` itself as `./`.
+pub(crate) fn export_tar<W: Write>(fs: &Filesystem, root: Inode, writer: W) -> Result<(), Error> {
+    if !matches!(root, Inode::Dir(_)) {
+        return Err(Error::not_dir().context("only a directory can be archived"));
+    }
+    let mut archive = tar::Builder::new(writer);
+    // The path each file with more than one link was first archived at,
+    // by serial, for its other links to refer to.
+    let mut linked: HashMap<u64, String> = HashMap::new();
+    // As in `hash::tree`, a stack rather than a recursion. Directories'
+    // paths end in `/`.
+    let mut pending: Vec<(String, Inode)> = vec![("./".to_owned(), root)];
+    while let Some((path, inode)) = pending.pop() {
+        let stat = inode.get_filestat(fs.device_id());
+        let mtim = stat
+            .mtim
+            .and_then(|mtim| mtim.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs());
+        let written = match &inode {
+            Inode::Dir(d) => {
+                let entries: Vec<(Arc<str>, Inode)> = d
+                    .read()
+                    .unwrap()
+                    .contents
+                    .iter()
+                    .map(|(name, inode)| (name.clone(), inode.clone()))
+                    .collect();
+                let prefix = if path == "./" { "" } else { &path };
+                pending.extend(entries.into_iter().rev().map(|(name, inode)| {
+                    let mut child = format!("{}{}", prefix, name);
+                    if let Inode::Dir(_) = inode {
+                        child.push('/');
+                    }
+                    (child, inode)
+                }));
+                let mut header = tar_header(EntryType::Directory, 0o755, mtim);
+                archive.append_data(&mut header, &path, io::empty())
+            }
+            Inode::File(_) if stat.nlink > 1 && linked.contains_key(&stat.inode) => {
+                let mut header = tar_header(EntryType::Link, 0o644, mtim);
+                archive.append_link(&mut header, &path, &linked[&stat.inode])
+            }
+            Inode::File(_) | Inode::Dynamic(_) => {
+                if stat.nlink > 1 {
+                    linked.insert(stat.inode, path.clone());
+                }
+                crate::hash::with_contents(fs, &inode, |f| {
+                    let mut header = tar_header(EntryType::Regular, 0o644, mtim);
+                    header.set_size(f.size());
+                    Ok(archive.append_data(&mut header, &path, FileReader { file: f, offset: 0 }))
+                })?
+            }
+            Inode::Symlink(l) => {
+                let target = l.read().unwrap().target.clone();
+                let mut header = tar_header(EntryType::Symlink, 0o777, mtim);
+                archive.append_link(&mut header, &path, target)
+            }
+            // A device, a FIFO, or a mount point is the host's, and nothing
+            // in an archive could restore it.
+            Inode::Device(_) | Inode::Fifo(_) | Inode::Mount(_) => continue,
+        };
+        written
+            .map_err(|e| Error::io().context(format!("writing {:?} to archive: {}", path, e)))?;
+    }
+    archive
+        .into_inner()
+        .map_err(|e| Error::io().context(format!("finishing archive: {}", e)))?;
+    Ok(())
+}
+
+/// A header with nothing that could differ between runs but what's given:
+/// no owners, and no times but the mtim.
+fn tar_header(kind: EntryType, mode: u32, mtim: u64) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(kind);
+    header.set_mode(mode);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(mtim);
+    header.set_size(0);
+    header
+}
+
+/// A file's contents as a `Read`, for the tar builder to copy from.
+struct FileReader<'a> {
+    file: &'a FileInode,
+    offset: u64,
+}
+
+impl Read for FileReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self
+            .file
+            .read_at(&mut [IoSliceMut::new(buf)], self.offset)
+            // Only an offset past the largest file size can fail.
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+}
+
+/// The file type bits of a Unix mode, and their value for a symlink.
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+/// A zip entry's timestamp, which is in no particular time zone, taken as
+/// UTC.
+fn zip_time(time: zip::DateTime) -> SystemTime {
+    let days = days_from_civil(
+        i64::from(time.year()),
+        i64::from(time.month().max(1)),
+        i64::from(time.day().max(1)),
+    );
+    let secs = days * 86400
+        + i64::from(time.hour()) * 3600
+        + i64::from(time.minute()) * 60
+        + i64::from(time.second());
+    SystemTime::from_std(std::time::UNIX_EPOCH + Duration::from_secs(secs as u64))
+}
+
+/// The number of days from 1970-01-01 to the given date, for dates after
+/// it. See Howard Hinnant's `days_from_civil`.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month_from_march = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_from_march + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Read the whole of an archive entry.
+fn read_contents<R: Read>(dir: &Dir, entry: &mut R) -> Result<Contents, Error> {
+    Contents::read_from(entry, dir.fs().zeroizes_contents()).map_err(read_failed)
+}
+
+/// The path of an archive entry as a path relative to the directory it's
+/// imported into. A leading `/` is stripped, as `tar` does, but a path with
+/// a `..` component, which could climb out of the directory, fails with
+/// EPERM, as does one starting with a drive letter. A path that isn't UTF-8
+/// fails with EILSEQ.
+fn sanitize(path: &[u8]) -> Result<String, Error> {
+    let path = std::str::from_utf8(path)
+        .map_err(|_| Error::illegal_byte_sequence().context("archive path isn't UTF-8"))?;
+    let bytes = path.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        return Err(Error::perm().context(format!("archive path {:?} has a drive letter", path)));
+    }
+    let mut names = Vec::new();
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                return Err(
+                    Error::perm().context(format!("archive path {:?} climbs out with ..", path))
+                )
+            }
+            name => names.push(name),
+        }
+    }
+    if names.is_empty() {
+        Ok(".".to_owned())
+    } else {
+        Ok(names.join("/"))
+    }
+}
+
+fn link_name<R: Read>(entry: &tar::Entry<R>) -> Result<String, Error> {
+    let target = entry
+        .link_name_bytes()
+        .ok_or_else(|| Error::invalid_argument().context("archive link has no target"))?;
+    String::from_utf8(target.into_owned())
+        .map_err(|_| Error::illegal_byte_sequence().context("archive link target isn't UTF-8"))
+}
+
+/// Create the directories above `path`, for archives which leave them out.
+fn create_parent(dir: &Dir, path: &str) -> Result<(), Error> {
+    match path.rfind('/') {
+        Some(slash) => dir.create_dir_all(&path[..slash]),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn set_mtim(dir: &Dir, path: &str, mtim: SystemTime) -> Result<(), Error> {
+    dir.entry_at(path)?.set_times(
+        Some(SystemTimeSpec::Absolute(mtim)),
+        Some(SystemTimeSpec::Absolute(mtim)),
+        dir.fs().now(),
+    );
+    Ok(())
+}
+
+fn zip_failed(e: ZipError) -> Error {
+    match e {
+        ZipError::Io(e) => read_failed(e),
+        ZipError::UnsupportedArchive(what) => {
+            Error::not_supported().context(format!("reading archive: {}", what))
+        }
+        e => Error::io().context(format!("reading archive: {}", e)),
+    }
+}
+
+fn read_failed(e: std::io::Error) -> Error {
+    Error::io().context(format!("reading archive: {}", e))
+}
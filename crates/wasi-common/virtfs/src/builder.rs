@@ -0,0 +1,139 @@
+//! Populating a new filesystem from a list of entries, for test fixtures and
+//! the like, without going through the `WasiDir` and `WasiFile` traits.
+//!
+//! ```
+//! use wasi_virtfs::{clock::FixedClock, Filesystem};
+//! # use cap_std::time::SystemTime;
+//!
+//! let fs = Filesystem::builder()
+//!     .dir("tmp")
+//!     .file("etc/hosts", b"127.0.0.1 localhost\n")
+//!     .file("etc/hostname", b"sandbox\n")
+//!     .build(Box::new(FixedClock::new(SystemTime::from_std(std::time::UNIX_EPOCH))), 0)
+//!     .expect("no conflicting entries");
+//! assert_eq!(fs.inode_count(), 5);
+//! ```
+
+use crate::contents::Contents;
+use crate::{Filesystem, Inode, StaticWrites};
+use cap_std::time::SystemTime;
+use std::collections::HashSet;
+use std::sync::Arc;
+use wasi_common::{clocks::WasiSystemClock, Error, ErrorExt, SystemTimeSpec};
+
+/// The timestamps to give a file made by `FilesystemBuilder::file_with_times`.
+/// Its ctim is the time the filesystem is built, as it would be after
+/// `set_times`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileTimes {
+    pub atim: SystemTime,
+    pub mtim: SystemTime,
+}
+
+enum Entry {
+    Dir,
+    File {
+        contents: Vec<u8>,
+        times: Option<FileTimes>,
+    },
+}
+
+/// A list of directories and files to create in a new filesystem, made with
+/// `Filesystem::builder`. Entries are created in the order they're added,
+/// along with any directories missing above them. Nothing is checked until
+/// `build`.
+#[derive(Default)]
+pub struct FilesystemBuilder {
+    entries: Vec<(String, Entry)>,
+}
+
+impl FilesystemBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an empty directory at `path`.
+    pub fn dir(mut self, path: &str) -> Self {
+        self.entries.push((path.to_owned(), Entry::Dir));
+        self
+    }
+
+    /// Add a file at `path` holding `contents`, with timestamps of the time
+    /// the filesystem is built.
+    pub fn file(mut self, path: &str, contents: &[u8]) -> Self {
+        self.entries.push((
+            path.to_owned(),
+            Entry::File {
+                contents: contents.to_vec(),
+                times: None,
+            },
+        ));
+        self
+    }
+
+    /// Like `file`, with the atim and mtim given by `times`.
+    pub fn file_with_times(mut self, path: &str, contents: &[u8], times: FileTimes) -> Self {
+        self.entries.push((
+            path.to_owned(),
+            Entry::File {
+                contents: contents.to_vec(),
+                times: Some(times),
+            },
+        ));
+        self
+    }
+
+    /// Create a filesystem as `Filesystem::new` does, and populate it. Fails
+    /// with EEXIST if two entries have the same path, or one would replace a
+    /// directory created for another, and with ENOTDIR if a path runs
+    /// through a file. Paths are relative to the root; `.` and repeated
+    /// slashes are ignored.
+    pub fn build(
+        self,
+        clock: Box<dyn WasiSystemClock>,
+        device_id: u64,
+    ) -> Result<Arc<Filesystem>, Error> {
+        let fs = Filesystem::new(clock, device_id);
+        let root = fs.root();
+        let mut seen = HashSet::new();
+        for (path, entry) in self.entries {
+            let key = normalize(&path);
+            if key.is_empty() {
+                return Err(Error::exist().context("the root directory already exists"));
+            }
+            if !seen.insert(key.clone()) {
+                return Err(Error::exist().context(format!("{:?} added twice", path)));
+            }
+            match entry {
+                Entry::Dir => fs.create_dir_all(&key),
+                Entry::File { contents, times } => {
+                    if let Some(slash) = key.rfind('/') {
+                        fs.create_dir_all(&key[..slash])?;
+                    }
+                    let mut data = Contents::default();
+                    data.write_at(&contents, 0)?;
+                    root.put_file(&key, data, StaticWrites::CopyOnWrite)?;
+                    if let (Some(times), Inode::File(f)) = (times, root.lookup(&key)?) {
+                        f.write().unwrap().set_times(
+                            Some(SystemTimeSpec::Absolute(times.atim)),
+                            Some(SystemTimeSpec::Absolute(times.mtim)),
+                            fs.now(),
+                        );
+                    }
+                    Ok(())
+                }
+            }
+            .map_err(|e| e.context(format!("creating {:?}", path)))?;
+        }
+        Ok(fs)
+    }
+}
+
+/// `path` with `.` components and empty ones, from repeated or trailing
+/// slashes, left out.
+fn normalize(path: &str) -> String {
+    path.split('/')
+        .filter(|name| !name.is_empty() && *name != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
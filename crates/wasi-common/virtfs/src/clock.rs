@@ -0,0 +1,92 @@
+//! Clocks for a `Filesystem` to take its timestamps from: the host's real
+//! clock, or clocks which don't depend on wall time, for filesystems whose
+//! timestamps must be reproducible from one run to the next.
+
+use crate::duration_from_nanos;
+use cap_std::time::{Duration, SystemTime};
+use cap_std::AmbientAuthority;
+use cap_time_ext::SystemClockExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use wasi_common::clocks::WasiSystemClock;
+
+/// The host's real-time clock.
+pub struct SystemClock(cap_std::time::SystemClock);
+
+impl SystemClock {
+    pub fn new(ambient_authority: AmbientAuthority) -> Self {
+        SystemClock(cap_std::time::SystemClock::new(ambient_authority))
+    }
+}
+
+impl WasiSystemClock for SystemClock {
+    fn resolution(&self) -> Duration {
+        self.0.resolution()
+    }
+    fn now(&self, precision: Duration) -> SystemTime {
+        self.0.now_with(precision)
+    }
+}
+
+/// A clock which is stopped at a single instant. Every timestamp the
+/// filesystem takes from it is the same.
+pub struct FixedClock(SystemTime);
+
+impl FixedClock {
+    pub fn new(time: SystemTime) -> Self {
+        FixedClock(time)
+    }
+}
+
+impl WasiSystemClock for FixedClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+    fn now(&self, _precision: Duration) -> SystemTime {
+        self.0
+    }
+}
+
+/// A clock which advances by a fixed step every time it is read, starting
+/// from a fixed instant. Timestamps depend only on the sequence of
+/// operations, not on wall time, but still preserve their order: a later
+/// write always has a later mtim.
+pub struct LogicalClock {
+    start: SystemTime,
+    step: Duration,
+    ticks: AtomicU64,
+}
+
+impl LogicalClock {
+    pub fn new(start: SystemTime, step: Duration) -> Self {
+        LogicalClock {
+            start,
+            step,
+            ticks: AtomicU64::new(0),
+        }
+    }
+
+    /// How many times the clock has been read.
+    pub fn ticks(&self) -> u64 {
+        self.ticks.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for LogicalClock {
+    /// Start at the Unix epoch and advance by one second per reading.
+    fn default() -> Self {
+        LogicalClock::new(
+            SystemTime::from_std(std::time::UNIX_EPOCH),
+            Duration::from_secs(1),
+        )
+    }
+}
+
+impl WasiSystemClock for LogicalClock {
+    fn resolution(&self) -> Duration {
+        self.step
+    }
+    fn now(&self, _precision: Duration) -> SystemTime {
+        let ticks = self.ticks.fetch_add(1, Ordering::Relaxed);
+        self.start + duration_from_nanos(self.step.as_nanos() * u128::from(ticks))
+    }
+}
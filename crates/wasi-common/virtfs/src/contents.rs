@@ -0,0 +1,432 @@
+use crate::LazyContents;
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use zeroize::Zeroize;
+
+/// The size of the chunks file contents are stored in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The contents of a regular file, stored in fixed-size chunks so that
+/// growing a file never copies more than one chunk, and truncating it frees
+/// whole chunks. Chunks which were never written are holes, which read as
+/// zeros, so extending a file with `set_filestat_size` or `allocate`, or
+/// writing far past its end, only costs memory for the chunks actually
+/// written.
+///
+/// Chunks are reference counted, so cloning `Contents` shares them rather
+/// than copying any data. A write to a shared chunk copies just that chunk
+/// first.
+///
+/// The contents may also start out as data the host holds, which is read in
+/// place or, for a `LazyContents` source, on demand. Writing to a chunk of it
+/// copies that chunk out first, just as for a shared chunk.
+#[derive(Clone, Default)]
+pub(crate) struct Contents {
+    len: u64,
+    /// Data held by the host, for the chunks which haven't been written.
+    base: Base,
+    /// Chunks keyed by index. Each holds the data from the start of the
+    /// chunk up to the last byte written in it; the rest of the chunk reads
+    /// as zeros.
+    chunks: BTreeMap<u64, Arc<Chunk>>,
+    /// Whether chunks created from now on are scrubbed. See `Chunk`.
+    zeroize: bool,
+}
+
+/// The data stored for one chunk.
+///
+/// A chunk may be marked to be scrubbed: it then overwrites its data with
+/// zeros when the last reference to it is dropped, and the bytes cut off
+/// when it's truncated. Such a chunk is allocated at its full size from the
+/// start and is never shrunk, so that no reallocation leaves a copy of its
+/// data behind in memory handed back to the allocator.
+struct Chunk {
+    data: Vec<u8>,
+    zeroize: bool,
+}
+
+impl Chunk {
+    /// A chunk holding a copy of `data`.
+    fn new(data: &[u8], zeroize: bool) -> Self {
+        let mut copy = Vec::with_capacity(if zeroize { CHUNK_SIZE } else { data.len() });
+        copy.extend_from_slice(data);
+        Chunk {
+            data: copy,
+            zeroize,
+        }
+    }
+
+    /// Keep only the first `len` bytes, releasing the memory the rest held
+    /// if that's a large fraction of it.
+    fn truncate(&mut self, len: usize) {
+        if self.zeroize {
+            if self.data.len() > len {
+                self.data[len..].zeroize();
+            }
+            self.data.truncate(len);
+            return;
+        }
+        self.data.truncate(len);
+        if self.data.len() < self.data.capacity() / 4 {
+            self.data.shrink_to_fit();
+        }
+    }
+
+    /// Release memory held beyond the data, unless the chunk is scrubbed.
+    fn shrink_to_fit(&mut self) {
+        if !self.zeroize {
+            self.data.shrink_to_fit();
+        }
+    }
+}
+
+impl Clone for Chunk {
+    fn clone(&self) -> Self {
+        Chunk::new(&self.data, self.zeroize)
+    }
+}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        if self.zeroize {
+            // Clears the spare capacity too.
+            self.data.zeroize();
+        }
+    }
+}
+
+impl Deref for Chunk {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.data
+    }
+}
+
+impl DerefMut for Chunk {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.data
+    }
+}
+
+/// Data the host holds for the start of a file.
+#[derive(Clone)]
+enum Base {
+    Static(&'static [u8]),
+    /// Data and the length of it which is still part of the file.
+    Shared(Arc<[u8]>, usize),
+    /// A source read on demand, and the length of it which is still part of
+    /// the file.
+    Lazy(Arc<dyn LazyContents>, u64),
+}
+
+impl Default for Base {
+    fn default() -> Self {
+        Base::Static(&[])
+    }
+}
+
+impl Base {
+    fn len(&self) -> u64 {
+        match self {
+            Base::Static(data) => data.len() as u64,
+            Base::Shared(_, len) => *len as u64,
+            Base::Lazy(_, len) => *len,
+        }
+    }
+
+    /// Fill `out` from `offset`, with zeros past the end.
+    fn read_at(&self, out: &mut [u8], offset: u64) -> io::Result<()> {
+        let avail = self.len().saturating_sub(offset).min(out.len() as u64) as usize;
+        let (data, zeros) = out.split_at_mut(avail);
+        match self {
+            Base::Static(bytes) => copy_from(data, bytes, offset),
+            Base::Shared(bytes, _) => copy_from(data, bytes, offset),
+            Base::Lazy(source, _) => {
+                let mut filled = 0;
+                while filled < avail {
+                    let n = source.read_at(&mut data[filled..], offset + filled as u64)?;
+                    // A source which has shrunk reads as zeros past its end.
+                    if n == 0 {
+                        break;
+                    }
+                    filled += n;
+                }
+                zero(&mut data[filled..]);
+            }
+        }
+        zero(zeros);
+        Ok(())
+    }
+
+    /// A copy of the part of chunk `index` before the end of the data.
+    fn chunk(&self, index: u64, zeroize: bool) -> io::Result<Chunk> {
+        let mut chunk = Chunk::new(&[], zeroize);
+        chunk.resize(self.chunk_len(index), 0);
+        self.read_at(&mut chunk, index * CHUNK_SIZE as u64)?;
+        Ok(chunk)
+    }
+
+    /// The length of the part of chunk `index` before the end of the data.
+    fn chunk_len(&self, index: u64) -> usize {
+        let start = index.saturating_mul(CHUNK_SIZE as u64);
+        self.len().saturating_sub(start).min(CHUNK_SIZE as u64) as usize
+    }
+
+    /// Keep only the first `size` bytes, letting go of the data entirely if
+    /// none are left.
+    fn truncate(&mut self, size: u64) {
+        if size == 0 {
+            *self = Base::default();
+            return;
+        }
+        match self {
+            Base::Static(data) => *data = &data[..(data.len() as u64).min(size) as usize],
+            Base::Shared(_, len) => *len = (*len as u64).min(size) as usize,
+            Base::Lazy(_, len) => *len = (*len).min(size),
+        }
+    }
+}
+
+/// Fill `out` from `bytes` at `offset`, which is past the end of `bytes`
+/// only if `out` is empty.
+fn copy_from(out: &mut [u8], bytes: &[u8], offset: u64) {
+    if out.is_empty() {
+        return;
+    }
+    let offset = offset as usize;
+    out.copy_from_slice(&bytes[offset..offset + out.len()]);
+}
+
+fn zero(out: &mut [u8]) {
+    for byte in out {
+        *byte = 0;
+    }
+}
+
+/// The index of the chunk containing `offset`, and the offset within it.
+fn locate(offset: u64) -> (u64, usize) {
+    let size = CHUNK_SIZE as u64;
+    (offset / size, (offset % size) as usize)
+}
+
+impl Contents {
+    /// Contents which read `data` in place.
+    pub(crate) fn from_static(data: &'static [u8]) -> Self {
+        Contents {
+            len: data.len() as u64,
+            base: Base::Static(data),
+            chunks: BTreeMap::new(),
+            zeroize: false,
+        }
+    }
+
+    /// Contents which read `data` in place, holding a reference to it.
+    pub(crate) fn from_shared(data: Arc<[u8]>) -> Self {
+        let len = data.len();
+        Contents {
+            len: len as u64,
+            base: Base::Shared(data, len),
+            chunks: BTreeMap::new(),
+            zeroize: false,
+        }
+    }
+
+    /// Contents which read `source` on demand.
+    pub(crate) fn from_lazy(source: Arc<dyn LazyContents>) -> Self {
+        let len = source.size();
+        Contents {
+            len,
+            base: Base::Lazy(source, len),
+            chunks: BTreeMap::new(),
+            zeroize: false,
+        }
+    }
+
+    /// Contents read from `reader` to its end, scrubbed from memory as
+    /// they're let go of if `zeroize` is set.
+    pub(crate) fn read_from<R: io::Read>(reader: &mut R, zeroize: bool) -> io::Result<Self> {
+        let mut contents = Contents::default();
+        contents.set_zeroize(zeroize);
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            contents.append(&buf[..n])?;
+        }
+        if zeroize {
+            buf.zeroize();
+        }
+        Ok(contents)
+    }
+
+    /// Scrub data from memory as it's let go of, from now on: see `Chunk`.
+    /// Chunks already stored, and data the host holds, are left as they
+    /// are.
+    pub(crate) fn set_zeroize(&mut self, zeroize: bool) {
+        self.zeroize = zeroize;
+    }
+
+    pub(crate) fn zeroize(&self) -> bool {
+        self.zeroize
+    }
+
+    /// The size of the file, including holes.
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// The number of bytes of data stored, which leaves out holes. Data
+    /// the host holds counts too.
+    pub(crate) fn materialized(&self) -> u64 {
+        let overwritten: u64 = (self.chunks.keys())
+            .map(|index| self.base.chunk_len(*index) as u64)
+            .sum();
+        let stored: u64 = self.chunks.values().map(|c| c.len() as u64).sum();
+        self.base.len() - overwritten + stored
+    }
+
+    /// The number of bytes of data stored in chunks not shared with any
+    /// other `Contents`.
+    pub(crate) fn owned(&self) -> u64 {
+        self.chunks
+            .values()
+            .filter(|c| Arc::strong_count(c) == 1)
+            .map(|c| c.len() as u64)
+            .sum()
+    }
+
+    /// The number of bytes of memory held for the contents, including
+    /// chunks shared with other `Contents` but not data the host holds.
+    pub(crate) fn capacity(&self) -> u64 {
+        self.chunks.values().map(|c| c.capacity() as u64).sum()
+    }
+
+    /// Release memory held beyond what the chunks need. Shared chunks are
+    /// left alone, since copying them would only take more memory, as are
+    /// chunks which are scrubbed.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        for chunk in self.chunks.values_mut() {
+            if let Some(chunk) = Arc::get_mut(chunk) {
+                chunk.shrink_to_fit();
+            }
+        }
+    }
+
+    /// Pass on a hint that `len` bytes from `offset` will be read soon to a
+    /// source read on demand.
+    pub(crate) fn will_need(&self, offset: u64, len: u64) {
+        if let Base::Lazy(source, size) = &self.base {
+            if offset < *size {
+                source.will_need(offset, len.min(size - offset));
+            }
+        }
+    }
+
+    /// Read from `offset` into `bufs`, stopping at the end of the file.
+    pub(crate) fn read_at(&self, bufs: &mut [io::IoSliceMut], offset: u64) -> io::Result<usize> {
+        let mut pos = offset;
+        for buf in bufs {
+            let mut buf = &mut buf[..];
+            while !buf.is_empty() && pos < self.len {
+                let (index, start) = locate(pos);
+                let n = buf
+                    .len()
+                    .min(CHUNK_SIZE - start)
+                    .min((self.len - pos) as usize);
+                let (out, rest) = buf.split_at_mut(n);
+                match self.chunks.get(&index) {
+                    Some(data) => {
+                        let stored = data.len().saturating_sub(start).min(n);
+                        if stored > 0 {
+                            out[..stored].copy_from_slice(&data[start..start + stored]);
+                        }
+                        zero(&mut out[stored..]);
+                    }
+                    None => self.base.read_at(out, pos)?,
+                }
+                buf = rest;
+                pos += n as u64;
+            }
+        }
+        Ok((pos - offset) as usize)
+    }
+
+    /// Write `data` at `offset`, growing the file if it ends past the end.
+    /// Only reading the host's data for a chunk not yet written can fail.
+    pub(crate) fn write_at(&mut self, mut data: &[u8], offset: u64) -> io::Result<()> {
+        let mut pos = offset;
+        while !data.is_empty() {
+            let (index, start) = locate(pos);
+            let n = data.len().min(CHUNK_SIZE - start);
+            if !self.chunks.contains_key(&index) {
+                let base = self.base.chunk(index, self.zeroize)?;
+                self.chunks.insert(index, Arc::new(base));
+            }
+            let chunk = Arc::make_mut(self.chunks.get_mut(&index).unwrap());
+            if chunk.len() < start {
+                chunk.resize(start, 0);
+            }
+            let overlap = (chunk.len() - start).min(n);
+            chunk[start..start + overlap].copy_from_slice(&data[..overlap]);
+            chunk.extend_from_slice(&data[overlap..n]);
+            data = &data[n..];
+            pos += n as u64;
+            self.len = self.len.max(pos);
+        }
+        Ok(())
+    }
+
+    /// Write `data` at the end of the file. This is `write_at` at `len`,
+    /// but for the common case of a file written from start to end, it
+    /// extends the last chunk in place with a single lookup.
+    pub(crate) fn append(&mut self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            let (index, start) = locate(self.len);
+            let n = data.len().min(CHUNK_SIZE - start);
+            let last = self.chunks.get_mut(&index).and_then(Arc::get_mut);
+            match last {
+                // The chunk holds everything up to the end of the file, with
+                // no hole to fill first.
+                Some(chunk) if chunk.len() == start => {
+                    chunk.extend_from_slice(&data[..n]);
+                    self.len += n as u64;
+                }
+                _ => self.write_at(&data[..n], self.len)?,
+            }
+            data = &data[n..];
+        }
+        Ok(())
+    }
+
+    /// Truncate the file to `size` bytes, or extend it with a hole. A chunk
+    /// truncated to a small fraction of the memory it holds releases the
+    /// rest, and a shared chunk is replaced by a copy of what's left of it.
+    /// Chunks dropped entirely are scrubbed, if they're marked to be, once
+    /// no other `Contents` shares them.
+    pub(crate) fn set_len(&mut self, size: u64) {
+        if size < self.len {
+            self.base.truncate(size);
+            let (index, start) = locate(size);
+            if start == 0 {
+                self.chunks.split_off(&index);
+            } else {
+                self.chunks.split_off(&(index + 1));
+                if let Some(chunk) = self.chunks.get_mut(&index) {
+                    if let Some(owned) = Arc::get_mut(chunk) {
+                        owned.truncate(start);
+                    } else if chunk.len() > start {
+                        *chunk = Arc::new(Chunk::new(&chunk[..start], chunk.zeroize));
+                    }
+                }
+            }
+        }
+        self.len = size;
+    }
+}
@@ -0,0 +1,670 @@
+use crate::file::FileMode;
+use crate::{set_time, Filesystem, InodeSlot};
+use cap_std::time::SystemTime;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::io::{self, SeekFrom};
+use std::sync::{Arc, Mutex, RwLock};
+use wasi_common::{
+    file::{Advice, FdFlags, FileCaps, FileType, Filestat, WasiFile},
+    Error, ErrorExt, RngCore, SystemTimeSpec,
+};
+
+/// What `ZeroDevice` and `RandomDevice` report as ready to read: they never
+/// run out, so a poll finds them ready, with a read's worth waiting.
+const ALWAYS_READY: u64 = 64 * 1024;
+
+/// A function giving the `WasiFile` for each open of a device, which is
+/// passed the fdflags the device is opened with.
+pub type OpenDevice = Box<dyn Fn(FdFlags) -> Result<Box<dyn WasiFile>, Error> + Send + Sync>;
+
+/// An `OpenDevice` as a device keeps it, which can give every open the same
+/// `WasiFile`.
+pub(crate) type SharedOpenDevice =
+    Arc<dyn Fn(FdFlags) -> Result<Arc<dyn WasiFile>, Error> + Send + Sync>;
+
+/// A character device, such as a terminal, whose reads and writes go to a
+/// `WasiFile` the host provides when it's opened.
+pub(crate) struct DeviceInode {
+    pub(crate) serial: u64,
+    /// Shared with the copies `Filesystem::fork` makes.
+    open: SharedOpenDevice,
+    _slot: InodeSlot,
+    atim: SystemTime,
+    mtim: SystemTime,
+    ctim: SystemTime,
+}
+
+impl DeviceInode {
+    pub(crate) fn new(
+        serial: u64,
+        open: SharedOpenDevice,
+        slot: InodeSlot,
+        now: SystemTime,
+    ) -> Self {
+        DeviceInode {
+            serial,
+            open,
+            _slot: slot,
+            atim: now,
+            mtim: now,
+            ctim: now,
+        }
+    }
+
+    /// A copy with serial `serial`, as `Filesystem::fork` makes.
+    pub(crate) fn fork(&self, serial: u64, slot: InodeSlot) -> Self {
+        DeviceInode {
+            serial,
+            open: self.open.clone(),
+            _slot: slot,
+            atim: self.atim,
+            mtim: self.mtim,
+            ctim: self.ctim,
+        }
+    }
+
+    pub(crate) fn update_ctim(&mut self, now: SystemTime) {
+        self.ctim = now;
+    }
+
+    pub(crate) fn set_times(
+        &mut self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+        now: SystemTime,
+    ) {
+        let atim_set = set_time(&mut self.atim, atime, now);
+        let mtim_set = set_time(&mut self.mtim, mtime, now);
+        if atim_set || mtim_set {
+            self.ctim = now;
+        }
+    }
+
+    pub(crate) fn get_filestat(&self, device_id: u64) -> Filestat {
+        Filestat {
+            device_id,
+            inode: self.serial,
+            filetype: FileType::CharacterDevice,
+            nlink: 1,
+            size: 0,
+            atim: Some(self.atim.into_std()),
+            mtim: Some(self.mtim.into_std()),
+            ctim: Some(self.ctim.into_std()),
+        }
+    }
+}
+
+/// An open character device. Reads, writes, and seeks are passed to the
+/// device, which decides whether they block; everything else is answered
+/// from the inode, so the guest always sees a character device whatever the
+/// device says it is. It has no size, and can't be read or written at an
+/// offset.
+pub(crate) struct DeviceFile {
+    fs: Arc<Filesystem>,
+    inode: Arc<RwLock<DeviceInode>>,
+    device: Arc<dyn WasiFile>,
+    fdflags: FdFlags,
+    mode: FileMode,
+    caps: FileCaps,
+}
+
+impl DeviceFile {
+    /// Open the device at `inode`, failing if the host's `OpenDevice` does.
+    pub(crate) fn open(
+        fs: Arc<Filesystem>,
+        inode: Arc<RwLock<DeviceInode>>,
+        fdflags: FdFlags,
+        mode: FileMode,
+        caps: FileCaps,
+    ) -> Result<Self, Error> {
+        // The host's function is called without the inode locked.
+        let open = inode.read().unwrap().open.clone();
+        let device = open(fdflags)?;
+        Ok(DeviceFile {
+            fs,
+            inode,
+            device,
+            fdflags,
+            mode,
+            caps,
+        })
+    }
+
+    fn require(&self, caps: FileCaps) -> Result<(), Error> {
+        if self.caps.contains(caps) {
+            Ok(())
+        } else {
+            Err(Error::not_capable().context(format!("{:?}", caps - self.caps)))
+        }
+    }
+
+    fn require_read(&self) -> Result<(), Error> {
+        if self.mode.is_read() {
+            Ok(())
+        } else {
+            Err(Error::badf())
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl WasiFile for DeviceFile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::CharacterDevice)
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        Ok(self.fdflags)
+    }
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        if fdflags.intersects(FdFlags::DSYNC | FdFlags::SYNC | FdFlags::RSYNC) {
+            return Err(Error::not_supported().context("cannot set DSYNC, SYNC, or RSYNC flag"));
+        }
+        self.fdflags = fdflags;
+        Ok(())
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        Ok(self.inode.read().unwrap().get_filestat(self.fs.device_id))
+    }
+    async fn set_filestat_size(&self, _size: u64) -> Result<(), Error> {
+        Err(Error::invalid_argument().context("cannot set the size of a device"))
+    }
+    async fn advise(&self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Err(Error::seek_pipe())
+    }
+    async fn allocate(&self, _offset: u64, _len: u64) -> Result<(), Error> {
+        Err(Error::not_supported().context("cannot allocate space on a device"))
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.require(FileCaps::FILESTAT_SET_TIMES)?;
+        self.fs.check_writable()?;
+        self.inode.write().unwrap().set_times(
+            self.fs.truncate_spec(atime),
+            self.fs.truncate_spec(mtime),
+            self.fs.now(),
+        );
+        Ok(())
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.require_read()?;
+        self.device.read_vectored(bufs).await
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        _bufs: &mut [io::IoSliceMut<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::seek_pipe())
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        if !self.mode.is_write() {
+            return Err(Error::badf());
+        }
+        self.device.write_vectored(bufs).await
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        _bufs: &[io::IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::seek_pipe())
+    }
+    async fn seek(&self, pos: SeekFrom) -> Result<u64, Error> {
+        self.device.seek(pos).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.require_read()?;
+        self.device.peek(buf).await
+    }
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.require_read()?;
+        self.device.num_ready_bytes().await
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.device.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.device.writable().await
+    }
+}
+
+/// A character device which reads back whatever was written to it, in
+/// order, like a terminal in a loopback. Reading when nothing is waiting
+/// reads nothing, as at the end of a file, rather than blocking.
+#[derive(Default)]
+pub struct EchoDevice {
+    pending: Mutex<VecDeque<u8>>,
+}
+
+impl EchoDevice {
+    pub fn new() -> Self {
+        EchoDevice::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl WasiFile for EchoDevice {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::CharacterDevice)
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        Ok(FdFlags::empty())
+    }
+    async fn set_fdflags(&mut self, _fdflags: FdFlags) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        Ok(Filestat {
+            device_id: 0,
+            inode: 0,
+            filetype: FileType::CharacterDevice,
+            nlink: 0,
+            size: 0,
+            atim: None,
+            mtim: None,
+            ctim: None,
+        })
+    }
+    async fn set_filestat_size(&self, _size: u64) -> Result<(), Error> {
+        Err(Error::invalid_argument())
+    }
+    async fn advise(&self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Err(Error::seek_pipe())
+    }
+    async fn allocate(&self, _offset: u64, _len: u64) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+    async fn set_times(
+        &self,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let mut pending = self.pending.lock().unwrap();
+        let mut n = 0;
+        for buf in bufs {
+            let len = buf.len().min(pending.len());
+            for (b, p) in buf.iter_mut().zip(pending.drain(..len)) {
+                *b = p;
+            }
+            n += len;
+        }
+        Ok(n.try_into()?)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        _bufs: &mut [io::IoSliceMut<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::seek_pipe())
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        let mut pending = self.pending.lock().unwrap();
+        let mut n = 0;
+        for buf in bufs {
+            pending.extend(buf.iter());
+            n += buf.len();
+        }
+        Ok(n.try_into()?)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        _bufs: &[io::IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::seek_pipe())
+    }
+    async fn seek(&self, _pos: SeekFrom) -> Result<u64, Error> {
+        Err(Error::seek_pipe())
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        let pending = self.pending.lock().unwrap();
+        let n = buf.len().min(pending.len());
+        for (b, p) in buf.iter_mut().zip(pending.iter()) {
+            *b = *p;
+        }
+        Ok(n.try_into()?)
+    }
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(self.pending.lock().unwrap().len().try_into()?)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A character device like `/dev/null`: reads are at the end of the file
+/// straight away, writes succeed and are thrown away, and seeks anywhere
+/// land at 0. See `Dir::put_null_device`.
+#[derive(Default)]
+pub struct NullDevice;
+
+impl NullDevice {
+    pub fn new() -> Self {
+        NullDevice
+    }
+}
+
+#[async_trait::async_trait]
+impl WasiFile for NullDevice {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::CharacterDevice)
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        Ok(FdFlags::empty())
+    }
+    async fn set_fdflags(&mut self, _fdflags: FdFlags) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        Ok(Filestat {
+            device_id: 0,
+            inode: 0,
+            filetype: FileType::CharacterDevice,
+            nlink: 0,
+            size: 0,
+            atim: None,
+            mtim: None,
+            ctim: None,
+        })
+    }
+    async fn set_filestat_size(&self, _size: u64) -> Result<(), Error> {
+        Err(Error::invalid_argument())
+    }
+    async fn advise(&self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn allocate(&self, _offset: u64, _len: u64) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+    async fn set_times(
+        &self,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+    async fn read_vectored<'a>(&self, _bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        _bufs: &mut [io::IoSliceMut<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        Ok(bufs.iter().map(|b| b.len()).sum::<usize>().try_into()?)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        self.write_vectored(bufs).await
+    }
+    async fn seek(&self, _pos: SeekFrom) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn peek(&self, _buf: &mut [u8]) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A character device like `/dev/zero`: reads fill the whole buffer with
+/// zeros however large it is, and, as with `NullDevice`, writes succeed and
+/// are thrown away and seeks land at 0. See `Dir::put_zero_device`.
+#[derive(Default)]
+pub struct ZeroDevice;
+
+impl ZeroDevice {
+    pub fn new() -> Self {
+        ZeroDevice
+    }
+}
+
+#[async_trait::async_trait]
+impl WasiFile for ZeroDevice {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::CharacterDevice)
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        Ok(FdFlags::empty())
+    }
+    async fn set_fdflags(&mut self, _fdflags: FdFlags) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        NullDevice.get_filestat().await
+    }
+    async fn set_filestat_size(&self, _size: u64) -> Result<(), Error> {
+        Err(Error::invalid_argument())
+    }
+    async fn advise(&self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn allocate(&self, _offset: u64, _len: u64) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+    async fn set_times(
+        &self,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let mut n = 0;
+        for buf in bufs {
+            buf.iter_mut().for_each(|b| *b = 0);
+            n += buf.len();
+        }
+        Ok(n.try_into()?)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        self.read_vectored(bufs).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        NullDevice.write_vectored(bufs).await
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        NullDevice.write_vectored(bufs).await
+    }
+    async fn seek(&self, _pos: SeekFrom) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        buf.iter_mut().for_each(|b| *b = 0);
+        Ok(buf.len().try_into()?)
+    }
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(ALWAYS_READY)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A character device like `/dev/urandom`: reads fill the whole buffer
+/// from a source of random bytes, by default the host's, and, as on Linux,
+/// writes succeed and are thrown away. See `Dir::put_random_device`.
+pub struct RandomDevice {
+    source: Mutex<Box<dyn RngCore + Send + Sync>>,
+}
+
+impl RandomDevice {
+    /// A device reading from the host's random number generator, as
+    /// `random_get` does.
+    pub fn new() -> Self {
+        RandomDevice::with_source(Box::new(cap_rand::rngs::OsRng::default(
+            cap_std::ambient_authority(),
+        )))
+    }
+
+    /// A device reading from `source` instead, such as
+    /// `wasi_common::random::Deterministic` for reproducible runs.
+    pub fn with_source(source: Box<dyn RngCore + Send + Sync>) -> Self {
+        RandomDevice {
+            source: Mutex::new(source),
+        }
+    }
+}
+
+impl Default for RandomDevice {
+    fn default() -> Self {
+        RandomDevice::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl WasiFile for RandomDevice {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::CharacterDevice)
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        Ok(FdFlags::empty())
+    }
+    async fn set_fdflags(&mut self, _fdflags: FdFlags) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        NullDevice.get_filestat().await
+    }
+    async fn set_filestat_size(&self, _size: u64) -> Result<(), Error> {
+        Err(Error::invalid_argument())
+    }
+    async fn advise(&self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn allocate(&self, _offset: u64, _len: u64) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+    async fn set_times(
+        &self,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        Err(Error::not_supported())
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        let mut source = self.source.lock().unwrap();
+        let mut n = 0;
+        for buf in bufs {
+            source
+                .try_fill_bytes(buf)
+                .map_err(|e| Error::io().context(e.to_string()))?;
+            n += buf.len();
+        }
+        Ok(n.try_into()?)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        self.read_vectored(bufs).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        NullDevice.write_vectored(bufs).await
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        NullDevice.write_vectored(bufs).await
+    }
+    async fn seek(&self, _pos: SeekFrom) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn peek(&self, _buf: &mut [u8]) -> Result<u64, Error> {
+        // What's peeked would have to be what's read next; there's nothing
+        // to gain by keeping random bytes around for that.
+        Err(Error::not_supported())
+    }
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(ALWAYS_READY)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,2283 @@
+use crate::archive::{TarOptions, ZipOptions};
+use crate::contents::Contents;
+use crate::device::{
+    DeviceFile, NullDevice, OpenDevice, RandomDevice, SharedOpenDevice, ZeroDevice,
+};
+use crate::fifo::{FifoFile, FifoReader, FifoWriter};
+use crate::file::{File, FileInode, FileMode};
+use crate::host::{HostDirOptions, SkippedEntry};
+use crate::mount::{Crossing, MountInode};
+use crate::readdir::{Cookies, Readdir};
+use crate::{
+    set_time, AtimeUpdates, Filesystem, GenerateContents, Inode, InodeSlot, LazyContents,
+    StaticWrites,
+};
+use cap_std::time::SystemTime;
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+use wasi_common::{
+    dir::{ReaddirCursor, ReaddirEntity, WasiDir},
+    file::{Advice, FdFlags, FileCaps, FileType, Filestat, OFlags, WasiFile},
+    Error, ErrorExt, SystemTimeSpec,
+};
+
+/// The maximum number of symlinks followed while resolving a single path,
+/// after which lookup fails with `ELOOP`.
+const MAX_SYMLINK_EXPANSIONS: usize = 32;
+
+pub(crate) struct DirInode {
+    pub(crate) serial: u64,
+    pub(crate) parent: Option<Weak<RwLock<DirInode>>>,
+    /// The entries, kept in byte order of their names, which is the order
+    /// `readdir` lists them in.
+    /// Names are shared with the cookies `readdir` hands out for them.
+    /// Add and remove entries with `insert_entry` and `remove_entry`, which
+    /// keep `subdirs` up to date.
+    pub(crate) contents: BTreeMap<Arc<str>, Inode>,
+    /// How many of the entries are directories, each with a `..` linking
+    /// back here.
+    subdirs: u64,
+    /// The directory has been removed from the tree, though it may still be
+    /// open.
+    removed: bool,
+    /// Set with `Dir::set_readonly_at`: the guest may change nothing in or
+    /// below the directory.
+    pub(crate) read_only: bool,
+    /// How many directories are read-only among this one and those above
+    /// it, which each file linked here counts in its `read_only_dirs`.
+    read_only_depth: u32,
+    pub(crate) cookies: Cookies,
+    _slot: InodeSlot,
+    atim: SystemTime,
+    mtim: SystemTime,
+    ctim: SystemTime,
+}
+
+impl DirInode {
+    pub(crate) fn new(
+        serial: u64,
+        parent: Option<Weak<RwLock<DirInode>>>,
+        slot: InodeSlot,
+        now: SystemTime,
+    ) -> Self {
+        DirInode {
+            serial,
+            parent,
+            contents: BTreeMap::new(),
+            subdirs: 0,
+            removed: false,
+            read_only: false,
+            read_only_depth: 0,
+            cookies: Cookies::default(),
+            _slot: slot,
+            atim: now,
+            mtim: now,
+            ctim: now,
+        }
+    }
+
+    /// An empty copy of the directory with serial `serial`, as
+    /// `Filesystem::fork` makes, for the entries to be added to.
+    pub(crate) fn fork(
+        &self,
+        serial: u64,
+        parent: Option<Weak<RwLock<DirInode>>>,
+        slot: InodeSlot,
+    ) -> Self {
+        DirInode {
+            read_only: self.read_only,
+            // Until it's added to its parent's copy.
+            read_only_depth: self.read_only as u32,
+            atim: self.atim,
+            mtim: self.mtim,
+            ctim: self.ctim,
+            ..DirInode::new(serial, parent, slot, self.ctim)
+        }
+    }
+
+    pub(crate) fn update_ctim(&mut self, now: SystemTime) {
+        self.ctim = now;
+    }
+
+    /// Add the entry `name`, returning the entry it replaced, if any. A
+    /// file or subtree added below a read-only directory is read-only too.
+    pub(crate) fn insert_entry(&mut self, name: &str, inode: Inode) -> Option<Inode> {
+        match &inode {
+            Inode::Dir(d) => {
+                self.subdirs += 1;
+                let inherited = {
+                    let d = d.read().unwrap();
+                    d.read_only_depth - d.read_only as u32
+                };
+                if inherited != self.read_only_depth {
+                    shift_read_only_depth(d, self.read_only_depth as i64 - inherited as i64);
+                }
+            }
+            Inode::File(f) => self.count_read_only(f, 1),
+            _ => {}
+        }
+        let replaced = self.contents.insert(name.into(), inode);
+        match &replaced {
+            Some(Inode::Dir(_)) => self.subdirs -= 1,
+            Some(Inode::File(f)) => self.count_read_only(f, -1),
+            _ => {}
+        }
+        replaced
+    }
+
+    pub(crate) fn remove_entry(&mut self, name: &str) -> Option<Inode> {
+        let removed = self.contents.remove(name);
+        match &removed {
+            Some(Inode::Dir(_)) => self.subdirs -= 1,
+            Some(Inode::File(f)) => self.count_read_only(f, -1),
+            _ => {}
+        }
+        removed
+    }
+
+    /// Mark the directory as gone from the tree, taking its entries, if it
+    /// had any.
+    pub(crate) fn detach(&mut self) -> BTreeMap<Arc<str>, Inode> {
+        self.removed = true;
+        self.subdirs = 0;
+        let contents = std::mem::take(&mut self.contents);
+        for inode in contents.values() {
+            if let Inode::File(f) = inode {
+                self.count_read_only(f, -1);
+            }
+        }
+        contents
+    }
+
+    /// Count the read-only directories above a link here to `f` into its
+    /// `read_only_dirs`, or out of it, for `sign` 1 or -1.
+    fn count_read_only(&self, f: &Arc<RwLock<FileInode>>, sign: i64) {
+        if self.read_only_depth > 0 {
+            let mut f = f.write().unwrap();
+            f.read_only_dirs =
+                (f.read_only_dirs as i64 + sign * self.read_only_depth as i64) as u32;
+        }
+    }
+
+    /// Two links for the directory's own name and its `.`, and one for the
+    /// `..` of each subdirectory, as on most Unix filesystems; none once the
+    /// directory is removed.
+    fn nlink(&self) -> u64 {
+        if self.removed {
+            0
+        } else {
+            2 + self.subdirs
+        }
+    }
+
+    pub(crate) fn set_times(
+        &mut self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+        now: SystemTime,
+    ) {
+        let atim_set = set_time(&mut self.atim, atime, now);
+        let mtim_set = set_time(&mut self.mtim, mtime, now);
+        if atim_set || mtim_set {
+            self.ctim = now;
+        }
+    }
+
+    /// Set all three timestamps as they were, as restoring a serialized
+    /// filesystem does.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore_times(&mut self, atim: SystemTime, mtim: SystemTime, ctim: SystemTime) {
+        self.atim = atim;
+        self.mtim = mtim;
+        self.ctim = ctim;
+    }
+
+    pub(crate) fn get_filestat(&self, device_id: u64) -> Filestat {
+        Filestat {
+            device_id,
+            inode: self.serial,
+            filetype: FileType::Directory,
+            nlink: self.nlink(),
+            size: 0,
+            atim: Some(self.atim.into_std()),
+            mtim: Some(self.mtim.into_std()),
+            ctim: Some(self.ctim.into_std()),
+        }
+    }
+}
+
+/// What an operation requires of the final component of its path. Everything
+/// before the final component must always resolve to a directory.
+#[derive(Clone, Copy, Default)]
+struct LookupOptions {
+    /// Follow a symlink in the final component.
+    follow: bool,
+    /// Fail with ENOENT if the final component doesn't exist.
+    must_exist: bool,
+    /// Fail with ENOENT if the final component doesn't exist, or ENOTDIR if
+    /// it isn't a directory. A trailing slash implies this, unless
+    /// `create_dir` is set.
+    must_be_dir: bool,
+    /// The operation may create a directory at the final component, and
+    /// checks any existing entry itself, so a trailing slash is fine there.
+    create_dir: bool,
+    /// The operation works on the directory mounted at a mount point in the
+    /// final component, rather than on the mount point, as opening or
+    /// stat'ing it does.
+    enter_mount: bool,
+}
+
+pub struct Dir {
+    fs: Arc<Filesystem>,
+    inode: Arc<RwLock<DirInode>>,
+    /// The preopened directory this handle was reached from. `..` can climb
+    /// up to it, but never above it.
+    preopen: Arc<RwLock<DirInode>>,
+}
+
+impl Dir {
+    /// A handle to `inode` to be used as a preopen.
+    pub(crate) fn new(fs: Arc<Filesystem>, inode: Arc<RwLock<DirInode>>) -> Self {
+        let preopen = inode.clone();
+        Dir { fs, inode, preopen }
+    }
+
+    /// A handle to `inode`, reached from this handle, within the same
+    /// preopen.
+    fn with_inode(&self, inode: Arc<RwLock<DirInode>>) -> Dir {
+        Dir {
+            fs: self.fs.clone(),
+            inode,
+            preopen: self.preopen.clone(),
+        }
+    }
+
+    fn inode(&self) -> RwLockReadGuard<DirInode> {
+        self.inode.read().unwrap()
+    }
+
+    fn inode_mut(&self) -> RwLockWriteGuard<DirInode> {
+        self.inode.write().unwrap()
+    }
+
+    /// Fail with EROFS if the guest may not change this directory's
+    /// entries or timestamps.
+    fn check_writable(&self) -> Result<(), Error> {
+        check_dir_writable(&self.fs, &self.inode)
+    }
+
+    /// Resolve `path` to the directory containing its final component, and
+    /// the name of that component within it. A final `..` resolves to `.` in
+    /// the parent directory.
+    ///
+    /// Symlinks in intermediate components are always followed, up to
+    /// `MAX_SYMLINK_EXPANSIONS` of them. `..` steps up to the parent
+    /// directory, but never above the preopen. `opts` says what the final
+    /// component must be.
+    ///
+    /// This is a loop rather than a recursion over components, so the depth
+    /// of a path (or of the symlinks within it) can't exhaust the host stack.
+    fn resolve<'a>(
+        &self,
+        path: &'a str,
+        opts: LookupOptions,
+    ) -> Result<(Dir, Cow<'a, str>), Error> {
+        if path.is_empty() {
+            return Err(Error::not_found().context("empty path"));
+        }
+        let mut dir = self.with_inode(self.inode.clone());
+        let mut links = 0;
+        let mut trailing_slash = false;
+        // `buf[pos..]` is the part of the path still to be resolved. Expanding
+        // a symlink replaces it with the link's target followed by whatever
+        // came after the link.
+        let mut buf = Cow::Borrowed(path);
+        let mut pos = 0;
+        loop {
+            // Only the path itself, or a symlink target, can start with a
+            // slash: slashes after a component are consumed with it.
+            if buf[pos..].starts_with('/') {
+                if !self.fs.strips_leading_slash() {
+                    return Err(Error::perm().context(
+                        "absolute path; paths are relative to a directory handle \
+                         unless Filesystem::set_strip_leading_slash is enabled",
+                    ));
+                }
+                // Resolve it relative to the handle the lookup started from.
+                dir = self.with_inode(self.inode.clone());
+                let rest = buf[pos..].trim_start_matches('/');
+                if rest.is_empty() {
+                    buf = Cow::Borrowed(".");
+                    pos = 0;
+                } else {
+                    pos = buf.len() - rest.len();
+                }
+            }
+            let (name, rest) = match buf[pos..].split_once('/') {
+                // Consecutive slashes are equivalent to a single one.
+                Some((name, rest)) => (name, Some(rest.trim_start_matches('/'))),
+                None => (&buf[pos..], None),
+            };
+            if let Some(rest) = rest.filter(|rest| !rest.is_empty()) {
+                if name == ".." {
+                    dir = dir.parent_dir()?;
+                } else if name != "." {
+                    let name = dir.stored_name(name);
+                    if let Some(target) = dir.symlink_target(&name, &mut links)? {
+                        buf = Cow::Owned(format!("{}/{}", target, rest));
+                        pos = 0;
+                        continue;
+                    }
+                    if let Some(mount) = dir.mount_at(&name) {
+                        return Err(Crossing::error(mount, rest));
+                    }
+                    dir = dir.child_dir(&name)?;
+                }
+                pos = buf.len() - rest.len();
+                continue;
+            }
+
+            // `name` is the final component.
+            trailing_slash |= rest.is_some();
+            if name == ".." {
+                return Ok((dir.parent_dir()?, Cow::Borrowed(".")));
+            }
+            let stored = match dir.stored_name(name) {
+                Cow::Owned(stored) => Some(stored),
+                Cow::Borrowed(_) => None,
+            };
+            let name = stored.as_deref().unwrap_or(name);
+            if opts.follow {
+                if let Some(target) = dir.symlink_target(name, &mut links)? {
+                    buf = Cow::Owned(target);
+                    pos = 0;
+                    continue;
+                }
+            }
+            if opts.enter_mount {
+                if let Some(mount) = dir.mount_at(name) {
+                    return Err(Crossing::error(mount, "."));
+                }
+            }
+            let must_be_dir = opts.must_be_dir || (trailing_slash && !opts.create_dir);
+            if must_be_dir || opts.must_exist {
+                match dir.entry(name) {
+                    Some(Inode::Dir(_)) | Some(Inode::Mount(_)) => {}
+                    Some(_) if must_be_dir => {
+                        return Err(Error::not_dir().context(if trailing_slash {
+                            format!("trailing slash on non-directory {:?}", name)
+                        } else {
+                            format!("{:?} is not a directory", name)
+                        }))
+                    }
+                    Some(_) => {}
+                    None => return Err(Error::not_found()),
+                }
+            }
+            if let Some(stored) = stored {
+                return Ok((dir, Cow::Owned(stored)));
+            }
+            let end = pos + name.len();
+            let name = match buf {
+                Cow::Borrowed(path) => Cow::Borrowed(&path[pos..end]),
+                Cow::Owned(buf) => Cow::Owned(buf[pos..end].to_owned()),
+            };
+            return Ok((dir, name));
+        }
+    }
+
+    /// The parent of this directory, clamped at the preopen and at the root
+    /// of the filesystem.
+    fn parent_dir(&self) -> Result<Dir, Error> {
+        if Arc::ptr_eq(&self.inode, &self.preopen) {
+            return Ok(self.with_inode(self.inode.clone()));
+        }
+        let parent = self.inode().parent.clone();
+        match parent {
+            Some(parent) => match parent.upgrade() {
+                Some(parent) => Ok(self.with_inode(parent)),
+                None => Err(Error::not_found().context("parent directory was removed")),
+            },
+            None => Ok(self.with_inode(self.inode.clone())),
+        }
+    }
+
+    /// If `name` is a symlink, count one more link traversal in `links`
+    /// against the limit and return its target.
+    fn symlink_target(&self, name: &str, links: &mut usize) -> Result<Option<String>, Error> {
+        let target = match self.inode().contents.get(name) {
+            Some(Inode::Symlink(l)) => l.read().unwrap().target.clone(),
+            _ => return Ok(None),
+        };
+        *links += 1;
+        if *links > MAX_SYMLINK_EXPANSIONS {
+            return Err(Error::loop_());
+        }
+        if target.is_empty() {
+            return Err(Error::not_found().context("empty symlink target"));
+        }
+        Ok(Some(target))
+    }
+
+    fn child_dir(&self, name: &str) -> Result<Dir, Error> {
+        if name == "." {
+            return Ok(self.with_inode(self.inode.clone()));
+        }
+        match self.inode().contents.get(name) {
+            Some(Inode::Dir(d)) => Ok(self.with_inode(d.clone())),
+            Some(_) => Err(Error::not_dir().context(format!("{:?} is not a directory", name))),
+            None => Err(Error::not_found().context(format!("no such directory {:?}", name))),
+        }
+    }
+
+    /// The mount point `name` in this directory, if it is one.
+    fn mount_at(&self, name: &str) -> Option<Arc<MountInode>> {
+        match self.inode().contents.get(name) {
+            Some(Inode::Mount(m)) => Some(m.clone()),
+            _ => None,
+        }
+    }
+
+    /// The spelling under which `name` is stored in this directory. With
+    /// normalization enabled, that's `name` in NFC. On a case-insensitive
+    /// filesystem, it's the name of an existing entry which matches `name`
+    /// ignoring case, if there is one. Otherwise it's `name` itself.
+    fn stored_name<'n>(&self, name: &'n str) -> Cow<'n, str> {
+        if name == "." || name == ".." {
+            return Cow::Borrowed(name);
+        }
+        let name = if self.fs.normalizes_names() && !is_nfc(name) {
+            Cow::Owned(name.nfc().collect::<String>())
+        } else {
+            Cow::Borrowed(name)
+        };
+        if !self.fs.is_case_insensitive() {
+            return name;
+        }
+        let d = self.inode();
+        if d.contents.contains_key(&*name) {
+            return name;
+        }
+        // There's no index by folded name, so this is a linear scan.
+        match d.contents.keys().find(|key| eq_ignore_case(key, &name)) {
+            Some(key) => Cow::Owned(key.to_string()),
+            None => name,
+        }
+    }
+
+    /// The final component of `path` as it's spelled there, in NFC if
+    /// names are normalized, rather than as it's stored.
+    fn spelling<'p>(&self, path: &'p str) -> Cow<'p, str> {
+        let name = path
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or(path);
+        if self.fs.normalizes_names() && !is_nfc(name) {
+            Cow::Owned(name.nfc().collect::<String>())
+        } else {
+            Cow::Borrowed(name)
+        }
+    }
+
+    /// Look up `name` in this directory, where `.` is the directory itself.
+    fn entry(&self, name: &str) -> Option<Inode> {
+        if name == "." {
+            return Some(Inode::Dir(self.inode.clone()));
+        }
+        self.inode().contents.get(name).cloned()
+    }
+
+    fn child_file(&self, name: &str) -> Result<Arc<RwLock<FileInode>>, Error> {
+        match self.entry(name) {
+            Some(Inode::File(f)) => Ok(f),
+            Some(Inode::Dir(_)) => Err(Error::perm().context("directories can't be linked")),
+            Some(Inode::Dynamic(_)) => Err(Error::perm().context("dynamic files can't be linked")),
+            Some(Inode::Device(_)) => Err(Error::perm().context("devices can't be linked")),
+            Some(Inode::Fifo(_)) => Err(Error::perm().context("FIFOs can't be linked")),
+            Some(Inode::Mount(_)) => Err(Error::perm().context("mount points can't be linked")),
+            _ => Err(Error::not_found()),
+        }
+    }
+
+    /// Whether `d`, this directory locked, has an entry `name`, or on a
+    /// case-insensitive filesystem one which matches it ignoring case.
+    fn has_entry(&self, d: &DirInode, name: &str) -> bool {
+        d.contents.contains_key(name)
+            || (self.fs.is_case_insensitive()
+                && d.contents.keys().any(|key| eq_ignore_case(key, name)))
+    }
+
+    /// Add the new entry `name`. Callers look for an existing one first,
+    /// but without holding the lock until now, so this fails with EEXIST
+    /// rather than replace one created in between.
+    fn insert(&self, name: &str, inode: Inode) -> Result<(), Error> {
+        let now = self.fs.now();
+        let mut d = self.inode_mut();
+        if self.has_entry(&d, name) {
+            return Err(Error::exist());
+        }
+        d.insert_entry(name, inode);
+        d.mtim = now;
+        Ok(())
+    }
+
+    /// Create an empty directory named `name` in this directory, which
+    /// mustn't already have an entry by that name.
+    fn insert_dir(&self, name: &str) -> Result<(), Error> {
+        validate_name(name)?;
+        check_depth(self.fs.max_depth(), &self.inode, None)?;
+        let new_dir = self.fs.new_dir(&self.inode)?;
+        self.insert(name, Inode::Dir(new_dir))
+    }
+
+    /// Open a file as `WasiDir::open_file` does, but with the rights granted
+    /// by `caps` rather than just read and write access. The returned file
+    /// fails operations `caps` doesn't allow with ENOTCAPABLE, for hosts
+    /// which hand virtfs files out without a `FileEntry` checking rights
+    /// for them. Read and write access follow from `caps` as they do in
+    /// `path_open`.
+    pub fn open_file_with_caps(
+        &self,
+        symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        caps: FileCaps,
+        fdflags: FdFlags,
+    ) -> Result<Box<dyn WasiFile>, Error> {
+        let read = caps.contains(FileCaps::READ);
+        let write =
+            caps.intersects(FileCaps::WRITE | FileCaps::ALLOCATE | FileCaps::FILESTAT_SET_SIZE);
+        let mode = FileMode::new(read, write);
+        self.open(symlink_follow, path, oflags, mode, caps, fdflags)
+    }
+
+    /// `open_file` and `open_file_with_caps`, once the access mode is known.
+    fn open(
+        &self,
+        symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        mode: FileMode,
+        caps: FileCaps,
+        fdflags: FdFlags,
+    ) -> Result<Box<dyn WasiFile>, Error> {
+        let write = mode.is_write();
+        self.fs.check_path_len(path)?;
+        validate_oflags(oflags, write)?;
+        if oflags.intersects(OFlags::CREATE | OFlags::TRUNCATE) {
+            self.fs.check_writable()?;
+        }
+        if oflags.contains(OFlags::DIRECTORY) {
+            let (dir, dirname) = self.resolve(
+                path,
+                LookupOptions {
+                    follow: symlink_follow,
+                    must_be_dir: true,
+                    enter_mount: true,
+                    ..LookupOptions::default()
+                },
+            )?;
+            if write {
+                return Err(Error::is_dir());
+            }
+            let d = dir.child_dir(&dirname)?;
+            return Ok(Box::new(DirFile::new(dir.fs.clone(), d.inode, fdflags)));
+        }
+        let (dir, filename) = self.resolve(
+            path,
+            LookupOptions {
+                follow: symlink_follow,
+                must_exist: !oflags.contains(OFlags::CREATE),
+                enter_mount: true,
+                ..LookupOptions::default()
+            },
+        )?;
+        let filename = &*filename;
+        if oflags.intersects(OFlags::CREATE | OFlags::TRUNCATE) {
+            dir.check_writable()?;
+        }
+        let exclusive = oflags.contains(OFlags::CREATE | OFlags::EXCLUSIVE);
+        let inode = match dir.entry(filename) {
+            // A directory, including "." itself, is never shadowed by a new
+            // file.
+            Some(Inode::Dir(d)) => {
+                if exclusive {
+                    return Err(Error::exist());
+                }
+                if write || oflags.contains(OFlags::CREATE) {
+                    return Err(Error::is_dir());
+                }
+                return Ok(Box::new(DirFile::new(dir.fs.clone(), d, fdflags)));
+            }
+            // If `symlink_follow` was set, `resolve` has already resolved any
+            // symlink in the final component.
+            Some(Inode::Symlink(_)) => {
+                if exclusive {
+                    return Err(Error::exist());
+                }
+                return Err(Error::loop_().context("symlink with nofollow"));
+            }
+            // A device has no contents for TRUNCATE to discard, and CREATE
+            // finds it already there.
+            Some(Inode::Device(c)) => {
+                if exclusive {
+                    return Err(Error::exist());
+                }
+                return Ok(Box::new(DeviceFile::open(
+                    dir.fs.clone(),
+                    c,
+                    fdflags,
+                    mode,
+                    caps,
+                )?));
+            }
+            Some(Inode::Fifo(p)) => {
+                if exclusive {
+                    return Err(Error::exist());
+                }
+                return Ok(Box::new(FifoFile::new(
+                    dir.fs.clone(),
+                    p,
+                    fdflags,
+                    mode,
+                    caps,
+                )));
+            }
+            // Mounted since `resolve` looked.
+            Some(Inode::Mount(m)) => return Err(Crossing::error(m, ".")),
+            // Each open gets its own snapshot of freshly generated contents.
+            Some(Inode::Dynamic(d)) => {
+                if exclusive {
+                    return Err(Error::exist());
+                }
+                if write {
+                    return Err(Error::read_only());
+                }
+                let now = dir.fs.now();
+                let snapshot = d.write().unwrap().snapshot(now)?;
+                Arc::new(RwLock::new(snapshot))
+            }
+            Some(Inode::File(inode)) => {
+                if exclusive {
+                    return Err(Error::exist());
+                }
+                if write {
+                    dir.check_writable()?;
+                }
+                let now = dir.fs.now();
+                let mut f = inode.write().unwrap();
+                if write && f.read_only {
+                    return Err(Error::read_only());
+                }
+                if write {
+                    f.check_read_only_dirs()?;
+                }
+                if !oflags.contains(OFlags::CREATE)
+                    && dir.fs.atime_updates() == AtimeUpdates::Strict
+                {
+                    f.update_atim(now);
+                }
+                if oflags.contains(OFlags::TRUNCATE) {
+                    f.truncate(now);
+                }
+                drop(f);
+                inode
+            }
+            // `resolve` has checked that the file exists unless CREATE is
+            // set.
+            None => {
+                validate_name(filename)?;
+                let inode = dir.fs.new_file()?;
+                match dir.insert(filename, Inode::File(inode.clone())) {
+                    Ok(()) => inode,
+                    // Created by someone else since the lookup, so open
+                    // that, as if it had been there all along.
+                    Err(_) if !exclusive => {
+                        return self.open(symlink_follow, path, oflags, mode, caps, fdflags)
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+        Ok(Box::new(File::new(
+            dir.fs.clone(),
+            inode,
+            fdflags,
+            mode,
+            caps,
+        )))
+    }
+
+    /// The path of the entry at `path`, relative to this directory, as an
+    /// absolute path from the root of the filesystem. `.`, `..`, and
+    /// symlinks, including a final symlink, are resolved just as `open_file`
+    /// resolves them. If the final entry doesn't exist, as for a dangling
+    /// symlink, this fails with ENOENT naming the resolved path.
+    pub fn canonicalize(&self, path: &str) -> Result<String, Error> {
+        let opts = LookupOptions {
+            follow: true,
+            ..LookupOptions::default()
+        };
+        let (dir, name) = self.resolve(path, opts)?;
+        let mut canonical = dir.path()?;
+        if name != "." {
+            if !canonical.ends_with('/') {
+                canonical.push('/');
+            }
+            canonical.push_str(&name);
+            if dir.entry(&name).is_none() {
+                return Err(Error::not_found().context(format!("{} does not exist", canonical)));
+            }
+        }
+        Ok(canonical)
+    }
+
+    /// The absolute path of this directory from the root of the filesystem,
+    /// found by walking up its parents.
+    fn path(&self) -> Result<String, Error> {
+        let removed = || Error::not_found().context("directory was removed");
+        let mut names = Vec::new();
+        let mut dir = self.inode.clone();
+        loop {
+            let parent = match &dir.read().unwrap().parent {
+                Some(parent) => parent.upgrade().ok_or_else(removed)?,
+                None => break,
+            };
+            let name = parent
+                .read()
+                .unwrap()
+                .contents
+                .iter()
+                .find_map(|(name, inode)| match inode {
+                    Inode::Dir(d) if Arc::ptr_eq(d, &dir) => Some(name.clone()),
+                    _ => None,
+                })
+                .ok_or_else(removed)?;
+            names.push(name);
+            dir = parent;
+        }
+        if names.is_empty() {
+            return Ok("/".to_owned());
+        }
+        Ok(names
+            .iter()
+            .rev()
+            .fold(String::new(), |path, name| path + "/" + name))
+    }
+
+    /// Atomically swap the entry at `path_a`, relative to this directory,
+    /// with the entry at `path_b`, relative to `dir_b`, like Linux's
+    /// `RENAME_EXCHANGE`. Both entries must exist, but they need not be of
+    /// the same type. Open handles stay with their inode, not the name.
+    /// Like `rename`, this fails with EBUSY if either is a mount point.
+    pub fn exchange(&self, path_a: &str, dir_b: &Dir, path_b: &str) -> Result<(), Error> {
+        if !Arc::ptr_eq(&self.fs, &dir_b.fs) {
+            return Err(Error::cross_device().context("exchange across filesystems"));
+        }
+        let must_exist = LookupOptions {
+            must_exist: true,
+            ..LookupOptions::default()
+        };
+        let (a_dir, a_name) = self.resolve(path_a, must_exist)?;
+        let (b_dir, b_name) = dir_b.resolve(path_b, must_exist)?;
+        let (a_name, b_name) = (&*a_name, &*b_name);
+        let now = a_dir.fs.now();
+        if Arc::ptr_eq(&a_dir.inode, &b_dir.inode) {
+            let mut d = a_dir.inode_mut();
+            let a = d
+                .contents
+                .get(a_name)
+                .cloned()
+                .ok_or_else(Error::not_found)?;
+            let b = d
+                .contents
+                .get(b_name)
+                .cloned()
+                .ok_or_else(Error::not_found)?;
+            if a_name == b_name {
+                return Ok(());
+            }
+            check_not_mount_point(&a)?;
+            check_not_mount_point(&b)?;
+            d.insert_entry(a_name, b.clone());
+            d.insert_entry(b_name, a.clone());
+            d.mtim = now;
+            a.update_ctim(now);
+            b.update_ctim(now);
+        } else {
+            let _rename_guard = a_dir.fs.rename_lock.lock().unwrap();
+            let a = a_dir.inode().contents.get(a_name).cloned();
+            let b = b_dir.inode().contents.get(b_name).cloned();
+            for (moving, dest) in &[(&a, &b_dir.inode), (&b, &a_dir.inode)] {
+                if let Some(Inode::Dir(moving)) = moving {
+                    if is_ancestor(moving, dest) {
+                        return Err(Error::invalid_argument()
+                            .context("cannot move a directory into itself"));
+                    }
+                    check_depth(a_dir.fs.max_depth(), dest, Some(moving))?;
+                }
+            }
+            let (mut a_parent, mut b_parent) = lock_pair(&a_dir.inode, &b_dir.inode);
+            let a = a_parent
+                .contents
+                .get(a_name)
+                .cloned()
+                .ok_or_else(Error::not_found)?;
+            let b = b_parent
+                .contents
+                .get(b_name)
+                .cloned()
+                .ok_or_else(Error::not_found)?;
+            check_not_mount_point(&a)?;
+            check_not_mount_point(&b)?;
+            if let Inode::Dir(d) = &a {
+                d.write().unwrap().parent = Some(Arc::downgrade(&b_dir.inode));
+            }
+            if let Inode::Dir(d) = &b {
+                d.write().unwrap().parent = Some(Arc::downgrade(&a_dir.inode));
+            }
+            a_parent.insert_entry(a_name, b.clone());
+            b_parent.insert_entry(b_name, a.clone());
+            a_parent.mtim = now;
+            b_parent.mtim = now;
+            a.update_ctim(now);
+            b.update_ctim(now);
+        }
+        Ok(())
+    }
+
+    /// Copy the regular file at `from`, relative to this directory, to `to`,
+    /// relative to `dir_to`, following symlinks in both. The copy shares its
+    /// data with the original until one of them is written to, so copying
+    /// even a large file takes no memory for its contents. If `to` is an
+    /// existing file, its contents are replaced, and handles open on it see
+    /// the new contents. `dir_to` may be on another filesystem, whose
+    /// maximum file size then applies to the copy.
+    pub fn copy_file(&self, from: &str, dir_to: &Dir, to: &str) -> Result<(), Error> {
+        self.fs.check_path_len(from)?;
+        dir_to.fs.check_path_len(to)?;
+        let follow = LookupOptions {
+            follow: true,
+            ..LookupOptions::default()
+        };
+        let (from_dir, from_name) = self.resolve(
+            from,
+            LookupOptions {
+                must_exist: true,
+                ..follow
+            },
+        )?;
+        let source = match from_dir.entry(&from_name) {
+            Some(Inode::File(f)) => f,
+            // The copy is of freshly generated contents.
+            Some(Inode::Dynamic(d)) => {
+                let snapshot = d.write().unwrap().snapshot(from_dir.fs.now())?;
+                Arc::new(RwLock::new(snapshot))
+            }
+            Some(Inode::Dir(_)) | Some(Inode::Mount(_)) => return Err(Error::is_dir()),
+            Some(Inode::Device(_)) => {
+                return Err(Error::not_supported().context("devices can't be copied"))
+            }
+            Some(Inode::Fifo(_)) => {
+                return Err(Error::not_supported().context("FIFOs can't be copied"))
+            }
+            _ => return Err(Error::not_found()),
+        };
+        let contents = source.read().unwrap().contents();
+        dir_to.fs.check_file_size(contents.len())?;
+        let (to_dir, to_name) = dir_to.resolve(to, follow)?;
+        let now = to_dir.fs.now();
+        match to_dir.entry(&to_name) {
+            Some(Inode::File(f)) => {
+                if !Arc::ptr_eq(&f, &source) {
+                    let mut f = f.write().unwrap();
+                    if f.read_only {
+                        return Err(Error::read_only());
+                    }
+                    f.set_contents(contents, now)?;
+                }
+            }
+            Some(Inode::Dir(_)) | Some(Inode::Mount(_)) => return Err(Error::is_dir()),
+            Some(Inode::Symlink(_)) => return Err(Error::loop_()),
+            Some(Inode::Dynamic(_)) => return Err(Error::read_only()),
+            Some(Inode::Device(_)) => {
+                return Err(Error::not_supported().context("devices can't be copied to"))
+            }
+            Some(Inode::Fifo(_)) => {
+                return Err(Error::not_supported().context("FIFOs can't be copied to"))
+            }
+            None => {
+                validate_name(&to_name)?;
+                let inode = to_dir.fs.new_file()?;
+                inode.write().unwrap().set_contents(contents, now)?;
+                to_dir.insert(&to_name, Inode::File(inode))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Create the directory at `path` along with any of the directories
+    /// above it which don't exist yet, like `mkdir -p`. It's fine for any
+    /// of them to exist already, including `path` itself, or to be a
+    /// symlink to a directory, but if one is anything else this fails with
+    /// ENOTDIR. Each directory created updates its parent's mtim as
+    /// `create_dir` would.
+    pub fn create_dir_all(&self, path: &str) -> Result<(), Error> {
+        self.fs.check_path_len(path)?;
+        if path.is_empty() {
+            return Err(Error::not_found().context("empty path"));
+        }
+        // Resolve each prefix of `path` in turn, ending at a component.
+        let ends = path
+            .match_indices('/')
+            .map(|(i, _)| i)
+            .chain(std::iter::once(path.len()))
+            .filter(|&i| i > 0 && !path[..i].ends_with('/'));
+        for end in ends {
+            let prefix = &path[..end];
+            let (dir, name) = self.resolve(
+                prefix,
+                LookupOptions {
+                    create_dir: true,
+                    ..LookupOptions::default()
+                },
+            )?;
+            match dir.entry(&name) {
+                Some(Inode::Dir(_)) | Some(Inode::Mount(_)) => {}
+                // Only a link to a directory will do.
+                Some(Inode::Symlink(_)) => {
+                    self.resolve(
+                        prefix,
+                        LookupOptions {
+                            follow: true,
+                            must_be_dir: true,
+                            ..LookupOptions::default()
+                        },
+                    )?;
+                }
+                Some(_) => {
+                    return Err(Error::not_dir().context(format!("{:?} is not a directory", name)))
+                }
+                None => {
+                    // One made by someone else since the lookup will do.
+                    if let Err(e) = dir.insert_dir(&name) {
+                        if !matches!(dir.entry(&name), Some(Inode::Dir(_))) {
+                            return Err(e);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the directory at `path` and everything in it, like `rm -r`,
+    /// returning how many entries were removed, counting the directory
+    /// itself. A symlink at `path` isn't followed, so this fails with
+    /// ENOTDIR as it does for a file. The directory is taken out of its
+    /// parent first, so the whole tree goes at once and nothing can fail
+    /// partway; the rest is then walked depth-first, and files lose a link
+    /// as if unlinked, so open handles on them keep working. A directory
+    /// still open below `path` is left empty, and is its own parent. Like
+    /// `remove_dir`, this refuses a final `.` or `..`, or the directory
+    /// `path` is resolved from. A mount point anywhere in the tree makes
+    /// this fail with EBUSY before anything is removed; `unmount` it first.
+    pub fn remove_dir_all(&self, path: &str) -> Result<u64, Error> {
+        let (dir, dirname) = self.resolve(
+            path,
+            LookupOptions {
+                must_be_dir: true,
+                ..LookupOptions::default()
+            },
+        )?;
+        check_detachable(path, &dirname)?;
+        // Nothing can be renamed into or out of the tree while it's walked.
+        let _rename_guard = dir.fs.rename_lock.lock().unwrap();
+        let now = dir.fs.now();
+        let top = {
+            let mut d = dir.inode_mut();
+            let top = match d.contents.get(&*dirname) {
+                Some(Inode::Dir(top)) => top.clone(),
+                Some(Inode::Mount(_)) => return Err(mount_point_busy()),
+                Some(_) => return Err(Error::not_dir()),
+                None => return Err(Error::not_found()),
+            };
+            if holds_mount(&top) {
+                return Err(mount_point_busy().context("in the tree being removed"));
+            }
+            d.remove_entry(&dirname);
+            d.mtim = now;
+            top
+        };
+
+        let mut removed = 1;
+        let mut stack = vec![top];
+        while let Some(d) = stack.pop() {
+            let contents = d.write().unwrap().detach();
+            for (_, inode) in contents {
+                removed += 1;
+                match inode {
+                    Inode::Dir(child) => stack.push(child),
+                    Inode::File(f) => dir.fs.unlinked(&f, now),
+                    // There are no mount points, as checked above.
+                    Inode::Symlink(_)
+                    | Inode::Dynamic(_)
+                    | Inode::Device(_)
+                    | Inode::Fifo(_)
+                    | Inode::Mount(_) => {}
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Create the directories, regular files, symlinks, and hard links in
+    /// the tar archive read from `reader` in the directory at `path`, which
+    /// must exist, along with any directories the archive leaves out.
+    /// Entries get the mtims the archive gives them; their modes and owners
+    /// are ignored. Paths in the archive are relative to the directory
+    /// whether they have a leading `/` or not, and nothing in the archive
+    /// can reach above it: an entry whose path has a `..` component fails
+    /// with EPERM, and symlinks created earlier are resolved as if the
+    /// directory were a preopen. An entry where something already exists
+    /// fails with EEXIST, unless both are directories. Entries which can't
+    /// be represented, such as devices, fail with ENOTSUP, unless `options`
+    /// says to skip them. Entries created before a failure are left in
+    /// place.
+    pub fn import_tar<R: Read>(
+        &self,
+        path: &str,
+        reader: R,
+        options: TarOptions,
+    ) -> Result<(), Error> {
+        crate::archive::import_tar(&self.import_root(path)?, reader, options)
+    }
+
+    /// Like `import_tar`, for a zip archive. Directories are created for
+    /// the directory entries in the archive, and for the paths of its
+    /// files. Files are decompressed as they're imported. Zip has no
+    /// standard way to store a symlink, so unless `options` says to follow
+    /// Info-ZIP's convention, they're imported as files holding their
+    /// targets. Timestamps in zip archives have no time zone, and are taken
+    /// as UTC. Entries compressed with anything but deflate, if at all,
+    /// fail with ENOTSUP.
+    pub fn import_zip<R: Read + Seek>(
+        &self,
+        path: &str,
+        reader: R,
+        options: ZipOptions,
+    ) -> Result<(), Error> {
+        crate::archive::import_zip(&self.import_root(path)?, reader, options)
+    }
+
+    /// Write the tree at `path`, following a symlink there, to `writer` as a
+    /// tar archive which `import_tar` would recreate it from. The archive is
+    /// the same for the same tree however it was built: entries come in
+    /// byte order of their names, each directory before what's in it, with
+    /// the directory itself as `./`, and nothing varies in their headers
+    /// but their types, sizes, and mtims, which are in whole seconds.
+    /// Modes are 0755 for directories, 0644 for files, and 0777 for
+    /// symlinks; owners are root. A file with more than one link in the
+    /// tree is archived once, with the rest as hard links to it. Dynamic
+    /// files are archived with freshly generated contents, files which have
+    /// been unlinked while open aren't in the tree to archive, and devices
+    /// are left out. Fails with ENOTDIR if `path` isn't a directory, and
+    /// with EIO if `writer` does.
+    pub fn export_tar<W: Write>(&self, path: &str, writer: W) -> Result<(), Error> {
+        let inode = self.lookup(path)?;
+        crate::archive::export_tar(&self.fs, inode, writer)
+    }
+
+    /// Copy the regular files, directories, and symlinks in the host
+    /// directory `host` into the directory at `path`, which must exist.
+    /// Everything is read through `host`, so nothing outside it is copied,
+    /// and what's copied is a snapshot: later changes on either side don't
+    /// show on the other. Entries keep their mtims; hard links on the host
+    /// become separate files. Symlinks are copied, followed, or left out as
+    /// `options` says; one that can't be followed, or whose target is
+    /// absolute, is left out. Anything else, such as sockets and devices,
+    /// is left out too, and the entries left out are returned. Entries
+    /// which already exist fail with EEXIST, unless both are directories,
+    /// and going over `options.max_bytes` fails with ENOSPC. Entries copied
+    /// before a failure are left in place, as with `import_tar`.
+    pub fn import_host_dir(
+        &self,
+        path: &str,
+        host: &cap_std::fs::Dir,
+        options: HostDirOptions,
+    ) -> Result<Vec<SkippedEntry>, Error> {
+        crate::host::import_host_dir(&self.import_root(path)?, host, options)
+    }
+
+    /// A handle to the directory at `path` to import into, which entries
+    /// can't reach above, as if it were a preopen.
+    fn import_root(&self, path: &str) -> Result<Dir, Error> {
+        let opts = LookupOptions {
+            follow: true,
+            must_be_dir: true,
+            ..LookupOptions::default()
+        };
+        let (dir, name) = self.resolve(path, opts)?;
+        let target = dir.child_dir(&name)?;
+        Ok(Dir::new(self.fs.clone(), target.inode))
+    }
+
+    /// The SHA-256 digest of the contents of the file at `path`, following
+    /// symlinks, as `sha256sum` would compute it. A dynamic file's contents
+    /// are generated afresh.
+    pub fn hash_file(&self, path: &str) -> Result<[u8; 32], Error> {
+        let inode = self.lookup(path)?;
+        match inode {
+            Inode::File(_) | Inode::Dynamic(_) => crate::hash::file(&self.fs, &inode),
+            Inode::Dir(_) | Inode::Mount(_) => Err(Error::is_dir()),
+            Inode::Symlink(_) => Err(Error::loop_()),
+            Inode::Device(_) => Err(Error::not_supported().context("devices have no contents")),
+            Inode::Fifo(_) => Err(Error::not_supported().context("FIFOs have no contents")),
+        }
+    }
+
+    /// A SHA-256 digest of the tree at `path`, following a symlink there,
+    /// covering the names, types, and contents of everything in it, and
+    /// their mtims if `times` is set. Symlinks within the tree are hashed
+    /// by their targets rather than followed. Trees with the same contents
+    /// have the same digest however they were built. `path` may name a file
+    /// too.
+    pub fn hash_tree(&self, path: &str, times: bool) -> Result<[u8; 32], Error> {
+        let inode = self.lookup(path)?;
+        crate::hash::tree(&self.fs, inode, times)
+    }
+
+    /// The entry at `path`, following symlinks.
+    pub(crate) fn lookup(&self, path: &str) -> Result<Inode, Error> {
+        let opts = LookupOptions {
+            follow: true,
+            must_exist: true,
+            ..LookupOptions::default()
+        };
+        let (dir, name) = self.resolve(path, opts)?;
+        dir.entry(&name).ok_or_else(Error::not_found)
+    }
+
+    /// Create a file at `path` whose contents are `data`, read in place
+    /// without being copied, as for data embedded with `include_bytes!`.
+    /// `writes` says whether the guest may modify the file. Like `O_EXCL`,
+    /// this fails with EEXIST if anything is already at `path`. The file
+    /// may be larger than the maximum file size, but can't then grow.
+    pub fn put_static_file(
+        &self,
+        path: &str,
+        data: &'static [u8],
+        writes: StaticWrites,
+    ) -> Result<(), Error> {
+        self.put_file(path, Contents::from_static(data), writes)
+    }
+
+    /// Like `put_static_file`, for data the host holds in an `Arc`. The file
+    /// keeps a reference to `data` until it's unlinked and closed, or
+    /// truncated to nothing.
+    pub fn put_shared_file(
+        &self,
+        path: &str,
+        data: Arc<[u8]>,
+        writes: StaticWrites,
+    ) -> Result<(), Error> {
+        self.put_file(path, Contents::from_shared(data), writes)
+    }
+
+    /// Like `put_static_file`, for contents read from `source` only as the
+    /// guest reads them, such as a large host file. With
+    /// `StaticWrites::CopyOnWrite`, each chunk written to is read from
+    /// `source` in full first. Errors reading `source` are passed on to the
+    /// guest.
+    pub fn put_lazy_file(
+        &self,
+        path: &str,
+        source: Arc<dyn LazyContents>,
+        writes: StaticWrites,
+    ) -> Result<(), Error> {
+        self.put_file(path, Contents::from_lazy(source), writes)
+    }
+
+    /// Create a file at `path` whose contents are produced by `generate`
+    /// each time it's opened, like a file in procfs. Each handle reads the
+    /// contents generated when it was opened, and `stat` reports the size
+    /// of the contents last generated. Opening the file for writing fails
+    /// with EROFS, and an error from `generate` fails the open. Like
+    /// `put_static_file`, this fails with EEXIST if anything is already at
+    /// `path`.
+    pub fn put_dynamic_file(&self, path: &str, generate: GenerateContents) -> Result<(), Error> {
+        let (dir, name) = self.resolve_new(path)?;
+        let inode = dir.fs.new_dynamic(generate)?;
+        dir.insert(&name, Inode::Dynamic(inode))?;
+        Ok(())
+    }
+
+    /// Create a character device at `path`, such as `/dev/tty`, whose reads
+    /// and writes go to `device`; every handle opened on it shares `device`.
+    /// `EchoDevice` is a simple one. See `put_device`.
+    pub fn put_char_device(&self, path: &str, device: Arc<dyn WasiFile>) -> Result<(), Error> {
+        self.put_device_inode(path, Arc::new(move |_| Ok(device.clone())))
+    }
+
+    /// Create a character device at `path` for which each open calls `open`
+    /// with the handle's fdflags, and whose reads and writes go to the
+    /// `WasiFile` it returns; an error fails the open. The guest sees a
+    /// character device whatever that file reports, which has no size and
+    /// its own serial and timestamps, and which seeks as the file does.
+    /// CREATE and TRUNCATE are ignored when the device is opened, but, as
+    /// for any existing file, CREATE with EXCLUSIVE fails with EEXIST. Like
+    /// `put_static_file`, this fails with EEXIST if anything is already at
+    /// `path`.
+    pub fn put_device(&self, path: &str, open: OpenDevice) -> Result<(), Error> {
+        self.put_device_inode(path, Arc::new(move |fdflags| open(fdflags).map(Arc::from)))
+    }
+
+    fn put_device_inode(&self, path: &str, open: SharedOpenDevice) -> Result<(), Error> {
+        let (dir, name) = self.resolve_new(path)?;
+        let inode = dir.fs.new_device(open)?;
+        dir.insert(&name, Inode::Device(inode))?;
+        Ok(())
+    }
+
+    /// Create a FIFO at `path`, such as `run/events`, which holds up to
+    /// `capacity` bytes, and return the host's ends of it. The guest opens
+    /// it as it would a file and sees a pipe, which it can't seek. The host
+    /// has the FIFO open for as long as it keeps the `FifoReader` and the
+    /// `FifoWriter`, so a guest reading an empty FIFO fails with EAGAIN
+    /// until the `FifoWriter` is dropped, and writing once the `FifoReader`
+    /// and every guest handle reading the FIFO are gone fails with EPIPE.
+    /// Nothing waits on a FIFO, which the host may be serving from the
+    /// guest's own thread: without data or room, reads and writes fail with
+    /// EAGAIN even without `FdFlags::NONBLOCK`, and the guest waits for them
+    /// with `poll_oneoff`. Like `put_static_file`, this fails
+    /// with EEXIST if anything is already at `path`, and a `capacity` of 0
+    /// fails with EINVAL.
+    pub fn create_fifo(
+        &self,
+        path: &str,
+        capacity: usize,
+    ) -> Result<(FifoReader, FifoWriter), Error> {
+        if capacity == 0 {
+            return Err(Error::invalid_argument().context("a FIFO must hold something"));
+        }
+        let (dir, name) = self.resolve_new(path)?;
+        let (inode, reader, writer) = dir.fs.new_fifo(capacity)?;
+        dir.insert(&name, Inode::Fifo(inode))?;
+        Ok((reader, writer))
+    }
+
+    /// Mount `dir` at `path`, which mustn't exist yet: the guest sees a
+    /// directory there, and paths through it are resolved by `dir` itself,
+    /// which can be another virtfs's root or a host directory, say. `..`
+    /// can't climb back out of it, nor can its symlinks reach outside it.
+    /// Renames and hard links across the mount point fail with EXDEV, as do
+    /// the host's own methods on paths through it, and the mount point
+    /// can't be removed or replaced (EBUSY) until it's unmounted with
+    /// `unmount`. What's mounted is no part of a fork, an archive, a hash,
+    /// or a serialized tree. Like `put_static_file`, this fails with EEXIST
+    /// if anything is already at `path`.
+    pub fn mount(&self, path: &str, dir: Box<dyn WasiDir>) -> Result<(), Error> {
+        // Not into a tree `remove_dir_all` has checked for mount points.
+        let _rename_guard = self.fs.rename_lock.lock().unwrap();
+        let (parent, name) = self.resolve_new(path)?;
+        let inode = parent.fs.new_mount(dir)?;
+        parent.insert(&name, Inode::Mount(inode))?;
+        Ok(())
+    }
+
+    /// Remove the mount point at `path` and return the directory that was
+    /// mounted there. This fails with EBUSY while any file or directory
+    /// opened through the mount point is still open, and with EINVAL if
+    /// `path` isn't a mount point.
+    pub fn unmount(&self, path: &str) -> Result<Box<dyn WasiDir>, Error> {
+        let (dir, name) = self.resolve(
+            path,
+            LookupOptions {
+                must_exist: true,
+                ..LookupOptions::default()
+            },
+        )?;
+        let now = dir.fs.now();
+        let mut d = dir.inode_mut();
+        let mount = match d.contents.get(&*name) {
+            Some(Inode::Mount(m)) => m.clone(),
+            Some(_) => return Err(Error::invalid_argument().context("not a mount point")),
+            None => return Err(Error::not_found()),
+        };
+        // Once every handle is closed, only the entry and `mount` are left.
+        if Arc::strong_count(&mount) > 2 {
+            return Err(mount_point_busy().context("open through the mount point"));
+        }
+        d.remove_entry(&name);
+        d.mtim = now;
+        drop(d);
+        match Arc::try_unwrap(mount) {
+            Ok(mount) => Ok(mount.dir),
+            Err(_) => Err(mount_point_busy()),
+        }
+    }
+
+    /// Create a `NullDevice` at `path`, such as `dev/null`, and any missing
+    /// directories above it. Like `put_device`, this fails with EEXIST if
+    /// anything is already at `path`.
+    pub fn put_null_device(&self, path: &str) -> Result<(), Error> {
+        self.put_builtin_device(path, Box::new(|_| Ok(Box::new(NullDevice::new()))))
+    }
+
+    /// Create a `ZeroDevice` at `path`, such as `dev/zero`, and any missing
+    /// directories above it, as `put_null_device` does.
+    pub fn put_zero_device(&self, path: &str) -> Result<(), Error> {
+        self.put_builtin_device(path, Box::new(|_| Ok(Box::new(ZeroDevice::new()))))
+    }
+
+    /// Create a `RandomDevice` at `path`, such as `dev/urandom`, reading
+    /// from the host's random number generator, and any missing directories
+    /// above it, as `put_null_device` does. A device with another source
+    /// can be put in place with `put_char_device`.
+    pub fn put_random_device(&self, path: &str) -> Result<(), Error> {
+        self.put_builtin_device(path, Box::new(|_| Ok(Box::new(RandomDevice::new()))))
+    }
+
+    fn put_builtin_device(&self, path: &str, open: OpenDevice) -> Result<(), Error> {
+        if let Some(end) = path.rfind('/') {
+            let parent = path[..end].trim_end_matches('/');
+            if !parent.is_empty() {
+                self.create_dir_all(parent)?;
+            }
+        }
+        self.put_device(path, open)
+    }
+
+    pub(crate) fn put_file(
+        &self,
+        path: &str,
+        contents: Contents,
+        writes: StaticWrites,
+    ) -> Result<(), Error> {
+        let (dir, name) = self.resolve_new(path)?;
+        let inode = dir.fs.new_file()?;
+        {
+            let mut f = inode.write().unwrap();
+            f.set_contents(contents, dir.fs.now())?;
+            f.read_only = writes == StaticWrites::ReadOnly;
+        }
+        dir.insert(&name, Inode::File(inode))?;
+        Ok(())
+    }
+
+    /// Resolve `path` for a new entry the host is creating, which must not
+    /// already exist.
+    fn resolve_new<'a>(&self, path: &'a str) -> Result<(Dir, Cow<'a, str>), Error> {
+        self.fs.check_path_len(path)?;
+        let (dir, name) = self.resolve(path, LookupOptions::default())?;
+        if dir.entry(&name).is_some() {
+            return Err(Error::exist());
+        }
+        validate_name(&name)?;
+        Ok((dir, name))
+    }
+
+    /// Create a symlink at `path` to `target`, as `WasiDir::symlink` does.
+    pub(crate) fn put_symlink(&self, target: &str, path: &str) -> Result<(), Error> {
+        self.fs.check_path_len(target)?;
+        self.fs.check_path_len(path)?;
+        let (dir, linkname) = self.resolve(path, LookupOptions::default())?;
+        if dir.entry(&linkname).is_some() {
+            return Err(Error::exist());
+        }
+        validate_name(&linkname)?;
+        let link = dir.fs.new_symlink(target.to_owned())?;
+        dir.insert(&linkname, Inode::Symlink(link))?;
+        Ok(())
+    }
+
+    /// Link the file at `src_path` to `target_path`, relative to
+    /// `target_dir`, as `WasiDir::hard_link` does. Both must be on the same
+    /// filesystem, or this fails with EXDEV.
+    pub(crate) fn link(
+        &self,
+        src_path: &str,
+        target_dir: &Dir,
+        target_path: &str,
+    ) -> Result<(), Error> {
+        if !Arc::ptr_eq(&self.fs, &target_dir.fs) {
+            return Err(Error::cross_device().context("link across filesystems"));
+        }
+        self.fs.check_path_len(src_path)?;
+        self.fs.check_path_len(target_path)?;
+        let (src_dir, src_name) = self.resolve(
+            src_path,
+            LookupOptions {
+                must_exist: true,
+                ..LookupOptions::default()
+            },
+        )?;
+        let inode = src_dir.child_file(&src_name)?;
+        let (dir, filename) = target_dir.resolve(target_path, LookupOptions::default())?;
+        if dir.entry(&filename).is_some() {
+            return Err(Error::exist());
+        }
+        validate_name(&filename)?;
+        let now = dir.fs.now();
+        let mut d = dir.inode_mut();
+        if dir.has_entry(&d, &filename) {
+            return Err(Error::exist());
+        }
+        {
+            let mut f = inode.write().unwrap();
+            f.nlink += 1;
+            f.update_ctim(now);
+        }
+        d.insert_entry(&filename, Inode::File(inode));
+        d.mtim = now;
+        Ok(())
+    }
+
+    /// Make the directory at `path` read-only to the guest, along with
+    /// everything below it, or writable again, as `Filesystem::set_readonly`
+    /// does for the whole filesystem. Nothing in the subtree can be
+    /// created, removed, renamed, linked, written or have its timestamps
+    /// set, including through handles opened before, and the guest can't
+    /// move or link anything out of it or into it. A file with a link
+    /// elsewhere is read-only by that name too. What the host adds to the
+    /// subtree later is read-only as well, and what it moves out, with
+    /// `exchange`, is writable again. Read-only subtrees may be nested,
+    /// and each stays read-only until it's made writable itself. A symlink
+    /// at `path` isn't followed, so this fails with ENOTDIR as it does for
+    /// a file.
+    pub fn set_readonly_at(&self, path: &str, read_only: bool) -> Result<(), Error> {
+        // Nothing moves while the subtree is counted.
+        let _rename_guard = self.fs.rename_lock.lock().unwrap();
+        let dir = match self.entry_at(path)? {
+            Inode::Dir(d) => d,
+            _ => return Err(Error::not_dir()),
+        };
+        {
+            let mut d = dir.write().unwrap();
+            if d.read_only == read_only {
+                return Ok(());
+            }
+            d.read_only = read_only;
+        }
+        shift_read_only_depth(&dir, if read_only { 1 } else { -1 });
+        Ok(())
+    }
+
+    /// The entry at `path`, not following a symlink there.
+    pub(crate) fn entry_at(&self, path: &str) -> Result<Inode, Error> {
+        let opts = LookupOptions {
+            must_exist: true,
+            ..LookupOptions::default()
+        };
+        let (dir, name) = self.resolve(path, opts)?;
+        dir.entry(&name).ok_or_else(Error::not_found)
+    }
+
+    pub(crate) fn fs(&self) -> &Arc<Filesystem> {
+        &self.fs
+    }
+}
+
+#[async_trait::async_trait]
+impl WasiDir for Dir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn open_file(
+        &self,
+        symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        read: bool,
+        write: bool,
+        fdflags: FdFlags,
+    ) -> Result<Box<dyn WasiFile>, Error> {
+        // Rights are checked by the `FileEntry` this file is put in, and
+        // read and write access by the file itself.
+        let mode = FileMode::new(read, write);
+        match self.open(symlink_follow, path, oflags, mode, FileCaps::all(), fdflags) {
+            Err(e) => {
+                let crossing = Crossing::of(e)?;
+                crossing
+                    .open_file(symlink_follow, oflags, read, write, fdflags)
+                    .await
+            }
+            opened => opened,
+        }
+    }
+
+    async fn open_dir(&self, symlink_follow: bool, path: &str) -> Result<Box<dyn WasiDir>, Error> {
+        let resolved = self.resolve(
+            path,
+            LookupOptions {
+                follow: symlink_follow,
+                must_be_dir: true,
+                enter_mount: true,
+                ..LookupOptions::default()
+            },
+        );
+        let (dir, dirname) = match resolved {
+            Ok(resolved) => resolved,
+            Err(e) => return Crossing::of(e)?.open_dir(symlink_follow).await,
+        };
+        Ok(Box::new(dir.child_dir(&dirname)?))
+    }
+
+    async fn create_dir(&self, path: &str) -> Result<(), Error> {
+        self.fs.check_writable()?;
+        self.fs.check_path_len(path)?;
+        let resolved = self.resolve(
+            path,
+            LookupOptions {
+                create_dir: true,
+                ..LookupOptions::default()
+            },
+        );
+        let (dir, dirname) = match resolved {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                let crossing = Crossing::of(e)?;
+                return crossing.mount.dir.create_dir(&crossing.rest).await;
+            }
+        };
+        dir.check_writable()?;
+        if dir.entry(&dirname).is_some() {
+            return Err(Error::exist());
+        }
+        dir.insert_dir(&dirname)
+    }
+
+    async fn readdir(
+        &self,
+        cursor: ReaddirCursor,
+    ) -> Result<Box<dyn Iterator<Item = Result<ReaddirEntity, Error>> + Send>, Error> {
+        Ok(Box::new(Readdir::new(self.inode.clone(), cursor)))
+    }
+
+    async fn symlink(&self, src_path: &str, dest_path: &str) -> Result<(), Error> {
+        self.fs.check_writable()?;
+        let (dir, _) = match self.resolve(dest_path, LookupOptions::default()) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                let crossing = Crossing::of(e)?;
+                return crossing.mount.dir.symlink(src_path, &crossing.rest).await;
+            }
+        };
+        dir.check_writable()?;
+        self.put_symlink(src_path, dest_path)
+    }
+
+    async fn remove_dir(&self, path: &str) -> Result<(), Error> {
+        self.fs.check_writable()?;
+        let resolved = self.resolve(
+            path,
+            LookupOptions {
+                must_be_dir: true,
+                ..LookupOptions::default()
+            },
+        );
+        let (dir, dirname) = match resolved {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                let crossing = Crossing::of(e)?;
+                return crossing.mount.dir.remove_dir(&crossing.rest).await;
+            }
+        };
+        check_detachable(path, &dirname)?;
+        dir.check_writable()?;
+        let now = dir.fs.now();
+        let mut d = dir.inode_mut();
+        match d.contents.get(&*dirname) {
+            Some(Inode::Dir(target)) => {
+                let mut target = target.write().unwrap();
+                if target.read_only {
+                    return Err(Error::read_only().context("read-only directory"));
+                }
+                if !target.contents.is_empty() {
+                    return Err(Error::not_empty());
+                }
+                target.detach();
+            }
+            Some(Inode::Mount(_)) => return Err(mount_point_busy()),
+            Some(_) => return Err(Error::not_dir()),
+            None => return Err(Error::not_found()),
+        }
+        d.remove_entry(&dirname);
+        d.mtim = now;
+        Ok(())
+    }
+
+    async fn unlink_file(&self, path: &str) -> Result<(), Error> {
+        self.fs.check_writable()?;
+        let resolved = self.resolve(
+            path,
+            LookupOptions {
+                must_exist: true,
+                ..LookupOptions::default()
+            },
+        );
+        let (dir, filename) = match resolved {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                let crossing = Crossing::of(e)?;
+                return crossing.mount.dir.unlink_file(&crossing.rest).await;
+            }
+        };
+        dir.check_writable()?;
+        let now = dir.fs.now();
+        let mut d = dir.inode_mut();
+        match d.contents.get(&*filename) {
+            Some(Inode::File(f)) => dir.fs.unlinked(f, now),
+            // Only the link itself goes away, never its target.
+            Some(Inode::Symlink(_))
+            | Some(Inode::Dynamic(_))
+            | Some(Inode::Device(_))
+            | Some(Inode::Fifo(_)) => {}
+            Some(Inode::Dir(_)) | Some(Inode::Mount(_)) => return Err(Error::is_dir()),
+            None => return Err(Error::not_found()),
+        }
+        d.remove_entry(&filename);
+        d.mtim = now;
+        Ok(())
+    }
+
+    async fn read_link(&self, path: &str) -> Result<PathBuf, Error> {
+        let resolved = self.resolve(
+            path,
+            LookupOptions {
+                must_exist: true,
+                ..LookupOptions::default()
+            },
+        );
+        let (dir, linkname) = match resolved {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                let crossing = Crossing::of(e)?;
+                return crossing.mount.dir.read_link(&crossing.rest).await;
+            }
+        };
+        let inode = dir.entry(&linkname).ok_or_else(Error::not_found)?;
+        match inode {
+            Inode::Symlink(l) => Ok(PathBuf::from(&l.read().unwrap().target)),
+            _ => Err(Error::invalid_argument().context("not a symlink")),
+        }
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        Ok(self.inode().get_filestat(self.fs.device_id))
+    }
+
+    async fn get_path_filestat(
+        &self,
+        path: &str,
+        follow_symlinks: bool,
+    ) -> Result<Filestat, Error> {
+        let resolved = self.resolve(
+            path,
+            LookupOptions {
+                follow: follow_symlinks,
+                must_exist: true,
+                enter_mount: true,
+                ..LookupOptions::default()
+            },
+        );
+        let (dir, filename) = match resolved {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                let crossing = Crossing::of(e)?;
+                let mounted = &crossing.mount.dir;
+                return mounted
+                    .get_path_filestat(&crossing.rest, follow_symlinks)
+                    .await;
+            }
+        };
+        let inode = dir.entry(&filename).ok_or_else(Error::not_found)?;
+        Ok(inode.get_filestat(dir.fs.device_id))
+    }
+
+    async fn rename(
+        &self,
+        src_path: &str,
+        dest_dir: &dyn WasiDir,
+        dest_path: &str,
+    ) -> Result<(), Error> {
+        // Anything other than a virtfs `Dir` on this same filesystem is a
+        // different device as far as the guest is concerned.
+        let dest_dir = match dest_dir.as_any().downcast_ref::<Self>() {
+            Some(d) if Arc::ptr_eq(&self.fs, &d.fs) => d,
+            _ => return Err(Error::cross_device().context("rename across filesystems")),
+        };
+        self.fs.check_writable()?;
+        self.fs.check_path_len(src_path)?;
+        self.fs.check_path_len(dest_path)?;
+        let src = self.resolve(
+            src_path,
+            LookupOptions {
+                must_exist: true,
+                ..LookupOptions::default()
+            },
+        );
+        let dest = dest_dir.resolve(
+            dest_path,
+            LookupOptions {
+                create_dir: true,
+                ..LookupOptions::default()
+            },
+        );
+        let ((src_dir, src_name), (dest_dir, dest_name)) = match (src, dest) {
+            (Ok(src), Ok(dest)) => (src, dest),
+            (Err(src), dest) => {
+                let (src, dest_rest) = within_mount(src, dest)?;
+                let mounted = &*src.mount.dir;
+                return mounted.rename(&src.rest, mounted, &dest_rest).await;
+            }
+            (_, Err(dest)) => return Err(dest),
+        };
+        let (src_name, dest_name) = (&*src_name, &*dest_name);
+        check_detachable(src_path, src_name)?;
+        check_detachable(dest_path, dest_name)?;
+        validate_name(dest_name)?;
+        // Moving an entry out of a read-only subtree changes it as much as
+        // moving one in.
+        src_dir.check_writable()?;
+        dest_dir.check_writable()?;
+        // A trailing slash on the destination only makes sense for a directory.
+        let dest_is_dir = dest_path.ends_with('/');
+        // Both names are updated while holding the write locks, so a
+        // concurrent lookup sees either the old name or the new one.
+        let now = src_dir.fs.now();
+        if Arc::ptr_eq(&src_dir.inode, &dest_dir.inode) {
+            let mut d = src_dir.inode_mut();
+            let inode = match d.contents.get(src_name) {
+                Some(inode) => inode.clone(),
+                None => return Err(Error::not_found()),
+            };
+            if dest_is_dir && !matches!(inode, Inode::Dir(_)) {
+                return Err(Error::not_dir());
+            }
+            if src_name == dest_name {
+                // On a case-insensitive filesystem, the destination may be
+                // the same entry spelled differently, which respells it.
+                let spelling = dest_dir.spelling(dest_path);
+                if !self.fs.is_case_insensitive() || spelling == src_name {
+                    return Ok(());
+                }
+                check_not_read_only(&inode)?;
+                check_not_mount_point(&inode)?;
+                d.remove_entry(src_name);
+                d.insert_entry(&spelling, inode.clone());
+                d.mtim = now;
+                inode.update_ctim(now);
+                return Ok(());
+            }
+            if let Some(existing) = d.contents.get(dest_name) {
+                if existing.serial() == inode.serial() {
+                    return Ok(());
+                }
+                check_not_mount_point(existing)?;
+                check_rename_over(&inode, existing)?;
+                check_not_read_only(existing)?;
+            }
+            check_not_read_only(&inode)?;
+            check_not_mount_point(&inode)?;
+            d.remove_entry(src_name);
+            let replaced = d.insert_entry(dest_name, inode.clone());
+            unlink_replaced(&dest_dir.fs, replaced, now);
+            d.mtim = now;
+            inode.update_ctim(now);
+        } else {
+            let _rename_guard = src_dir.fs.rename_lock.lock().unwrap();
+            let moving = match src_dir.inode().contents.get(src_name) {
+                Some(Inode::Dir(d)) => Some(d.clone()),
+                _ => None,
+            };
+            if let Some(moving) = moving {
+                if is_ancestor(&moving, &dest_dir.inode) {
+                    return Err(
+                        Error::invalid_argument().context("cannot move a directory into itself")
+                    );
+                }
+                check_depth(dest_dir.fs.max_depth(), &dest_dir.inode, Some(&moving))?;
+            }
+            let (mut src, mut dest) = lock_pair(&src_dir.inode, &dest_dir.inode);
+            let inode = match src.contents.get(src_name) {
+                Some(inode) => inode.clone(),
+                None => return Err(Error::not_found()),
+            };
+            if dest_is_dir && !matches!(inode, Inode::Dir(_)) {
+                return Err(Error::not_dir());
+            }
+            if let Some(existing) = dest.contents.get(dest_name) {
+                if existing.serial() == inode.serial() {
+                    return Ok(());
+                }
+                check_not_mount_point(existing)?;
+                check_rename_over(&inode, existing)?;
+                check_not_read_only(existing)?;
+            }
+            check_not_read_only(&inode)?;
+            check_not_mount_point(&inode)?;
+            src.remove_entry(src_name);
+            if let Inode::Dir(d) = &inode {
+                d.write().unwrap().parent = Some(Arc::downgrade(&dest_dir.inode));
+            }
+            let replaced = dest.insert_entry(dest_name, inode.clone());
+            unlink_replaced(&dest_dir.fs, replaced, now);
+            src.mtim = now;
+            dest.mtim = now;
+            inode.update_ctim(now);
+        }
+        Ok(())
+    }
+
+    async fn hard_link(
+        &self,
+        src_path: &str,
+        target_dir: &dyn WasiDir,
+        target_path: &str,
+    ) -> Result<(), Error> {
+        // As with rename, anything else is a different device, including a
+        // directory opened through a mount point.
+        let target_dir = match target_dir.as_any().downcast_ref::<Self>() {
+            Some(d) if Arc::ptr_eq(&self.fs, &d.fs) => d,
+            _ => return Err(Error::cross_device().context("link across filesystems")),
+        };
+        target_dir.fs.check_writable()?;
+        // A link out of a read-only subtree would change the file's link
+        // count, and give it a name the guest could write it by.
+        let src = self.resolve(
+            src_path,
+            LookupOptions {
+                must_exist: true,
+                ..LookupOptions::default()
+            },
+        );
+        let (src_dir, src_name) = match src {
+            Ok(src) => src,
+            Err(src) => {
+                let dest = target_dir.resolve(target_path, LookupOptions::default());
+                let (src, dest_rest) = within_mount(src, dest)?;
+                let mounted = &*src.mount.dir;
+                return mounted.hard_link(&src.rest, mounted, &dest_rest).await;
+            }
+        };
+        src_dir.check_writable()?;
+        if let Some(inode) = src_dir.entry(&src_name) {
+            check_not_read_only(&inode)?;
+        }
+        let (dir, _) = target_dir.resolve(target_path, LookupOptions::default())?;
+        dir.check_writable()?;
+        self.link(src_path, target_dir, target_path)
+    }
+
+    async fn set_times(
+        &self,
+        path: &str,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+        follow_symlinks: bool,
+    ) -> Result<(), Error> {
+        self.fs.check_writable()?;
+        let resolved = self.resolve(
+            path,
+            LookupOptions {
+                follow: follow_symlinks,
+                must_exist: true,
+                enter_mount: true,
+                ..LookupOptions::default()
+            },
+        );
+        let (dir, name) = match resolved {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                let crossing = Crossing::of(e)?;
+                let mounted = &crossing.mount.dir;
+                return mounted
+                    .set_times(&crossing.rest, atime, mtime, follow_symlinks)
+                    .await;
+            }
+        };
+        dir.check_writable()?;
+        let inode = dir.entry(&name).ok_or_else(Error::not_found)?;
+        check_not_read_only(&inode)?;
+        inode.set_times(
+            dir.fs.truncate_spec(atime),
+            dir.fs.truncate_spec(mtime),
+            dir.fs.now(),
+        );
+        Ok(())
+    }
+}
+
+/// Check that `oflags` make sense for an `open_file` with the given access,
+/// before anything is looked up or created:
+///
+/// * `DIRECTORY` can't be combined with `CREATE` or `TRUNCATE`.
+/// * `TRUNCATE` needs write access.
+/// * `EXCLUSIVE` without `CREATE` is ignored, as the host backend does.
+pub fn validate_oflags(oflags: OFlags, write: bool) -> Result<(), Error> {
+    if oflags.contains(OFlags::DIRECTORY) && oflags.intersects(OFlags::CREATE | OFlags::TRUNCATE) {
+        return Err(Error::invalid_argument().context("directory oflags"));
+    }
+    if oflags.contains(OFlags::TRUNCATE) && !write {
+        return Err(Error::invalid_argument().context("TRUNCATE without write access"));
+    }
+    Ok(())
+}
+
+/// Check that `name` may be used as the name of a directory entry: it must
+/// not be empty, `.` or `..`, and must not contain `/` or NUL. Path-based
+/// operations only ever pass single components here, but anything building
+/// a tree from names it didn't get from a path should check them first.
+pub fn validate_name(name: &str) -> Result<(), Error> {
+    if name.is_empty() {
+        return Err(Error::invalid_argument().context("empty name"));
+    }
+    if name == "." || name == ".." {
+        return Err(Error::invalid_argument().context(format!("reserved name {:?}", name)));
+    }
+    if name.contains('/') {
+        return Err(Error::invalid_argument().context("name contains a slash"));
+    }
+    if name.contains('\0') {
+        return Err(Error::illegal_byte_sequence().context("name contains NUL"));
+    }
+    Ok(())
+}
+
+/// Fail unless `name`, the final component of `path` once resolved, is an
+/// entry which can be taken out of its directory or replaced. As in POSIX,
+/// a final `.` or `..` fails with EINVAL. A path of nothing but slashes
+/// names the directory it's resolved from, such as a preopen or the root of
+/// the filesystem, and fails with EPERM, so neither is ever detached.
+fn check_detachable(path: &str, name: &str) -> Result<(), Error> {
+    let last = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+    if last == "." || last == ".." {
+        return Err(Error::invalid_argument().context(format!("final component is {:?}", last)));
+    }
+    if name == "." {
+        return Err(Error::perm().context("cannot detach the directory a path is resolved from"));
+    }
+    Ok(())
+}
+
+/// Compare two names the way a case-insensitive filesystem does, folding
+/// Unicode letters to lower case.
+fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a.chars()
+        .flat_map(char::to_lowercase)
+        .eq(b.chars().flat_map(char::to_lowercase))
+}
+
+/// Check whether `moving` may replace `existing` in a rename. As in POSIX, a
+/// non-directory may replace a non-directory, and a directory may replace an
+/// empty directory.
+fn check_rename_over(moving: &Inode, existing: &Inode) -> Result<(), Error> {
+    match (moving, existing) {
+        (Inode::Dir(_), Inode::Dir(d)) => {
+            if d.read().unwrap().contents.is_empty() {
+                Ok(())
+            } else {
+                Err(Error::not_empty())
+            }
+        }
+        (Inode::Dir(_), _) => Err(Error::not_dir()),
+        (_, Inode::Dir(_)) => Err(Error::is_dir()),
+        _ => Ok(()),
+    }
+}
+
+/// EBUSY, for removing, replacing, or moving a mount point, which only
+/// `Dir::unmount` takes away.
+fn mount_point_busy() -> Error {
+    Error::busy().context("mount point")
+}
+
+/// Whether there's a mount point anywhere in the tree below `top`.
+fn holds_mount(top: &Arc<RwLock<DirInode>>) -> bool {
+    let mut stack = vec![top.clone()];
+    while let Some(d) = stack.pop() {
+        for inode in d.read().unwrap().contents.values() {
+            match inode {
+                Inode::Dir(child) => stack.push(child.clone()),
+                Inode::Mount(_) => return true,
+                _ => {}
+            }
+        }
+    }
+    false
+}
+
+fn check_not_mount_point(inode: &Inode) -> Result<(), Error> {
+    match inode {
+        Inode::Mount(_) => Err(mount_point_busy()),
+        _ => Ok(()),
+    }
+}
+
+/// The crossing a rename or a link from `src`, an error from resolving its
+/// source path, makes into a mount, and the rest of the destination path
+/// there, given the result of resolving that. Unless both paths cross into
+/// the same mount, this is `src` itself or EXDEV.
+fn within_mount(
+    src: Error,
+    dest: Result<(Dir, Cow<str>), Error>,
+) -> Result<(Crossing, String), Error> {
+    let src = Crossing::of(src)?;
+    match dest.map_err(Crossing::of) {
+        Err(Ok(dest)) if Arc::ptr_eq(&src.mount, &dest.mount) => Ok((src, dest.rest)),
+        Err(Err(e)) => Err(e),
+        _ => Err(Error::cross_device().context("across a mount point")),
+    }
+}
+
+/// Account for the link lost by an entry that was replaced by a rename. Any
+/// open handles keep the inode alive, with its contents, until they close.
+fn unlink_replaced(fs: &Filesystem, replaced: Option<Inode>, now: SystemTime) {
+    match replaced {
+        Some(Inode::File(f)) => fs.unlinked(&f, now),
+        // Only an empty directory can be replaced.
+        Some(Inode::Dir(d)) => {
+            d.write().unwrap().detach();
+        }
+        _ => {}
+    }
+}
+
+/// Fail with EROFS if the guest may not change `dir`'s entries or
+/// timestamps: if the filesystem is read-only, or `dir` is in or below a
+/// directory made read-only with `Dir::set_readonly_at`.
+fn check_dir_writable(fs: &Filesystem, dir: &Arc<RwLock<DirInode>>) -> Result<(), Error> {
+    fs.check_writable()?;
+    let mut dir = dir.clone();
+    loop {
+        let parent = {
+            let d = dir.read().unwrap();
+            if d.read_only {
+                return Err(Error::read_only().context("read-only directory"));
+            }
+            d.parent.as_ref().and_then(Weak::upgrade)
+        };
+        match parent {
+            Some(parent) => dir = parent,
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Add `delta` to the read-only depth of `dir` and every directory below
+/// it. Directories are checked by walking up from them, but files don't
+/// know where they are, so each link below is counted in or out too.
+fn shift_read_only_depth(dir: &Arc<RwLock<DirInode>>, delta: i64) {
+    let mut pending = vec![dir.clone()];
+    while let Some(dir) = pending.pop() {
+        let mut d = dir.write().unwrap();
+        d.read_only_depth = (d.read_only_depth as i64 + delta) as u32;
+        for inode in d.contents.values() {
+            match inode {
+                Inode::Dir(d) => pending.push(d.clone()),
+                Inode::File(f) => {
+                    let mut f = f.write().unwrap();
+                    f.read_only_dirs = (f.read_only_dirs as i64 + delta) as u32;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Fail with EROFS if `inode` is itself the top of a read-only subtree, or
+/// a file in one, which its directory being writable doesn't make it.
+fn check_not_read_only(inode: &Inode) -> Result<(), Error> {
+    match inode {
+        Inode::Dir(d) if d.read().unwrap().read_only => {
+            Err(Error::read_only().context("read-only directory"))
+        }
+        Inode::File(f) => f.read().unwrap().check_read_only_dirs(),
+        _ => Ok(()),
+    }
+}
+
+/// Is `ancestor` either `dir` itself or one of the directories above it?
+fn is_ancestor(ancestor: &Arc<RwLock<DirInode>>, dir: &Arc<RwLock<DirInode>>) -> bool {
+    let mut dir = dir.clone();
+    loop {
+        if Arc::ptr_eq(&dir, ancestor) {
+            return true;
+        }
+        let parent = dir.read().unwrap().parent.as_ref().and_then(Weak::upgrade);
+        match parent {
+            Some(parent) => dir = parent,
+            None => return false,
+        }
+    }
+}
+
+/// Fail with ENOSPC if putting a directory in `parent` would nest it, or
+/// anything below it, deeper than `max_depth`. `moving` is the directory
+/// being moved there, or `None` for a new, empty directory.
+fn check_depth(
+    max_depth: usize,
+    parent: &Arc<RwLock<DirInode>>,
+    moving: Option<&Arc<RwLock<DirInode>>>,
+) -> Result<(), Error> {
+    if max_depth == usize::MAX {
+        return Ok(());
+    }
+    let height = 1 + moving.map_or(0, height);
+    if depth(parent).saturating_add(height) > max_depth {
+        return Err(Error::no_space().context("maximum directory depth exceeded"));
+    }
+    Ok(())
+}
+
+/// How many levels below the root of the filesystem `dir` is.
+fn depth(dir: &Arc<RwLock<DirInode>>) -> usize {
+    let mut depth = 0;
+    let mut parent = dir.read().unwrap().parent.as_ref().and_then(Weak::upgrade);
+    while let Some(p) = parent {
+        depth += 1;
+        parent = p.read().unwrap().parent.as_ref().and_then(Weak::upgrade);
+    }
+    depth
+}
+
+/// How many levels of directories there are below `dir`.
+fn height(dir: &Arc<RwLock<DirInode>>) -> usize {
+    let mut height = 0;
+    let mut stack = vec![(dir.clone(), 0)];
+    while let Some((dir, level)) = stack.pop() {
+        height = height.max(level);
+        for inode in dir.read().unwrap().contents.values() {
+            if let Inode::Dir(d) = inode {
+                stack.push((d.clone(), level + 1));
+            }
+        }
+    }
+    height
+}
+
+/// Write-lock two distinct directories. The locks are always taken in the same
+/// order, so two renames going in opposite directions can't deadlock.
+fn lock_pair<'a>(
+    a: &'a Arc<RwLock<DirInode>>,
+    b: &'a Arc<RwLock<DirInode>>,
+) -> (
+    RwLockWriteGuard<'a, DirInode>,
+    RwLockWriteGuard<'a, DirInode>,
+) {
+    if Arc::as_ptr(a) < Arc::as_ptr(b) {
+        let a = a.write().unwrap();
+        let b = b.write().unwrap();
+        (a, b)
+    } else {
+        let b = b.write().unwrap();
+        let a = a.write().unwrap();
+        (a, b)
+    }
+}
+
+/// A directory opened through `open_file`, either with `OFlags::DIRECTORY`
+/// or as a plain read-only open, as the host backend allows. It can be stat'ed and have its
+/// times set, but has no contents to read or write.
+pub(crate) struct DirFile {
+    fs: Arc<Filesystem>,
+    inode: Arc<RwLock<DirInode>>,
+    fdflags: FdFlags,
+}
+
+impl DirFile {
+    pub(crate) fn new(fs: Arc<Filesystem>, inode: Arc<RwLock<DirInode>>, fdflags: FdFlags) -> Self {
+        DirFile { fs, inode, fdflags }
+    }
+}
+
+#[async_trait::async_trait]
+impl WasiFile for DirFile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::Directory)
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        Ok(self.fdflags)
+    }
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        self.fdflags = fdflags;
+        Ok(())
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        Ok(self.inode.read().unwrap().get_filestat(self.fs.device_id()))
+    }
+    async fn set_filestat_size(&self, _size: u64) -> Result<(), Error> {
+        Err(Error::is_dir())
+    }
+    async fn advise(&self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn allocate(&self, _offset: u64, _len: u64) -> Result<(), Error> {
+        Err(Error::is_dir())
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        check_dir_writable(&self.fs, &self.inode)?;
+        self.inode.write().unwrap().set_times(
+            self.fs.truncate_spec(atime),
+            self.fs.truncate_spec(mtime),
+            self.fs.now(),
+        );
+        Ok(())
+    }
+    async fn read_vectored<'a>(&self, _bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        Err(Error::is_dir())
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        _bufs: &mut [IoSliceMut<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::is_dir())
+    }
+    async fn write_vectored<'a>(&self, _bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        Err(Error::is_dir())
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        _bufs: &[IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::is_dir())
+    }
+    async fn seek(&self, _pos: SeekFrom) -> Result<u64, Error> {
+        Err(Error::is_dir())
+    }
+    async fn peek(&self, _buf: &mut [u8]) -> Result<u64, Error> {
+        Err(Error::is_dir())
+    }
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Err(Error::is_dir())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Err(Error::is_dir())
+    }
+}
@@ -0,0 +1,100 @@
+use crate::contents::Contents;
+use crate::file::FileInode;
+use crate::{set_time, InodeSlot};
+use cap_std::time::SystemTime;
+use std::sync::Arc;
+use wasi_common::{
+    file::{FileType, Filestat},
+    Error, SystemTimeSpec,
+};
+
+/// A function producing the contents of a dynamic file each time it's
+/// opened.
+pub type GenerateContents = Box<dyn Fn() -> Result<Vec<u8>, Error> + Send + Sync>;
+
+/// A regular file whose contents are generated by the host each time it's
+/// opened. Each handle reads the snapshot taken when it was opened.
+pub(crate) struct DynamicInode {
+    pub(crate) serial: u64,
+    /// Shared with the copies `Filesystem::fork` makes.
+    generate: Arc<dyn Fn() -> Result<Vec<u8>, Error> + Send + Sync>,
+    /// The size of the contents last generated, reported by `stat`.
+    size: u64,
+    _slot: InodeSlot,
+    atim: SystemTime,
+    mtim: SystemTime,
+    ctim: SystemTime,
+}
+
+impl DynamicInode {
+    pub(crate) fn new(
+        serial: u64,
+        generate: GenerateContents,
+        slot: InodeSlot,
+        now: SystemTime,
+    ) -> Self {
+        DynamicInode {
+            serial,
+            generate: generate.into(),
+            size: 0,
+            _slot: slot,
+            atim: now,
+            mtim: now,
+            ctim: now,
+        }
+    }
+
+    /// Generate fresh contents, as a read-only regular file which isn't
+    /// linked anywhere but shares this inode's serial number.
+    pub(crate) fn snapshot(&mut self, now: SystemTime) -> Result<FileInode, Error> {
+        let data = (self.generate)()?;
+        self.size = data.len() as u64;
+        let mut f = FileInode::new(self.serial, false, None, InodeSlot::default(), now);
+        f.set_contents(Contents::from_shared(data.into()), now)?;
+        f.read_only = true;
+        Ok(f)
+    }
+
+    /// A copy with serial `serial`, as `Filesystem::fork` makes.
+    pub(crate) fn fork(&self, serial: u64, slot: InodeSlot) -> Self {
+        DynamicInode {
+            serial,
+            generate: self.generate.clone(),
+            size: self.size,
+            _slot: slot,
+            atim: self.atim,
+            mtim: self.mtim,
+            ctim: self.ctim,
+        }
+    }
+
+    pub(crate) fn update_ctim(&mut self, now: SystemTime) {
+        self.ctim = now;
+    }
+
+    pub(crate) fn set_times(
+        &mut self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+        now: SystemTime,
+    ) {
+        let atim_set = set_time(&mut self.atim, atime, now);
+        let mtim_set = set_time(&mut self.mtim, mtime, now);
+        if atim_set || mtim_set {
+            self.ctim = now;
+        }
+    }
+
+    pub(crate) fn get_filestat(&self, device_id: u64) -> Filestat {
+        Filestat {
+            device_id,
+            inode: self.serial,
+            filetype: FileType::RegularFile,
+            nlink: 1,
+            size: self.size,
+            atim: Some(self.atim.into_std()),
+            mtim: Some(self.mtim.into_std()),
+            ctim: Some(self.ctim.into_std()),
+        }
+    }
+}
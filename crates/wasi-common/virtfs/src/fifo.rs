@@ -0,0 +1,487 @@
+use crate::file::FileMode;
+use crate::{set_time, Filesystem, InodeSlot};
+use cap_std::time::SystemTime;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::future::Future;
+use std::io::{self, IoSlice, IoSliceMut, Read, SeekFrom, Write};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::task::{Context, Poll, Waker};
+use wasi_common::{
+    file::{Advice, FdFlags, FileCaps, FileType, Filestat, WasiFile},
+    Error, ErrorExt, SystemTimeSpec,
+};
+
+/// The buffer of a FIFO, shared by its inode and every handle on it, the
+/// guest's and the host's.
+///
+/// Nothing here ever waits: the host may fill or drain the FIFO on the same
+/// thread the guest runs on, so a read or write which waited for it would
+/// never finish. Reads and writes which can't go ahead fail with
+/// `WouldBlock` instead, and `Ready` is how a guest waits for them to.
+pub(crate) struct Fifo {
+    state: Mutex<FifoState>,
+    capacity: usize,
+}
+
+struct FifoState {
+    data: VecDeque<u8>,
+    readers: usize,
+    writers: usize,
+    /// The tasks waiting in `Ready`, woken whenever data is read or written,
+    /// or a handle is closed, to look again.
+    waiting: Vec<Waker>,
+}
+
+impl Fifo {
+    fn new(capacity: usize) -> Self {
+        Fifo {
+            state: Mutex::new(FifoState {
+                data: VecDeque::new(),
+                readers: 0,
+                writers: 0,
+                waiting: Vec::new(),
+            }),
+            capacity,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<FifoState> {
+        self.state.lock().unwrap()
+    }
+
+    fn open(&self, read: bool, write: bool) {
+        let mut state = self.state();
+        state.readers += read as usize;
+        state.writers += write as usize;
+    }
+
+    fn close(&self, read: bool, write: bool) {
+        let mut state = self.state();
+        state.readers -= read as usize;
+        state.writers -= write as usize;
+        wake(state);
+    }
+
+    /// Read what's buffered into `bufs`. When nothing is, this is the end
+    /// of the FIFO if nothing has it open for writing, and otherwise fails
+    /// with `WouldBlock`.
+    fn read(&self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        let mut state = self.state();
+        if state.data.is_empty() {
+            if state.writers == 0 {
+                return Ok(0);
+            }
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let mut n = 0;
+        for buf in bufs {
+            let len = buf.len().min(state.data.len());
+            for (b, d) in buf.iter_mut().zip(state.data.drain(..len)) {
+                *b = d;
+            }
+            n += len;
+        }
+        wake(state);
+        Ok(n)
+    }
+
+    /// Write as much of `bufs` as there's room for. When there's none, this
+    /// fails with `WouldBlock`, and writing with nothing left to read fails
+    /// with `BrokenPipe`.
+    fn write(&self, bufs: &[IoSlice]) -> io::Result<usize> {
+        if bufs.iter().all(|b| b.is_empty()) {
+            return Ok(0);
+        }
+        let mut state = self.state();
+        if state.readers == 0 {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        if state.data.len() == self.capacity {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let mut n = 0;
+        for buf in bufs {
+            let len = buf.len().min(self.capacity - state.data.len());
+            state.data.extend(&buf[..len]);
+            n += len;
+        }
+        wake(state);
+        Ok(n)
+    }
+
+    /// Whether a read would go ahead rather than fail with `WouldBlock`:
+    /// there's data, or the FIFO is at its end.
+    fn is_readable(&self, state: &FifoState) -> bool {
+        !state.data.is_empty() || state.writers == 0
+    }
+
+    /// Whether a write would go ahead rather than fail with `WouldBlock`:
+    /// there's room, or nothing is left to read and it fails with
+    /// `BrokenPipe`.
+    fn is_writable(&self, state: &FifoState) -> bool {
+        state.data.len() < self.capacity || state.readers == 0
+    }
+
+    fn len(&self) -> usize {
+        self.state().data.len()
+    }
+}
+
+/// Wake every task waiting in `Ready`, once `state` is unlocked.
+fn wake(mut state: MutexGuard<FifoState>) {
+    let waiting = std::mem::take(&mut state.waiting);
+    drop(state);
+    for waker in waiting {
+        waker.wake();
+    }
+}
+
+/// A future which resolves once `ready` holds of a FIFO, for
+/// `WasiFile::readable` and `writable`.
+struct Ready<'a> {
+    fifo: &'a Fifo,
+    ready: fn(&Fifo, &FifoState) -> bool,
+}
+
+impl Future for Ready<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let mut state = self.fifo.state();
+        if (self.ready)(self.fifo, &state) {
+            return Poll::Ready(());
+        }
+        if !state.waiting.iter().any(|w| w.will_wake(cx.waker())) {
+            state.waiting.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// A FIFO, or named pipe, through which the guest and the host pass a
+/// stream of bytes. See `Dir::create_fifo`.
+pub(crate) struct FifoInode {
+    pub(crate) serial: u64,
+    /// Shared with the copies `Filesystem::fork` makes.
+    fifo: Arc<Fifo>,
+    _slot: InodeSlot,
+    atim: SystemTime,
+    mtim: SystemTime,
+    ctim: SystemTime,
+}
+
+impl FifoInode {
+    /// A FIFO holding up to `capacity` bytes, and the host's ends of it.
+    pub(crate) fn new(
+        serial: u64,
+        capacity: usize,
+        slot: InodeSlot,
+        now: SystemTime,
+    ) -> (Self, FifoReader, FifoWriter) {
+        let fifo = Arc::new(Fifo::new(capacity));
+        let inode = FifoInode {
+            serial,
+            fifo: fifo.clone(),
+            _slot: slot,
+            atim: now,
+            mtim: now,
+            ctim: now,
+        };
+        (inode, FifoReader::new(fifo.clone()), FifoWriter::new(fifo))
+    }
+
+    /// A copy with serial `serial`, as `Filesystem::fork` makes.
+    pub(crate) fn fork(&self, serial: u64, slot: InodeSlot) -> Self {
+        FifoInode {
+            serial,
+            fifo: self.fifo.clone(),
+            _slot: slot,
+            atim: self.atim,
+            mtim: self.mtim,
+            ctim: self.ctim,
+        }
+    }
+
+    pub(crate) fn update_ctim(&mut self, now: SystemTime) {
+        self.ctim = now;
+    }
+
+    pub(crate) fn set_times(
+        &mut self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+        now: SystemTime,
+    ) {
+        let atim_set = set_time(&mut self.atim, atime, now);
+        let mtim_set = set_time(&mut self.mtim, mtime, now);
+        if atim_set || mtim_set {
+            self.ctim = now;
+        }
+    }
+
+    pub(crate) fn get_filestat(&self, device_id: u64) -> Filestat {
+        Filestat {
+            device_id,
+            inode: self.serial,
+            filetype: FileType::Pipe,
+            nlink: 1,
+            size: 0,
+            atim: Some(self.atim.into_std()),
+            mtim: Some(self.mtim.into_std()),
+            ctim: Some(self.ctim.into_std()),
+        }
+    }
+}
+
+/// The host's end of a FIFO for reading what the guest writes. Reads never
+/// block: when the FIFO is empty they fail with `WouldBlock` while anything
+/// has it open for writing, this `FifoWriter` included, and read nothing
+/// once nothing does.
+pub struct FifoReader {
+    fifo: Arc<Fifo>,
+}
+
+impl FifoReader {
+    fn new(fifo: Arc<Fifo>) -> Self {
+        fifo.open(true, false);
+        FifoReader { fifo }
+    }
+
+    /// The number of bytes waiting to be read.
+    pub fn ready_bytes(&self) -> usize {
+        self.fifo.len()
+    }
+}
+
+impl Read for FifoReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fifo.read(&mut [IoSliceMut::new(buf)])
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> io::Result<usize> {
+        self.fifo.read(bufs)
+    }
+}
+
+impl Drop for FifoReader {
+    fn drop(&mut self) {
+        self.fifo.close(true, false);
+    }
+}
+
+/// The host's end of a FIFO for writing what the guest reads. Writes never
+/// block: they write what there's room for, fail with `WouldBlock` when
+/// there's none, and fail with `BrokenPipe` once nothing has the FIFO open
+/// for reading, this `FifoReader` included.
+pub struct FifoWriter {
+    fifo: Arc<Fifo>,
+}
+
+impl FifoWriter {
+    fn new(fifo: Arc<Fifo>) -> Self {
+        fifo.open(false, true);
+        FifoWriter { fifo }
+    }
+}
+
+impl Write for FifoWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.fifo.write(&[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.fifo.write(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for FifoWriter {
+    fn drop(&mut self) {
+        self.fifo.close(false, true);
+    }
+}
+
+/// An open FIFO. Reads take what's buffered and writes add what there's
+/// room for. As the host's end may be on the guest's own thread, neither
+/// ever waits: they fail with EAGAIN when there's no data or no room,
+/// whether or not `FdFlags::NONBLOCK` is set, and the guest waits with
+/// `poll_oneoff`, for which `readable` and `writable` resolve once they
+/// would go ahead. Writing with nothing to read fails with EPIPE. Unlike
+/// POSIX, opening never waits for the other end. It can't be seeked, and
+/// it has no size.
+pub(crate) struct FifoFile {
+    fs: Arc<Filesystem>,
+    inode: Arc<RwLock<FifoInode>>,
+    fifo: Arc<Fifo>,
+    fdflags: FdFlags,
+    mode: FileMode,
+    caps: FileCaps,
+}
+
+impl FifoFile {
+    pub(crate) fn new(
+        fs: Arc<Filesystem>,
+        inode: Arc<RwLock<FifoInode>>,
+        fdflags: FdFlags,
+        mode: FileMode,
+        caps: FileCaps,
+    ) -> Self {
+        let fifo = inode.read().unwrap().fifo.clone();
+        fifo.open(mode.is_read(), mode.is_write());
+        FifoFile {
+            fs,
+            inode,
+            fifo,
+            fdflags,
+            mode,
+            caps,
+        }
+    }
+
+    fn require(&self, caps: FileCaps) -> Result<(), Error> {
+        if self.caps.contains(caps) {
+            Ok(())
+        } else {
+            Err(Error::not_capable().context(format!("{:?}", caps - self.caps)))
+        }
+    }
+}
+
+impl Drop for FifoFile {
+    fn drop(&mut self) {
+        self.fifo.close(self.mode.is_read(), self.mode.is_write());
+    }
+}
+
+fn fifo_error(e: io::Error) -> Error {
+    match e.kind() {
+        io::ErrorKind::WouldBlock => Error::would_block(),
+        io::ErrorKind::BrokenPipe => Error::broken_pipe(),
+        _ => Error::io().context(e.to_string()),
+    }
+}
+
+#[async_trait::async_trait]
+impl WasiFile for FifoFile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::Pipe)
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        Ok(self.fdflags)
+    }
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        if fdflags.intersects(FdFlags::DSYNC | FdFlags::SYNC | FdFlags::RSYNC) {
+            return Err(Error::not_supported().context("cannot set DSYNC, SYNC, or RSYNC flag"));
+        }
+        self.fdflags = fdflags;
+        Ok(())
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        Ok(self.inode.read().unwrap().get_filestat(self.fs.device_id))
+    }
+    async fn set_filestat_size(&self, _size: u64) -> Result<(), Error> {
+        Err(Error::invalid_argument().context("cannot set the size of a FIFO"))
+    }
+    async fn advise(&self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Err(Error::seek_pipe())
+    }
+    async fn allocate(&self, _offset: u64, _len: u64) -> Result<(), Error> {
+        Err(Error::not_supported().context("cannot allocate space in a FIFO"))
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.require(FileCaps::FILESTAT_SET_TIMES)?;
+        self.fs.check_writable()?;
+        self.inode.write().unwrap().set_times(
+            self.fs.truncate_spec(atime),
+            self.fs.truncate_spec(mtime),
+            self.fs.now(),
+        );
+        Ok(())
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        if !self.mode.is_read() {
+            return Err(Error::badf());
+        }
+        let n = self.fifo.read(bufs).map_err(fifo_error)?;
+        Ok(n.try_into()?)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        _bufs: &mut [io::IoSliceMut<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::seek_pipe())
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        if !self.mode.is_write() {
+            return Err(Error::badf());
+        }
+        let n = self.fifo.write(bufs).map_err(fifo_error)?;
+        Ok(n.try_into()?)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        _bufs: &[io::IoSlice<'a>],
+        _offset: u64,
+    ) -> Result<u64, Error> {
+        Err(Error::seek_pipe())
+    }
+    async fn seek(&self, _pos: SeekFrom) -> Result<u64, Error> {
+        Err(Error::seek_pipe())
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        if !self.mode.is_read() {
+            return Err(Error::badf());
+        }
+        let state = self.fifo.state();
+        let n = buf.len().min(state.data.len());
+        for (b, d) in buf.iter_mut().zip(state.data.iter()) {
+            *b = *d;
+        }
+        Ok(n.try_into()?)
+    }
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(self.fifo.len().try_into()?)
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        if !self.mode.is_read() {
+            return Err(Error::badf());
+        }
+        Ready {
+            fifo: &self.fifo,
+            ready: Fifo::is_readable,
+        }
+        .await;
+        Ok(())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        if !self.mode.is_write() {
+            return Err(Error::badf());
+        }
+        Ready {
+            fifo: &self.fifo,
+            ready: Fifo::is_writable,
+        }
+        .await;
+        Ok(())
+    }
+}
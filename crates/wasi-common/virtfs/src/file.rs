@@ -0,0 +1,873 @@
+use crate::contents::Contents;
+use crate::lock::{self, LockKind, Locks};
+use crate::{set_time, AtimeUpdates, Filesystem, InodeSlot, SyncKind};
+use cap_std::time::SystemTime;
+use std::any::Any;
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::convert::{TryFrom, TryInto};
+use std::future::Future;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::task::{Context, Poll};
+use wasi_common::{
+    file::{Advice, FdFlags, FileCaps, FileType, Filestat, WasiFile},
+    Error, ErrorExt, SystemTimeSpec,
+};
+
+/// The bytes of file contents a filesystem holds, counting each file's size
+/// once however many links it has, and the most it may hold. Each of the
+/// filesystem's files holds on to it, to account for itself until it's
+/// dropped.
+pub(crate) struct ByteUsage {
+    used: AtomicU64,
+    /// `u64::MAX` for no limit.
+    limit: AtomicU64,
+    /// How many files there are of each size, leaving out empty ones, for
+    /// the size of the largest.
+    sizes: Mutex<BTreeMap<u64, u64>>,
+}
+
+impl Default for ByteUsage {
+    fn default() -> Self {
+        ByteUsage {
+            used: AtomicU64::new(0),
+            limit: AtomicU64::new(u64::MAX),
+            sizes: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl ByteUsage {
+    pub(crate) fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn limit(&self) -> Option<u64> {
+        match self.limit.load(Ordering::Relaxed) {
+            u64::MAX => None,
+            limit => Some(limit),
+        }
+    }
+
+    pub(crate) fn set_limit(&self, limit: Option<u64>) {
+        self.limit
+            .store(limit.unwrap_or(u64::MAX), Ordering::Relaxed)
+    }
+
+    /// Take `bytes` more, or fail with ENOSPC if that would go over the
+    /// limit.
+    fn reserve(&self, bytes: u64) -> Result<(), Error> {
+        let limit = self.limit.load(Ordering::Relaxed);
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(bytes).filter(|&total| total <= limit)
+            })
+            .map(|_| ())
+            .map_err(|_| Error::no_space().context("over the filesystem's byte limit"))
+    }
+
+    /// Take as many of `bytes` more as the limit allows, returning how
+    /// many that is.
+    fn reserve_up_to(&self, bytes: u64) -> u64 {
+        let limit = self.limit.load(Ordering::Relaxed);
+        let mut granted = 0;
+        let _ = self
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                granted = bytes.min(limit.saturating_sub(used));
+                Some(used + granted)
+            });
+        granted
+    }
+
+    fn release(&self, bytes: u64) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    /// The size of the largest file.
+    pub(crate) fn largest(&self) -> u64 {
+        let sizes = self.sizes.lock().unwrap();
+        sizes.keys().next_back().copied().unwrap_or(0)
+    }
+
+    /// Account for a file's size changing from `from` to `to`, once the
+    /// bytes have been reserved or released.
+    fn resized(&self, from: u64, to: u64) {
+        if from == to {
+            return;
+        }
+        let mut sizes = self.sizes.lock().unwrap();
+        if from > 0 {
+            if let Entry::Occupied(mut count) = sizes.entry(from) {
+                *count.get_mut() -= 1;
+                if *count.get() == 0 {
+                    count.remove();
+                }
+            }
+        }
+        if to > 0 {
+            *sizes.entry(to).or_insert(0) += 1;
+        }
+    }
+}
+
+pub(crate) struct FileInode {
+    pub(crate) serial: u64,
+    pub(crate) nlink: u64,
+    /// How many open file descriptions there are on the file. Handles
+    /// cloned with `File::try_clone` share one.
+    pub(crate) open: u64,
+    /// Opening the file for writing fails with EROFS.
+    pub(crate) read_only: bool,
+    /// How many directories made read-only with `Dir::set_readonly_at` the
+    /// file is in or below, counting each of its links there. Writing to
+    /// it fails with EROFS while there are any.
+    pub(crate) read_only_dirs: u32,
+    contents: Contents,
+    /// Where the file's size is counted, unless it's a snapshot of a
+    /// dynamic file, which isn't.
+    usage: Option<Arc<ByteUsage>>,
+    /// Counts the file against the filesystem's inode limit until it's
+    /// dropped.
+    _slot: InodeSlot,
+    /// Advisory locks held by open handles.
+    locks: Locks,
+    atim: SystemTime,
+    mtim: SystemTime,
+    ctim: SystemTime,
+}
+
+impl FileInode {
+    /// A new, empty file, whose contents are scrubbed from memory as they're
+    /// let go of if `zeroize` is set, and whose size counts towards `usage`.
+    pub(crate) fn new(
+        serial: u64,
+        zeroize: bool,
+        usage: Option<Arc<ByteUsage>>,
+        slot: InodeSlot,
+        now: SystemTime,
+    ) -> Self {
+        let mut contents = Contents::default();
+        contents.set_zeroize(zeroize);
+        FileInode {
+            serial,
+            nlink: 1,
+            open: 0,
+            read_only: false,
+            read_only_dirs: 0,
+            contents,
+            usage,
+            _slot: slot,
+            locks: Locks::default(),
+            atim: now,
+            mtim: now,
+            ctim: now,
+        }
+    }
+
+    /// A copy of the file with serial `serial`, as `Filesystem::fork` makes,
+    /// sharing the contents until either is written to. It has one link,
+    /// and no open handles or locks. Its size counts towards `usage`,
+    /// whatever the limit.
+    pub(crate) fn fork(&self, serial: u64, usage: Arc<ByteUsage>, slot: InodeSlot) -> Self {
+        usage.used.fetch_add(self.size(), Ordering::Relaxed);
+        usage.resized(0, self.size());
+        FileInode {
+            serial,
+            nlink: 1,
+            open: 0,
+            read_only: self.read_only,
+            // Counted as it's linked into the copy.
+            read_only_dirs: 0,
+            contents: self.contents.clone(),
+            usage: Some(usage),
+            _slot: slot,
+            locks: Locks::default(),
+            atim: self.atim,
+            mtim: self.mtim,
+            ctim: self.ctim,
+        }
+    }
+
+    pub(crate) fn update_atim(&mut self, now: SystemTime) {
+        self.atim = now;
+    }
+
+    /// Mark the contents as modified, which is also a change to the inode.
+    fn update_mtim(&mut self, now: SystemTime) {
+        self.mtim = now;
+        self.ctim = now;
+    }
+
+    pub(crate) fn update_ctim(&mut self, now: SystemTime) {
+        self.ctim = now;
+    }
+
+    /// Truncate the contents to `size` bytes, or extend them with a hole
+    /// which reads as zeros. As with `ftruncate`, mtim and ctim are updated
+    /// only if the size changes. Fails with ENOSPC if growing the file would
+    /// go over the filesystem's byte limit.
+    fn set_size(&mut self, size: u64, now: SystemTime) -> Result<(), Error> {
+        if size != self.contents.len() {
+            self.resize(size)?;
+            self.contents.set_len(size);
+            self.update_mtim(now);
+        }
+        Ok(())
+    }
+
+    /// Account for the file's size changing to `size`.
+    fn resize(&self, size: u64) -> Result<(), Error> {
+        let len = self.contents.len();
+        if let Some(usage) = &self.usage {
+            if size > len {
+                usage.reserve(size - len)?;
+            } else {
+                usage.release(len - size);
+            }
+            usage.resized(len, size);
+        }
+        Ok(())
+    }
+
+    /// Fail with EROFS if the file is in a directory made read-only, so
+    /// the guest may not change it.
+    pub(crate) fn check_read_only_dirs(&self) -> Result<(), Error> {
+        if self.read_only_dirs > 0 {
+            return Err(Error::read_only().context("file in a read-only directory"));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn size(&self) -> u64 {
+        self.contents.len()
+    }
+
+    /// The number of bytes stored for this file alone. See
+    /// `File::owned_size`.
+    pub(crate) fn owned(&self) -> u64 {
+        self.contents.owned()
+    }
+
+    /// Whether the file has lost its last name while still open, so that
+    /// it lives on, unreachable, until its last handle is closed.
+    pub(crate) fn is_orphaned(&self) -> bool {
+        self.nlink == 0 && self.open > 0
+    }
+
+    /// Account for a name of the file going away.
+    pub(crate) fn unlink(&mut self, now: SystemTime) {
+        self.nlink -= 1;
+        self.update_ctim(now);
+    }
+
+    /// Read from `offset` into `bufs`. An offset at or past the end reads
+    /// nothing.
+    pub(crate) fn read_at(&self, bufs: &mut [io::IoSliceMut], offset: u64) -> Result<usize, Error> {
+        Ok(self.contents.read_at(bufs, offset)?)
+    }
+
+    /// Write `bufs` at `offset`. Writing past the end leaves a hole, which
+    /// reads as zeros, as writing after an `lseek` past the end does.
+    ///
+    /// The file may not grow past `max_size` bytes. As with `RLIMIT_FSIZE`,
+    /// a write which would cross the limit is cut short at it, and one
+    /// which would start at or beyond it fails with EFBIG before anything
+    /// is allocated. A file which is already larger than `max_size` may be
+    /// rewritten, but not grown. Growing the file past the filesystem's
+    /// byte limit is cut short the same way, failing with ENOSPC if nothing
+    /// would be written.
+    fn write_at(
+        &mut self,
+        bufs: &[io::IoSlice],
+        offset: u64,
+        max_size: u64,
+    ) -> Result<usize, Error> {
+        let len: usize = bufs.iter().map(|b| b.len()).sum();
+        if len == 0 {
+            return Ok(0);
+        }
+        let limit = max_size.max(self.contents.len());
+        if offset >= limit {
+            return Err(Error::file_too_big().context("write past maximum file size"));
+        }
+        let mut end = offset.saturating_add(len as u64).min(limit);
+        let size = self.contents.len();
+        let mut granted = 0;
+        if let (Some(usage), true) = (&self.usage, end > size) {
+            granted = usage.reserve_up_to(end - size);
+            if size + granted <= offset {
+                usage.release(granted);
+                return Err(Error::no_space().context("over the filesystem's byte limit"));
+            }
+            end = size + granted;
+        }
+        let copied = self.copy_at(bufs, offset, end);
+        if let Some(usage) = &self.usage {
+            // A copy which failed part way, such as on reading a lazy
+            // file's source, grew the file by less than was reserved.
+            let grown = self.contents.len() - size;
+            usage.release(granted - grown);
+            usage.resized(size, self.contents.len());
+        }
+        copied?;
+        Ok((end - offset) as usize)
+    }
+
+    /// Copy `bufs` in from `offset`, stopping at `end`.
+    fn copy_at(&mut self, bufs: &[io::IoSlice], offset: u64, end: u64) -> Result<(), Error> {
+        let mut pos = offset;
+        for buf in bufs {
+            let n = (buf.len() as u64).min(end - pos) as usize;
+            if pos == self.contents.len() {
+                self.contents.append(&buf[..n])?;
+            } else {
+                self.contents.write_at(&buf[..n], pos)?;
+            }
+            pos += n as u64;
+        }
+        Ok(())
+    }
+
+    /// A copy of the contents, which shares their data until either is
+    /// written to.
+    pub(crate) fn contents(&self) -> Contents {
+        self.contents.clone()
+    }
+
+    /// Replace the contents with `contents`, as copying another file over
+    /// this one does.
+    /// Fails with ENOSPC if that would grow the file past the filesystem's
+    /// byte limit.
+    pub(crate) fn set_contents(
+        &mut self,
+        mut contents: Contents,
+        now: SystemTime,
+    ) -> Result<(), Error> {
+        self.resize(contents.len())?;
+        contents.set_zeroize(self.contents.zeroize());
+        self.contents = contents;
+        self.update_mtim(now);
+        Ok(())
+    }
+
+    /// Discard the contents, as `open` with `O_TRUNC` does.
+    pub(crate) fn truncate(&mut self, now: SystemTime) {
+        self.resize(0).expect("shrinking can't fail");
+        self.contents.set_len(0);
+        self.mtim = now;
+        self.ctim = now;
+    }
+
+    pub(crate) fn set_times(
+        &mut self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+        now: SystemTime,
+    ) {
+        let atim_set = set_time(&mut self.atim, atime, now);
+        let mtim_set = set_time(&mut self.mtim, mtime, now);
+        if atim_set || mtim_set {
+            self.ctim = now;
+        }
+    }
+
+    /// Set all three timestamps as they were, as restoring a serialized
+    /// filesystem does.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore_times(&mut self, atim: SystemTime, mtim: SystemTime, ctim: SystemTime) {
+        self.atim = atim;
+        self.mtim = mtim;
+        self.ctim = ctim;
+    }
+
+    pub(crate) fn get_filestat(&self, device_id: u64) -> Filestat {
+        Filestat {
+            device_id,
+            inode: self.serial,
+            filetype: FileType::RegularFile,
+            nlink: self.nlink,
+            size: self.contents.len(),
+            atim: Some(self.atim.into_std()),
+            mtim: Some(self.mtim.into_std()),
+            ctim: Some(self.ctim.into_std()),
+        }
+    }
+}
+
+impl Drop for FileInode {
+    fn drop(&mut self) {
+        if let Some(usage) = &self.usage {
+            usage.release(self.contents.len());
+            usage.resized(self.contents.len(), 0);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileMode {
+    /// Neither reads nor writes, for opens which only need to stat the file
+    /// or set its times.
+    None,
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+}
+
+impl FileMode {
+    pub(crate) fn new(read: bool, write: bool) -> Self {
+        if read && write {
+            FileMode::ReadWrite
+        } else if write {
+            FileMode::WriteOnly
+        } else if read {
+            FileMode::ReadOnly
+        } else {
+            FileMode::None
+        }
+    }
+
+    pub(crate) fn is_read(self) -> bool {
+        matches!(self, FileMode::ReadOnly | FileMode::ReadWrite)
+    }
+
+    pub(crate) fn is_write(self) -> bool {
+        matches!(self, FileMode::WriteOnly | FileMode::ReadWrite)
+    }
+}
+
+/// An open regular file. Reads and writes on the contents never block, so
+/// `FdFlags::NONBLOCK` is kept for `get_fdflags` but changes nothing: a read
+/// at the end of the file returns 0 rather than EAGAIN, and the file is
+/// always ready for `poll_oneoff`.
+///
+/// Each handle may hold an advisory lock on the file, which is released
+/// when the handle, and every handle cloned from it, is dropped. See
+/// `File::try_lock`.
+pub struct File {
+    fs: Arc<Filesystem>,
+    description: Arc<Description>,
+    fdflags: FdFlags,
+    mode: FileMode,
+    caps: FileCaps,
+}
+
+/// The state shared by a handle and its clones from `File::try_clone`, as
+/// file descriptors made by `dup` share an open file description.
+struct Description {
+    inode: Arc<RwLock<FileInode>>,
+    position: AtomicU64,
+    /// Identifies the handles sharing the description as the holder of a
+    /// lock.
+    lock_owner: u64,
+}
+
+impl Description {
+    fn new(inode: Arc<RwLock<FileInode>>) -> Arc<Self> {
+        inode.write().unwrap().open += 1;
+        Arc::new(Description {
+            inode,
+            position: AtomicU64::new(0),
+            lock_owner: lock::new_owner(),
+        })
+    }
+}
+
+impl Drop for Description {
+    fn drop(&mut self) {
+        if let Ok(mut inode) = self.inode.write() {
+            inode.open -= 1;
+            inode.locks.unlock(self.lock_owner);
+        }
+    }
+}
+
+impl File {
+    pub(crate) fn new(
+        fs: Arc<Filesystem>,
+        inode: Arc<RwLock<FileInode>>,
+        fdflags: FdFlags,
+        mode: FileMode,
+        caps: FileCaps,
+    ) -> Self {
+        File {
+            fs,
+            description: Description::new(inode),
+            fdflags,
+            mode,
+            caps,
+        }
+    }
+
+    /// Another handle on the file, as `dup` makes. The two share a position,
+    /// so reads and writes through either pick up where the last one left
+    /// off, and share any advisory lock. The new handle may read, write, and
+    /// do whatever else this one may. It starts with this one's fdflags,
+    /// `APPEND` included, but `set_fdflags` on either changes only that
+    /// one.
+    pub fn try_clone(&self) -> Result<File, Error> {
+        Ok(File {
+            fs: self.fs.clone(),
+            description: self.description.clone(),
+            fdflags: self.fdflags,
+            mode: self.mode,
+            caps: self.caps,
+        })
+    }
+
+    /// Another handle on the file, as opening it again would make, but
+    /// with the same access, capabilities, and fdflags as this one. It has
+    /// a position of its own, starting at 0, and no advisory lock. The file
+    /// may have been renamed or unlinked since this handle was opened.
+    pub fn reopen(&self) -> Result<File, Error> {
+        Ok(File::new(
+            self.fs.clone(),
+            self.description.inode.clone(),
+            self.fdflags,
+            self.mode,
+            self.caps,
+        ))
+    }
+
+    fn inode(&self) -> RwLockReadGuard<FileInode> {
+        self.description.inode.read().unwrap()
+    }
+
+    fn inode_mut(&self) -> RwLockWriteGuard<FileInode> {
+        self.description.inode.write().unwrap()
+    }
+
+    fn position(&self) -> &AtomicU64 {
+        &self.description.position
+    }
+
+    fn is_read(&self) -> bool {
+        self.mode.is_read()
+    }
+
+    fn is_write(&self) -> bool {
+        self.mode.is_write()
+    }
+
+    /// The number of bytes of memory held for the contents, which may be
+    /// more than the size of the file.
+    pub fn capacity(&self) -> u64 {
+        self.inode().contents.capacity()
+    }
+
+    /// The number of bytes of the file actually stored, which leaves out
+    /// holes.
+    pub fn materialized_size(&self) -> u64 {
+        self.inode().contents.materialized()
+    }
+
+    /// The number of bytes stored for this file alone. Data still shared
+    /// with a copy made by `Dir::copy_file` is left out, so this is the
+    /// memory which unlinking the file would free.
+    pub fn owned_size(&self) -> u64 {
+        self.inode().contents.owned()
+    }
+
+    /// Take an advisory lock of `kind` on the file, as `flock` does with
+    /// `LOCK_NB`, replacing any lock this handle already holds. Fails with
+    /// EAGAIN if another handle holds a conflicting lock. A handle and its
+    /// clones from `try_clone` hold a lock together. Locks are only
+    /// advisory: they get in the way of nothing but other locks, and any
+    /// handle may lock the file, whatever it was opened for.
+    pub fn try_lock(&self, kind: LockKind) -> Result<(), Error> {
+        if self
+            .inode_mut()
+            .locks
+            .try_lock(self.description.lock_owner, kind)
+        {
+            Ok(())
+        } else {
+            Err(Error::would_block().context("file is locked by another handle"))
+        }
+    }
+
+    /// Take an advisory lock of `kind` on the file, as `flock` does without
+    /// `LOCK_NB`, waiting until no other handle holds a conflicting lock.
+    /// The future is pending while it waits, so it needs an executor which
+    /// can wait.
+    pub fn lock(&self, kind: LockKind) -> impl Future<Output = ()> + '_ {
+        Lock { file: self, kind }
+    }
+
+    /// Release the advisory lock this handle holds, if any.
+    pub fn unlock(&self) {
+        self.inode_mut().locks.unlock(self.description.lock_owner)
+    }
+
+    /// The kind of advisory lock this handle holds, if any.
+    pub fn lock_held(&self) -> Option<LockKind> {
+        self.inode().locks.held(self.description.lock_owner)
+    }
+
+    /// Fail with ENOTCAPABLE unless the file was opened with all of `caps`.
+    fn require(&self, caps: FileCaps) -> Result<(), Error> {
+        if self.caps.contains(caps) {
+            Ok(())
+        } else {
+            Err(Error::not_capable().context(format!("{:?}", caps - self.caps)))
+        }
+    }
+
+    fn is_append(&self) -> bool {
+        self.fdflags.contains(FdFlags::APPEND)
+    }
+
+    /// Update atim after a read of `nbytes`, if the filesystem's
+    /// `AtimeUpdates` policy asks for it. Reads at end of file don't count.
+    fn accessed(&self, nbytes: usize) {
+        if nbytes > 0 && self.fs.atime_updates() == AtimeUpdates::Strict {
+            self.inode_mut().update_atim(self.fs.now());
+        }
+    }
+}
+
+/// The future returned by `File::lock`.
+struct Lock<'a> {
+    file: &'a File,
+    kind: LockKind,
+}
+
+impl Future for Lock<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let mut inode = self.file.inode_mut();
+        if inode
+            .locks
+            .try_lock(self.file.description.lock_owner, self.kind)
+        {
+            Poll::Ready(())
+        } else {
+            inode.locks.wait(cx.waker());
+            Poll::Pending
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl WasiFile for File {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.fs.sync(&self.description.inode, SyncKind::DataSync)
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.fs.sync(&self.description.inode, SyncKind::Sync)
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        Ok(self.fdflags)
+    }
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        // Only APPEND and NONBLOCK can be changed. The access mode isn't an
+        // fdflag, so it can't be changed here at all.
+        if fdflags.intersects(FdFlags::DSYNC | FdFlags::SYNC | FdFlags::RSYNC) {
+            return Err(Error::not_supported().context("cannot set DSYNC, SYNC, or RSYNC flag"));
+        }
+        self.fdflags = fdflags;
+        Ok(())
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        Ok(self.inode().get_filestat(self.fs.device_id))
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.require(FileCaps::FILESTAT_SET_SIZE)?;
+        if !self.is_write() {
+            return Err(Error::badf());
+        }
+        self.fs.check_writable()?;
+        let mut inode = self.inode_mut();
+        inode.check_read_only_dirs()?;
+        if size > inode.contents.len() {
+            self.fs.check_file_size(size)?;
+        }
+        inode.set_size(size, self.fs.now())
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        let end = offset
+            .checked_add(len)
+            .ok_or_else(|| Error::overflow().context("offset + len"))?;
+        match advice {
+            Advice::DontNeed => {
+                // Advice not to need the whole file, where a `len` of 0
+                // means through the end as it does for `posix_fadvise`,
+                // releases any memory held beyond its size.
+                let mut inode = self.inode_mut();
+                if offset == 0 && (len == 0 || end >= inode.contents.len()) {
+                    inode.contents.shrink_to_fit();
+                }
+            }
+            Advice::WillNeed => {
+                let inode = self.inode();
+                let len = if len == 0 { u64::MAX } else { len };
+                inode.contents.will_need(offset, len);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        self.require(FileCaps::ALLOCATE)?;
+        let required = offset
+            .checked_add(len)
+            .ok_or_else(|| Error::overflow().context("offset + len"))?;
+        if !self.is_write() {
+            return Err(Error::badf());
+        }
+        self.fs.check_writable()?;
+        let mut inode = self.inode_mut();
+        inode.check_read_only_dirs()?;
+        if required > inode.contents.len() {
+            self.fs.check_file_size(required)?;
+            inode.set_size(required, self.fs.now())?;
+        }
+        Ok(())
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.require(FileCaps::FILESTAT_SET_TIMES)?;
+        self.fs.check_writable()?;
+        let mut inode = self.inode_mut();
+        inode.check_read_only_dirs()?;
+        inode.set_times(
+            self.fs.truncate_spec(atime),
+            self.fs.truncate_spec(mtime),
+            self.fs.now(),
+        );
+        Ok(())
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [io::IoSliceMut<'a>]) -> Result<u64, Error> {
+        if !self.is_read() {
+            return Err(Error::badf());
+        }
+        let nbytes = {
+            let inode = self.inode();
+            let position = self.position().load(Ordering::Relaxed);
+            let nbytes = inode.read_at(bufs, position)?;
+            self.position()
+                .store(position + nbytes as u64, Ordering::Relaxed);
+            nbytes
+        };
+        self.accessed(nbytes);
+        Ok(nbytes.try_into()?)
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [io::IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.require(FileCaps::READ | FileCaps::SEEK)?;
+        if !self.is_read() {
+            return Err(Error::badf());
+        }
+        let nbytes = self.inode().read_at(bufs, offset)?;
+        self.accessed(nbytes);
+        Ok(nbytes.try_into()?)
+    }
+    async fn write_vectored<'a>(&self, bufs: &[io::IoSlice<'a>]) -> Result<u64, Error> {
+        if !self.is_write() {
+            return Err(Error::badf());
+        }
+        self.fs.check_writable()?;
+        let mut inode = self.inode_mut();
+        inode.check_read_only_dirs()?;
+        // In append mode every write goes to the end of the file, wherever
+        // the position is. Holding the inode lock makes that atomic.
+        let position = if self.is_append() {
+            inode.contents.len()
+        } else {
+            self.position().load(Ordering::Relaxed)
+        };
+        let nbytes = inode.write_at(bufs, position, self.fs.max_file_size())?;
+        // Writing nothing leaves the position alone, even in append mode.
+        if nbytes > 0 {
+            self.position()
+                .store(position + nbytes as u64, Ordering::Relaxed);
+            inode.update_mtim(self.fs.now());
+        }
+        Ok(nbytes.try_into()?)
+    }
+    async fn write_vectored_at<'a>(
+        &self,
+        bufs: &[io::IoSlice<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.require(FileCaps::WRITE | FileCaps::SEEK)?;
+        if !self.is_write() {
+            return Err(Error::badf());
+        }
+        self.fs.check_writable()?;
+        let mut inode = self.inode_mut();
+        inode.check_read_only_dirs()?;
+        let nbytes = inode.write_at(bufs, offset, self.fs.max_file_size())?;
+        if nbytes > 0 {
+            inode.update_mtim(self.fs.now());
+        }
+        Ok(nbytes.try_into()?)
+    }
+    async fn seek(&self, pos: SeekFrom) -> Result<u64, Error> {
+        self.require(match pos {
+            SeekFrom::Current(0) => FileCaps::TELL,
+            _ => FileCaps::SEEK,
+        })?;
+        // Writes in append mode ignore the position, so only the seeks
+        // `ftell` and `fseek(SEEK_END)` use to learn where the end is are
+        // allowed.
+        if self.is_append() {
+            match pos {
+                SeekFrom::Current(0) => return Ok(self.position().load(Ordering::Relaxed)),
+                SeekFrom::End(_) => {}
+                _ => return Err(Error::badf().context("cannot seek in append mode")),
+            }
+        }
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::Current(delta) => (self.position().load(Ordering::Relaxed), delta),
+            SeekFrom::End(delta) => (self.inode().contents.len(), delta),
+        };
+        let position = u64::try_from(i128::from(base) + i128::from(delta)).map_err(|_| {
+            Error::invalid_argument().context("seek to a negative or overflowing position")
+        })?;
+        self.position().store(position, Ordering::Relaxed);
+        Ok(position)
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        if !self.is_read() {
+            return Err(Error::badf());
+        }
+        let nbytes = self.inode().read_at(
+            &mut [io::IoSliceMut::new(buf)],
+            self.position().load(Ordering::Relaxed),
+        )?;
+        self.accessed(nbytes);
+        Ok(nbytes.try_into()?)
+    }
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        if !self.is_read() {
+            return Err(Error::badf());
+        }
+        // The position may be past the end, after a seek or after another
+        // handle truncated the file, and then nothing is ready.
+        let len = self.inode().contents.len();
+        Ok(len.saturating_sub(self.position().load(Ordering::Relaxed)))
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
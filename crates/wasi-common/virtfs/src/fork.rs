@@ -0,0 +1,109 @@
+//! Copying a filesystem's tree into another, as `Filesystem::fork` does.
+
+use crate::file::FileInode;
+use crate::{Filesystem, Inode};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Copy the tree of `from` into `to`, whose root must be empty, giving the
+/// copies fresh serials from 1 up if `fresh_serials` is set, or the same
+/// serials otherwise. Returns the serial `to` should hand out next.
+pub(crate) fn copy_tree(from: &Filesystem, to: &Filesystem, fresh_serials: bool) -> u64 {
+    let mut next_serial = 1;
+    let mut serial = |original: u64| {
+        if fresh_serials {
+            next_serial += 1;
+            next_serial - 1
+        } else {
+            original
+        }
+    };
+    // The copy of each file with more than one link, by its serial in
+    // `from`, for its other links to share.
+    let mut files: HashMap<u64, Arc<RwLock<FileInode>>> = HashMap::new();
+
+    *to.root.write().unwrap() = from.root.read().unwrap().fork(0, None, to.inodes.charge());
+    let mut pending = vec![(from.root.clone(), to.root.clone())];
+    while let Some((original, copy)) = pending.pop() {
+        let entries: Vec<(Arc<str>, Inode)> = original
+            .read()
+            .unwrap()
+            .contents
+            .iter()
+            .map(|(name, inode)| (name.clone(), inode.clone()))
+            .collect();
+        let mut copy_dir = copy.write().unwrap();
+        for (name, inode) in entries {
+            let original_serial = inode.serial();
+            let forked = match &inode {
+                Inode::Dir(d) => {
+                    let d = d.clone();
+                    let forked = d.read().unwrap().fork(
+                        serial(original_serial),
+                        Some(Arc::downgrade(&copy)),
+                        to.inodes.charge(),
+                    );
+                    let forked = Arc::new(RwLock::new(forked));
+                    pending.push((d, forked.clone()));
+                    Inode::Dir(forked)
+                }
+                Inode::File(f) => {
+                    let f = f.read().unwrap();
+                    match files.get(&original_serial) {
+                        Some(forked) => {
+                            forked.write().unwrap().nlink += 1;
+                            Inode::File(forked.clone())
+                        }
+                        None => {
+                            let forked = Arc::new(RwLock::new(f.fork(
+                                serial(original_serial),
+                                to.usage.clone(),
+                                to.inodes.charge(),
+                            )));
+                            if f.nlink > 1 {
+                                files.insert(original_serial, forked.clone());
+                            }
+                            Inode::File(forked)
+                        }
+                    }
+                }
+                Inode::Dynamic(d) => {
+                    let forked = d
+                        .read()
+                        .unwrap()
+                        .fork(serial(original_serial), to.inodes.charge());
+                    Inode::Dynamic(Arc::new(RwLock::new(forked)))
+                }
+                Inode::Symlink(l) => {
+                    let forked = l
+                        .read()
+                        .unwrap()
+                        .fork(serial(original_serial), to.inodes.charge());
+                    Inode::Symlink(Arc::new(RwLock::new(forked)))
+                }
+                Inode::Device(d) => {
+                    let forked = d
+                        .read()
+                        .unwrap()
+                        .fork(serial(original_serial), to.inodes.charge());
+                    Inode::Device(Arc::new(RwLock::new(forked)))
+                }
+                Inode::Fifo(p) => {
+                    let forked = p
+                        .read()
+                        .unwrap()
+                        .fork(serial(original_serial), to.inodes.charge());
+                    Inode::Fifo(Arc::new(RwLock::new(forked)))
+                }
+                // What's mounted stays with the original.
+                Inode::Mount(_) => continue,
+            };
+            copy_dir.insert_entry(&name, forked);
+        }
+    }
+    if fresh_serials {
+        next_serial
+    } else {
+        from.next_serial()
+    }
+}
@@ -0,0 +1,119 @@
+//! SHA-256 digests of files and trees, for hosts checking what a guest
+//! wrote without reading it back through `WasiFile`.
+//!
+//! A tree is hashed as a pre-order walk, with each directory's entries in
+//! byte order of their names, so the digest doesn't depend on the order
+//! entries were created in. Integers are little-endian, and each entry is
+//! fed to the hash as:
+//!
+//! * its name, as a `u64` length and the name's bytes, except for the root
+//!   of the tree, which has no name;
+//! * a tag byte: `d` for a directory, `f` for a regular file, `l` for a
+//!   symlink, `c` for a character device;
+//! * its mtim, as an `i128` of nanoseconds since the Unix epoch, only when
+//!   timestamps are included;
+//! * for a directory, a `u64` count of its entries, followed by the entries;
+//! * for a file, a `u64` size followed by the contents;
+//! * for a symlink, a `u64` length followed by the target;
+//! * for a character device, nothing more.
+
+use crate::file::FileInode;
+use crate::{Filesystem, Inode};
+use sha2::{Digest, Sha256};
+use std::io::IoSliceMut;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use wasi_common::Error;
+
+/// The SHA-256 digest of a file's contents.
+pub(crate) fn file(fs: &Filesystem, file: &Inode) -> Result<[u8; 32], Error> {
+    let mut hasher = Sha256::new();
+    with_contents(fs, file, |f| feed(f, &mut hasher))?;
+    Ok(hasher.finalize().into())
+}
+
+/// The digest of the tree rooted at `root`, with or without timestamps.
+pub(crate) fn tree(fs: &Filesystem, root: Inode, times: bool) -> Result<[u8; 32], Error> {
+    let mut hasher = Sha256::new();
+    // A stack of entries still to hash, rather than a recursion, so a deep
+    // tree can't exhaust the host stack.
+    let mut pending: Vec<(Option<Arc<str>>, Inode)> = vec![(None, root)];
+    while let Some((name, inode)) = pending.pop() {
+        if let Some(name) = name {
+            write_bytes(&mut hasher, name.as_bytes());
+        }
+        hasher.update(match &inode {
+            Inode::Dir(_) => b"d",
+            Inode::File(_) | Inode::Dynamic(_) => b"f",
+            Inode::Symlink(_) => b"l",
+            Inode::Device(_) => b"c",
+            Inode::Fifo(_) => b"p",
+            Inode::Mount(_) => b"m",
+        });
+        if times {
+            let mtim = inode.get_filestat(fs.device_id()).mtim;
+            hasher.update(nanos(mtim.unwrap_or(UNIX_EPOCH)).to_le_bytes());
+        }
+        match &inode {
+            Inode::Dir(d) => {
+                let entries: Vec<(Arc<str>, Inode)> = d
+                    .read()
+                    .unwrap()
+                    .contents
+                    .iter()
+                    .map(|(name, inode)| (name.clone(), inode.clone()))
+                    .collect();
+                hasher.update((entries.len() as u64).to_le_bytes());
+                pending.extend(entries.into_iter().rev().map(|(n, i)| (Some(n), i)));
+            }
+            Inode::File(_) | Inode::Dynamic(_) => with_contents(fs, &inode, |f| {
+                hasher.update(f.size().to_le_bytes());
+                feed(f, &mut hasher)
+            })?,
+            Inode::Symlink(l) => write_bytes(&mut hasher, l.read().unwrap().target.as_bytes()),
+            // Only the type of a device, a FIFO, or a mount point is hashed.
+            Inode::Device(_) | Inode::Fifo(_) | Inode::Mount(_) => {}
+        }
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Call `f` with a regular file, or with freshly generated contents for a
+/// dynamic one.
+pub(crate) fn with_contents<T>(
+    fs: &Filesystem,
+    file: &Inode,
+    f: impl FnOnce(&FileInode) -> Result<T, Error>,
+) -> Result<T, Error> {
+    match file {
+        Inode::File(file) => f(&file.read().unwrap()),
+        Inode::Dynamic(d) => f(&d.write().unwrap().snapshot(fs.now())?),
+        _ => unreachable!("only files have contents"),
+    }
+}
+
+/// Feed a file's contents to `hasher`.
+fn feed(f: &FileInode, hasher: &mut Sha256) -> Result<(), Error> {
+    let mut buf = vec![0; 64 * 1024];
+    let mut offset = 0;
+    loop {
+        let n = f.read_at(&mut [IoSliceMut::new(&mut buf)], offset)?;
+        if n == 0 {
+            return Ok(());
+        }
+        hasher.update(&buf[..n]);
+        offset += n as u64;
+    }
+}
+
+fn write_bytes(hasher: &mut Sha256, bytes: &[u8]) {
+    hasher.update((bytes.len() as u64).to_le_bytes());
+    hasher.update(bytes);
+}
+
+fn nanos(time: SystemTime) -> i128 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_nanos() as i128,
+        Err(e) => -(e.duration().as_nanos() as i128),
+    }
+}
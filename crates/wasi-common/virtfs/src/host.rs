@@ -0,0 +1,240 @@
+//! Populating a tree by copying a host directory through cap-std, as
+//! `Dir::import_host_dir` does.
+
+use crate::archive::set_mtim;
+use crate::contents::Contents;
+use crate::dir::Dir;
+use crate::StaticWrites;
+use cap_std::time::SystemTime;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use wasi_common::{Error, ErrorExt};
+
+/// What `Dir::import_host_dir` does with the symlinks it finds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostSymlinks {
+    /// Create a symlink with the same target.
+    Copy,
+    /// Copy what the symlink points to in its place, as long as that's
+    /// inside the host directory.
+    Follow,
+    /// Leave it out, and report it as skipped.
+    Skip,
+}
+
+/// How `Dir::import_host_dir` copies a host directory. By default, symlinks
+/// are copied, and there's no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HostDirOptions {
+    pub symlinks: HostSymlinks,
+    /// The most bytes of file contents to copy, if any limit. Going over it
+    /// fails the import with ENOSPC.
+    pub max_bytes: Option<u64>,
+}
+
+impl Default for HostDirOptions {
+    fn default() -> Self {
+        HostDirOptions {
+            symlinks: HostSymlinks::Copy,
+            max_bytes: None,
+        }
+    }
+}
+
+/// An entry `Dir::import_host_dir` left out, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedEntry {
+    /// The path of the entry, relative to the host directory.
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// How many symlinked directories may be followed inside one another before
+/// the import fails with ELOOP, as they would in a cycle.
+const MAX_FOLLOWED_DIRS: usize = 8;
+
+/// Copy the contents of `host` into `dir`, returning the entries left out.
+pub(crate) fn import_host_dir(
+    dir: &Dir,
+    host: &cap_std::fs::Dir,
+    options: HostDirOptions,
+) -> Result<Vec<SkippedEntry>, Error> {
+    let mut import = Import {
+        dir,
+        host,
+        options,
+        copied: 0,
+        skipped: Vec::new(),
+        dir_times: Vec::new(),
+    };
+    import.copy_dir(Path::new(""), "", 0)?;
+    // As with archives, directories get their mtims once everything in them
+    // is created.
+    for (path, mtim) in import.dir_times.into_iter().rev() {
+        set_mtim(dir, &path, mtim)?;
+    }
+    Ok(import.skipped)
+}
+
+struct Import<'a> {
+    dir: &'a Dir,
+    /// Everything is opened by its path from here, rather than from the
+    /// directory it's in, so that symlinks can be followed anywhere in it.
+    host: &'a cap_std::fs::Dir,
+    options: HostDirOptions,
+    /// Bytes of file contents copied so far.
+    copied: u64,
+    skipped: Vec<SkippedEntry>,
+    dir_times: Vec<(String, SystemTime)>,
+}
+
+impl Import<'_> {
+    /// Copy the entries of the host directory at `host_path` into the
+    /// directory at `path`. `followed` is the number of symlinked
+    /// directories `host_path` runs through.
+    fn copy_dir(&mut self, host_path: &Path, path: &str, followed: usize) -> Result<(), Error> {
+        let read_dir = if host_path.as_os_str().is_empty() {
+            self.host.entries()
+        } else {
+            self.host.read_dir(host_path)
+        };
+        let mut entries = Vec::new();
+        for entry in read_dir.map_err(host_failed(host_path))? {
+            entries.push(entry.map_err(host_failed(host_path))?);
+        }
+        // In a stable order, so that which entry goes over `max_bytes` is too.
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let file_name = entry.file_name();
+            let entry_path = host_path.join(&file_name);
+            let name = match file_name.to_str() {
+                Some(name) => name,
+                None => {
+                    self.skip(entry_path, "name isn't UTF-8");
+                    continue;
+                }
+            };
+            let path = if path.is_empty() {
+                name.to_owned()
+            } else {
+                format!("{}/{}", path, name)
+            };
+            let mut file_type = entry.file_type().map_err(host_failed(&entry_path))?;
+            let mut followed = followed;
+            if file_type.is_symlink() {
+                match self.options.symlinks {
+                    HostSymlinks::Skip => {
+                        self.skip(entry_path, "symlink");
+                        continue;
+                    }
+                    HostSymlinks::Copy => {
+                        self.copy_symlink(entry_path, &path)?;
+                        continue;
+                    }
+                    HostSymlinks::Follow => match self.host.metadata(&entry_path) {
+                        Ok(metadata) => {
+                            file_type = metadata.file_type();
+                            followed += 1;
+                        }
+                        Err(e) => {
+                            self.skip(entry_path, format!("following symlink: {}", e));
+                            continue;
+                        }
+                    },
+                }
+            }
+
+            if file_type.is_dir() {
+                if followed > MAX_FOLLOWED_DIRS {
+                    return Err(Error::loop_().context(format!(
+                        "following symlinked directories to {:?}",
+                        entry_path
+                    )));
+                }
+                let mtim = modified(self.host.metadata(&entry_path), &entry_path)?;
+                self.dir.create_dir_all(&path)?;
+                self.dir_times.push((path.clone(), mtim));
+                self.copy_dir(&entry_path, &path, followed)?;
+            } else if file_type.is_file() {
+                let file = self
+                    .host
+                    .open(&entry_path)
+                    .map_err(host_failed(&entry_path))?;
+                let mtim = modified(file.metadata(), &entry_path)?;
+                let contents = self.read(file, &entry_path)?;
+                self.dir
+                    .put_file(&path, contents, StaticWrites::CopyOnWrite)
+                    .map_err(|e| e.context(format!("copying {:?} from host", entry_path)))?;
+                set_mtim(self.dir, &path, mtim)?;
+            } else {
+                self.skip(entry_path, "not a regular file, directory, or symlink");
+            }
+        }
+        Ok(())
+    }
+
+    fn copy_symlink(&mut self, entry_path: PathBuf, path: &str) -> Result<(), Error> {
+        // cap-std won't read a link with an absolute target, which would
+        // name something outside the host directory anyway.
+        let target = match self.host.read_link(&entry_path) {
+            Ok(target) => target,
+            Err(e) => {
+                self.skip(entry_path, format!("reading symlink: {}", e));
+                return Ok(());
+            }
+        };
+        let target = match target.to_str() {
+            Some(target) => target.to_owned(),
+            None => {
+                self.skip(entry_path, "symlink target isn't UTF-8");
+                return Ok(());
+            }
+        };
+        let mtim = modified(self.host.symlink_metadata(&entry_path), &entry_path)?;
+        self.dir
+            .put_symlink(&target, path)
+            .map_err(|e| e.context(format!("copying {:?} from host", entry_path)))?;
+        set_mtim(self.dir, path, mtim)
+    }
+
+    /// The contents of `file`, as long as they fit in what's left of
+    /// `max_bytes`.
+    fn read(&mut self, mut file: cap_std::fs::File, entry_path: &Path) -> Result<Contents, Error> {
+        let zeroize = self.dir.fs().zeroizes_contents();
+        let contents = match self.options.max_bytes {
+            None => Contents::read_from(&mut file, zeroize),
+            Some(max) => {
+                // Reading one byte more than is left shows the file is over.
+                let left = max.saturating_sub(self.copied);
+                Contents::read_from(&mut file.take(left.saturating_add(1)), zeroize)
+            }
+        }
+        .map_err(host_failed(entry_path))?;
+        self.copied += contents.len();
+        match self.options.max_bytes {
+            Some(max) if self.copied > max => Err(Error::no_space().context(format!(
+                "copying {:?} from host goes over the limit of {} bytes",
+                entry_path, max
+            ))),
+            _ => Ok(contents),
+        }
+    }
+
+    fn skip(&mut self, path: PathBuf, reason: impl Into<String>) {
+        self.skipped.push(SkippedEntry {
+            path,
+            reason: reason.into(),
+        });
+    }
+}
+
+fn modified(metadata: io::Result<cap_std::fs::Metadata>, path: &Path) -> Result<SystemTime, Error> {
+    metadata
+        .and_then(|metadata| metadata.modified())
+        .map_err(host_failed(path))
+}
+
+fn host_failed(path: &Path) -> impl Fn(io::Error) -> Error + '_ {
+    move |e| Error::io().context(format!("reading {:?} from host: {}", path, e))
+}
@@ -0,0 +1,50 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Mutex;
+
+/// A source for the contents of a file, read on demand rather than loaded up
+/// front. See `Dir::put_lazy_file`.
+pub trait LazyContents: Send + Sync {
+    /// The size of the contents. It's read once, when the file is created,
+    /// and the file's size is independent of the source after that.
+    fn size(&self) -> u64;
+
+    /// Read into `buf` from `offset`, returning the number of bytes read. As
+    /// for `Read::read`, fewer bytes than asked for may be returned, and 0
+    /// only at the end of the contents.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+
+    /// A hint that `len` bytes from `offset` will be read soon, passed on
+    /// from `advise` with `Advice::WillNeed`. Does nothing by default.
+    fn will_need(&self, _offset: u64, _len: u64) {}
+}
+
+/// `LazyContents` read from a `Read + Seek` source such as a host file,
+/// seeking before each read.
+pub struct ReadSeekContents<R> {
+    source: Mutex<R>,
+    size: u64,
+}
+
+impl<R: Read + Seek> ReadSeekContents<R> {
+    /// Contents read from `source`, whose size is found by seeking to its
+    /// end.
+    pub fn new(mut source: R) -> io::Result<Self> {
+        let size = source.seek(SeekFrom::End(0))?;
+        Ok(ReadSeekContents {
+            source: Mutex::new(source),
+            size,
+        })
+    }
+}
+
+impl<R: Read + Seek + Send> LazyContents for ReadSeekContents<R> {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let mut source = self.source.lock().unwrap();
+        source.seek(SeekFrom::Start(offset))?;
+        source.read(buf)
+    }
+}
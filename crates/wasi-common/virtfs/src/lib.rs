@@ -0,0 +1,1163 @@
+//! The `wasi-virtfs` crate provides an in-memory implementation of the
+//! `WasiFile` and `WasiDir` traits from `wasi-common`.
+//!
+//! A `Filesystem` owns a tree of inodes rooted at a single directory. Any
+//! directory in the tree can be handed to a `WasiCtx` as a preopen via
+//! `Filesystem::root`, and guests then operate on the tree through the usual
+//! WASI path and fd operations. Nothing in this crate touches the host
+//! filesystem, except to copy a host directory in when asked to, through
+//! cap-std.
+//!
+//! Inodes are shared between the tree and any open handles via `Arc`, so a
+//! file which is unlinked while a guest still has it open keeps its contents
+//! until the last handle is dropped. Its stat reports no links meanwhile,
+//! and `Filesystem::orphaned_bytes` counts the memory such files hold.
+//!
+//! This implementation is still incomplete: some `WasiDir` operations are not
+//! yet supported, and many error cases do not yet match what a real
+//! filesystem would report.
+
+mod archive;
+mod builder;
+pub mod clock;
+mod contents;
+mod device;
+mod dir;
+mod dynamic;
+mod fifo;
+mod file;
+mod fork;
+mod hash;
+mod host;
+mod lazy;
+mod lock;
+mod mount;
+mod readdir;
+#[cfg(feature = "serde")]
+mod serialize;
+mod symlink;
+mod sync;
+
+pub use archive::{TarOptions, ZipOptions};
+pub use builder::{FileTimes, FilesystemBuilder};
+pub use device::{EchoDevice, NullDevice, OpenDevice, RandomDevice, ZeroDevice};
+pub use dir::{validate_name, validate_oflags, Dir};
+pub use dynamic::GenerateContents;
+pub use fifo::{FifoReader, FifoWriter};
+pub use file::File;
+pub use host::{HostDirOptions, HostSymlinks, SkippedEntry};
+pub use lazy::{LazyContents, ReadSeekContents};
+pub use lock::LockKind;
+#[cfg(feature = "serde")]
+pub use serialize::SerializedFilesystem;
+pub use sync::{SyncEvent, SyncKind};
+
+use cap_std::ambient_authority;
+use cap_std::time::{Duration, SystemTime};
+use device::{DeviceInode, SharedOpenDevice};
+use dir::DirInode;
+use dynamic::DynamicInode;
+use fifo::FifoInode;
+use file::{ByteUsage, FileInode};
+use mount::MountInode;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::{Read, Seek, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use symlink::SymlinkInode;
+use sync::SyncHandler;
+use wasi_common::{
+    clocks::WasiSystemClock,
+    dir::WasiDir,
+    file::{FileType, Filestat},
+    Error, ErrorExt, SystemTimeSpec,
+};
+
+/// The default limit on the length of a single path component, in bytes.
+pub const DEFAULT_NAME_MAX: usize = 255;
+/// The default limit on the length of a whole path, in bytes.
+pub const DEFAULT_PATH_MAX: usize = 4096;
+/// The default limit on the size of a single file, in bytes.
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 1 << 30;
+
+/// Whether reads update a file's atim, like the `strictatime` and `noatime`
+/// mount options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtimeUpdates {
+    /// Every read updates atim. This is the default.
+    Strict,
+    /// Reads never update atim, which saves taking a write lock on the file
+    /// for each read. `set_times` still sets it.
+    Never,
+}
+
+/// A snapshot of how much of its limits a filesystem uses, as
+/// `Filesystem::stats` takes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsStats {
+    /// The limit on the total size of all the files, if any. See
+    /// `Filesystem::set_byte_limit`.
+    pub total_bytes_limit: Option<u64>,
+    /// The total size of all the files, as counted against the byte limit.
+    pub bytes_used: u64,
+    /// The limit on the number of inodes, if any. See
+    /// `Filesystem::set_inode_limit`.
+    pub inode_limit: Option<u64>,
+    /// The number of inodes, the root included.
+    pub inodes_used: u64,
+    /// The bytes held by files unlinked while open. See
+    /// `Filesystem::orphaned_bytes`.
+    pub orphaned_bytes: u64,
+    /// The size of the largest file.
+    pub largest_file: u64,
+}
+
+/// What a guest may do to a file whose data the host holds, created with
+/// `Dir::put_static_file`, `Dir::put_shared_file`, or `Dir::put_lazy_file`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaticWrites {
+    /// Opening the file for writing fails with EROFS.
+    ReadOnly,
+    /// The file may be written like any other. Each chunk of the host's
+    /// data is copied the first time it is written to, and the host's data
+    /// itself is never modified.
+    CopyOnWrite,
+}
+
+pub struct Filesystem {
+    root: Arc<RwLock<DirInode>>,
+    clock: Box<dyn WasiSystemClock>,
+    device_id: u64,
+    next_serial: AtomicU64,
+    name_max: AtomicUsize,
+    path_max: AtomicUsize,
+    max_depth: AtomicUsize,
+    max_file_size: AtomicU64,
+    /// The bytes the files hold, shared with each of them, and the limit
+    /// on them.
+    usage: Arc<ByteUsage>,
+    /// The number of inodes, shared with each of them, and the limit on it.
+    inodes: Arc<InodeUsage>,
+    strip_leading_slash: AtomicBool,
+    normalize_names: AtomicBool,
+    atime_updates: AtomicU8,
+    zeroize_contents: AtomicBool,
+    read_only: AtomicBool,
+    sync_handler: RwLock<Option<SyncHandler>>,
+    /// In nanoseconds.
+    timestamp_granularity: AtomicU64,
+    case_insensitive: bool,
+    /// Serializes renames between directories, so that the check against
+    /// moving a directory into its own subtree can't race with another
+    /// rename.
+    rename_lock: Mutex<()>,
+    /// Files which have lost their last name while open. Entries whose
+    /// last handle has since closed are pruned as the list is used.
+    orphans: Mutex<Vec<Weak<RwLock<FileInode>>>>,
+}
+
+impl Filesystem {
+    pub fn new(clock: Box<dyn WasiSystemClock>, device_id: u64) -> Arc<Filesystem> {
+        Self::with_case_sensitivity(clock, device_id, false)
+    }
+
+    /// Like `new`, using the host's real-time clock, as `WasiCtxBuilder`
+    /// does.
+    pub fn new_with_system_clock(device_id: u64) -> Arc<Filesystem> {
+        Self::new(
+            Box::new(clock::SystemClock::new(ambient_authority())),
+            device_id,
+        )
+    }
+
+    /// Like `new`, but with a `LogicalClock` in place of a real one, so that
+    /// the same sequence of operations always produces the same timestamps.
+    pub fn new_deterministic(device_id: u64) -> Arc<Filesystem> {
+        Self::new(Box::new(clock::LogicalClock::default()), device_id)
+    }
+
+    /// Like `new`, but names are looked up without regard to case, as on
+    /// many Windows and macOS filesystems. Names keep the spelling they were
+    /// created with, and creating `Foo` where `foo` exists fails with EEXIST.
+    /// Renaming `foo` to `Foo` changes only the spelling.
+    pub fn new_case_insensitive(
+        clock: Box<dyn WasiSystemClock>,
+        device_id: u64,
+    ) -> Arc<Filesystem> {
+        Self::with_case_sensitivity(clock, device_id, true)
+    }
+
+    fn with_case_sensitivity(
+        clock: Box<dyn WasiSystemClock>,
+        device_id: u64,
+        case_insensitive: bool,
+    ) -> Arc<Filesystem> {
+        Arc::new(Self::build(clock, device_id, case_insensitive))
+    }
+
+    fn build(clock: Box<dyn WasiSystemClock>, device_id: u64, case_insensitive: bool) -> Self {
+        let now = clock.now(Duration::from_secs(0));
+        let inodes = Arc::new(InodeUsage::default());
+        let root = Arc::new(RwLock::new(DirInode::new(0, None, inodes.charge(), now)));
+        Filesystem {
+            root,
+            clock,
+            device_id,
+            next_serial: AtomicU64::new(1),
+            name_max: AtomicUsize::new(DEFAULT_NAME_MAX),
+            path_max: AtomicUsize::new(DEFAULT_PATH_MAX),
+            max_depth: AtomicUsize::new(usize::MAX),
+            max_file_size: AtomicU64::new(DEFAULT_MAX_FILE_SIZE),
+            usage: Arc::new(ByteUsage::default()),
+            inodes,
+            strip_leading_slash: AtomicBool::new(false),
+            normalize_names: AtomicBool::new(false),
+            atime_updates: AtomicU8::new(AtimeUpdates::Strict as u8),
+            zeroize_contents: AtomicBool::new(false),
+            read_only: AtomicBool::new(false),
+            sync_handler: RwLock::new(None),
+            timestamp_granularity: AtomicU64::new(1),
+            case_insensitive,
+            rename_lock: Mutex::new(()),
+            orphans: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// A builder for a filesystem populated with the directories and files
+    /// given to it. See `FilesystemBuilder`.
+    pub fn builder() -> FilesystemBuilder {
+        FilesystemBuilder::new()
+    }
+
+    /// An independent copy of the filesystem, timestamped by `clock`, with
+    /// the same tree and settings, for resetting a guest's files to a
+    /// template cheaply. Files share their contents with the originals
+    /// until either is written to, so forking costs about as much as the
+    /// number of inodes, whatever their size. Files with several links
+    /// keep them, as one inode. The copies have the same serials as the
+    /// originals, or, if `fresh_serials` is set, serials from 1 up. Handles
+    /// open on the original stay on the original, and files unlinked while
+    /// open aren't copied. Dynamic files, devices, and the sync handler are
+    /// shared with the original.
+    pub fn fork(&self, clock: Box<dyn WasiSystemClock>, fresh_serials: bool) -> Arc<Filesystem> {
+        let fs = Self::build(clock, self.device_id, self.case_insensitive);
+        let copy_usize = |to: &AtomicUsize, from: &AtomicUsize| {
+            to.store(from.load(Ordering::Relaxed), Ordering::Relaxed)
+        };
+        copy_usize(&fs.name_max, &self.name_max);
+        copy_usize(&fs.path_max, &self.path_max);
+        copy_usize(&fs.max_depth, &self.max_depth);
+        let copy_bool = |to: &AtomicBool, from: &AtomicBool| {
+            to.store(from.load(Ordering::Relaxed), Ordering::Relaxed)
+        };
+        copy_bool(&fs.strip_leading_slash, &self.strip_leading_slash);
+        copy_bool(&fs.normalize_names, &self.normalize_names);
+        copy_bool(&fs.zeroize_contents, &self.zeroize_contents);
+        copy_bool(&fs.read_only, &self.read_only);
+        fs.max_file_size.store(
+            self.max_file_size.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        fs.atime_updates.store(
+            self.atime_updates.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        fs.timestamp_granularity.store(
+            self.timestamp_granularity.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        fs.usage.set_limit(self.usage.limit());
+        fs.inodes.set_limit(self.inodes.limit());
+        *fs.sync_handler.write().unwrap() = self.sync_handler.read().unwrap().clone();
+        let next_serial = fork::copy_tree(self, &fs, fresh_serials);
+        fs.next_serial.store(next_serial, Ordering::Relaxed);
+        Arc::new(fs)
+    }
+
+    /// A filesystem as `new` makes, populated from the tar archive read from
+    /// `reader`. See `Dir::import_tar`.
+    pub fn from_tar<R: Read>(
+        reader: R,
+        clock: Box<dyn WasiSystemClock>,
+        device_id: u64,
+    ) -> Result<Arc<Filesystem>, Error> {
+        let fs = Self::new(clock, device_id);
+        fs.import_tar_at(".", reader, TarOptions::default())?;
+        Ok(fs)
+    }
+
+    /// Create the entries in the tar archive read from `reader` in the
+    /// directory at `path`, relative to the root. See `Dir::import_tar`.
+    pub fn import_tar_at<R: Read>(
+        self: &Arc<Self>,
+        path: &str,
+        reader: R,
+        options: TarOptions,
+    ) -> Result<(), Error> {
+        self.root().import_tar(path, reader, options)
+    }
+
+    /// A filesystem as `new` makes, populated from the zip archive read from
+    /// `reader`. See `Dir::import_zip`.
+    pub fn from_zip<R: Read + Seek>(
+        reader: R,
+        clock: Box<dyn WasiSystemClock>,
+        device_id: u64,
+    ) -> Result<Arc<Filesystem>, Error> {
+        let fs = Self::new(clock, device_id);
+        fs.import_zip_at(".", reader, ZipOptions::default())?;
+        Ok(fs)
+    }
+
+    /// Create the entries in the zip archive read from `reader` in the
+    /// directory at `path`, relative to the root. See `Dir::import_zip`.
+    pub fn import_zip_at<R: Read + Seek>(
+        self: &Arc<Self>,
+        path: &str,
+        reader: R,
+        options: ZipOptions,
+    ) -> Result<(), Error> {
+        self.root().import_zip(path, reader, options)
+    }
+
+    /// Capture the tree for serde to serialize: its directories, files,
+    /// and symlinks, with their timestamps and serials, and the next serial
+    /// to hand out. Files with several links are captured once. Dynamic
+    /// files and devices are left out, for the host to put back, as are
+    /// files unlinked while open; settings such as limits aren't captured
+    /// either. Serializing the filesystem itself does the same.
+    #[cfg(feature = "serde")]
+    pub fn to_serialized(&self) -> Result<SerializedFilesystem, Error> {
+        serialize::serialize(self)
+    }
+
+    /// A filesystem as `new` makes, with the tree `data` captured, inodes
+    /// keeping their serials and hard links their shared inode. Fails with
+    /// ENOTSUP if `data` comes from a later version of this crate which
+    /// changed the format, and with EINVAL if it doesn't describe a tree.
+    #[cfg(feature = "serde")]
+    pub fn from_serialized(
+        data: SerializedFilesystem,
+        clock: Box<dyn WasiSystemClock>,
+        device_id: u64,
+    ) -> Result<Arc<Filesystem>, Error> {
+        let fs = Self::new(clock, device_id);
+        serialize::restore(&fs, data)?;
+        Ok(fs)
+    }
+
+    /// Write the whole tree to `writer` as a tar archive. See
+    /// `Dir::export_tar`.
+    pub fn to_tar<W: Write>(self: &Arc<Self>, writer: W) -> Result<(), Error> {
+        self.to_tar_at(".", writer)
+    }
+
+    /// Write the tree at `path`, relative to the root, to `writer` as a tar
+    /// archive. See `Dir::export_tar`.
+    pub fn to_tar_at<W: Write>(self: &Arc<Self>, path: &str, writer: W) -> Result<(), Error> {
+        self.root().export_tar(path, writer)
+    }
+
+    /// A filesystem as `new` makes, populated with a copy of the host
+    /// directory `host`, along with the entries left out of it. See
+    /// `Dir::import_host_dir`.
+    pub fn from_host_dir(
+        host: &cap_std::fs::Dir,
+        options: HostDirOptions,
+        clock: Box<dyn WasiSystemClock>,
+        device_id: u64,
+    ) -> Result<(Arc<Filesystem>, Vec<SkippedEntry>), Error> {
+        let fs = Self::new(clock, device_id);
+        let skipped = fs.import_host_dir_at(".", host, options)?;
+        Ok((fs, skipped))
+    }
+
+    /// Copy the host directory `host` into the directory at `path`,
+    /// relative to the root. See `Dir::import_host_dir`.
+    pub fn import_host_dir_at(
+        self: &Arc<Self>,
+        path: &str,
+        host: &cap_std::fs::Dir,
+        options: HostDirOptions,
+    ) -> Result<Vec<SkippedEntry>, Error> {
+        self.root().import_host_dir(path, host, options)
+    }
+
+    /// A handle to the root directory of the filesystem, suitable for use as
+    /// a preopen.
+    pub fn root(self: &Arc<Self>) -> Dir {
+        Dir::new(self.clone(), self.root.clone())
+    }
+
+    /// Atomically swap the entries at `path_a` and `path_b`, both relative
+    /// to the root. See `Dir::exchange`.
+    pub fn exchange(self: &Arc<Self>, path_a: &str, path_b: &str) -> Result<(), Error> {
+        let root = self.root();
+        root.exchange(path_a, &root, path_b)
+    }
+
+    /// Copy the file at `from` to `to`, both relative to the root, sharing
+    /// its data until either is written to. See `Dir::copy_file`.
+    pub fn copy_file(self: &Arc<Self>, from: &str, to: &str) -> Result<(), Error> {
+        let root = self.root();
+        root.copy_file(from, &root, to)
+    }
+
+    /// Create the directory at `path`, relative to the root, and any missing
+    /// directories above it. See `Dir::create_dir_all`.
+    pub fn create_dir_all(self: &Arc<Self>, path: &str) -> Result<(), Error> {
+        self.root().create_dir_all(path)
+    }
+
+    /// Create a FIFO at `path`, relative to the root, holding up to
+    /// `capacity` bytes, and return the host's ends of it. See
+    /// `Dir::create_fifo`.
+    pub fn create_fifo(
+        self: &Arc<Self>,
+        path: &str,
+        capacity: usize,
+    ) -> Result<(FifoReader, FifoWriter), Error> {
+        self.root().create_fifo(path, capacity)
+    }
+
+    /// Mount `dir` at `path`, relative to the root, as in
+    /// `fs.mount("mnt/data", Box::new(host_dir))`. See `Dir::mount`.
+    pub fn mount(self: &Arc<Self>, path: &str, dir: Box<dyn WasiDir>) -> Result<(), Error> {
+        self.root().mount(path, dir)
+    }
+
+    /// Remove the mount point at `path`, relative to the root, and return
+    /// the directory that was mounted there. See `Dir::unmount`.
+    pub fn unmount(self: &Arc<Self>, path: &str) -> Result<Box<dyn WasiDir>, Error> {
+        self.root().unmount(path)
+    }
+
+    /// Create a `NullDevice` at `path`, relative to the root, and any
+    /// missing directories above it, as in `fs.put_null_device("dev/null")`.
+    /// See `Dir::put_null_device`.
+    pub fn put_null_device(self: &Arc<Self>, path: &str) -> Result<(), Error> {
+        self.root().put_null_device(path)
+    }
+
+    /// Create a `ZeroDevice` at `path`, relative to the root. See
+    /// `Dir::put_zero_device`.
+    pub fn put_zero_device(self: &Arc<Self>, path: &str) -> Result<(), Error> {
+        self.root().put_zero_device(path)
+    }
+
+    /// Create a `RandomDevice` at `path`, relative to the root, reading from
+    /// the host's random number generator. See `Dir::put_random_device`.
+    pub fn put_random_device(self: &Arc<Self>, path: &str) -> Result<(), Error> {
+        self.root().put_random_device(path)
+    }
+
+    /// Remove the directory at `path`, relative to the root, and everything
+    /// in it, returning how many entries were removed. See
+    /// `Dir::remove_dir_all`.
+    pub fn remove_dir_all(self: &Arc<Self>, path: &str) -> Result<u64, Error> {
+        self.root().remove_dir_all(path)
+    }
+
+    /// The SHA-256 digest of the contents of the file at `path`, relative
+    /// to the root. See `Dir::hash_file`.
+    pub fn hash_file(self: &Arc<Self>, path: &str) -> Result<[u8; 32], Error> {
+        self.root().hash_file(path)
+    }
+
+    /// A digest of the tree at `path`, relative to the root, leaving out
+    /// timestamps. See `Dir::hash_tree`.
+    pub fn hash_tree(self: &Arc<Self>, path: &str) -> Result<[u8; 32], Error> {
+        self.root().hash_tree(path, false)
+    }
+
+    /// Resolve `path`, relative to the root, to the absolute path of the
+    /// entry it names. A leading `/` is accepted. See `Dir::canonicalize`.
+    pub fn canonicalize(self: &Arc<Self>, path: &str) -> Result<String, Error> {
+        let path = path.trim_start_matches('/');
+        self.root()
+            .canonicalize(if path.is_empty() { "." } else { path })
+    }
+
+    /// The number of bytes held by files which have been unlinked but are
+    /// still open somewhere, as in the tmpfile pattern of creating a file,
+    /// opening it, and unlinking it straight away. Their contents are freed
+    /// when their last handle is closed. As with `File::owned_size`, data
+    /// still shared with a copy is left out.
+    pub fn orphaned_bytes(&self) -> u64 {
+        let mut orphans = self.orphans.lock().unwrap();
+        orphans.retain(|f| f.strong_count() > 0);
+        orphans
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|f| {
+                let f = f.read().unwrap();
+                if f.is_orphaned() {
+                    f.owned()
+                } else {
+                    0
+                }
+            })
+            .sum()
+    }
+
+    /// Account for a name of `file` going away. If that was its last name
+    /// and it's still open, it's kept track of as an orphan.
+    pub(crate) fn unlinked(&self, file: &Arc<RwLock<FileInode>>, now: SystemTime) {
+        let orphaned = {
+            let mut f = file.write().unwrap();
+            f.unlink(now);
+            f.is_orphaned()
+        };
+        // Not under the file's lock, which `orphaned_bytes` takes after
+        // this one.
+        if orphaned {
+            let mut orphans = self.orphans.lock().unwrap();
+            orphans.retain(|f| f.strong_count() > 0);
+            orphans.push(Arc::downgrade(file));
+        }
+    }
+
+    /// The number of inodes in the tree, the root included, plus any
+    /// unlinked files still open. A file with several hard links counts
+    /// once.
+    pub fn inode_count(&self) -> u64 {
+        let mut serials = HashSet::new();
+        let mut stack = vec![self.root.clone()];
+        while let Some(dir) = stack.pop() {
+            let dir = dir.read().unwrap();
+            serials.insert(dir.serial);
+            for inode in dir.contents.values() {
+                match inode {
+                    Inode::Dir(d) => stack.push(d.clone()),
+                    inode => {
+                        serials.insert(inode.serial());
+                    }
+                }
+            }
+        }
+        let orphans = self.orphans.lock().unwrap();
+        let orphans = orphans
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|f| f.read().unwrap().is_orphaned())
+            .count();
+        serials.len() as u64 + orphans as u64
+    }
+
+    /// The serial number the next inode created will get. Serials count up
+    /// from 1, the root being 0.
+    pub fn next_serial(&self) -> u64 {
+        self.next_serial.load(Ordering::Relaxed)
+    }
+
+    /// Skip ahead so the next inode created gets `serial`, as when the tree
+    /// is to use serials clear of another's. Serials are never reused, so
+    /// this fails with EINVAL if `serial` is behind `next_serial`. With
+    /// `u64::MAX`, no more inodes can be created.
+    pub fn set_next_serial(&self, serial: u64) -> Result<(), Error> {
+        self.next_serial
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                if serial >= n {
+                    Some(serial)
+                } else {
+                    None
+                }
+            })
+            .map(|_| ())
+            .map_err(|_| Error::invalid_argument().context("serial numbers can't go backwards"))
+    }
+
+    pub fn device_id(&self) -> u64 {
+        self.device_id
+    }
+
+    /// The longest path component, in bytes, that operations on this
+    /// filesystem accept. Defaults to `DEFAULT_NAME_MAX`.
+    pub fn name_max(&self) -> usize {
+        self.name_max.load(Ordering::Relaxed)
+    }
+
+    pub fn set_name_max(&self, bytes: usize) {
+        self.name_max.store(bytes, Ordering::Relaxed)
+    }
+
+    /// The longest path, in bytes, that operations on this filesystem
+    /// accept. Defaults to `DEFAULT_PATH_MAX`.
+    pub fn path_max(&self) -> usize {
+        self.path_max.load(Ordering::Relaxed)
+    }
+
+    pub fn set_path_max(&self, bytes: usize) {
+        self.path_max.store(bytes, Ordering::Relaxed)
+    }
+
+    /// Whether a leading `/`, in a path or a symlink target, is ignored
+    /// rather than rejected. See `set_strip_leading_slash`.
+    pub fn strips_leading_slash(&self) -> bool {
+        self.strip_leading_slash.load(Ordering::Relaxed)
+    }
+
+    /// By default, as with the cap-std backend, absolute paths fail with
+    /// EPERM. With this enabled, a leading `/` is stripped instead, so that
+    /// `/tmp/out.txt` means `tmp/out.txt` relative to whichever directory
+    /// handle the lookup started from.
+    pub fn set_strip_leading_slash(&self, strip: bool) {
+        self.strip_leading_slash.store(strip, Ordering::Relaxed)
+    }
+
+    /// Whether names are normalized to Unicode NFC. See
+    /// `set_normalize_names`.
+    pub fn normalizes_names(&self) -> bool {
+        self.normalize_names.load(Ordering::Relaxed)
+    }
+
+    /// By default names are compared byte for byte, so `café` spelled with a
+    /// precomposed `é` and `café` spelled with `e` and a combining accent are
+    /// different entries. With this enabled, as on macOS, names are
+    /// normalized to NFC when entries are created and when they are looked
+    /// up: either spelling finds the same entry, `readdir` reports the NFC
+    /// form, and creating one spelling where the other exists fails with
+    /// EEXIST. Enable it before populating the tree; names created earlier
+    /// are left as they were.
+    pub fn set_normalize_names(&self, normalize: bool) {
+        self.normalize_names.store(normalize, Ordering::Relaxed)
+    }
+
+    pub fn atime_updates(&self) -> AtimeUpdates {
+        match self.atime_updates.load(Ordering::Relaxed) {
+            x if x == AtimeUpdates::Never as u8 => AtimeUpdates::Never,
+            _ => AtimeUpdates::Strict,
+        }
+    }
+
+    pub fn set_atime_updates(&self, updates: AtimeUpdates) {
+        self.atime_updates.store(updates as u8, Ordering::Relaxed)
+    }
+
+    /// Whether file contents are scrubbed from memory as they're let go of.
+    /// See `set_zeroize_contents`.
+    pub fn zeroizes_contents(&self) -> bool {
+        self.zeroize_contents.load(Ordering::Relaxed)
+    }
+
+    /// By default the memory holding a file's contents is simply freed once
+    /// they're gone. With this enabled, for files holding secrets, it's
+    /// overwritten with zeros first: when a file is unlinked and its last
+    /// handle closed, when it's truncated by `set_filestat_size` or by
+    /// opening it with `O_TRUNC`, and when another file is copied over it.
+    /// Data shared with a copy is scrubbed when the last file holding it
+    /// lets go of it. Data the host holds, as for `Dir::put_static_file`, is
+    /// never touched.
+    ///
+    /// This applies to files created from now on, so enable it before
+    /// populating the tree. Scrubbed files are stored in whole chunks, which
+    /// takes more memory for small files.
+    pub fn set_zeroize_contents(&self, zeroize: bool) {
+        self.zeroize_contents.store(zeroize, Ordering::Relaxed)
+    }
+
+    /// Whether the guest is kept from changing anything. See
+    /// `set_readonly`.
+    pub fn is_readonly(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Make the filesystem read-only to the guest, like a read-only mount,
+    /// whatever rights its preopens grant. Every call which would change
+    /// the tree, or a file's contents or timestamps, fails with EROFS, as
+    /// do writes through handles opened before, and opening a file with
+    /// CREATE or TRUNCATE or for writing. Reads, stats, and `readdir` work
+    /// as before, and devices may still be written to. The host's own
+    /// methods, such as `put_static_file` and `import_tar`, aren't
+    /// affected, so the tree can be populated after this is set.
+    pub fn set_readonly(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Relaxed)
+    }
+
+    /// Make the directory at `path`, relative to the root, and everything
+    /// below it read-only to the guest, or writable again. See
+    /// `Dir::set_readonly_at`.
+    pub fn set_readonly_at(self: &Arc<Self>, path: &str, read_only: bool) -> Result<(), Error> {
+        self.root().set_readonly_at(path, read_only)
+    }
+
+    /// Fail with EROFS if the filesystem is read-only to the guest.
+    pub(crate) fn check_writable(&self) -> Result<(), Error> {
+        if self.is_readonly() {
+            return Err(Error::read_only().context("read-only filesystem"));
+        }
+        Ok(())
+    }
+
+    /// Call `handler` whenever the guest syncs a file with `fd_sync` or
+    /// `fd_datasync`, so that the host can make its contents durable, in
+    /// place of any handler set before. If the handler fails, so does the
+    /// sync, with EIO. Syncs of directories don't call it.
+    pub fn set_sync_handler<F>(&self, handler: F)
+    where
+        F: Fn(&SyncEvent) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        *self.sync_handler.write().unwrap() = Some(Arc::new(handler));
+    }
+
+    /// Stop calling the handler set with `set_sync_handler`, so that syncs
+    /// do nothing, as they do by default.
+    pub fn clear_sync_handler(&self) {
+        *self.sync_handler.write().unwrap() = None;
+    }
+
+    /// Pass a sync of `file` to the sync handler, if there is one.
+    pub(crate) fn sync(&self, file: &Arc<RwLock<FileInode>>, kind: SyncKind) -> Result<(), Error> {
+        // Not held while the handler runs, which may set another.
+        let handler = match &*self.sync_handler.read().unwrap() {
+            Some(handler) => handler.clone(),
+            None => return Ok(()),
+        };
+        let (serial, contents) = {
+            let f = file.read().unwrap();
+            (f.serial, f.contents())
+        };
+        let event = SyncEvent {
+            kind,
+            serial,
+            path: sync::path_of(&self.root, file),
+            contents,
+        };
+        handler(&event).map_err(|e| Error::io().context(format!("sync handler failed: {:#}", e)))
+    }
+
+    /// The precision of stored timestamps. Defaults to 1ns, which keeps
+    /// whatever the clock reports.
+    pub fn timestamp_granularity(&self) -> Duration {
+        Duration::from_nanos(self.timestamp_granularity.load(Ordering::Relaxed))
+    }
+
+    /// Truncate every timestamp stored from now on to a multiple of
+    /// `granularity` since the epoch, like a filesystem with coarse
+    /// timestamps: FAT, for instance, keeps mtim to 2 seconds. This applies
+    /// to times taken from the clock and to absolute times passed to
+    /// `set_times` alike.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `granularity` is zero, or longer than `u64::MAX`
+    /// nanoseconds.
+    pub fn set_timestamp_granularity(&self, granularity: Duration) {
+        let nanos = u64::try_from(granularity.as_nanos()).expect("granularity out of range");
+        assert!(nanos > 0, "granularity must be nonzero");
+        self.timestamp_granularity.store(nanos, Ordering::Relaxed)
+    }
+
+    pub fn is_case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+
+    /// How deep directories may be nested, counting the root as depth 0.
+    /// Unlimited by default.
+    pub fn max_depth(&self) -> usize {
+        self.max_depth.load(Ordering::Relaxed)
+    }
+
+    /// Limit how deep directories may be nested. `create_dir`, and renames
+    /// which would move a directory tree below the limit, fail with ENOSPC.
+    /// Directories which are already deeper are left alone.
+    pub fn set_max_depth(&self, depth: usize) {
+        self.max_depth.store(depth, Ordering::Relaxed)
+    }
+
+    /// The largest size, in bytes, any file may grow to. Defaults to
+    /// `DEFAULT_MAX_FILE_SIZE`.
+    pub fn max_file_size(&self) -> u64 {
+        self.max_file_size.load(Ordering::Relaxed)
+    }
+
+    /// Limit the size of every file, like `RLIMIT_FSIZE`. A write which
+    /// would grow a file past the limit writes what fits and returns a short
+    /// count, and one which starts at the limit fails with EFBIG, as do
+    /// `allocate` and `set_filestat_size`. Nothing is allocated for growth
+    /// which fails. Files which are already larger are left alone, but can't
+    /// grow.
+    pub fn set_max_file_size(&self, bytes: u64) {
+        self.max_file_size.store(bytes, Ordering::Relaxed)
+    }
+
+    /// The limit on the total size of all the files, if any.
+    pub fn byte_limit(&self) -> Option<u64> {
+        self.usage.limit()
+    }
+
+    /// Limit the total size of all the files, counting a file with several
+    /// links once, and one unlinked while open until it's closed. Sizes
+    /// are logical, so holes count. A write which would go over the limit
+    /// writes what fits and returns a short count, and one which fits
+    /// nothing fails with ENOSPC, as do `allocate`, `set_filestat_size`,
+    /// and copies and imports of files, before anything is allocated.
+    /// Lowering the limit below what's used leaves the files alone, but
+    /// none can grow until enough is freed.
+    pub fn set_byte_limit(&self, bytes: Option<u64>) {
+        self.usage.set_limit(bytes)
+    }
+
+    /// The total size of all the files, as counted against the byte limit.
+    pub fn bytes_used(&self) -> u64 {
+        self.usage.used()
+    }
+
+    /// The limit on the number of inodes, if any.
+    pub fn inode_limit(&self) -> Option<u64> {
+        self.inodes.limit()
+    }
+
+    /// Limit the number of inodes, the root included, counting a file with
+    /// several links once, and one unlinked while open until it's closed.
+    /// Creating a file, directory, symlink or device past the limit fails
+    /// with ENOSPC, as do copies and imports which would. Lowering the
+    /// limit below what's used leaves the inodes alone, but no more can be
+    /// created until enough are removed.
+    pub fn set_inode_limit(&self, inodes: Option<u64>) {
+        self.inodes.set_limit(inodes)
+    }
+
+    /// The number of inodes, as counted against the inode limit. The same
+    /// as `inode_count`, without walking the tree.
+    pub fn inodes_used(&self) -> u64 {
+        self.inodes.used()
+    }
+
+    /// How much of its limits the filesystem uses, for monitoring it or
+    /// answering a guest's `statvfs`. Each figure is kept up to date as the
+    /// tree changes, so this doesn't walk it, but the figures are taken one
+    /// after another, so a snapshot taken while the guest is busy may not
+    /// add up exactly.
+    pub fn stats(&self) -> FsStats {
+        FsStats {
+            total_bytes_limit: self.usage.limit(),
+            bytes_used: self.usage.used(),
+            inode_limit: self.inodes.limit(),
+            inodes_used: self.inodes.used(),
+            orphaned_bytes: self.orphaned_bytes(),
+            largest_file: self.usage.largest(),
+        }
+    }
+
+    /// Fail with EFBIG if a file may not grow to `size` bytes.
+    pub(crate) fn check_file_size(&self, size: u64) -> Result<(), Error> {
+        if size > self.max_file_size() {
+            return Err(Error::file_too_big().context("file would exceed maximum file size"));
+        }
+        Ok(())
+    }
+
+    /// Fail with ENAMETOOLONG if `path`, or any component of it, is longer
+    /// than the configured limits.
+    fn check_path_len(&self, path: &str) -> Result<(), Error> {
+        if path.len() > self.path_max() {
+            return Err(Error::name_too_long().context("path exceeds PATH_MAX"));
+        }
+        let name_max = self.name_max();
+        if path.split('/').any(|name| name.len() > name_max) {
+            return Err(Error::name_too_long().context("path component exceeds NAME_MAX"));
+        }
+        Ok(())
+    }
+
+    fn now(&self) -> SystemTime {
+        self.truncate_time(self.clock.now(Duration::from_secs(0)))
+    }
+
+    /// Round `time` down to the configured granularity.
+    fn truncate_time(&self, time: SystemTime) -> SystemTime {
+        let granularity = u128::from(self.timestamp_granularity.load(Ordering::Relaxed));
+        if granularity == 1 {
+            return time;
+        }
+        let epoch = SystemTime::from_std(std::time::UNIX_EPOCH);
+        match time.duration_since(epoch) {
+            Ok(since) => epoch + duration_from_nanos(since.as_nanos() / granularity * granularity),
+            Err(_) => {
+                let before = epoch.duration_since(time).unwrap().as_nanos();
+                epoch
+                    - duration_from_nanos(
+                        before + (granularity - before % granularity) % granularity,
+                    )
+            }
+        }
+    }
+
+    /// Apply the configured granularity to an absolute time from
+    /// `set_times`.
+    fn truncate_spec(&self, spec: Option<SystemTimeSpec>) -> Option<SystemTimeSpec> {
+        match spec {
+            Some(SystemTimeSpec::Absolute(time)) => {
+                Some(SystemTimeSpec::Absolute(self.truncate_time(time)))
+            }
+            spec => spec,
+        }
+    }
+
+    /// A serial number no inode has had before. Serials are never reused,
+    /// so a guest comparing `(dev, ino)` pairs can't mistake a new file for
+    /// one it saw earlier; once they run out, this fails with ENOSPC.
+    fn fresh_serial(&self) -> Result<u64, Error> {
+        self.next_serial
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_add(1))
+            .map_err(|_| Error::no_space().context("inode serial numbers exhausted"))
+    }
+
+    fn new_file(&self) -> Result<Arc<RwLock<FileInode>>, Error> {
+        let slot = self.inodes.take()?;
+        Ok(Arc::new(RwLock::new(FileInode::new(
+            self.fresh_serial()?,
+            self.zeroizes_contents(),
+            Some(self.usage.clone()),
+            slot,
+            self.now(),
+        ))))
+    }
+
+    fn new_dir(&self, parent: &Arc<RwLock<DirInode>>) -> Result<Arc<RwLock<DirInode>>, Error> {
+        let slot = self.inodes.take()?;
+        Ok(Arc::new(RwLock::new(DirInode::new(
+            self.fresh_serial()?,
+            Some(Arc::downgrade(parent)),
+            slot,
+            self.now(),
+        ))))
+    }
+
+    fn new_dynamic(&self, generate: GenerateContents) -> Result<Arc<RwLock<DynamicInode>>, Error> {
+        let slot = self.inodes.take()?;
+        Ok(Arc::new(RwLock::new(DynamicInode::new(
+            self.fresh_serial()?,
+            generate,
+            slot,
+            self.now(),
+        ))))
+    }
+
+    fn new_device(&self, open: SharedOpenDevice) -> Result<Arc<RwLock<DeviceInode>>, Error> {
+        let slot = self.inodes.take()?;
+        Ok(Arc::new(RwLock::new(DeviceInode::new(
+            self.fresh_serial()?,
+            open,
+            slot,
+            self.now(),
+        ))))
+    }
+
+    fn new_fifo(
+        &self,
+        capacity: usize,
+    ) -> Result<(Arc<RwLock<FifoInode>>, FifoReader, FifoWriter), Error> {
+        let slot = self.inodes.take()?;
+        let (inode, reader, writer) =
+            FifoInode::new(self.fresh_serial()?, capacity, slot, self.now());
+        Ok((Arc::new(RwLock::new(inode)), reader, writer))
+    }
+
+    fn new_mount(&self, dir: Box<dyn WasiDir>) -> Result<Arc<MountInode>, Error> {
+        let slot = self.inodes.take()?;
+        Ok(Arc::new(MountInode::new(
+            self.fresh_serial()?,
+            dir,
+            slot,
+            self.now(),
+        )))
+    }
+
+    fn new_symlink(&self, target: String) -> Result<Arc<RwLock<SymlinkInode>>, Error> {
+        let slot = self.inodes.take()?;
+        Ok(Arc::new(RwLock::new(SymlinkInode::new(
+            self.fresh_serial()?,
+            target,
+            slot,
+            self.now(),
+        ))))
+    }
+}
+
+fn duration_from_nanos(nanos: u128) -> Duration {
+    const NANOS_PER_SEC: u128 = 1_000_000_000;
+    Duration::new(
+        (nanos / NANOS_PER_SEC) as u64,
+        (nanos % NANOS_PER_SEC) as u32,
+    )
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Filesystem {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error as _;
+        self.to_serialized()
+            .map_err(S::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+impl Default for Filesystem {
+    /// An empty filesystem using the host's real-time clock, with device id
+    /// 0. See `Filesystem::new_with_system_clock`.
+    fn default() -> Self {
+        let clock = clock::SystemClock::new(ambient_authority());
+        Self::build(Box::new(clock), 0, false)
+    }
+}
+
+/// Apply one half of a `set_times` request to `time`. Returns whether the
+/// timestamp was set.
+fn set_time(time: &mut SystemTime, spec: Option<SystemTimeSpec>, now: SystemTime) -> bool {
+    match spec {
+        Some(SystemTimeSpec::Absolute(t)) => *time = t,
+        Some(SystemTimeSpec::SymbolicNow) => *time = now,
+        None => return false,
+    }
+    true
+}
+
+/// The number of inodes a filesystem has, and the most it may have.
+pub(crate) struct InodeUsage {
+    used: AtomicU64,
+    /// `u64::MAX` for no limit.
+    limit: AtomicU64,
+}
+
+impl Default for InodeUsage {
+    fn default() -> Self {
+        InodeUsage {
+            used: AtomicU64::new(0),
+            limit: AtomicU64::new(u64::MAX),
+        }
+    }
+}
+
+impl InodeUsage {
+    fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    fn limit(&self) -> Option<u64> {
+        match self.limit.load(Ordering::Relaxed) {
+            u64::MAX => None,
+            limit => Some(limit),
+        }
+    }
+
+    fn set_limit(&self, limit: Option<u64>) {
+        self.limit
+            .store(limit.unwrap_or(u64::MAX), Ordering::Relaxed)
+    }
+
+    /// A place for one more inode, or ENOSPC if there are already as many
+    /// as the limit allows.
+    pub(crate) fn take(self: &Arc<Self>) -> Result<InodeSlot, Error> {
+        let limit = self.limit.load(Ordering::Relaxed);
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some(used + 1).filter(|&total| total <= limit)
+            })
+            .map_err(|_| Error::no_space().context("over the filesystem's inode limit"))?;
+        Ok(InodeSlot(Some(self.clone())))
+    }
+
+    /// A place for one more inode, whatever the limit, for the copies
+    /// `Filesystem::fork` makes.
+    pub(crate) fn charge(self: &Arc<Self>) -> InodeSlot {
+        self.used.fetch_add(1, Ordering::Relaxed);
+        InodeSlot(Some(self.clone()))
+    }
+}
+
+/// An inode's place in its filesystem's count of inodes, given back when
+/// the inode is dropped. The default holds no place, for inodes which
+/// aren't counted.
+#[derive(Default)]
+pub(crate) struct InodeSlot(Option<Arc<InodeUsage>>);
+
+impl Drop for InodeSlot {
+    fn drop(&mut self) {
+        if let Some(usage) = &self.0 {
+            usage.used.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// An entry in a directory.
+#[derive(Clone)]
+pub(crate) enum Inode {
+    Dir(Arc<RwLock<DirInode>>),
+    File(Arc<RwLock<FileInode>>),
+    Symlink(Arc<RwLock<SymlinkInode>>),
+    Dynamic(Arc<RwLock<DynamicInode>>),
+    Device(Arc<RwLock<DeviceInode>>),
+    Fifo(Arc<RwLock<FifoInode>>),
+    /// Nothing about a mount point changes once it's made, so it needs no
+    /// lock.
+    Mount(Arc<MountInode>),
+}
+
+impl Inode {
+    fn serial(&self) -> u64 {
+        match self {
+            Inode::Dir(d) => d.read().unwrap().serial,
+            Inode::File(f) => f.read().unwrap().serial,
+            Inode::Symlink(l) => l.read().unwrap().serial,
+            Inode::Dynamic(f) => f.read().unwrap().serial,
+            Inode::Device(c) => c.read().unwrap().serial,
+            Inode::Fifo(p) => p.read().unwrap().serial,
+            Inode::Mount(m) => m.serial,
+        }
+    }
+
+    fn filetype(&self) -> FileType {
+        match self {
+            Inode::Dir(_) | Inode::Mount(_) => FileType::Directory,
+            Inode::File(_) | Inode::Dynamic(_) => FileType::RegularFile,
+            Inode::Symlink(_) => FileType::SymbolicLink,
+            Inode::Device(_) => FileType::CharacterDevice,
+            Inode::Fifo(_) => FileType::Pipe,
+        }
+    }
+
+    fn update_ctim(&self, now: SystemTime) {
+        match self {
+            Inode::Dir(d) => d.write().unwrap().update_ctim(now),
+            Inode::File(f) => f.write().unwrap().update_ctim(now),
+            Inode::Symlink(l) => l.write().unwrap().update_ctim(now),
+            Inode::Dynamic(f) => f.write().unwrap().update_ctim(now),
+            Inode::Device(c) => c.write().unwrap().update_ctim(now),
+            Inode::Fifo(p) => p.write().unwrap().update_ctim(now),
+            // Changes are made to the mounted directory itself.
+            Inode::Mount(_) => {}
+        }
+    }
+
+    fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+        now: SystemTime,
+    ) {
+        match self {
+            Inode::Dir(d) => d.write().unwrap().set_times(atime, mtime, now),
+            Inode::File(f) => f.write().unwrap().set_times(atime, mtime, now),
+            Inode::Symlink(l) => l.write().unwrap().set_times(atime, mtime, now),
+            Inode::Dynamic(f) => f.write().unwrap().set_times(atime, mtime, now),
+            Inode::Device(c) => c.write().unwrap().set_times(atime, mtime, now),
+            Inode::Fifo(p) => p.write().unwrap().set_times(atime, mtime, now),
+            Inode::Mount(_) => {}
+        }
+    }
+
+    fn get_filestat(&self, device_id: u64) -> Filestat {
+        match self {
+            Inode::Dir(d) => d.read().unwrap().get_filestat(device_id),
+            Inode::File(f) => f.read().unwrap().get_filestat(device_id),
+            Inode::Symlink(l) => l.read().unwrap().get_filestat(device_id),
+            Inode::Dynamic(f) => f.read().unwrap().get_filestat(device_id),
+            Inode::Device(c) => c.read().unwrap().get_filestat(device_id),
+            Inode::Fifo(p) => p.read().unwrap().get_filestat(device_id),
+            Inode::Mount(m) => m.get_filestat(device_id),
+        }
+    }
+}
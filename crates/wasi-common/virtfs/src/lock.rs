@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::Waker;
+
+/// The kinds of advisory lock a handle can hold on a file, as for `flock`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    /// Any number of handles may hold a shared lock at once, so long as
+    /// none holds an exclusive lock.
+    Shared,
+    /// Only one handle may hold an exclusive lock, and then no other handle
+    /// may hold any lock.
+    Exclusive,
+}
+
+/// The advisory locks held on one file. Nothing but other attempts to lock
+/// the file pays any attention to them.
+#[derive(Default)]
+pub(crate) struct Locks {
+    /// The kind of lock each holder holds, keyed by `LockOwner`.
+    holders: HashMap<u64, LockKind>,
+    /// Tasks waiting for a lock to be released.
+    waiters: Vec<Waker>,
+}
+
+/// An identity for the holder of a lock, unique for the life of the
+/// process.
+pub(crate) fn new_owner() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Locks {
+    /// Take a lock of `kind` for `owner`, replacing any it already holds, as
+    /// `flock` converts one kind to the other. Fails if another owner holds
+    /// a conflicting lock.
+    pub(crate) fn try_lock(&mut self, owner: u64, kind: LockKind) -> bool {
+        let conflict = self.holders.iter().any(|(o, k)| {
+            *o != owner && (kind == LockKind::Exclusive || *k == LockKind::Exclusive)
+        });
+        if conflict {
+            return false;
+        }
+        if self.holders.insert(owner, kind) == Some(LockKind::Exclusive) {
+            // Downgraded, so waiters for a shared lock may now have it.
+            self.wake();
+        }
+        true
+    }
+
+    /// Release whatever lock `owner` holds.
+    pub(crate) fn unlock(&mut self, owner: u64) {
+        if self.holders.remove(&owner).is_some() {
+            self.wake();
+        }
+    }
+
+    pub(crate) fn held(&self, owner: u64) -> Option<LockKind> {
+        self.holders.get(&owner).copied()
+    }
+
+    /// Have `waker` woken the next time a lock is released.
+    pub(crate) fn wait(&mut self, waker: &Waker) {
+        if !self.waiters.iter().any(|w| w.will_wake(waker)) {
+            self.waiters.push(waker.clone());
+        }
+    }
+
+    fn wake(&mut self) {
+        for waker in self.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+}
@@ -0,0 +1,333 @@
+use crate::InodeSlot;
+use cap_std::time::SystemTime;
+use std::any::Any;
+use std::fmt;
+use std::io::{IoSlice, IoSliceMut, SeekFrom};
+use std::path::PathBuf;
+use std::sync::Arc;
+use wasi_common::{
+    dir::{ReaddirCursor, ReaddirEntity, WasiDir},
+    file::{Advice, FdFlags, FileType, Filestat, OFlags, WasiFile},
+    Error, ErrorExt, SystemTimeSpec,
+};
+
+/// A mount point: a directory of some other `WasiDir` implementation, such
+/// as another virtfs or a host directory, which paths through the mount
+/// point resolve in. See `Dir::mount`.
+///
+/// Every handle opened through the mount point holds on to it, so that
+/// `Dir::unmount` can tell whether any are still open.
+pub(crate) struct MountInode {
+    pub(crate) serial: u64,
+    pub(crate) dir: Box<dyn WasiDir>,
+    _slot: InodeSlot,
+    mounted: SystemTime,
+}
+
+impl MountInode {
+    pub(crate) fn new(
+        serial: u64,
+        dir: Box<dyn WasiDir>,
+        slot: InodeSlot,
+        now: SystemTime,
+    ) -> Self {
+        MountInode {
+            serial,
+            dir,
+            _slot: slot,
+            mounted: now,
+        }
+    }
+
+    /// The stat of the mount point itself, for walks of the tree which
+    /// don't look inside the mount, such as `hash_tree`. Lookups which reach
+    /// the mount point see the mounted directory's instead.
+    pub(crate) fn get_filestat(&self, device_id: u64) -> Filestat {
+        let mounted = Some(self.mounted.into_std());
+        Filestat {
+            device_id,
+            inode: self.serial,
+            filetype: FileType::Directory,
+            nlink: 1,
+            size: 0,
+            atim: mounted,
+            mtim: mounted,
+            ctim: mounted,
+        }
+    }
+}
+
+/// A lookup which reached a mount point with `rest` still to resolve,
+/// carried out of `Dir::resolve` as the context of an EXDEV error. Guest
+/// operations take it back out with `Crossing::of` and carry on in the
+/// mounted directory; anything else, such as the host's `put_*` methods,
+/// fails with EXDEV.
+pub(crate) struct Crossing {
+    pub(crate) mount: Arc<MountInode>,
+    /// The rest of the path, relative to the mounted directory, which is
+    /// `.` for a path ending at the mount point.
+    pub(crate) rest: String,
+}
+
+impl Crossing {
+    pub(crate) fn error(mount: Arc<MountInode>, rest: &str) -> Error {
+        Error::cross_device().context(Crossing {
+            mount,
+            rest: rest.to_owned(),
+        })
+    }
+
+    /// The crossing `e` carries, or `e` itself if it's any other error.
+    pub(crate) fn of(e: Error) -> Result<Crossing, Error> {
+        e.downcast()
+    }
+
+    pub(crate) async fn open_file(
+        self,
+        symlink_follow: bool,
+        oflags: OFlags,
+        read: bool,
+        write: bool,
+        fdflags: FdFlags,
+    ) -> Result<Box<dyn WasiFile>, Error> {
+        let file = self
+            .mount
+            .dir
+            .open_file(symlink_follow, &self.rest, oflags, read, write, fdflags)
+            .await?;
+        Ok(Box::new(MountedFile::new(self.mount, file)))
+    }
+
+    pub(crate) async fn open_dir(self, symlink_follow: bool) -> Result<Box<dyn WasiDir>, Error> {
+        let dir = self.mount.dir.open_dir(symlink_follow, &self.rest).await?;
+        Ok(Box::new(MountedDir {
+            mount: self.mount,
+            dir,
+        }))
+    }
+}
+
+impl fmt::Display for Crossing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "path crosses a mount point")
+    }
+}
+
+impl fmt::Debug for Crossing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Crossing")
+            .field("serial", &self.mount.serial)
+            .field("rest", &self.rest)
+            .finish()
+    }
+}
+
+/// A directory opened through a mount point, which forwards everything to
+/// the mounted directory's own handle.
+struct MountedDir {
+    mount: Arc<MountInode>,
+    dir: Box<dyn WasiDir>,
+}
+
+impl MountedDir {
+    /// The handle within the same mount as this one that `other` wraps, if
+    /// it does; renames and links go no further than the mount.
+    fn same_mount<'a>(&self, other: &'a dyn WasiDir) -> Result<&'a dyn WasiDir, Error> {
+        match other.as_any().downcast_ref::<Self>() {
+            Some(other) if Arc::ptr_eq(&self.mount, &other.mount) => Ok(&*other.dir),
+            _ => Err(Error::cross_device().context("across a mount point")),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl WasiDir for MountedDir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn open_file(
+        &self,
+        symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        read: bool,
+        write: bool,
+        fdflags: FdFlags,
+    ) -> Result<Box<dyn WasiFile>, Error> {
+        let file = self
+            .dir
+            .open_file(symlink_follow, path, oflags, read, write, fdflags)
+            .await?;
+        Ok(Box::new(MountedFile::new(self.mount.clone(), file)))
+    }
+
+    async fn open_dir(&self, symlink_follow: bool, path: &str) -> Result<Box<dyn WasiDir>, Error> {
+        let dir = self.dir.open_dir(symlink_follow, path).await?;
+        Ok(Box::new(MountedDir {
+            mount: self.mount.clone(),
+            dir,
+        }))
+    }
+
+    async fn create_dir(&self, path: &str) -> Result<(), Error> {
+        self.dir.create_dir(path).await
+    }
+
+    async fn readdir(
+        &self,
+        cursor: ReaddirCursor,
+    ) -> Result<Box<dyn Iterator<Item = Result<ReaddirEntity, Error>> + Send>, Error> {
+        self.dir.readdir(cursor).await
+    }
+
+    async fn symlink(&self, src_path: &str, dest_path: &str) -> Result<(), Error> {
+        self.dir.symlink(src_path, dest_path).await
+    }
+
+    async fn remove_dir(&self, path: &str) -> Result<(), Error> {
+        self.dir.remove_dir(path).await
+    }
+
+    async fn unlink_file(&self, path: &str) -> Result<(), Error> {
+        self.dir.unlink_file(path).await
+    }
+
+    async fn read_link(&self, path: &str) -> Result<PathBuf, Error> {
+        self.dir.read_link(path).await
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.dir.get_filestat().await
+    }
+
+    async fn get_path_filestat(
+        &self,
+        path: &str,
+        follow_symlinks: bool,
+    ) -> Result<Filestat, Error> {
+        self.dir.get_path_filestat(path, follow_symlinks).await
+    }
+
+    async fn rename(
+        &self,
+        src_path: &str,
+        dest_dir: &dyn WasiDir,
+        dest_path: &str,
+    ) -> Result<(), Error> {
+        let dest_dir = self.same_mount(dest_dir)?;
+        self.dir.rename(src_path, dest_dir, dest_path).await
+    }
+
+    async fn hard_link(
+        &self,
+        src_path: &str,
+        target_dir: &dyn WasiDir,
+        target_path: &str,
+    ) -> Result<(), Error> {
+        let target_dir = self.same_mount(target_dir)?;
+        self.dir.hard_link(src_path, target_dir, target_path).await
+    }
+
+    async fn set_times(
+        &self,
+        path: &str,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+        follow_symlinks: bool,
+    ) -> Result<(), Error> {
+        self.dir
+            .set_times(path, atime, mtime, follow_symlinks)
+            .await
+    }
+}
+
+/// A file opened through a mount point, which forwards everything to the
+/// mounted directory's own handle.
+struct MountedFile {
+    _mount: Arc<MountInode>,
+    file: Box<dyn WasiFile>,
+}
+
+impl MountedFile {
+    fn new(mount: Arc<MountInode>, file: Box<dyn WasiFile>) -> Self {
+        MountedFile {
+            _mount: mount,
+            file,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl WasiFile for MountedFile {
+    /// The mounted file's own, so that a host backend which downcasts its
+    /// files, such as to poll them, still recognizes it.
+    fn as_any(&self) -> &dyn Any {
+        self.file.as_any()
+    }
+    async fn datasync(&self) -> Result<(), Error> {
+        self.file.datasync().await
+    }
+    async fn sync(&self) -> Result<(), Error> {
+        self.file.sync().await
+    }
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.file.get_filetype().await
+    }
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.file.get_fdflags().await
+    }
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        self.file.set_fdflags(fdflags).await
+    }
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.file.get_filestat().await
+    }
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.file.set_filestat_size(size).await
+    }
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.file.advise(offset, len, advice).await
+    }
+    async fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        self.file.allocate(offset, len).await
+    }
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.file.set_times(atime, mtime).await
+    }
+    async fn read_vectored<'a>(&self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.file.read_vectored(bufs).await
+    }
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.file.read_vectored_at(bufs, offset).await
+    }
+    async fn write_vectored<'a>(&self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        self.file.write_vectored(bufs).await
+    }
+    async fn write_vectored_at<'a>(&self, bufs: &[IoSlice<'a>], offset: u64) -> Result<u64, Error> {
+        self.file.write_vectored_at(bufs, offset).await
+    }
+    async fn seek(&self, pos: SeekFrom) -> Result<u64, Error> {
+        self.file.seek(pos).await
+    }
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.file.peek(buf).await
+    }
+    async fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.file.num_ready_bytes().await
+    }
+    async fn readable(&self) -> Result<(), Error> {
+        self.file.readable().await
+    }
+    async fn writable(&self) -> Result<(), Error> {
+        self.file.writable().await
+    }
+}
@@ -0,0 +1,197 @@
+use crate::dir::DirInode;
+use crate::Inode;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ops::Bound;
+use std::sync::{Arc, RwLock, Weak};
+use wasi_common::{
+    dir::{ReaddirCursor, ReaddirEntity},
+    file::FileType,
+    Error,
+};
+
+/// The cookie which starts a listing at the first entry after `.` and `..`.
+const FIRST_ENTRY: u64 = 2;
+
+/// How many cookies for entries which have since been removed a directory
+/// keeps. A guest resuming from one older than that sees the rest of the
+/// listing as empty.
+const STALE_COOKIES: usize = 1024;
+
+/// The cookies `readdir` has handed out for a directory's entries. A cookie
+/// names the entry it follows rather than a position, so a listing resumed
+/// from it carries on after that name whatever was created or removed in
+/// the meantime, as on Linux: no entry is listed twice, and none which was
+/// there throughout is skipped.
+#[derive(Default)]
+pub(crate) struct Cookies {
+    /// The name each cookie resumes after, in the order they were issued,
+    /// which is also the order of the cookies. A `Vec` rather than a map,
+    /// so issuing one doesn't allocate.
+    names: Vec<(u64, Arc<str>)>,
+    by_name: HashMap<Arc<str>, u64>,
+    /// How many cookies have been issued. The nth is `FIRST_ENTRY + n`, so
+    /// none is 0, 1, or `FIRST_ENTRY`.
+    issued: u64,
+}
+
+impl Cookies {
+    /// The cookie which resumes after `name`, an entry in `contents`. A
+    /// name keeps its cookie for as long as it's in the directory.
+    fn cookie(&mut self, name: &Arc<str>, contents: &BTreeMap<Arc<str>, Inode>) -> u64 {
+        if let Some(cookie) = self.by_name.get(name) {
+            return *cookie;
+        }
+        self.issued += 1;
+        let cookie = FIRST_ENTRY + self.issued;
+        self.names.push((cookie, name.clone()));
+        self.by_name.insert(name.clone(), cookie);
+        if self.names.len() > contents.len() + 2 * STALE_COOKIES {
+            self.forget_stale(contents);
+        }
+        cookie
+    }
+
+    /// Forget the oldest cookies for names no longer in `contents`, keeping
+    /// the newest `STALE_COOKIES`.
+    fn forget_stale(&mut self, contents: &BTreeMap<Arc<str>, Inode>) {
+        let stale = self
+            .names
+            .iter()
+            .filter(|(_, name)| !contents.contains_key(name))
+            .count();
+        let mut forget = stale.saturating_sub(STALE_COOKIES);
+        let by_name = &mut self.by_name;
+        self.names.retain(|(_, name)| {
+            if forget == 0 || contents.contains_key(name) {
+                return true;
+            }
+            forget -= 1;
+            by_name.remove(name);
+            false
+        });
+    }
+
+    /// The name `cookie` resumes after, if it's one still kept.
+    fn name(&self, cookie: u64) -> Option<&Arc<str>> {
+        let i = self.names.binary_search_by_key(&cookie, |(c, _)| *c).ok()?;
+        Some(&self.names[i].1)
+    }
+}
+
+/// Where a listing has got to.
+enum Position {
+    Dot,
+    DotDot,
+    /// Past `.` and `..`, and after the entry named, if any.
+    After(Option<Arc<str>>),
+    End,
+}
+
+/// The listing `readdir` returns: `.` and `..`, then the entries in byte
+/// order of their names, which is the same on every host and every run.
+pub(crate) struct Readdir {
+    dir: Arc<RwLock<DirInode>>,
+    position: Position,
+    /// Entries already read from the directory, up to `position`.
+    pending: VecDeque<ReaddirEntity>,
+}
+
+/// How many entries `Readdir` reads from the directory at a time, so it
+/// takes the lock and finds its place once per batch rather than once per
+/// entry. Changes made to the directory partway through a batch show up
+/// from the next.
+const BATCH: usize = 64;
+
+impl Readdir {
+    /// The listing of `dir` from `cursor`, which is 0 for the start, or a
+    /// cookie `readdir` returned. Any other value, such as a cookie for a
+    /// long-gone entry or one from another directory, lists nothing rather
+    /// than failing. Cookies are never truncated, so none can stand for
+    /// another, or for `.` or `..`, on any host.
+    pub(crate) fn new(dir: Arc<RwLock<DirInode>>, cursor: ReaddirCursor) -> Self {
+        let position = match u64::from(cursor) {
+            0 => Position::Dot,
+            1 => Position::DotDot,
+            FIRST_ENTRY => Position::After(None),
+            cookie => match dir.read().unwrap().cookies.name(cookie) {
+                Some(name) => Position::After(Some(name.clone())),
+                None => Position::End,
+            },
+        };
+        Readdir {
+            dir,
+            position,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Read the next batch of entries after `last` into `pending`.
+    fn fill(&mut self, last: Option<Arc<str>>) {
+        let mut d = self.dir.write().unwrap();
+        let DirInode {
+            contents, cookies, ..
+        } = &mut *d;
+        let after = match &last {
+            Some(last) => Bound::Excluded(&**last),
+            None => Bound::Unbounded,
+        };
+        let mut read = 0;
+        let mut last = None;
+        for (name, inode) in contents
+            .range::<str, _>((after, Bound::Unbounded))
+            .take(BATCH)
+        {
+            self.pending.push_back(ReaddirEntity {
+                next: ReaddirCursor::from(cookies.cookie(name, contents)),
+                inode: inode.serial(),
+                name: name.to_string(),
+                filetype: inode.filetype(),
+            });
+            read += 1;
+            last = Some(name);
+        }
+        // The position shares the name with the directory rather than
+        // copying it out of the entry.
+        self.position = match last {
+            Some(name) if read == BATCH => Position::After(Some(name.clone())),
+            _ => Position::End,
+        };
+    }
+}
+
+impl Iterator for Readdir {
+    type Item = Result<ReaddirEntity, Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(entry) = self.pending.pop_front() {
+            return Some(Ok(entry));
+        }
+        let (inode, name, next) = match std::mem::replace(&mut self.position, Position::End) {
+            Position::Dot => {
+                self.position = Position::DotDot;
+                (self.dir.read().unwrap().serial, ".", 1)
+            }
+            Position::DotDot => {
+                self.position = Position::After(None);
+                // At the root, and in a directory which was removed along
+                // with its parent, `..` is the directory itself.
+                let d = self.dir.read().unwrap();
+                let serial = match d.parent.as_ref().and_then(Weak::upgrade) {
+                    Some(parent) => parent.read().unwrap().serial,
+                    None => d.serial,
+                };
+                (serial, "..", FIRST_ENTRY)
+            }
+            Position::After(last) => {
+                self.fill(last);
+                return self.pending.pop_front().map(Ok);
+            }
+            Position::End => return None,
+        };
+        Some(Ok(ReaddirEntity {
+            next: ReaddirCursor::from(next),
+            inode,
+            name: name.to_owned(),
+            filetype: FileType::Directory,
+        }))
+    }
+}
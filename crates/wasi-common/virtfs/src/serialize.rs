@@ -0,0 +1,272 @@
+//! A self-contained form of a filesystem's tree which serde can serialize,
+//! for hosts keeping a guest's files between runs, and rebuilding a tree
+//! from it. See `Filesystem::to_serialized`.
+//!
+//! The tree is stored as a table of inodes, each with its serial, and each
+//! directory lists its entries by name and the serial of the inode they
+//! name, so that a file with several links is stored once. Each kind of
+//! inode is a variant of `SerializedInode`: kinds added later get variants
+//! of their own, leaving what older snapshots hold readable, and `version`
+//! changes only if the meaning of what's there does.
+
+use crate::contents::Contents;
+use crate::dir::{validate_name, DirInode};
+use crate::file::FileInode;
+use crate::symlink::SymlinkInode;
+use crate::{Filesystem, Inode};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::IoSliceMut;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use wasi_common::{file::Filestat, Error, ErrorExt};
+
+/// The version of the format `Filesystem::to_serialized` writes.
+/// `Filesystem::from_serialized` reads it and every earlier version.
+const FORMAT_VERSION: u32 = 1;
+
+/// A filesystem's tree as `Filesystem::to_serialized` captures it, to
+/// serialize with serde in whatever format the host likes.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SerializedFilesystem {
+    version: u32,
+    next_serial: u64,
+    /// Every inode in the tree, the root first.
+    inodes: Vec<SerializedInode>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+enum SerializedInode {
+    Dir {
+        serial: u64,
+        times: Times,
+        /// Names and the serials of the inodes they name.
+        entries: Vec<(String, u64)>,
+    },
+    File {
+        serial: u64,
+        times: Times,
+        read_only: bool,
+        contents: Vec<u8>,
+    },
+    Symlink {
+        serial: u64,
+        times: Times,
+        target: String,
+    },
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct Times {
+    atim: SystemTime,
+    mtim: SystemTime,
+    ctim: SystemTime,
+}
+
+impl Times {
+    fn atim(&self) -> cap_std::time::SystemTime {
+        cap_std::time::SystemTime::from_std(self.atim)
+    }
+
+    fn mtim(&self) -> cap_std::time::SystemTime {
+        cap_std::time::SystemTime::from_std(self.mtim)
+    }
+
+    fn ctim(&self) -> cap_std::time::SystemTime {
+        cap_std::time::SystemTime::from_std(self.ctim)
+    }
+
+    fn of(stat: &Filestat) -> Self {
+        let time = |t: Option<SystemTime>| t.unwrap_or(std::time::UNIX_EPOCH);
+        Times {
+            atim: time(stat.atim),
+            mtim: time(stat.mtim),
+            ctim: time(stat.ctim),
+        }
+    }
+}
+
+/// Capture the tree of `fs`.
+pub(crate) fn serialize(fs: &Filesystem) -> Result<SerializedFilesystem, Error> {
+    let mut inodes = Vec::new();
+    let mut seen = HashSet::new();
+    let mut pending = vec![Inode::Dir(fs.root.clone())];
+    while let Some(inode) = pending.pop() {
+        let stat = inode.get_filestat(fs.device_id());
+        if !seen.insert(stat.inode) {
+            // Another link to a file already stored.
+            continue;
+        }
+        let times = Times::of(&stat);
+        inodes.push(match &inode {
+            Inode::Dir(d) => {
+                let mut entries = Vec::new();
+                for (name, inode) in d.read().unwrap().contents.iter() {
+                    // Dynamic files, devices, FIFOs, and mount points are the
+                    // host's, to put back itself.
+                    if let Inode::Dynamic(_) | Inode::Device(_) | Inode::Fifo(_) | Inode::Mount(_) =
+                        inode
+                    {
+                        continue;
+                    }
+                    entries.push((name.to_string(), inode.serial()));
+                    pending.push(inode.clone());
+                }
+                SerializedInode::Dir {
+                    serial: stat.inode,
+                    times,
+                    entries,
+                }
+            }
+            Inode::File(f) => {
+                let f = f.read().unwrap();
+                SerializedInode::File {
+                    serial: stat.inode,
+                    times,
+                    read_only: f.read_only,
+                    contents: read_all(&f)?,
+                }
+            }
+            Inode::Symlink(l) => SerializedInode::Symlink {
+                serial: stat.inode,
+                times,
+                target: l.read().unwrap().target.clone(),
+            },
+            Inode::Dynamic(_) | Inode::Device(_) | Inode::Fifo(_) | Inode::Mount(_) => {
+                unreachable!("left out above")
+            }
+        });
+    }
+    Ok(SerializedFilesystem {
+        version: FORMAT_VERSION,
+        next_serial: fs.next_serial(),
+        inodes,
+    })
+}
+
+fn read_all(f: &FileInode) -> Result<Vec<u8>, Error> {
+    let mut contents = vec![0; f.size() as usize];
+    let mut offset = 0;
+    while offset < contents.len() {
+        let n = f.read_at(
+            &mut [IoSliceMut::new(&mut contents[offset..])],
+            offset as u64,
+        )?;
+        if n == 0 {
+            break;
+        }
+        offset += n;
+    }
+    Ok(contents)
+}
+
+/// Rebuild the tree in `data` in `fs`, which must be empty.
+pub(crate) fn restore(fs: &Filesystem, data: SerializedFilesystem) -> Result<(), Error> {
+    if data.version > FORMAT_VERSION {
+        return Err(Error::not_supported().context(format!(
+            "serialized filesystem version {} is newer than {}",
+            data.version, FORMAT_VERSION
+        )));
+    }
+    let mut records = HashMap::new();
+    for record in data.inodes {
+        let serial = record.serial();
+        if serial >= data.next_serial {
+            return Err(invalid(format!(
+                "inode {} is past the next serial, {}",
+                serial, data.next_serial
+            )));
+        }
+        if records.insert(serial, record).is_some() {
+            return Err(invalid(format!("inode {} appears twice", serial)));
+        }
+    }
+    let (root_times, root_entries) = match records.remove(&0) {
+        Some(SerializedInode::Dir { times, entries, .. }) => (times, entries),
+        _ => return Err(invalid("the root isn't a directory".to_owned())),
+    };
+
+    // Inodes are made as they're first reached from the root, so that one
+    // nothing reaches is left out, and a directory reached twice, which
+    // would make the tree a graph, is missing the second time.
+    let mut files: HashMap<u64, Arc<RwLock<FileInode>>> = HashMap::new();
+    let mut pending = vec![(fs.root.clone(), root_times, root_entries)];
+    while let Some((dir, times, entries)) = pending.pop() {
+        let mut d = dir.write().unwrap();
+        for (name, serial) in entries {
+            validate_name(&name)?;
+            let inode = match files.get(&serial) {
+                Some(f) => {
+                    f.write().unwrap().nlink += 1;
+                    Inode::File(f.clone())
+                }
+                None => match records.remove(&serial) {
+                    Some(SerializedInode::Dir { times, entries, .. }) => {
+                        let sub = Arc::new(RwLock::new(DirInode::new(
+                            serial,
+                            Some(Arc::downgrade(&dir)),
+                            fs.inodes.take()?,
+                            fs.now(),
+                        )));
+                        pending.push((sub.clone(), times, entries));
+                        Inode::Dir(sub)
+                    }
+                    Some(SerializedInode::File {
+                        times,
+                        read_only,
+                        contents,
+                        ..
+                    }) => {
+                        let zeroize = fs.zeroizes_contents();
+                        let mut f = FileInode::new(
+                            serial,
+                            zeroize,
+                            Some(fs.usage.clone()),
+                            fs.inodes.take()?,
+                            fs.now(),
+                        );
+                        let contents = Contents::read_from(&mut &contents[..], zeroize)
+                            .map_err(|e| Error::io().context(e.to_string()))?;
+                        f.set_contents(contents, fs.now())?;
+                        f.read_only = read_only;
+                        f.restore_times(times.atim(), times.mtim(), times.ctim());
+                        let f = Arc::new(RwLock::new(f));
+                        files.insert(serial, f.clone());
+                        Inode::File(f)
+                    }
+                    Some(SerializedInode::Symlink { times, target, .. }) => {
+                        let mut l = SymlinkInode::new(serial, target, fs.inodes.take()?, fs.now());
+                        l.restore_times(times.atim(), times.mtim(), times.ctim());
+                        Inode::Symlink(Arc::new(RwLock::new(l)))
+                    }
+                    None => {
+                        return Err(invalid(format!(
+                            "entry {:?} names inode {}, which is missing or a directory \
+                             already in the tree",
+                            name, serial
+                        )))
+                    }
+                },
+            };
+            if d.insert_entry(&name, inode).is_some() {
+                return Err(invalid(format!("entry {:?} appears twice", name)));
+            }
+        }
+        d.restore_times(times.atim(), times.mtim(), times.ctim());
+    }
+    fs.set_next_serial(data.next_serial)
+}
+
+impl SerializedInode {
+    fn serial(&self) -> u64 {
+        match self {
+            SerializedInode::Dir { serial, .. }
+            | SerializedInode::File { serial, .. }
+            | SerializedInode::Symlink { serial, .. } => *serial,
+        }
+    }
+}
+
+fn invalid(what: String) -> Error {
+    Error::invalid_argument().context(format!("bad serialized filesystem: {}", what))
+}
@@ -0,0 +1,79 @@
+use crate::{set_time, InodeSlot};
+use cap_std::time::SystemTime;
+use wasi_common::{
+    file::{FileType, Filestat},
+    SystemTimeSpec,
+};
+
+pub(crate) struct SymlinkInode {
+    pub(crate) serial: u64,
+    pub(crate) target: String,
+    _slot: InodeSlot,
+    atim: SystemTime,
+    mtim: SystemTime,
+    ctim: SystemTime,
+}
+
+impl SymlinkInode {
+    pub(crate) fn new(serial: u64, target: String, slot: InodeSlot, now: SystemTime) -> Self {
+        SymlinkInode {
+            serial,
+            target,
+            _slot: slot,
+            atim: now,
+            mtim: now,
+            ctim: now,
+        }
+    }
+
+    /// A copy with serial `serial`, as `Filesystem::fork` makes.
+    pub(crate) fn fork(&self, serial: u64, slot: InodeSlot) -> Self {
+        SymlinkInode {
+            serial,
+            target: self.target.clone(),
+            _slot: slot,
+            atim: self.atim,
+            mtim: self.mtim,
+            ctim: self.ctim,
+        }
+    }
+
+    pub(crate) fn update_ctim(&mut self, now: SystemTime) {
+        self.ctim = now;
+    }
+
+    pub(crate) fn set_times(
+        &mut self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+        now: SystemTime,
+    ) {
+        let atim_set = set_time(&mut self.atim, atime, now);
+        let mtim_set = set_time(&mut self.mtim, mtime, now);
+        if atim_set || mtim_set {
+            self.ctim = now;
+        }
+    }
+
+    /// Set all three timestamps as they were, as restoring a serialized
+    /// filesystem does.
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore_times(&mut self, atim: SystemTime, mtim: SystemTime, ctim: SystemTime) {
+        self.atim = atim;
+        self.mtim = mtim;
+        self.ctim = ctim;
+    }
+
+    pub(crate) fn get_filestat(&self, device_id: u64) -> Filestat {
+        Filestat {
+            device_id,
+            inode: self.serial,
+            filetype: FileType::SymbolicLink,
+            nlink: 1,
+            size: self.target.len() as u64,
+            atim: Some(self.atim.into_std()),
+            mtim: Some(self.mtim.into_std()),
+            ctim: Some(self.ctim.into_std()),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use crate::contents::Contents;
+use crate::dir::DirInode;
+use crate::file::FileInode;
+use crate::Inode;
+use std::io;
+use std::sync::{Arc, RwLock};
+use wasi_common::Error;
+
+/// A function the host sets with `Filesystem::set_sync_handler`.
+pub(crate) type SyncHandler = Arc<dyn Fn(&SyncEvent) -> Result<(), Error> + Send + Sync>;
+
+/// Which call the guest made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncKind {
+    /// `fd_sync`: the contents and metadata should be made durable.
+    Sync,
+    /// `fd_datasync`: only the contents need be.
+    DataSync,
+}
+
+/// A file the guest synced, as passed to a `SyncHandler`. The contents are
+/// a snapshot taken when the guest made the call, which writes made after
+/// it don't change, so the handler may take its time over them.
+pub struct SyncEvent {
+    pub(crate) kind: SyncKind,
+    pub(crate) serial: u64,
+    pub(crate) path: Option<String>,
+    pub(crate) contents: Contents,
+}
+
+impl SyncEvent {
+    pub fn kind(&self) -> SyncKind {
+        self.kind
+    }
+
+    /// The file's inode number, which stays the same across renames.
+    pub fn serial(&self) -> u64 {
+        self.serial
+    }
+
+    /// An absolute path to the file from the root of the filesystem, or
+    /// `None` if the file has been unlinked. A file with several hard links
+    /// has several paths, and this is the first found walking the tree with
+    /// the entries of each directory in byte order.
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.contents.len()
+    }
+
+    /// Read from `offset` into `buf`, returning the number of bytes read,
+    /// which is less than `buf.len()` only at the end of the file. Only
+    /// reading data the host provides on demand, as for
+    /// `Dir::put_lazy_file`, can fail.
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.contents
+            .read_at(&mut [io::IoSliceMut::new(buf)], offset)
+    }
+
+    /// The whole contents of the file.
+    pub fn to_vec(&self) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; self.size() as usize];
+        self.read_at(&mut data, 0)?;
+        Ok(data)
+    }
+}
+
+/// The first path to `file` found walking the tree under `root`, visiting
+/// entries in byte order, or `None` if no directory links to it.
+pub(crate) fn path_of(
+    root: &Arc<RwLock<DirInode>>,
+    file: &Arc<RwLock<FileInode>>,
+) -> Option<String> {
+    let mut pending = vec![(String::new(), Inode::Dir(root.clone()))];
+    while let Some((path, inode)) = pending.pop() {
+        match inode {
+            Inode::File(f) if Arc::ptr_eq(&f, file) => return Some(path),
+            Inode::Dir(d) => {
+                let d = d.read().unwrap();
+                // Reversed, so they're popped in order.
+                pending.extend(
+                    d.contents
+                        .iter()
+                        .rev()
+                        .map(|(name, inode)| (format!("{}/{}", path, name), inode.clone())),
+                );
+            }
+            _ => {}
+        }
+    }
+    None
+}
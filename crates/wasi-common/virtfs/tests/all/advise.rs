@@ -0,0 +1,94 @@
+use crate::{capacity, create_file, filesystem, run};
+use std::io::IoSlice;
+use wasi_common::{
+    dir::WasiDir,
+    file::{Advice, FdFlags, OFlags, WasiFile},
+    ErrorKind,
+};
+
+const MIB: u64 = 1 << 20;
+const KIB: u64 = 1 << 10;
+// Files are stored in chunks of this size.
+const CHUNK: u64 = 64 * KIB;
+// Ends partway into a chunk.
+const SHRUNK: u64 = 10 * MIB + 40 * KIB;
+
+fn open_shrunk_file(root: &dyn WasiDir) -> Box<dyn WasiFile> {
+    create_file(root, "file", b"");
+    let f = run(root.open_file(false, "file", OFlags::empty(), true, true, FdFlags::empty()))
+        .expect("open file");
+    let data = vec![1; 16 * MIB as usize];
+    run(f.write_vectored(&[IoSlice::new(&data)])).expect("write");
+    // Whole chunks past the new end are freed, but the last chunk is cut by
+    // too little for `set_filestat_size` to release its memory itself.
+    run(f.set_filestat_size(SHRUNK)).expect("shrink");
+    f
+}
+
+#[test]
+fn dont_need_releases_memory() {
+    let fs = filesystem();
+    let root = fs.root();
+    let f = open_shrunk_file(&root);
+    assert_eq!(capacity(&*f), 10 * MIB + CHUNK);
+
+    run(f.advise(0, 0, Advice::DontNeed)).expect("advise");
+    assert_eq!(capacity(&*f), SHRUNK);
+    assert_eq!(run(f.get_filestat()).unwrap().size, SHRUNK);
+}
+
+#[test]
+fn dont_need_with_explicit_length() {
+    let fs = filesystem();
+    let root = fs.root();
+    let f = open_shrunk_file(&root);
+
+    run(f.advise(0, SHRUNK, Advice::DontNeed)).expect("advise");
+    assert_eq!(capacity(&*f), SHRUNK);
+}
+
+#[test]
+fn partial_or_other_advice_keeps_memory() {
+    let fs = filesystem();
+    let root = fs.root();
+    let f = open_shrunk_file(&root);
+
+    run(f.advise(0, MIB, Advice::DontNeed)).expect("advise");
+    run(f.advise(MIB, 0, Advice::DontNeed)).expect("advise");
+    for advice in &[
+        Advice::Normal,
+        Advice::Sequential,
+        Advice::Random,
+        Advice::WillNeed,
+        Advice::NoReuse,
+    ] {
+        run(f.advise(0, 0, advice.clone())).expect("advise");
+    }
+    assert_eq!(capacity(&*f), 10 * MIB + CHUNK);
+}
+
+#[test]
+fn advise_overflow() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    let f = run(root.open_file(
+        false,
+        "file",
+        OFlags::empty(),
+        true,
+        false,
+        FdFlags::empty(),
+    ))
+    .expect("open file");
+
+    assert_errkind!(
+        run(f.advise(u64::MAX, 1, Advice::DontNeed)),
+        ErrorKind::Overflow
+    );
+    assert_errkind!(
+        run(f.advise(1, u64::MAX, Advice::Normal)),
+        ErrorKind::Overflow
+    );
+    run(f.advise(u64::MAX, 0, Advice::Normal)).expect("advise");
+}
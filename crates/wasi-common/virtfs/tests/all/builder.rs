@@ -0,0 +1,76 @@
+use crate::{names_at, read_file, run};
+use cap_std::time::{Duration, SystemTime};
+use wasi_common::{dir::WasiDir, file::FileType, ErrorKind};
+use wasi_virtfs::{clock::FixedClock, FileTimes, Filesystem, FilesystemBuilder};
+
+fn epoch_plus(secs: u64) -> SystemTime {
+    SystemTime::from_std(std::time::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+fn build(builder: FilesystemBuilder) -> Result<std::sync::Arc<Filesystem>, wasi_common::Error> {
+    builder.build(Box::new(FixedClock::new(epoch_plus(1000))), 7)
+}
+
+#[test]
+fn builds_the_tree_described() {
+    let times = FileTimes {
+        atim: epoch_plus(10),
+        mtim: epoch_plus(20),
+    };
+    let fs = build(
+        Filesystem::builder()
+            .dir("etc")
+            .file("etc/hosts", b"127.0.0.1 localhost\n")
+            .file_with_times("data/deep/a.bin", &[0, 1, 2, 3], times)
+            .dir("empty/")
+            .file("data/b.bin", b""),
+    )
+    .expect("build");
+    let root = fs.root();
+    assert_eq!(fs.device_id(), 7);
+    assert_eq!(names_at(&root, "."), [".", "..", "data", "empty", "etc"]);
+    assert_eq!(names_at(&root, "data"), [".", "..", "b.bin", "deep"]);
+    assert_eq!(names_at(&root, "empty"), [".", ".."]);
+
+    let stat = run(root.get_path_filestat("data/deep/a.bin", false)).expect("stat");
+    assert_eq!(stat.filetype, FileType::RegularFile);
+    assert_eq!(stat.atim, Some(epoch_plus(10).into_std()));
+    assert_eq!(stat.mtim, Some(epoch_plus(20).into_std()));
+    assert_eq!(stat.ctim, Some(epoch_plus(1000).into_std()));
+    let stat = run(root.get_path_filestat("etc/hosts", false)).expect("stat");
+    assert_eq!(stat.mtim, Some(epoch_plus(1000).into_std()));
+    assert_eq!(read_file(&root, "etc/hosts"), b"127.0.0.1 localhost\n");
+    assert_eq!(read_file(&root, "data/deep/a.bin"), [0, 1, 2, 3]);
+
+    // The files can be written like any other.
+    run(root.unlink_file("etc/hosts")).expect("unlink");
+    crate::create_file(&root, "etc/hosts", b"rewritten");
+    assert_eq!(read_file(&root, "etc/hosts"), b"rewritten");
+}
+
+#[test]
+fn conflicting_entries_fail_the_build() {
+    let conflicts = vec![
+        Filesystem::builder().file("a", b"").dir("a"),
+        Filesystem::builder().dir("a").file("a", b""),
+        Filesystem::builder().file("a", b"1").file("a", b"2"),
+        Filesystem::builder().dir("a").dir("./a/"),
+        Filesystem::builder().file("a/b", b"").file("a", b""),
+        Filesystem::builder().dir("."),
+    ];
+    for builder in conflicts {
+        assert_errkind!(build(builder), ErrorKind::Exist);
+    }
+    assert_errkind!(
+        build(Filesystem::builder().file("a", b"").file("a/b", b"")),
+        ErrorKind::Notdir
+    );
+    assert_errkind!(
+        build(Filesystem::builder().file("a", b"").dir("a/b")),
+        ErrorKind::Notdir
+    );
+
+    // Directories made implicitly may be added explicitly later.
+    let fs = build(Filesystem::builder().file("a/b", b"").dir("a")).expect("build");
+    assert_eq!(names_at(&fs.root(), "a"), [".", "..", "b"]);
+}
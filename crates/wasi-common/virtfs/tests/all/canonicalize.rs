@@ -0,0 +1,99 @@
+use crate::{create_file, filesystem, open_read, run};
+use wasi_common::{dir::WasiDir, ErrorKind};
+
+fn populate(dir: &dyn WasiDir) {
+    run(dir.create_dir("a")).expect("create a");
+    run(dir.create_dir("a/b")).expect("create a/b");
+    create_file(dir, "a/b/file", b"contents");
+    run(dir.symlink("a/b", "to_b")).expect("create to_b");
+    run(dir.symlink("../b/file", "a/b/up_and_back")).expect("create up_and_back");
+    run(dir.symlink("to_b/up_and_back", "chain")).expect("create chain");
+    run(dir.symlink("missing", "a/dangling")).expect("create dangling");
+    run(dir.symlink("loop", "loop")).expect("create loop");
+}
+
+#[test]
+fn plain_paths() {
+    let fs = filesystem();
+    populate(&fs.root());
+
+    assert_eq!(fs.canonicalize("").unwrap(), "/");
+    assert_eq!(fs.canonicalize(".").unwrap(), "/");
+    assert_eq!(fs.canonicalize("/").unwrap(), "/");
+    assert_eq!(fs.canonicalize("a").unwrap(), "/a");
+    assert_eq!(fs.canonicalize("/a/b/file").unwrap(), "/a/b/file");
+    assert_eq!(fs.canonicalize("./a//b/./").unwrap(), "/a/b");
+    assert_eq!(fs.canonicalize("a/b/..").unwrap(), "/a");
+}
+
+#[test]
+fn dotdot_past_root() {
+    let fs = filesystem();
+    populate(&fs.root());
+
+    assert_eq!(fs.canonicalize("..").unwrap(), "/");
+    assert_eq!(fs.canonicalize("../../a").unwrap(), "/a");
+    assert_eq!(fs.canonicalize("a/../../a/b").unwrap(), "/a/b");
+}
+
+#[test]
+fn symlinks() {
+    let fs = filesystem();
+    let root = fs.root();
+    populate(&root);
+
+    assert_eq!(fs.canonicalize("to_b").unwrap(), "/a/b");
+    assert_eq!(fs.canonicalize("to_b/file").unwrap(), "/a/b/file");
+    assert_eq!(fs.canonicalize("to_b/..").unwrap(), "/a");
+    assert_eq!(fs.canonicalize("chain").unwrap(), "/a/b/file");
+    assert_eq!(fs.canonicalize("a/b/up_and_back").unwrap(), "/a/b/file");
+
+    // The answer names the same inode that `open_file` opens.
+    let canonical = fs.canonicalize("chain").unwrap();
+    let via_link = run(open_read(&root, true, "chain").unwrap().get_filestat()).unwrap();
+    let direct = run(open_read(&root, true, &canonical[1..])
+        .unwrap()
+        .get_filestat())
+    .unwrap();
+    assert_eq!(via_link.inode, direct.inode);
+}
+
+#[test]
+fn relative_to_a_subdirectory() {
+    let fs = filesystem();
+    let root = fs.root();
+    populate(&root);
+    let b = run(root.open_dir(false, "a/b")).expect("open a/b");
+    let b = b
+        .as_any()
+        .downcast_ref::<wasi_virtfs::Dir>()
+        .expect("a virtfs dir");
+
+    assert_eq!(b.canonicalize(".").unwrap(), "/a/b");
+    assert_eq!(b.canonicalize("file").unwrap(), "/a/b/file");
+    assert_eq!(b.canonicalize("..").unwrap(), "/a");
+    assert_eq!(b.canonicalize("../../../..").unwrap(), "/");
+}
+
+#[test]
+fn errors() {
+    let fs = filesystem();
+    populate(&fs.root());
+
+    assert_errkind!(fs.canonicalize("missing"), ErrorKind::Noent);
+    assert_errkind!(fs.canonicalize("missing/file"), ErrorKind::Noent);
+    assert_errkind!(fs.canonicalize("a/b/file/x"), ErrorKind::Notdir);
+    assert_errkind!(fs.canonicalize("loop"), ErrorKind::Loop);
+    assert_errkind!(fs.canonicalize("loop/x"), ErrorKind::Loop);
+
+    let err = fs.canonicalize("a/dangling").unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<ErrorKind>(),
+        Some(ErrorKind::Noent)
+    ));
+    assert!(
+        format!("{:?}", err).contains("/a/missing"),
+        "error names the missing entry: {:?}",
+        err
+    );
+}
@@ -0,0 +1,95 @@
+use crate::{create_file, filesystem, run};
+use std::io::{IoSlice, IoSliceMut, SeekFrom};
+use wasi_common::{
+    file::{FdFlags, FileCaps, OFlags, WasiFile},
+    ErrorKind, SystemTimeSpec,
+};
+use wasi_virtfs::Dir;
+
+fn open(dir: &Dir, caps: FileCaps) -> Box<dyn WasiFile> {
+    dir.open_file_with_caps(false, "file", OFlags::empty(), caps, FdFlags::empty())
+        .expect("open file")
+}
+
+#[test]
+fn all_caps() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    let f = open(&root, FileCaps::all());
+    let mut buf = [0; 4];
+
+    run(f.seek(SeekFrom::Start(2))).expect("seek");
+    assert_eq!(run(f.seek(SeekFrom::Current(0))).expect("tell"), 2);
+    run(f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)).expect("pread");
+    run(f.write_vectored_at(&[IoSlice::new(b"xy")], 0)).expect("pwrite");
+    run(f.set_filestat_size(4)).expect("set_filestat_size");
+    run(f.allocate(0, 8)).expect("allocate");
+    run(f.set_times(Some(SystemTimeSpec::SymbolicNow), None)).expect("set_times");
+}
+
+#[test]
+fn missing_caps() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    let mut buf = [0; 4];
+
+    let f = open(&root, FileCaps::all() - FileCaps::SEEK);
+    assert_errkind!(run(f.seek(SeekFrom::Start(2))), ErrorKind::NotCapable);
+    assert_errkind!(run(f.seek(SeekFrom::End(0))), ErrorKind::NotCapable);
+    assert_errkind!(
+        run(f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)),
+        ErrorKind::NotCapable
+    );
+    assert_errkind!(
+        run(f.write_vectored_at(&[IoSlice::new(b"xy")], 0)),
+        ErrorKind::NotCapable
+    );
+    // Telling only needs TELL, and reading and writing at the position
+    // don't need SEEK at all.
+    assert_eq!(run(f.seek(SeekFrom::Current(0))).expect("tell"), 0);
+    run(f.read_vectored(&mut [IoSliceMut::new(&mut buf)])).expect("read");
+    run(f.write_vectored(&[IoSlice::new(b"xy")])).expect("write");
+
+    let f = open(&root, FileCaps::all() - FileCaps::TELL);
+    assert_errkind!(run(f.seek(SeekFrom::Current(0))), ErrorKind::NotCapable);
+
+    let f = open(&root, FileCaps::all() - FileCaps::FILESTAT_SET_SIZE);
+    assert_errkind!(run(f.set_filestat_size(4)), ErrorKind::NotCapable);
+    run(f.allocate(0, 16)).expect("allocate");
+
+    let f = open(&root, FileCaps::all() - FileCaps::ALLOCATE);
+    assert_errkind!(run(f.allocate(0, 32)), ErrorKind::NotCapable);
+    run(f.set_filestat_size(4)).expect("set_filestat_size");
+
+    let f = open(&root, FileCaps::all() - FileCaps::FILESTAT_SET_TIMES);
+    assert_errkind!(
+        run(f.set_times(Some(SystemTimeSpec::SymbolicNow), None)),
+        ErrorKind::NotCapable
+    );
+}
+
+#[test]
+fn caps_imply_access_mode() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    let mut buf = [0; 4];
+
+    // As in `path_open`, READ grants read access, and any of WRITE,
+    // ALLOCATE, or FILESTAT_SET_SIZE grants write access.
+    let f = open(&root, FileCaps::READ | FileCaps::SEEK);
+    run(f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)).expect("pread");
+    assert_errkind!(
+        run(f.write_vectored(&[IoSlice::new(b"xy")])),
+        ErrorKind::Badf
+    );
+
+    let f = open(&root, FileCaps::FILESTAT_SET_SIZE);
+    run(f.set_filestat_size(4)).expect("set_filestat_size");
+    assert_errkind!(
+        run(f.read_vectored(&mut [IoSliceMut::new(&mut buf)])),
+        ErrorKind::Badf
+    );
+}
@@ -0,0 +1,130 @@
+use crate::{create_file, filesystem, open_read, read_file, run, sorted_names};
+use std::sync::Arc;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags},
+    ErrorKind,
+};
+use wasi_virtfs::Filesystem;
+
+fn case_insensitive() -> Arc<Filesystem> {
+    Filesystem::new_case_insensitive(wasi_cap_std_sync::clocks_ctx().system, 0)
+}
+
+#[test]
+fn lookup_ignores_case() {
+    let fs = case_insensitive();
+    assert!(fs.is_case_insensitive());
+    let root = fs.root();
+    run(root.create_dir("textures")).expect("create textures");
+    create_file(&root, "textures/logo.png", b"png");
+
+    assert_eq!(read_file(&root, "Textures/Logo.PNG"), b"png");
+    assert_eq!(read_file(&root, "TEXTURES/logo.png"), b"png");
+    let dir = run(root.open_dir(false, "TeXtUrEs")).expect("open dir");
+    assert_eq!(read_file(&*dir, "LOGO.png"), b"png");
+    let stat = run(root.get_path_filestat("textures/logo.png", false)).expect("stat");
+    let folded = run(root.get_path_filestat("TEXTURES/LOGO.PNG", false)).expect("stat");
+    assert_eq!(folded.inode, stat.inode);
+
+    run(root.symlink("Textures", "link")).expect("create symlink");
+    assert_eq!(read_file(&root, "LINK/Logo.png"), b"png");
+}
+
+#[test]
+fn names_keep_their_spelling() {
+    let fs = case_insensitive();
+    let root = fs.root();
+    run(root.create_dir("Textures")).expect("create Textures");
+    create_file(&root, "Textures/Logo.png", b"png");
+
+    // Opening with a different spelling, even with CREATE, uses the
+    // existing entry.
+    let f = run(root.open_file(
+        false,
+        "textures/LOGO.PNG",
+        OFlags::CREATE,
+        false,
+        true,
+        FdFlags::empty(),
+    ))
+    .expect("open with CREATE");
+    run(f.write_vectored(&[std::io::IoSlice::new(b"new")])).expect("write");
+    let dir = run(root.open_dir(false, "textures")).expect("open dir");
+    assert_eq!(sorted_names(&*dir), vec![".", "..", "Logo.png"]);
+    assert_eq!(sorted_names(&root), vec![".", "..", "Textures"]);
+    assert_eq!(read_file(&root, "Textures/Logo.png"), b"new");
+}
+
+#[test]
+fn collisions() {
+    let fs = case_insensitive();
+    let root = fs.root();
+    run(root.create_dir("foo")).expect("create foo");
+    create_file(&root, "file", b"contents");
+
+    assert_errkind!(run(root.create_dir("Foo")), ErrorKind::Exist);
+    assert_errkind!(run(root.create_dir("FOO/")), ErrorKind::Exist);
+    assert_errkind!(
+        run(root.open_file(
+            false,
+            "FILE",
+            OFlags::CREATE | OFlags::EXCLUSIVE,
+            false,
+            true,
+            FdFlags::empty()
+        )),
+        ErrorKind::Exist
+    );
+    assert_errkind!(run(root.symlink("target", "File")), ErrorKind::Exist);
+    assert_errkind!(run(root.hard_link("file", &root, "FILE")), ErrorKind::Exist);
+    assert_eq!(sorted_names(&root), vec![".", "..", "file", "foo"]);
+
+    run(root.rename("FILE", &root, "moved")).expect("rename");
+    run(root.unlink_file("MOVED")).expect("unlink");
+    run(root.remove_dir("FOO")).expect("remove dir");
+    assert_eq!(sorted_names(&root), vec![".", ".."]);
+}
+
+#[test]
+fn unicode_folding() {
+    let fs = case_insensitive();
+    let root = fs.root();
+    create_file(&root, "Ärger", b"contents");
+
+    assert_eq!(read_file(&root, "äRGER"), b"contents");
+    assert_errkind!(run(root.create_dir("ärger")), ErrorKind::Exist);
+}
+
+#[test]
+fn case_sensitive_by_default() {
+    let fs = filesystem();
+    assert!(!fs.is_case_insensitive());
+    let root = fs.root();
+    create_file(&root, "file", b"lower");
+    create_file(&root, "FILE", b"upper");
+
+    assert_eq!(read_file(&root, "file"), b"lower");
+    assert_eq!(read_file(&root, "FILE"), b"upper");
+    assert_errkind!(open_read(&root, false, "File"), ErrorKind::Noent);
+}
+
+#[test]
+fn rename_changes_the_spelling() {
+    let fs = case_insensitive();
+    let root = fs.root();
+    create_file(&root, "foo", b"contents");
+    run(root.create_dir("dir")).expect("mkdir");
+    let before = run(root.get_path_filestat("foo", false)).expect("stat");
+
+    run(root.rename("foo", &root, "FOO")).expect("rename");
+    run(root.rename("DIR", &root, "Dir/")).expect("rename");
+    assert_eq!(sorted_names(&root), vec![".", "..", "Dir", "FOO"]);
+    let after = run(root.get_path_filestat("foo", false)).expect("stat");
+    assert_eq!(after.inode, before.inode);
+    assert_eq!(read_file(&root, "Foo"), b"contents");
+
+    // The same spelling changes nothing.
+    run(root.rename("FOO", &root, "FOO")).expect("rename");
+    assert_eq!(sorted_names(&root), vec![".", "..", "Dir", "FOO"]);
+}
@@ -0,0 +1,189 @@
+use crate::{capacity, create_file, filesystem, open_write, read_file, run};
+use std::io::{IoSlice, IoSliceMut, SeekFrom};
+
+// Files are stored in chunks of this size.
+const CHUNK: usize = 64 * 1024;
+
+/// A byte pattern which differs at every offset within a chunk, so data
+/// landing in the wrong chunk or at the wrong offset is caught.
+fn pattern(len: usize, seed: u8) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i % 251) as u8 ^ (i / 251) as u8 ^ seed)
+        .collect()
+}
+
+#[test]
+fn write_straddling_a_boundary() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = open_write(&root, "file").expect("open file");
+
+    let data = pattern(100, 1);
+    let offset = (CHUNK - 50) as u64;
+    let n = run(f.write_vectored_at(&[IoSlice::new(&data)], offset)).expect("pwrite");
+    assert_eq!(n, 100);
+    assert_eq!(run(f.get_filestat()).unwrap().size, offset + 100);
+
+    let mut buf = vec![0xff; 100];
+    let n = run(f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], offset)).expect("pread");
+    assert_eq!(n, 100);
+    assert_eq!(buf, data);
+
+    let contents = read_file(&root, "file");
+    assert!(contents[..CHUNK - 50].iter().all(|b| *b == 0));
+    assert_eq!(&contents[CHUNK - 50..], &data[..]);
+}
+
+#[test]
+fn write_spanning_many_chunks() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = open_write(&root, "file").expect("open file");
+
+    // Starts and ends partway into a chunk, covering several whole chunks.
+    let data = pattern(3 * CHUNK + 200, 2);
+    run(f.write_vectored_at(&[IoSlice::new(&data)], 100)).expect("pwrite");
+    assert_eq!(&read_file(&root, "file")[100..], &data[..]);
+    assert!(capacity(&*f) <= 4 * CHUNK as u64);
+
+    // Overwrite across a boundary in the middle.
+    let patch = pattern(1000, 3);
+    let offset = 2 * CHUNK - 500;
+    run(f.write_vectored_at(&[IoSlice::new(&patch)], offset as u64)).expect("pwrite");
+    let mut expected = vec![0; 100];
+    expected.extend_from_slice(&data);
+    expected[offset..offset + 1000].copy_from_slice(&patch);
+    assert_eq!(read_file(&root, "file"), expected);
+}
+
+#[test]
+fn vectored_io_across_boundaries() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = open_write(&root, "file").expect("open file");
+
+    // Buffers whose edges fall on either side of chunk boundaries.
+    let a = pattern(CHUNK - 10, 4);
+    let b = pattern(20, 5);
+    let c = pattern(CHUNK + 7, 6);
+    let n = run(f.write_vectored(&[IoSlice::new(&a), IoSlice::new(&b), IoSlice::new(&c)]))
+        .expect("write");
+    assert_eq!(n as usize, a.len() + b.len() + c.len());
+
+    run(f.seek(SeekFrom::Start(0))).expect("seek");
+    let mut x = vec![0xff; 3];
+    let mut y = vec![0xff; CHUNK];
+    let mut z = vec![0xff; 2 * CHUNK];
+    let n = run(f.read_vectored(&mut [
+        IoSliceMut::new(&mut x),
+        IoSliceMut::new(&mut y),
+        IoSliceMut::new(&mut z),
+    ]))
+    .expect("read");
+    assert_eq!(n as usize, a.len() + b.len() + c.len());
+
+    let mut expected = a.clone();
+    expected.extend_from_slice(&b);
+    expected.extend_from_slice(&c);
+    let mut got = x;
+    got.extend_from_slice(&y);
+    got.extend_from_slice(&z[..n as usize - 3 - CHUNK]);
+    assert_eq!(got, expected);
+    assert!(z[n as usize - 3 - CHUNK..].iter().all(|b| *b == 0xff));
+}
+
+#[test]
+fn read_straddling_a_hole() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = open_write(&root, "file").expect("open file");
+
+    // Data in the first and third chunks, with the second left as a hole.
+    let head = pattern(CHUNK, 7);
+    let tail = pattern(CHUNK, 8);
+    run(f.write_vectored_at(&[IoSlice::new(&head)], 0)).expect("pwrite");
+    run(f.write_vectored_at(&[IoSlice::new(&tail)], 2 * CHUNK as u64)).expect("pwrite");
+    assert!(capacity(&*f) <= 2 * CHUNK as u64);
+
+    let mut buf = vec![0xff; CHUNK + 20];
+    let offset = (CHUNK - 10) as u64;
+    let n = run(f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], offset)).expect("pread");
+    assert_eq!(n, CHUNK as u64 + 20);
+    assert_eq!(&buf[..10], &head[CHUNK - 10..]);
+    assert!(buf[10..CHUNK + 10].iter().all(|b| *b == 0));
+    assert_eq!(&buf[CHUNK + 10..], &tail[..10]);
+}
+
+#[test]
+fn truncate_to_a_boundary() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = open_write(&root, "file").expect("open file");
+
+    let data = pattern(4 * CHUNK, 9);
+    run(f.write_vectored_at(&[IoSlice::new(&data)], 0)).expect("pwrite");
+    assert_eq!(capacity(&*f), 4 * CHUNK as u64);
+
+    run(f.set_filestat_size(2 * CHUNK as u64)).expect("shrink");
+    assert_eq!(capacity(&*f), 2 * CHUNK as u64);
+    assert_eq!(read_file(&root, "file"), &data[..2 * CHUNK]);
+
+    run(f.set_filestat_size(3 * CHUNK as u64)).expect("grow");
+    let contents = read_file(&root, "file");
+    assert_eq!(&contents[..2 * CHUNK], &data[..2 * CHUNK]);
+    assert!(contents[2 * CHUNK..].iter().all(|b| *b == 0));
+}
+
+#[test]
+fn appends_match_writes_anywhere_else() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = open_write(&root, "file").expect("open file");
+    let mut expected = Vec::new();
+
+    // Small appends filling chunks, then iovecs straddling the end of one.
+    let line = pattern(100, 2);
+    for _ in 0..CHUNK / 100 {
+        run(f.write_vectored(&[IoSlice::new(&line)])).expect("append");
+        expected.extend_from_slice(&line);
+    }
+    let (a, b) = (pattern(30, 3), pattern(70, 4));
+    run(f.write_vectored(&[IoSlice::new(&a), IoSlice::new(&b)])).expect("append");
+    expected.extend_from_slice(&a);
+    expected.extend_from_slice(&b);
+    assert_eq!(read_file(&root, "file"), expected);
+
+    // Writes which start before the end and run past it.
+    run(f.seek(SeekFrom::Start(expected.len() as u64 - 10))).expect("seek");
+    let tail = pattern(40, 5);
+    run(f.write_vectored(&[IoSlice::new(&tail)])).expect("write over the end");
+    let at = expected.len() - 10;
+    expected.truncate(at);
+    expected.extend_from_slice(&tail);
+    assert_eq!(read_file(&root, "file"), expected);
+
+    // Appending after a hole, left by growing the file, fills the hole with
+    // zeros rather than the data.
+    let len = expected.len();
+    run(f.set_filestat_size(len as u64 + 10)).expect("grow");
+    run(f.seek(SeekFrom::End(0))).expect("seek to the end");
+    run(f.write_vectored(&[IoSlice::new(b"after")])).expect("append");
+    expected.extend_from_slice(&[0; 10]);
+    expected.extend_from_slice(b"after");
+    assert_eq!(read_file(&root, "file"), expected);
+
+    // Appending to a chunk shared with a copy leaves the copy alone.
+    let before = expected.clone();
+    fs.copy_file("file", "copy").expect("copy");
+    run(f.write_vectored(&[IoSlice::new(b"!")])).expect("append");
+    expected.push(b'!');
+    assert_eq!(read_file(&root, "file"), expected);
+    assert_eq!(read_file(&root, "copy"), before);
+    assert!(capacity(&*f) >= expected.len() as u64);
+}
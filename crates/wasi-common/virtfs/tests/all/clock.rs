@@ -0,0 +1,148 @@
+use crate::{create_file, read_file, run};
+use cap_std::time::{Duration, SystemTime};
+use std::io::IoSlice;
+use std::sync::Arc;
+use wasi_common::{
+    clocks::WasiSystemClock,
+    dir::WasiDir,
+    file::{FdFlags, Filestat, OFlags},
+};
+use wasi_virtfs::{
+    clock::{FixedClock, LogicalClock},
+    Filesystem,
+};
+
+/// Build a small tree, returning the stats of everything in it.
+fn workload(fs: &Arc<Filesystem>) -> Vec<Filestat> {
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+    create_file(&root, "dir/file", b"contents");
+    run(root.symlink("dir/file", "link")).expect("create link");
+    let f = run(root.open_file(
+        false,
+        "dir/file",
+        OFlags::empty(),
+        true,
+        true,
+        FdFlags::empty(),
+    ))
+    .expect("open");
+    run(f.write_vectored_at(&[IoSlice::new(b" and more")], 8)).expect("write");
+    assert_eq!(read_file(&root, "dir/file"), b"contents and more");
+    [".", "dir", "dir/file", "link"]
+        .iter()
+        .map(|path| run(root.get_path_filestat(path, false)).expect("stat"))
+        .collect()
+}
+
+#[test]
+fn deterministic_runs_match() {
+    let first = workload(&Filesystem::new_deterministic(7));
+    let second = workload(&Filesystem::new_deterministic(7));
+    assert_eq!(first, second);
+    let epoch = SystemTime::from_std(std::time::UNIX_EPOCH);
+    assert!(first
+        .iter()
+        .all(|stat| stat.mtim.unwrap() < (epoch + Duration::from_secs(100)).into_std()));
+}
+
+#[test]
+fn logical_clock_preserves_order() {
+    let start = SystemTime::from_std(std::time::UNIX_EPOCH) + Duration::from_secs(1_000_000);
+    let fs = Filesystem::new(
+        Box::new(LogicalClock::new(start, Duration::from_millis(1))),
+        0,
+    );
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = run(root.open_file(
+        false,
+        "file",
+        OFlags::empty(),
+        false,
+        true,
+        FdFlags::empty(),
+    ))
+    .expect("open");
+
+    let mut last = run(f.get_filestat()).unwrap();
+    assert!(last.ctim.unwrap() > start.into_std());
+    for _ in 0..10 {
+        run(f.write_vectored(&[IoSlice::new(b"x")])).expect("write");
+        let now = run(f.get_filestat()).unwrap();
+        assert!(now.mtim > last.mtim);
+        assert!(now.ctim > last.ctim);
+        last = now;
+    }
+}
+
+#[test]
+fn logical_clock_counts_readings() {
+    let clock = LogicalClock::new(
+        SystemTime::from_std(std::time::UNIX_EPOCH),
+        Duration::from_secs(2),
+    );
+    assert_eq!(clock.resolution(), Duration::from_secs(2));
+    let first = clock.now(Duration::from_secs(0));
+    let second = clock.now(Duration::from_secs(0));
+    assert_eq!(
+        second.duration_since(first).unwrap(),
+        Duration::from_secs(2)
+    );
+    assert_eq!(clock.ticks(), 2);
+}
+
+#[test]
+fn fixed_clock() {
+    let time = SystemTime::from_std(std::time::UNIX_EPOCH) + Duration::from_secs(1234);
+    let fs = Filesystem::new(Box::new(FixedClock::new(time)), 0);
+    for stat in workload(&fs) {
+        assert_eq!(stat.atim, Some(time.into_std()));
+        assert_eq!(stat.mtim, Some(time.into_std()));
+        assert_eq!(stat.ctim, Some(time.into_std()));
+    }
+}
+
+#[test]
+fn system_clock() {
+    let fs = Filesystem::new_with_system_clock(3);
+    assert_eq!(fs.device_id(), 3);
+    let root = fs.root();
+    let start = std::time::SystemTime::now();
+    create_file(&root, "file", b"");
+    let created = run(root.get_path_filestat("file", false)).unwrap();
+    assert!(created.mtim.unwrap() >= start - std::time::Duration::from_secs(1));
+
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    let f = run(root.open_file(
+        false,
+        "file",
+        OFlags::empty(),
+        false,
+        true,
+        FdFlags::empty(),
+    ))
+    .expect("open");
+    run(f.write_vectored(&[IoSlice::new(b"later")])).expect("write");
+    let written = run(f.get_filestat()).unwrap();
+    let elapsed = written
+        .mtim
+        .unwrap()
+        .duration_since(created.mtim.unwrap())
+        .expect("mtim moves forward");
+    assert!(elapsed >= std::time::Duration::from_millis(20));
+    assert!(written.mtim.unwrap() <= std::time::SystemTime::now());
+}
+
+#[test]
+fn default_filesystem() {
+    let fs = Arc::new(Filesystem::default());
+    assert_eq!(fs.device_id(), 0);
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    let stat = run(root.get_path_filestat("file", false)).unwrap();
+    let age = std::time::SystemTime::now()
+        .duration_since(stat.mtim.unwrap())
+        .expect("mtim is in the past");
+    assert!(age < std::time::Duration::from_secs(60));
+}
@@ -0,0 +1,137 @@
+use crate::{create_file, filesystem, open, read, read_file, run, virtfs_file, write_all};
+use std::io::{IoSliceMut, SeekFrom};
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, FileCaps, OFlags, WasiFile},
+    ErrorKind,
+};
+use wasi_virtfs::{File, LockKind};
+
+fn tell(f: &File) -> u64 {
+    run(f.seek(SeekFrom::Current(0))).expect("tell")
+}
+
+#[test]
+fn clones_share_the_position() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "log", b"");
+    let opened = open(&root, "log", true, true, FdFlags::empty());
+    let a = virtfs_file(&*opened);
+    let b = a.try_clone().expect("clone");
+
+    write_all(a, b"one ");
+    write_all(&b, b"two ");
+    write_all(a, b"three");
+    assert_eq!(read_file(&root, "log"), b"one two three");
+    assert_eq!(tell(a), 13);
+    assert_eq!(tell(&b), 13);
+
+    run(b.seek(SeekFrom::Start(4))).expect("seek");
+    assert_eq!(read(a, 3).expect("read"), b"two");
+    assert_eq!(read(&b, 64).expect("read"), b" three");
+    assert_eq!(tell(a), 13);
+
+    // The position outlives the handle it was first opened with.
+    drop(opened);
+    run(b.seek(SeekFrom::Start(0))).expect("seek");
+    let c = b.try_clone().expect("clone");
+    drop(b);
+    assert_eq!(read(&c, 3).expect("read"), b"one");
+}
+
+#[test]
+fn reopened_handles_have_their_own_position() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"0123456789");
+    let opened = open(&root, "file", true, true, FdFlags::empty());
+    let a = virtfs_file(&*opened);
+    assert_eq!(read(a, 4).expect("read"), b"0123");
+
+    let b = a.reopen().expect("reopen");
+    assert_eq!(tell(&b), 0);
+    write_all(&b, b"ab");
+    assert_eq!(read(a, 2).expect("read"), b"45");
+    assert_eq!(tell(&b), 2);
+    assert_eq!(read_file(&root, "file"), b"ab23456789");
+
+    // Even once the file has no name.
+    run(root.unlink_file("file")).expect("unlink");
+    let c = b.reopen().expect("reopen");
+    assert_eq!(read(&c, 64).expect("read"), b"ab23456789");
+}
+
+#[test]
+fn modes_and_flags_carry_over() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"start");
+
+    // Append mode: writes through either handle go to the end.
+    let opened = open(&root, "file", true, true, FdFlags::APPEND);
+    let a = virtfs_file(&*opened);
+    let mut b = a.try_clone().expect("clone");
+    let c = a.reopen().expect("reopen");
+    for f in &[&b, &c] {
+        assert_eq!(run(f.get_fdflags()).unwrap(), FdFlags::APPEND);
+    }
+    write_all(&b, b" b");
+    write_all(&c, b" c");
+    assert_eq!(read_file(&root, "file"), b"start b c");
+
+    // fdflags belong to each handle.
+    run(b.set_fdflags(FdFlags::empty())).expect("clear APPEND");
+    assert_eq!(run(a.get_fdflags()).unwrap(), FdFlags::APPEND);
+    run(b.seek(SeekFrom::Start(0))).expect("seek");
+    write_all(&b, b"S");
+    assert_eq!(read_file(&root, "file"), b"Start b c");
+
+    // Write-only handles stay write-only.
+    let opened = open(&root, "file", false, true, FdFlags::empty());
+    let w = virtfs_file(&*opened);
+    for f in &[w.try_clone().expect("clone"), w.reopen().expect("reopen")] {
+        let mut buf = [0; 4];
+        assert_errkind!(
+            run(f.read_vectored(&mut [IoSliceMut::new(&mut buf)])),
+            ErrorKind::Badf
+        );
+        write_all(f, b"w");
+    }
+
+    // And so do capabilities.
+    let f = root
+        .open_file_with_caps(
+            false,
+            "file",
+            OFlags::empty(),
+            FileCaps::all() - FileCaps::SEEK,
+            FdFlags::empty(),
+        )
+        .expect("open");
+    let f = virtfs_file(&*f);
+    for f in &[f.try_clone().expect("clone"), f.reopen().expect("reopen")] {
+        assert_errkind!(run(f.seek(SeekFrom::Start(1))), ErrorKind::NotCapable);
+    }
+}
+
+#[test]
+fn clones_share_locks() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let opened = open(&root, "file", true, true, FdFlags::empty());
+    let a = virtfs_file(&*opened);
+    let b = a.try_clone().expect("clone");
+    let other = a.reopen().expect("reopen");
+
+    a.try_lock(LockKind::Exclusive).expect("lock");
+    assert_eq!(b.lock_held(), Some(LockKind::Exclusive));
+    assert_errkind!(other.try_lock(LockKind::Shared), ErrorKind::Again);
+
+    // The lock stays until the last clone is dropped.
+    drop(opened);
+    assert_errkind!(other.try_lock(LockKind::Shared), ErrorKind::Again);
+    drop(b);
+    other.try_lock(LockKind::Shared).expect("lock");
+}
@@ -0,0 +1,114 @@
+use crate::{create_file, filesystem, open_read, open_write, pread, read_file, run, virtfs_file};
+use std::io::IoSlice;
+use wasi_common::{dir::WasiDir, ErrorKind};
+
+const MB: usize = 1_000_000;
+// Files are stored, and copied on write, in chunks of this size.
+const CHUNK: u64 = 64 * 1024;
+
+#[test]
+fn copy_shares_data_until_written() {
+    let fs = filesystem();
+    let root = fs.root();
+    let data = vec![7; 100 * MB];
+    create_file(&root, "original", &data);
+    drop(data);
+
+    fs.copy_file("original", "copy").expect("copy");
+    let original = open_write(&root, "original").expect("open file");
+    let copy = open_write(&root, "copy").expect("open file");
+    for f in &[&original, &copy] {
+        let f = virtfs_file(&***f);
+        assert_eq!(f.materialized_size(), 100 * MB as u64);
+        assert_eq!(f.owned_size(), 0, "all data is shared");
+    }
+    let stat = run(copy.get_filestat()).unwrap();
+    assert_eq!(stat.size, 100 * MB as u64);
+    assert_ne!(stat.inode, run(original.get_filestat()).unwrap().inode);
+
+    // Writing one byte copies only the chunk it lands in.
+    let offset = 50 * MB as u64;
+    run(copy.write_vectored_at(&[IoSlice::new(b"x")], offset)).expect("pwrite");
+    assert_eq!(virtfs_file(&*copy).owned_size(), CHUNK);
+    assert_eq!(virtfs_file(&*original).owned_size(), CHUNK);
+    assert_eq!(pread(&*copy, 3, offset - 1), b"\x07x\x07");
+    assert_eq!(pread(&*original, 3, offset - 1), b"\x07\x07\x07");
+}
+
+#[test]
+fn truncating_a_copy_leaves_the_original() {
+    let fs = filesystem();
+    let root = fs.root();
+    let data: Vec<u8> = (0..3 * CHUNK).map(|i| i as u8).collect();
+    create_file(&root, "original", &data);
+    fs.copy_file("original", "copy").expect("copy");
+
+    let copy = open_write(&root, "copy").expect("open file");
+    run(copy.set_filestat_size(CHUNK + 10)).expect("shrink");
+    run(copy.set_filestat_size(2 * CHUNK)).expect("grow");
+    assert_eq!(virtfs_file(&*copy).owned_size(), 10);
+    assert_eq!(read_file(&root, "original"), data);
+    let contents = read_file(&root, "copy");
+    assert_eq!(
+        &contents[..CHUNK as usize + 10],
+        &data[..CHUNK as usize + 10]
+    );
+    assert!(contents[CHUNK as usize + 10..].iter().all(|b| *b == 0));
+}
+
+#[test]
+fn copy_over_an_existing_file() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "a", b"new contents");
+    create_file(&root, "b", b"old");
+    let handle = open_read(&root, false, "b").expect("open b");
+
+    fs.copy_file("a", "b").expect("copy");
+    assert_eq!(read_file(&root, "b"), b"new contents");
+    assert_eq!(pread(&*handle, 64, 0), b"new contents");
+
+    // Copying a file onto itself changes nothing.
+    fs.copy_file("a", "a").expect("copy onto itself");
+    assert_eq!(read_file(&root, "a"), b"new contents");
+}
+
+#[test]
+fn copy_follows_symlinks_and_crosses_directories() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    run(root.symlink("file", "link")).expect("symlink");
+    run(root.create_dir("dir")).expect("mkdir");
+    let dir = run(root.open_dir(false, "dir")).expect("open dir");
+    let dir = dir
+        .as_any()
+        .downcast_ref::<wasi_virtfs::Dir>()
+        .expect("a virtfs dir");
+
+    root.copy_file("link", dir, "copy").expect("copy");
+    assert_eq!(read_file(&root, "dir/copy"), b"contents");
+}
+
+#[test]
+fn copy_errors() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    run(root.create_dir("dir")).expect("mkdir");
+
+    assert_errkind!(fs.copy_file("missing", "copy"), ErrorKind::Noent);
+    assert_errkind!(fs.copy_file("dir", "copy"), ErrorKind::Isdir);
+    assert_errkind!(fs.copy_file("file", "dir"), ErrorKind::Isdir);
+    assert_errkind!(fs.copy_file("file", "missing/copy"), ErrorKind::Noent);
+
+    // The destination filesystem's size limit applies.
+    let other = filesystem();
+    other.set_max_file_size(4);
+    assert_errkind!(
+        root.copy_file("file", &other.root(), "copy"),
+        ErrorKind::Fbig
+    );
+    root.copy_file("file", &filesystem().root(), "copy")
+        .expect("copy to another filesystem");
+}
@@ -0,0 +1,91 @@
+use crate::{create_file, readdir, run, ticking_filesystem};
+use wasi_common::{dir::WasiDir, file::FileType, file::Filestat, ErrorKind};
+
+fn stat(dir: &dyn WasiDir, path: &str) -> Filestat {
+    run(dir.get_path_filestat(path, false)).expect("stat")
+}
+
+#[test]
+fn creates_every_missing_directory() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    let before = stat(&root, ".");
+
+    fs.create_dir_all("a/b/c").expect("mkdir -p");
+    for path in &["a", "a/b", "a/b/c"] {
+        assert_eq!(stat(&root, path).filetype, FileType::Directory);
+    }
+    assert!(stat(&root, ".").mtim > before.mtim);
+    assert!(stat(&root, "a").mtim > stat(&root, "a/b").ctim);
+    let listed: Vec<String> = readdir(&root).into_iter().map(|e| e.name).collect();
+    assert_eq!(listed, [".", "..", "a"]);
+
+    // Doing it again changes nothing.
+    let c = stat(&root, "a/b/c");
+    let a = stat(&root, "a");
+    fs.create_dir_all("a/b/c").expect("mkdir -p again");
+    assert_eq!(stat(&root, "a/b/c"), c);
+    assert_eq!(stat(&root, "a"), a);
+}
+
+#[test]
+fn existing_directories_are_kept() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    run(root.create_dir("a")).expect("mkdir");
+    create_file(&root, "a/file", b"kept");
+    run(root.symlink("a", "link")).expect("symlink");
+    let a = stat(&root, "a");
+
+    fs.create_dir_all("a/b/c").expect("mkdir -p");
+    let after = stat(&root, "a");
+    assert_eq!(after.inode, a.inode);
+    assert!(after.mtim > a.mtim, "a has a new entry");
+    assert_eq!(stat(&root, "a/file").size, 4);
+
+    // Symlinks to directories are followed, in the middle or at the end.
+    fs.create_dir_all("link/b/d")
+        .expect("mkdir -p through a link");
+    assert_eq!(stat(&root, "a/b/d").filetype, FileType::Directory);
+    fs.create_dir_all("link").expect("mkdir -p of a link");
+    fs.create_dir_all("./a/../a/b").expect("mkdir -p with dots");
+    fs.create_dir_all(".").expect("mkdir -p of the root");
+}
+
+#[test]
+fn files_in_the_way_fail_with_enotdir() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    run(root.create_dir("a")).expect("mkdir");
+    create_file(&root, "a/file", b"");
+    run(root.symlink("file", "a/link")).expect("symlink");
+    run(root.symlink("missing", "a/dangling")).expect("symlink");
+
+    assert_errkind!(fs.create_dir_all("a/file"), ErrorKind::Notdir);
+    assert_errkind!(fs.create_dir_all("a/file/b"), ErrorKind::Notdir);
+    assert_errkind!(fs.create_dir_all("a/link/b"), ErrorKind::Notdir);
+    assert_errkind!(fs.create_dir_all("a/dangling"), ErrorKind::Noent);
+    assert_errkind!(fs.create_dir_all(""), ErrorKind::Noent);
+    let listed: Vec<String> = readdir(&*run(root.open_dir(false, "a")).unwrap())
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    assert_eq!(listed, [".", "..", "dangling", "file", "link"]);
+}
+
+#[test]
+fn trailing_slashes_are_fine() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    fs.create_dir_all("a/b/")
+        .expect("mkdir -p with a trailing slash");
+    assert_eq!(stat(&root, "a/b").filetype, FileType::Directory);
+    root.create_dir_all("a//c//")
+        .expect("mkdir -p with doubled slashes");
+    assert_eq!(stat(&root, "a/c").filetype, FileType::Directory);
+    fs.create_dir_all("a/b/")
+        .expect("mkdir -p of an existing path");
+
+    create_file(&root, "file", b"");
+    assert_errkind!(fs.create_dir_all("file/"), ErrorKind::Notdir);
+}
@@ -0,0 +1,374 @@
+use crate::{create_file, filesystem, names_at, open, read, read_file, readdir, run, write_all};
+use std::io::{Cursor, IoSlice, IoSliceMut, SeekFrom};
+use std::sync::{Arc, Mutex, RwLock};
+use wasi_cap_std_sync::WasiCtxBuilder;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, FileCaps, FileType, OFlags, WasiFile},
+    pipe::WritePipe,
+    random::Deterministic,
+    snapshots::preview_1::{types, wasi_snapshot_preview1::WasiSnapshotPreview1},
+    ErrorExt, ErrorKind, WasiCtx,
+};
+use wasi_virtfs::{Dir, EchoDevice, NullDevice, RandomDevice};
+
+#[test]
+fn devices_are_character_devices_everywhere() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dev")).expect("mkdir");
+    root.put_char_device("dev/tty", Arc::new(EchoDevice::new()))
+        .expect("put device");
+
+    let f = open(&root, "dev/tty", true, true, FdFlags::empty());
+    assert_eq!(run(f.get_filetype()).unwrap(), FileType::CharacterDevice);
+    let stat = run(f.get_filestat()).expect("fstat");
+    assert_eq!(stat.filetype, FileType::CharacterDevice);
+    assert_eq!(stat.size, 0);
+    let path_stat = run(root.get_path_filestat("dev/tty", false)).expect("stat");
+    assert_eq!(path_stat.filetype, FileType::CharacterDevice);
+    assert_eq!(path_stat.inode, stat.inode);
+    let dev = run(root.open_dir(false, "dev")).expect("open dir");
+    let entries = readdir(&*dev);
+    let tty = entries.iter().find(|e| e.name == "tty").expect("tty entry");
+    assert_eq!(tty.filetype, FileType::CharacterDevice);
+    assert_eq!(tty.inode, stat.inode);
+
+    // Like a terminal, it can't be seeked.
+    assert_errkind!(run(f.seek(SeekFrom::Start(0))), ErrorKind::Spipe);
+    assert_errkind!(run(f.seek(SeekFrom::Current(0))), ErrorKind::Spipe);
+    let mut buf = [0; 4];
+    assert_errkind!(
+        run(f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)),
+        ErrorKind::Spipe
+    );
+
+    // Nor can it be linked, copied, or created over.
+    assert_errkind!(
+        run(root.hard_link("dev/tty", &root, "tty")),
+        ErrorKind::Perm
+    );
+    assert_errkind!(root.copy_file("dev/tty", &root, "tty"), ErrorKind::Notsup);
+    assert_errkind!(
+        run(root.open_file(
+            false,
+            "dev/tty",
+            OFlags::CREATE | OFlags::EXCLUSIVE,
+            false,
+            true,
+            FdFlags::empty(),
+        )),
+        ErrorKind::Exist
+    );
+    assert_errkind!(
+        root.put_char_device("dev/tty", Arc::new(EchoDevice::new())),
+        ErrorKind::Exist
+    );
+
+    // Unlinking it leaves open handles working.
+    run(root.unlink_file("dev/tty")).expect("unlink");
+    write_all(&*f, b"still here");
+    assert_eq!(read(&*f, 64).expect("read"), b"still here");
+}
+
+#[test]
+fn echo_device_reads_back_writes() {
+    let fs = filesystem();
+    let root = fs.root();
+    root.put_char_device("loop", Arc::new(EchoDevice::new()))
+        .expect("put device");
+    let writer = open(&root, "loop", false, true, FdFlags::empty());
+    let reader = open(&root, "loop", true, false, FdFlags::empty());
+
+    assert_eq!(
+        read(&*reader, 16).expect("read"),
+        b"",
+        "nothing written yet"
+    );
+    write_all(&*writer, b"hello ");
+    write_all(&*writer, b"world\n");
+    assert_eq!(run(reader.num_ready_bytes()).unwrap(), 12);
+    let mut peeked = [0; 5];
+    assert_eq!(run(reader.peek(&mut peeked)).unwrap(), 5);
+    assert_eq!(&peeked, b"hello");
+    assert_eq!(read(&*reader, 3).expect("read"), b"hel");
+    assert_eq!(read(&*reader, 64).expect("read"), b"lo world\n");
+    assert_eq!(run(reader.num_ready_bytes()).unwrap(), 0);
+
+    // The access mode still applies.
+    let mut buf = [0; 4];
+    assert_errkind!(
+        run(writer.read_vectored(&mut [IoSliceMut::new(&mut buf)])),
+        ErrorKind::Badf
+    );
+    assert_errkind!(
+        run(reader.write_vectored(&[IoSlice::new(b"x")])),
+        ErrorKind::Badf
+    );
+}
+
+#[test]
+fn host_devices_see_the_guests_writes() {
+    let fs = filesystem();
+    let root = fs.root();
+    let output = Arc::new(RwLock::new(Cursor::new(Vec::new())));
+    root.put_char_device("console", Arc::new(WritePipe::from_shared(output.clone())))
+        .expect("put device");
+
+    let f = open(&root, "console", false, true, FdFlags::empty());
+    write_all(&*f, b"boot ok\n");
+    assert_eq!(output.read().unwrap().get_ref(), b"boot ok\n");
+    // The pipe calls itself a pipe, but the guest sees a character device.
+    assert_eq!(run(f.get_filetype()).unwrap(), FileType::CharacterDevice);
+}
+
+/// What wasi-libc's `isatty` decides about `fd`: a terminal is a character
+/// device which can't be seeked or told on.
+fn isatty(ctx: &mut WasiCtx, fd: u32) -> bool {
+    let stat = run(ctx.fd_fdstat_get(types::Fd::from(fd))).expect("fdstat");
+    stat.fs_filetype == types::Filetype::CharacterDevice
+        && !stat
+            .fs_rights_base
+            .intersects(types::Rights::FD_SEEK | types::Rights::FD_TELL)
+}
+
+/// A guest which prints a progress bar to a terminal and plain lines
+/// otherwise, deciding by what's on its stdout and writing to `stdout`.
+fn report_progress(ctx: &mut WasiCtx, stdout: &dyn WasiFile) {
+    let line: &[u8] = if isatty(ctx, 1) {
+        b"\r[=====     ] 50%"
+    } else {
+        b"50% done\n"
+    };
+    write_all(stdout, line);
+}
+
+fn tty_caps() -> FileCaps {
+    FileCaps::all() - FileCaps::SEEK - FileCaps::TELL
+}
+
+/// A context whose stdout is the file at `path`, with `caps`, and another
+/// handle on the file for the guest to write through.
+fn ctx_with_stdout(root: &Dir, path: &str, caps: FileCaps) -> (WasiCtx, Box<dyn WasiFile>) {
+    let open = || {
+        root.open_file_with_caps(false, path, OFlags::empty(), caps, FdFlags::empty())
+            .expect("open stdout")
+    };
+    let mut ctx = WasiCtxBuilder::new().build();
+    ctx.insert_file(1, open(), caps);
+    (ctx, open())
+}
+
+#[test]
+fn isatty_in_a_guest() {
+    let fs = filesystem();
+    let root = fs.root();
+    root.put_char_device("tty", Arc::new(EchoDevice::new()))
+        .expect("put device");
+    create_file(&root, "log", b"");
+
+    let (mut ctx, stdout) = ctx_with_stdout(&root, "tty", tty_caps());
+    assert!(isatty(&mut ctx, 1));
+    report_progress(&mut ctx, &*stdout);
+    let tty = open(&root, "tty", true, false, FdFlags::empty());
+    assert_eq!(read(&*tty, 64).expect("read"), b"\r[=====     ] 50%");
+
+    let (mut ctx, stdout) = ctx_with_stdout(&root, "log", FileCaps::all());
+    assert!(!isatty(&mut ctx, 1));
+    report_progress(&mut ctx, &*stdout);
+    assert_eq!(read_file(&root, "log"), b"50% done\n");
+
+    // A regular file isn't a terminal even without the rights to seek it,
+    // and wasi-libc doesn't take a device which can be seeked for one.
+    let (mut ctx, _) = ctx_with_stdout(&root, "log", tty_caps());
+    assert!(!isatty(&mut ctx, 1));
+    let (mut ctx, _) = ctx_with_stdout(&root, "tty", FileCaps::all());
+    assert!(!isatty(&mut ctx, 1));
+}
+
+#[test]
+fn null_device() {
+    let fs = filesystem();
+    let root = fs.root();
+    fs.put_null_device("dev/null").expect("put device");
+    assert_errkind!(fs.put_null_device("dev/null"), ErrorKind::Exist);
+    let stat = run(root.get_path_filestat("dev", false)).expect("stat");
+    assert_eq!(stat.filetype, FileType::Directory);
+
+    let f = open(&root, "dev/null", true, true, FdFlags::empty());
+    assert_eq!(run(f.get_filetype()).unwrap(), FileType::CharacterDevice);
+    assert_eq!(run(f.get_filestat()).expect("fstat").size, 0);
+    let n =
+        run(f.write_vectored(&[IoSlice::new(b"thrown "), IoSlice::new(b"away")])).expect("write");
+    assert_eq!(n, 11);
+    let mut a = [1; 4];
+    let mut b = [2; 4];
+    let n = run(f.read_vectored(&mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)]))
+        .expect("read");
+    assert_eq!(n, 0);
+    assert_eq!((a, b), ([1; 4], [2; 4]));
+    assert_eq!(run(f.seek(SeekFrom::End(100))).expect("seek"), 0);
+    assert_eq!(run(f.seek(SeekFrom::Current(0))).expect("tell"), 0);
+    assert_eq!(run(f.num_ready_bytes()).expect("ready"), 0);
+
+    // A guest redirecting its output there.
+    let (mut ctx, stdout) = ctx_with_stdout(&root, "dev/null", FileCaps::all());
+    assert!(!isatty(&mut ctx, 1));
+    report_progress(&mut ctx, &*stdout);
+    assert_eq!(read(&*f, 64).expect("read"), b"");
+}
+
+/// Read `len` bytes from `f` in reads of up to 64KiB spread over two
+/// buffers, as a guest filling a large buffer would.
+fn read_exactly(f: &dyn WasiFile, len: usize) -> Vec<u8> {
+    let mut data = vec![0xff; len];
+    let mut offset = 0;
+    while offset < len {
+        let end = len.min(offset + (1 << 16));
+        let (a, b) = data[offset..end].split_at_mut((end - offset) / 2);
+        let n = run(f.read_vectored(&mut [IoSliceMut::new(a), IoSliceMut::new(b)])).expect("read");
+        assert_eq!(n as usize, end - offset, "short read");
+        offset = end;
+    }
+    data
+}
+
+#[test]
+fn zero_and_random_devices() {
+    let fs = filesystem();
+    let root = fs.root();
+    fs.put_zero_device("dev/zero").expect("put device");
+    fs.put_random_device("dev/urandom").expect("put device");
+    run(root.create_dir("det")).expect("mkdir");
+    root.put_char_device(
+        "det/urandom",
+        Arc::new(RandomDevice::with_source(Box::new(Deterministic::new(
+            vec![1, 2, 3],
+        )))),
+    )
+    .expect("put device");
+    assert_eq!(names_at(&root, "dev"), [".", "..", "urandom", "zero"]);
+
+    let zero = open(&root, "dev/zero", true, true, FdFlags::empty());
+    assert_eq!(read_exactly(&*zero, 1 << 20), vec![0; 1 << 20]);
+    let mut peeked = [1; 8];
+    assert_eq!(run(zero.peek(&mut peeked)).unwrap(), 8);
+    assert_eq!(peeked, [0; 8]);
+    write_all(&*zero, b"discarded");
+    assert_eq!(run(zero.seek(SeekFrom::Start(10))).expect("seek"), 0);
+
+    let urandom = open(&root, "dev/urandom", true, true, FdFlags::empty());
+    let data = read_exactly(&*urandom, 1 << 20);
+    // A megabyte of the host's randomness won't all be one byte.
+    assert!(data.iter().any(|&b| b != data[0]));
+    write_all(&*urandom, b"seed?");
+
+    let det = open(&root, "det/urandom", true, false, FdFlags::empty());
+    let data = read_exactly(&*det, 1 << 20);
+    assert!(data
+        .iter()
+        .enumerate()
+        .all(|(i, &b)| b == (i % 3) as u8 + 1));
+
+    // Both are always ready, without claiming endless bytes.
+    for f in &[&zero, &urandom] {
+        assert_eq!(run(f.get_filetype()).unwrap(), FileType::CharacterDevice);
+        let ready = run(f.num_ready_bytes()).expect("ready");
+        assert!(ready > 0 && ready < u64::from(u32::MAX));
+    }
+}
+
+/// A host buffer a device writes to.
+type Sink = Arc<RwLock<Cursor<Vec<u8>>>>;
+
+#[test]
+fn device_factories() {
+    let fs = filesystem();
+    let root = fs.root();
+    // A metrics sink: each open writes to a pipe of its own, kept by the
+    // host along with the fdflags it was opened with.
+    let opened: Arc<Mutex<Vec<(FdFlags, Sink)>>> = Arc::default();
+    let sinks = opened.clone();
+    root.put_device(
+        "metrics",
+        Box::new(move |fdflags| {
+            let sink = Arc::new(RwLock::new(Cursor::new(Vec::new())));
+            sinks.lock().unwrap().push((fdflags, sink.clone()));
+            Ok(Box::new(WritePipe::from_shared(sink)))
+        }),
+    )
+    .expect("put device");
+    assert_eq!(opened.lock().unwrap().len(), 0, "opened only by the guest");
+
+    let stat = run(root.get_path_filestat("metrics", false)).expect("stat");
+    assert_eq!(stat.filetype, FileType::CharacterDevice);
+    let entry = readdir(&root)
+        .into_iter()
+        .find(|e| e.name == "metrics")
+        .expect("entry");
+    assert_eq!(entry.filetype, FileType::CharacterDevice);
+    assert_eq!(entry.inode, stat.inode);
+
+    let a = open(&root, "metrics", false, true, FdFlags::empty());
+    // CREATE and TRUNCATE don't apply to a device, but EXCLUSIVE does.
+    let b = run(root.open_file(
+        false,
+        "metrics",
+        OFlags::CREATE | OFlags::TRUNCATE,
+        false,
+        true,
+        FdFlags::APPEND,
+    ))
+    .expect("open");
+    assert_errkind!(
+        run(root.open_file(
+            false,
+            "metrics",
+            OFlags::CREATE | OFlags::EXCLUSIVE,
+            false,
+            true,
+            FdFlags::empty(),
+        )),
+        ErrorKind::Exist
+    );
+    write_all(&*a, b"requests 10\n");
+    write_all(&*b, b"errors 0\n");
+    write_all(&*a, b"requests 11\n");
+    for f in &[&a, &b] {
+        assert_eq!(run(f.get_filetype()).unwrap(), FileType::CharacterDevice);
+        assert_eq!(run(f.get_filestat()).expect("fstat").inode, stat.inode);
+    }
+    {
+        let opened = opened.lock().unwrap();
+        assert_eq!(opened.len(), 2);
+        assert_eq!(opened[0].0, FdFlags::empty());
+        assert_eq!(
+            opened[0].1.read().unwrap().get_ref(),
+            b"requests 10\nrequests 11\n"
+        );
+        assert_eq!(opened[1].0, FdFlags::APPEND);
+        assert_eq!(opened[1].1.read().unwrap().get_ref(), b"errors 0\n");
+    }
+
+    // A failing factory fails the open.
+    root.put_device(
+        "busy",
+        Box::new(|_| Err(wasi_common::Error::perm().context("sink is busy"))),
+    )
+    .expect("put device");
+    assert_errkind!(
+        run(root.open_file(
+            false,
+            "busy",
+            OFlags::empty(),
+            false,
+            true,
+            FdFlags::empty()
+        )),
+        ErrorKind::Perm
+    );
+    assert_errkind!(
+        root.put_device("busy", Box::new(|_| Ok(Box::new(NullDevice::new())))),
+        ErrorKind::Exist
+    );
+}
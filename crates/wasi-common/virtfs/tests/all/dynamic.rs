@@ -0,0 +1,146 @@
+use crate::{filesystem, open_read, read_file, readdir, run};
+use std::io::IoSliceMut;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, FileType, OFlags},
+    Error, ErrorExt, ErrorKind,
+};
+
+/// A generator producing `opened <n>` for the nth open.
+fn counter() -> (Arc<AtomicU64>, wasi_virtfs::GenerateContents) {
+    let count = Arc::new(AtomicU64::new(0));
+    let generator = count.clone();
+    let generate = Box::new(move || {
+        let n = generator.fetch_add(1, Ordering::Relaxed) + 1;
+        Ok(format!("opened {}", n).into_bytes())
+    });
+    (count, generate)
+}
+
+#[test]
+fn each_open_generates_contents() {
+    let fs = filesystem();
+    let root = fs.root();
+    let (count, generate) = counter();
+    root.put_dynamic_file("status", generate)
+        .expect("put dynamic file");
+    assert_eq!(
+        count.load(Ordering::Relaxed),
+        0,
+        "nothing generated up front"
+    );
+
+    let first = open_read(&root, false, "status").expect("open");
+    let second = open_read(&root, false, "status").expect("open");
+    assert_eq!(count.load(Ordering::Relaxed), 2);
+
+    // Each handle keeps the snapshot it opened, however many reads it takes.
+    let mut buf = [0; 6];
+    let n = run(first.read_vectored(&mut [IoSliceMut::new(&mut buf)])).expect("read");
+    assert_eq!(&buf[..n as usize], b"opened");
+    assert_eq!(read_file(&root, "status"), b"opened 3");
+    let mut buf = [0; 64];
+    let n = run(first.read_vectored(&mut [IoSliceMut::new(&mut buf)])).expect("read");
+    assert_eq!(&buf[..n as usize], b" 1");
+    let n = run(second.read_vectored(&mut [IoSliceMut::new(&mut buf)])).expect("read");
+    assert_eq!(&buf[..n as usize], b"opened 2");
+}
+
+#[test]
+fn stat_and_readdir() {
+    let fs = filesystem();
+    let root = fs.root();
+    root.put_dynamic_file("metrics", Box::new(|| Ok(b"requests 12\n".to_vec())))
+        .expect("put dynamic file");
+
+    let stat = run(root.get_path_filestat("metrics", false)).expect("stat");
+    assert_eq!(stat.filetype, FileType::RegularFile);
+    assert_eq!(stat.size, 0, "nothing has been generated yet");
+    let f = open_read(&root, false, "metrics").expect("open");
+    assert_eq!(run(f.get_filestat()).unwrap().size, 12);
+    assert_eq!(run(f.num_ready_bytes()).unwrap(), 12);
+    let after = run(root.get_path_filestat("metrics", false)).expect("stat");
+    assert_eq!(after.size, 12, "stat reports the last size generated");
+    assert_eq!(after.inode, stat.inode);
+    assert_eq!(run(f.get_filestat()).unwrap().inode, stat.inode);
+
+    let entries = readdir(&root);
+    let entry = entries
+        .iter()
+        .find(|e| e.name == "metrics")
+        .expect("readdir lists the file");
+    assert_eq!(entry.filetype, FileType::RegularFile);
+    assert_eq!(entry.inode, stat.inode);
+}
+
+#[test]
+fn writes_are_rejected() {
+    let fs = filesystem();
+    let root = fs.root();
+    let (count, generate) = counter();
+    root.put_dynamic_file("status", generate)
+        .expect("put dynamic file");
+
+    for oflags in &[OFlags::empty(), OFlags::TRUNCATE, OFlags::CREATE] {
+        assert_errkind!(
+            run(root.open_file(false, "status", *oflags, true, true, FdFlags::empty())),
+            ErrorKind::Rofs
+        );
+    }
+    assert_errkind!(
+        run(root.open_file(
+            false,
+            "status",
+            OFlags::CREATE | OFlags::EXCLUSIVE,
+            true,
+            true,
+            FdFlags::empty()
+        )),
+        ErrorKind::Exist
+    );
+    crate::create_file(&root, "other", b"other");
+    assert_errkind!(fs.copy_file("other", "status"), ErrorKind::Rofs);
+    assert_eq!(count.load(Ordering::Relaxed), 0);
+
+    // A copy is an ordinary file holding freshly generated contents.
+    fs.copy_file("status", "copy").expect("copy");
+    assert_eq!(read_file(&root, "copy"), b"opened 1");
+    assert_eq!(read_file(&root, "copy"), b"opened 1");
+}
+
+#[test]
+fn generator_errors_fail_the_open() {
+    let fs = filesystem();
+    let root = fs.root();
+    root.put_dynamic_file(
+        "broken",
+        Box::new(|| Err(Error::not_supported().context("no metrics yet"))),
+    )
+    .expect("put dynamic file");
+    assert_errkind!(open_read(&root, false, "broken"), ErrorKind::Notsup);
+}
+
+#[test]
+fn directory_operations() {
+    let fs = filesystem();
+    let root = fs.root();
+    root.put_dynamic_file("status", Box::new(|| Ok(b"ok".to_vec())))
+        .expect("put dynamic file");
+
+    assert_errkind!(
+        root.put_dynamic_file("status", Box::new(|| Ok(Vec::new()))),
+        ErrorKind::Exist
+    );
+    assert_errkind!(
+        run(root.hard_link("status", &root, "link")),
+        ErrorKind::Perm
+    );
+    run(root.rename("status", &root, "renamed")).expect("rename");
+    assert_eq!(read_file(&root, "renamed"), b"ok");
+    run(root.symlink("renamed", "link")).expect("symlink");
+    assert_eq!(read_file(&root, "link"), b"ok");
+    run(root.unlink_file("renamed")).expect("unlink");
+    assert_errkind!(open_read(&root, false, "renamed"), ErrorKind::Noent);
+}
@@ -0,0 +1,157 @@
+use crate::{create_file, filesystem, run, ticking_filesystem};
+use cap_std::time::{Duration, SystemTime};
+use std::sync::Arc;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags},
+    ErrorKind,
+};
+use wasi_virtfs::{clock::FixedClock, Filesystem};
+
+/// A tree of every kind of entry an archive holds, with distinct mtims.
+fn fixture() -> Arc<Filesystem> {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    run(root.create_dir("bin")).expect("mkdir");
+    create_file(&root, "bin/tool", b"#!/bin/sh\necho hi\n");
+    run(root.hard_link("bin/tool", &root, "bin/alias")).expect("link");
+    run(root.symlink("tool", "bin/short")).expect("symlink");
+    run(root.create_dir("empty")).expect("mkdir");
+    run(root.create_dir("out")).expect("mkdir");
+    create_file(&root, "out/big.bin", &vec![7; 100_000]);
+    create_file(&root, "out/nested name with spaces", b"");
+    fs.create_dir_all(&format!("deep/{}", "x".repeat(120)))
+        .expect("mkdir");
+    fs
+}
+
+fn export(fs: &Arc<Filesystem>, path: &str) -> Vec<u8> {
+    let mut archive = Vec::new();
+    fs.to_tar_at(path, &mut archive).expect("export");
+    archive
+}
+
+fn reimport(archive: &[u8]) -> Arc<Filesystem> {
+    let clock = FixedClock::new(SystemTime::from_std(std::time::UNIX_EPOCH));
+    Filesystem::from_tar(archive, Box::new(clock), 0).expect("import")
+}
+
+fn paths(archive: &[u8]) -> Vec<String> {
+    tar::Archive::new(archive)
+        .entries()
+        .expect("entries")
+        .map(|e| {
+            let e = e.expect("entry");
+            e.path().expect("path").to_str().expect("UTF-8").to_owned()
+        })
+        .collect()
+}
+
+#[test]
+fn round_trips_through_from_tar() {
+    let fs = fixture();
+    let archive = export(&fs, ".");
+    let copy = reimport(&archive);
+    assert_eq!(
+        copy.root().hash_tree(".", true).expect("hash"),
+        fs.root().hash_tree(".", true).expect("hash")
+    );
+    let tool = run(copy.root().get_path_filestat("bin/tool", false)).expect("stat");
+    let alias = run(copy.root().get_path_filestat("bin/alias", false)).expect("stat");
+    assert_eq!(tool.inode, alias.inode);
+    assert_eq!(tool.nlink, 2);
+
+    assert_eq!(
+        paths(&archive)[..8],
+        [
+            "./",
+            "bin/",
+            "bin/alias",
+            "bin/short",
+            "bin/tool",
+            "deep/",
+            &format!("deep/{}/", "x".repeat(120)),
+            "empty/",
+        ]
+    );
+}
+
+#[test]
+fn archives_are_deterministic() {
+    let fs = fixture();
+    let archive = export(&fs, ".");
+    assert_eq!(export(&fs, "."), archive, "exporting twice");
+
+    // The same tree, built in another order at the same time, archives the
+    // same.
+    let build = |reversed: bool| {
+        let clock = FixedClock::new(SystemTime::from_std(
+            std::time::UNIX_EPOCH + Duration::from_secs(1_000),
+        ));
+        let fs = Filesystem::new(Box::new(clock), 0);
+        let root = fs.root();
+        let mut names = vec!["a", "b", "c"];
+        if reversed {
+            names.reverse();
+        }
+        for name in names {
+            run(root.create_dir(name)).expect("mkdir");
+            create_file(&root, &format!("{}/file", name), name.as_bytes());
+        }
+        export(&fs, ".")
+    };
+    assert_eq!(build(false), build(true));
+}
+
+#[test]
+fn exports_a_subtree() {
+    let fs = fixture();
+    let root = fs.root();
+    // A file held open after it's unlinked isn't in the tree any more.
+    create_file(&root, "out/tmp", b"scratch");
+    let _tmp = run(root.open_file(
+        false,
+        "out/tmp",
+        OFlags::empty(),
+        true,
+        false,
+        FdFlags::empty(),
+    ))
+    .expect("open");
+    run(root.unlink_file("out/tmp")).expect("unlink");
+    // A hard link to a file outside the subtree archives as the file.
+    run(root.hard_link("bin/tool", &root, "out/tool")).expect("link");
+
+    let archive = export(&fs, "out");
+    assert_eq!(
+        paths(&archive),
+        ["./", "big.bin", "nested name with spaces", "tool"]
+    );
+    let copy = reimport(&archive);
+    assert_eq!(
+        copy.root().hash_tree(".", true).expect("hash"),
+        root.hash_tree("out", true).expect("hash")
+    );
+    assert_eq!(
+        run(copy.root().get_path_filestat("tool", false))
+            .expect("stat")
+            .nlink,
+        1
+    );
+
+    assert_errkind!(fs.to_tar_at("bin/tool", Vec::new()), ErrorKind::Notdir);
+    assert_errkind!(fs.to_tar_at("missing", Vec::new()), ErrorKind::Noent);
+    assert_errkind!(filesystem().to_tar(FailingWriter), ErrorKind::Io);
+}
+
+/// A writer with no room for anything.
+struct FailingWriter;
+
+impl std::io::Write for FailingWriter {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Ok(0)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+use crate::{filesystem, open, read, readdir, run, write};
+use std::future::Future;
+use std::io::{self, IoSlice, Read, SeekFrom, Write};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, FileType},
+    ErrorKind,
+};
+
+fn host_read(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut buf = [0; 256];
+    let n = reader.read(&mut buf)?;
+    Ok(buf[..n].to_vec())
+}
+
+#[test]
+fn guest_writes_host_reads() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("run")).expect("mkdir");
+    let (mut events, writer) = fs.create_fifo("run/events", 64).expect("create fifo");
+    drop(writer);
+
+    let stat = run(root.get_path_filestat("run/events", false)).expect("stat");
+    assert_eq!(stat.filetype, FileType::Pipe);
+    assert_eq!(stat.size, 0);
+    let dir = run(root.open_dir(false, "run")).expect("open dir");
+    let entry = readdir(&*dir)
+        .into_iter()
+        .find(|e| e.name == "events")
+        .expect("entry");
+    assert_eq!(entry.filetype, FileType::Pipe);
+    assert_eq!(entry.inode, stat.inode);
+
+    // Nothing has it open for writing yet, so it's at its end.
+    assert_eq!(host_read(&mut events).expect("read"), b"");
+    let guest = open(&root, "run/events", false, true, FdFlags::NONBLOCK);
+    assert_eq!(run(guest.get_filetype()).unwrap(), FileType::Pipe);
+    assert_eq!(
+        host_read(&mut events).unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+
+    // The host drains lines as the guest writes them.
+    let mut lines = Vec::new();
+    for i in 0..10 {
+        let line = format!("event {}\n", i);
+        assert_eq!(
+            write(&*guest, line.as_bytes()).expect("write"),
+            line.len() as u64
+        );
+        if i % 3 == 1 {
+            lines.extend(host_read(&mut events).expect("read"));
+        }
+    }
+    assert_eq!(events.ready_bytes(), 16);
+    lines.extend(host_read(&mut events).expect("read"));
+    let expected: String = (0..10).map(|i| format!("event {}\n", i)).collect();
+    assert_eq!(String::from_utf8(lines).unwrap(), expected);
+
+    assert_errkind!(run(guest.seek(SeekFrom::Start(0))), ErrorKind::Spipe);
+    assert_errkind!(
+        run(guest.write_vectored_at(&[IoSlice::new(b"x")], 0)),
+        ErrorKind::Spipe
+    );
+    assert_errkind!(read(&*guest, 1), ErrorKind::Badf);
+    assert_errkind!(
+        run(root.hard_link("run/events", &root, "events")),
+        ErrorKind::Perm
+    );
+    assert_errkind!(fs.create_fifo("run/events", 64), ErrorKind::Exist);
+    assert_errkind!(fs.create_fifo("other", 0), ErrorKind::Inval);
+
+    // Once the guest closes it, the host reads to the end.
+    drop(guest);
+    assert_eq!(host_read(&mut events).expect("read"), b"");
+
+    // With the host's reader gone, there's no one to write to.
+    let guest = open(&root, "run/events", false, true, FdFlags::NONBLOCK);
+    drop(events);
+    assert_errkind!(write(&*guest, b"lost"), ErrorKind::Pipe);
+}
+
+#[test]
+fn full_fifos_push_back() {
+    let fs = filesystem();
+    let root = fs.root();
+    let (mut reader, mut writer) = fs.create_fifo("fifo", 8).expect("create fifo");
+    let guest_writer = open(&root, "fifo", false, true, FdFlags::NONBLOCK);
+    let guest_reader = open(&root, "fifo", true, false, FdFlags::NONBLOCK);
+
+    // The guest's writes are cut short when the FIFO fills, and then fail.
+    assert_eq!(write(&*guest_writer, b"0123456789").expect("write"), 8);
+    assert_errkind!(write(&*guest_writer, b"89"), ErrorKind::Again);
+    assert_eq!(
+        writer.write(b"x").unwrap_err().kind(),
+        io::ErrorKind::WouldBlock
+    );
+    assert_eq!(run(guest_reader.num_ready_bytes()).unwrap(), 8);
+    let mut buf = [0; 5];
+    reader.read_exact(&mut buf).expect("read");
+    assert_eq!(&buf, b"01234");
+    assert_eq!(write(&*guest_writer, b"89abcdef").expect("write"), 5);
+    assert_eq!(read(&*guest_reader, 64).expect("read"), b"56789abc");
+
+    // The same goes for the host writing to the guest.
+    assert_errkind!(read(&*guest_reader, 64), ErrorKind::Again);
+    assert_eq!(writer.write(b"hello, guest").expect("write"), 8);
+    let mut peeked = [0; 5];
+    assert_eq!(run(guest_reader.peek(&mut peeked)).unwrap(), 5);
+    assert_eq!(&peeked, b"hello");
+    assert_eq!(read(&*guest_reader, 64).expect("read"), b"hello, g");
+
+    // A guest handle reading and writing keeps both ends open.
+    drop((guest_writer, guest_reader, reader, writer));
+    let both = open(&root, "fifo", true, true, FdFlags::NONBLOCK);
+    assert_eq!(write(&*both, b"loop").expect("write"), 4);
+    assert_eq!(read(&*both, 64).expect("read"), b"loop");
+    assert_errkind!(read(&*both, 64), ErrorKind::Again);
+}
+
+/// Counts the times it's woken.
+struct Wakes(AtomicUsize);
+
+impl Wake for Wakes {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn poll<T>(
+    future: &mut Pin<Box<dyn Future<Output = T> + Send + '_>>,
+    wakes: &Arc<Wakes>,
+) -> Poll<T> {
+    let waker = Waker::from(wakes.clone());
+    future.as_mut().poll(&mut Context::from_waker(&waker))
+}
+
+#[test]
+fn guest_and_host_take_turns_on_one_thread() {
+    let fs = filesystem();
+    let root = fs.root();
+    let (mut reader, mut writer) = fs.create_fifo("fifo", 4).expect("create fifo");
+    // Without NONBLOCK, as most guests open files.
+    let guest_writer = open(&root, "fifo", false, true, FdFlags::empty());
+    let guest_reader = open(&root, "fifo", true, false, FdFlags::empty());
+
+    // Neither a read of an empty FIFO nor a write to a full one waits for
+    // the host, which can only run once the guest's call returns.
+    let mut sent = Vec::new();
+    let mut received = Vec::new();
+    for i in 0..5u8 {
+        let chunk = [b'a' + i; 3];
+        let mut written = 0;
+        while written < chunk.len() {
+            match write(&*guest_writer, &chunk[written..]) {
+                Ok(n) => written += n as usize,
+                Err(e) => {
+                    assert!(
+                        matches!(e.downcast_ref::<ErrorKind>(), Some(ErrorKind::Again)),
+                        "{:?}",
+                        e
+                    );
+                    received.extend(host_read(&mut reader).expect("read"));
+                }
+            }
+        }
+        sent.extend(&chunk);
+    }
+    received.extend(host_read(&mut reader).expect("read"));
+    assert_eq!(received, sent);
+    assert_errkind!(read(&*guest_reader, 64), ErrorKind::Again);
+
+    // And the other way around.
+    let mut received = Vec::new();
+    for chunk in [b"ping".as_ref(), b"pong"].iter() {
+        assert_eq!(writer.write(chunk).expect("write"), 4);
+        assert_eq!(
+            writer.write(b"!").unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        received.extend(read(&*guest_reader, 64).expect("read"));
+        assert_errkind!(read(&*guest_reader, 64), ErrorKind::Again);
+    }
+    assert_eq!(received, b"pingpong");
+
+    // The guest reads the end once nothing else can write.
+    drop((writer, guest_writer));
+    assert_eq!(read(&*guest_reader, 64).expect("read"), b"");
+}
+
+#[test]
+fn readiness_waits_for_data_and_room() {
+    let fs = filesystem();
+    let root = fs.root();
+    let (mut reader, mut writer) = fs.create_fifo("fifo", 4).expect("create fifo");
+    let guest_reader = open(&root, "fifo", true, false, FdFlags::NONBLOCK);
+    let guest_writer = open(&root, "fifo", false, true, FdFlags::NONBLOCK);
+    let wakes = Arc::new(Wakes(AtomicUsize::new(0)));
+    let woken = || wakes.0.load(Ordering::SeqCst);
+
+    // Readable once the host writes.
+    let mut readable = guest_reader.readable();
+    assert!(poll(&mut readable, &wakes).is_pending());
+    assert_eq!(woken(), 0);
+    writer.write_all(b"hi").expect("write");
+    assert_eq!(woken(), 1);
+    assert!(matches!(poll(&mut readable, &wakes), Poll::Ready(Ok(()))));
+    drop(readable);
+    assert_eq!(read(&*guest_reader, 64).expect("read"), b"hi");
+
+    // Writable once the host makes room.
+    assert!(matches!(
+        poll(&mut guest_writer.writable(), &wakes),
+        Poll::Ready(Ok(()))
+    ));
+    assert_eq!(write(&*guest_writer, b"full").expect("write"), 4);
+    let mut writable = guest_writer.writable();
+    assert!(poll(&mut writable, &wakes).is_pending());
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf).expect("read");
+    assert_eq!(woken(), 2);
+    assert!(matches!(poll(&mut writable, &wakes), Poll::Ready(Ok(()))));
+    drop(writable);
+
+    // The end of the FIFO is readable too.
+    assert_eq!(read(&*guest_reader, 64).expect("read"), b"ull");
+    let mut readable = guest_reader.readable();
+    assert!(poll(&mut readable, &wakes).is_pending());
+    drop((writer, guest_writer));
+    assert!(matches!(poll(&mut readable, &wakes), Poll::Ready(Ok(()))));
+    drop(readable);
+    assert_errkind!(run(guest_reader.writable()), ErrorKind::Badf);
+}
@@ -0,0 +1,525 @@
+use crate::{capacity, create_file, filesystem, read_file, run, ticking_filesystem};
+use std::io::{IoSlice, IoSliceMut, SeekFrom};
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags, WasiFile},
+    ErrorKind,
+};
+
+fn open_rw(dir: &dyn WasiDir, path: &str, fdflags: FdFlags) -> Box<dyn WasiFile> {
+    run(dir.open_file(false, path, OFlags::empty(), true, true, fdflags)).expect("open file")
+}
+
+fn write(f: &dyn WasiFile, data: &[u8]) -> u64 {
+    run(f.write_vectored(&[IoSlice::new(data)])).expect("write")
+}
+
+fn pwrite(f: &dyn WasiFile, data: &[u8], offset: u64) -> u64 {
+    run(f.write_vectored_at(&[IoSlice::new(data)], offset)).expect("pwrite")
+}
+
+fn tell(f: &dyn WasiFile) -> u64 {
+    run(f.seek(SeekFrom::Current(0))).expect("tell")
+}
+
+#[test]
+fn pwrite_leaves_position_alone() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = open_rw(&root, "file", FdFlags::empty());
+
+    assert_eq!(write(&*f, b"hello "), 6);
+    assert_eq!(tell(&*f), 6);
+    assert_eq!(pwrite(&*f, b"J", 0), 1);
+    assert_eq!(tell(&*f), 6);
+    assert_eq!(write(&*f, b"world"), 5);
+    assert_eq!(tell(&*f), 11);
+    assert_eq!(read_file(&root, "file"), b"Jello world");
+
+    // A pwrite past the current position doesn't move it either.
+    assert_eq!(pwrite(&*f, b"!", 11), 1);
+    assert_eq!(tell(&*f), 11);
+    assert_eq!(write(&*f, b"?"), 1);
+    assert_eq!(read_file(&root, "file"), b"Jello world?");
+}
+
+#[test]
+fn append_writes_at_end() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "log", b"first\n");
+    let f = open_rw(&root, "log", FdFlags::APPEND);
+
+    assert_eq!(write(&*f, b"second\n"), 7);
+    assert_eq!(tell(&*f), 13);
+    assert_eq!(read_file(&root, "log"), b"first\nsecond\n");
+
+    // Another handle extending the file doesn't get overwritten.
+    let other = open_rw(&root, "log", FdFlags::empty());
+    assert_eq!(pwrite(&*other, b"third\n", 13), 6);
+    assert_eq!(write(&*f, b"fourth\n"), 7);
+    assert_eq!(tell(&*f), 26);
+    assert_eq!(read_file(&root, "log"), b"first\nsecond\nthird\nfourth\n");
+}
+
+#[test]
+fn append_after_shrink() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "log", b"");
+    let f = open_rw(&root, "log", FdFlags::APPEND);
+
+    write(&*f, b"0123456789");
+    run(f.set_filestat_size(4)).expect("shrink");
+    assert_eq!(write(&*f, b"abc"), 3);
+    assert_eq!(tell(&*f), 7);
+    assert_eq!(read_file(&root, "log"), b"0123abc");
+
+    run(f.set_filestat_size(0)).expect("truncate");
+    write(&*f, b"xyz");
+    assert_eq!(read_file(&root, "log"), b"xyz");
+}
+
+#[test]
+fn seek_in_append_mode() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "log", b"0123456789");
+    let f = open_rw(&root, "log", FdFlags::APPEND);
+
+    assert_eq!(tell(&*f), 0);
+    assert_eq!(run(f.seek(SeekFrom::End(0))).expect("seek to end"), 10);
+    assert_eq!(tell(&*f), 10);
+    assert_eq!(run(f.seek(SeekFrom::End(-4))).expect("seek before end"), 6);
+    assert_eq!(tell(&*f), 6);
+    write(&*f, b"abc");
+    assert_eq!(tell(&*f), 13);
+    assert_eq!(run(f.seek(SeekFrom::End(0))).unwrap(), 13);
+
+    assert_errkind!(run(f.seek(SeekFrom::Start(0))), ErrorKind::Badf);
+    assert_errkind!(run(f.seek(SeekFrom::Current(1))), ErrorKind::Badf);
+}
+
+fn ready(f: &dyn WasiFile) -> u64 {
+    run(f.num_ready_bytes()).expect("num_ready_bytes")
+}
+
+#[test]
+fn num_ready_bytes() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"0123456789");
+    let f = open_rw(&root, "file", FdFlags::empty());
+
+    assert_eq!(ready(&*f), 10);
+    run(f.seek(SeekFrom::Start(4))).expect("seek");
+    assert_eq!(ready(&*f), 6);
+    run(f.seek(SeekFrom::End(0))).expect("seek to end");
+    assert_eq!(ready(&*f), 0);
+}
+
+#[test]
+fn num_ready_bytes_past_end() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"0123456789");
+    let f = open_rw(&root, "file", FdFlags::empty());
+
+    run(f.seek(SeekFrom::Start(100))).expect("seek past end");
+    assert_eq!(ready(&*f), 0);
+    run(f.seek(SeekFrom::Start(u64::MAX))).expect("seek far past end");
+    assert_eq!(ready(&*f), 0);
+}
+
+#[test]
+fn num_ready_bytes_after_truncate() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"0123456789");
+    let f = open_rw(&root, "file", FdFlags::empty());
+    run(f.seek(SeekFrom::Start(8))).expect("seek");
+
+    let other = open_rw(&root, "file", FdFlags::empty());
+    run(other.set_filestat_size(3)).expect("truncate");
+    assert_eq!(ready(&*f), 0);
+
+    // Growing the file again makes the bytes past the position ready.
+    run(other.set_filestat_size(12)).expect("extend");
+    assert_eq!(ready(&*f), 4);
+}
+
+#[test]
+fn reads_at_end_of_file() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"0123456789");
+    let f = open_rw(&root, "file", FdFlags::empty());
+    let atim = run(f.get_filestat()).unwrap().atim;
+    let mut buf = [0; 4];
+
+    for offset in &[10, 11, u64::MAX] {
+        let nbytes = run(f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], *offset));
+        assert_eq!(nbytes.expect("pread"), 0, "pread at {}", offset);
+
+        run(f.seek(SeekFrom::Start(*offset))).expect("seek");
+        let nbytes = run(f.read_vectored(&mut [IoSliceMut::new(&mut buf)]));
+        assert_eq!(nbytes.expect("read"), 0, "read at {}", offset);
+        assert_eq!(tell(&*f), *offset, "read at {} leaves the position", offset);
+        assert_eq!(
+            run(f.peek(&mut buf)).expect("peek"),
+            0,
+            "peek at {}",
+            offset
+        );
+    }
+    assert_eq!(run(f.get_filestat()).unwrap().atim, atim);
+}
+
+#[test]
+fn pread_until_end() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"0123456789");
+    let f = open_rw(&root, "file", FdFlags::empty());
+    let mut buf = [0; 4];
+    let mut contents = Vec::new();
+    loop {
+        let offset = contents.len() as u64;
+        let nbytes = run(f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], offset))
+            .expect("pread") as usize;
+        if nbytes == 0 {
+            break;
+        }
+        contents.extend_from_slice(&buf[..nbytes]);
+    }
+    assert_eq!(contents, b"0123456789");
+}
+
+#[test]
+fn short_reads_split_across_iovecs() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"0123456789");
+    let f = open_rw(&root, "file", FdFlags::empty());
+    run(f.seek(SeekFrom::Start(3))).expect("seek");
+
+    // Each read fills the buffers in order, stops at the end of the file,
+    // and leaves what it didn't reach untouched.
+    let (mut a, mut b, mut c) = ([0xff; 2], [0xff; 4], [0xff; 8]);
+    let nbytes = run(f.read_vectored(&mut [
+        IoSliceMut::new(&mut a),
+        IoSliceMut::new(&mut b),
+        IoSliceMut::new(&mut c),
+    ]))
+    .expect("read");
+    assert_eq!(nbytes, 7);
+    assert_eq!((&a, &b), (b"34", b"5678"));
+    assert_eq!(&c, b"9\xff\xff\xff\xff\xff\xff\xff");
+    assert_eq!(tell(&*f), 10);
+
+    let (mut a, mut b) = ([0xff; 3], [0xff; 3]);
+    let nbytes =
+        run(f.read_vectored_at(&mut [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)], 6))
+            .expect("pread");
+    assert_eq!(nbytes, 4);
+    assert_eq!((&a, &b), (b"678", b"9\xff\xff"));
+    assert_eq!(tell(&*f), 10, "pread leaves the position alone");
+
+    run(f.seek(SeekFrom::Start(8))).expect("seek");
+    let mut buf = [0xff; 4];
+    assert_eq!(run(f.peek(&mut buf)).expect("peek"), 2);
+    assert_eq!(&buf, b"89\xff\xff");
+    assert_eq!(tell(&*f), 8, "peek leaves the position alone");
+}
+
+#[test]
+fn write_past_end_fills_gap() {
+    const GAP: u64 = 1 << 20;
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"head");
+    let f = open_rw(&root, "file", FdFlags::empty());
+
+    assert_eq!(run(f.seek(SeekFrom::End(GAP as i64))).unwrap(), 4 + GAP);
+    assert_eq!(write(&*f, b"tail"), 4);
+    assert_eq!(tell(&*f), 8 + GAP);
+    assert_eq!(run(f.get_filestat()).unwrap().size, 8 + GAP);
+
+    let contents = read_file(&root, "file");
+    assert_eq!(contents.len() as u64, 8 + GAP);
+    assert_eq!(&contents[..4], b"head");
+    assert!(contents[4..4 + GAP as usize].iter().all(|b| *b == 0));
+    assert_eq!(&contents[4 + GAP as usize..], b"tail");
+
+    // pwrite past the end fills the gap the same way.
+    assert_eq!(pwrite(&*f, b"more", 16 + GAP), 4);
+    let contents = read_file(&root, "file");
+    assert_eq!(&contents[8 + GAP as usize..], b"\0\0\0\0\0\0\0\0more");
+}
+
+#[test]
+fn write_past_addressable_size() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    let f = open_rw(&root, "file", FdFlags::empty());
+
+    for offset in &[u64::MAX - 1, u64::MAX] {
+        assert_errkind!(
+            run(f.write_vectored_at(&[IoSlice::new(b"xyz")], *offset)),
+            ErrorKind::Fbig
+        );
+        run(f.seek(SeekFrom::Start(*offset))).expect("seek");
+        assert_errkind!(
+            run(f.write_vectored(&[IoSlice::new(b"xyz")])),
+            ErrorKind::Fbig
+        );
+        assert_eq!(tell(&*f), *offset);
+    }
+    assert_eq!(read_file(&root, "file"), b"contents");
+}
+
+#[test]
+fn empty_iovecs() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"0123456789");
+    for fdflags in &[FdFlags::empty(), FdFlags::APPEND] {
+        let f = open_rw(&root, "file", *fdflags);
+        if !fdflags.contains(FdFlags::APPEND) {
+            run(f.seek(SeekFrom::Start(4))).expect("seek");
+        }
+        let before = run(f.get_filestat()).unwrap();
+        let position = tell(&*f);
+        let mut buf = [0; 0];
+
+        let empty: &[IoSlice] = &[];
+        assert_eq!(run(f.write_vectored(empty)).expect("write"), 0);
+        assert_eq!(run(f.write_vectored_at(empty, 100)).expect("pwrite"), 0);
+        let zeros = [IoSlice::new(b""), IoSlice::new(b""), IoSlice::new(b"")];
+        assert_eq!(run(f.write_vectored(&zeros)).expect("write"), 0);
+        assert_eq!(run(f.write_vectored_at(&zeros, 100)).expect("pwrite"), 0);
+
+        assert_eq!(run(f.read_vectored(&mut [])).expect("read"), 0);
+        assert_eq!(run(f.read_vectored_at(&mut [], 0)).expect("pread"), 0);
+        let mut zeros = [IoSliceMut::new(&mut buf)];
+        assert_eq!(run(f.read_vectored(&mut zeros)).expect("read"), 0);
+        assert_eq!(run(f.read_vectored_at(&mut zeros, 0)).expect("pread"), 0);
+        assert_eq!(run(f.peek(&mut buf)).expect("peek"), 0);
+
+        let after = run(f.get_filestat()).unwrap();
+        assert_eq!(after.size, 10, "{:?}", fdflags);
+        assert_eq!(after.atim, before.atim, "{:?}", fdflags);
+        assert_eq!(after.mtim, before.mtim, "{:?}", fdflags);
+        assert_eq!(after.ctim, before.ctim, "{:?}", fdflags);
+        assert_eq!(tell(&*f), position, "{:?}", fdflags);
+    }
+    assert_eq!(read_file(&root, "file"), b"0123456789");
+}
+
+#[test]
+fn interleaved_empty_iovecs() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = open_rw(&root, "file", FdFlags::empty());
+
+    let bufs = [
+        IoSlice::new(b""),
+        IoSlice::new(b"ab"),
+        IoSlice::new(b""),
+        IoSlice::new(b""),
+        IoSlice::new(b"cde"),
+        IoSlice::new(b""),
+        IoSlice::new(b"f"),
+        IoSlice::new(b""),
+    ];
+    assert_eq!(run(f.write_vectored(&bufs)).expect("write"), 6);
+    assert_eq!(tell(&*f), 6);
+    assert_eq!(run(f.write_vectored_at(&bufs, 6)).expect("pwrite"), 6);
+    assert_eq!(read_file(&root, "file"), b"abcdefabcdef");
+
+    let (mut a, mut b, mut c) = ([0; 0], [0; 5], [0; 4]);
+    let mut empty = [0; 0];
+    let mut bufs = [
+        IoSliceMut::new(&mut a),
+        IoSliceMut::new(&mut b),
+        IoSliceMut::new(&mut empty),
+        IoSliceMut::new(&mut c),
+    ];
+    assert_eq!(run(f.read_vectored_at(&mut bufs, 1)).expect("pread"), 9);
+    assert_eq!(&b, b"bcdef");
+    assert_eq!(&c, b"abcd");
+}
+
+#[test]
+fn set_fdflags_toggles_append() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"0123456789");
+    let mut f = open_rw(&root, "file", FdFlags::empty());
+
+    assert_eq!(write(&*f, b"ab"), 2);
+    run(f.set_fdflags(FdFlags::APPEND)).expect("set APPEND");
+    assert_eq!(run(f.get_fdflags()).unwrap(), FdFlags::APPEND);
+    assert_eq!(write(&*f, b"cd"), 2);
+    assert_eq!(tell(&*f), 12);
+    assert_eq!(read_file(&root, "file"), b"ab23456789cd");
+
+    // Turning APPEND off again writes at the current position, which the
+    // last append left at the end.
+    run(f.set_fdflags(FdFlags::empty())).expect("clear APPEND");
+    run(f.seek(SeekFrom::Start(4))).expect("seek");
+    assert_eq!(write(&*f, b"ef"), 2);
+    assert_eq!(read_file(&root, "file"), b"ab23ef6789cd");
+
+    run(f.set_fdflags(FdFlags::NONBLOCK)).expect("set NONBLOCK");
+    assert_eq!(run(f.get_fdflags()).unwrap(), FdFlags::NONBLOCK);
+}
+
+#[test]
+fn set_fdflags_rejects_sync_flags() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let mut f = open_rw(&root, "file", FdFlags::APPEND);
+
+    for flags in &[FdFlags::DSYNC, FdFlags::RSYNC, FdFlags::SYNC] {
+        assert_errkind!(
+            run(f.set_fdflags(*flags | FdFlags::APPEND)),
+            ErrorKind::Notsup
+        );
+        assert_eq!(run(f.get_fdflags()).unwrap(), FdFlags::APPEND);
+    }
+}
+
+#[test]
+fn set_fdflags_keeps_access_mode() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    let mut f = run(root.open_file(
+        false,
+        "file",
+        OFlags::empty(),
+        true,
+        false,
+        FdFlags::empty(),
+    ))
+    .expect("open read-only");
+
+    run(f.set_fdflags(FdFlags::APPEND)).expect("set APPEND");
+    assert_errkind!(
+        run(f.write_vectored(&[IoSlice::new(b"more")])),
+        ErrorKind::Badf
+    );
+    assert_eq!(read_file(&root, "file"), b"contents");
+}
+
+#[test]
+fn nonblock_round_trips() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let mut f = open_rw(&root, "file", FdFlags::NONBLOCK);
+    assert_eq!(run(f.get_fdflags()).unwrap(), FdFlags::NONBLOCK);
+
+    run(f.set_fdflags(FdFlags::NONBLOCK | FdFlags::APPEND)).expect("set APPEND");
+    assert_eq!(
+        run(f.get_fdflags()).unwrap(),
+        FdFlags::NONBLOCK | FdFlags::APPEND
+    );
+    run(f.set_fdflags(FdFlags::empty())).expect("clear flags");
+    assert_eq!(run(f.get_fdflags()).unwrap(), FdFlags::empty());
+}
+
+#[test]
+fn nonblock_and_blocking_handles() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let nonblocking = open_rw(&root, "file", FdFlags::NONBLOCK);
+    let blocking = open_rw(&root, "file", FdFlags::empty());
+    let mut buf = [0; 8];
+
+    // Neither handle waits for data, even on an empty file.
+    for f in &[&*nonblocking, &*blocking] {
+        run(f.readable()).expect("readable");
+        run(f.writable()).expect("writable");
+        assert_eq!(ready(*f), 0);
+        let nbytes = run(f.read_vectored(&mut [IoSliceMut::new(&mut buf)]));
+        assert_eq!(nbytes.expect("read at end of file"), 0);
+    }
+
+    // Data written through one is ready on the other.
+    assert_eq!(write(&*nonblocking, b"abc"), 3);
+    assert_eq!(ready(&*blocking), 3);
+    let nbytes = run(blocking.read_vectored(&mut [IoSliceMut::new(&mut buf)]));
+    assert_eq!(nbytes.expect("read"), 3);
+    assert_eq!(&buf[..3], b"abc");
+
+    assert_eq!(write(&*blocking, b"def"), 3);
+    assert_eq!(ready(&*nonblocking), 3);
+    let nbytes = run(nonblocking.read_vectored(&mut [IoSliceMut::new(&mut buf)]));
+    assert_eq!(nbytes.expect("read"), 3);
+    assert_eq!(&buf[..3], b"def");
+}
+
+#[test]
+fn set_size_grows_with_zeros() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"abc");
+    let f = open_rw(&root, "file", FdFlags::empty());
+    let other = open_rw(&root, "file", FdFlags::empty());
+
+    run(f.set_filestat_size(8)).expect("grow");
+    assert_eq!(run(other.get_filestat()).unwrap().size, 8);
+    assert_eq!(read_file(&root, "file"), b"abc\0\0\0\0\0");
+    assert_eq!(tell(&*f), 0);
+}
+
+#[test]
+fn set_size_shrink_releases_memory() {
+    const MIB: u64 = 1 << 20;
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = open_rw(&root, "file", FdFlags::empty());
+
+    let data = vec![1; 16 * MIB as usize];
+    write(&*f, &data);
+    assert!(capacity(&*f) >= 16 * MIB);
+    run(f.set_filestat_size(1024)).expect("shrink");
+    assert!(capacity(&*f) < MIB, "capacity {}", capacity(&*f));
+    assert_eq!(run(f.get_filestat()).unwrap().size, 1024);
+
+    write(&*f, &data);
+    run(f.set_filestat_size(0)).expect("truncate");
+    assert_eq!(capacity(&*f), 0);
+}
+
+#[test]
+fn set_size_under_another_fd() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"0123456789");
+    let f = open_rw(&root, "file", FdFlags::empty());
+    run(f.seek(SeekFrom::Start(8))).expect("seek");
+
+    let other = open_rw(&root, "file", FdFlags::empty());
+    run(other.set_filestat_size(4)).expect("shrink");
+
+    // The position stays past the new end, where reads find nothing.
+    assert_eq!(tell(&*f), 8);
+    assert_eq!(run(f.get_filestat()).unwrap().size, 4);
+    let mut buf = [0; 4];
+    let nbytes = run(f.read_vectored(&mut [IoSliceMut::new(&mut buf)]));
+    assert_eq!(nbytes.expect("read"), 0);
+    assert_eq!(tell(&*f), 8);
+
+    // Writing there fills the gap with zeros rather than the old contents.
+    assert_eq!(write(&*f, b"xy"), 2);
+    assert_eq!(read_file(&root, "file"), b"0123\0\0\0\0xy");
+}
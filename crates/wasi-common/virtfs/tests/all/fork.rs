@@ -0,0 +1,132 @@
+use crate::{create_file, names, read_file, run, ticking_filesystem, virtfs_file};
+use std::io::IoSlice;
+use std::sync::Arc;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags, WasiFile},
+    ErrorKind,
+};
+use wasi_virtfs::{clock::FixedClock, Filesystem};
+
+fn clock() -> Box<FixedClock> {
+    Box::new(FixedClock::new(cap_std::time::SystemTime::from_std(
+        std::time::UNIX_EPOCH,
+    )))
+}
+
+fn template() -> Arc<Filesystem> {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    run(root.create_dir("etc")).expect("mkdir");
+    create_file(&root, "etc/config", b"verbose = false\n");
+    run(root.hard_link("etc/config", &root, "config")).expect("link");
+    run(root.symlink("etc", "conf.d")).expect("symlink");
+    run(root.create_dir("data")).expect("mkdir");
+    create_file(&root, "data/big", &vec![1; 1 << 20]);
+    fs
+}
+
+fn open_write(dir: &dyn WasiDir, path: &str) -> Box<dyn WasiFile> {
+    run(dir.open_file(false, path, OFlags::empty(), true, true, FdFlags::APPEND)).expect("open")
+}
+
+fn append(f: &dyn WasiFile, data: &[u8]) {
+    run(f.write_vectored(&[IoSlice::new(data)])).expect("write");
+}
+
+fn serial(dir: &dyn WasiDir, path: &str) -> u64 {
+    run(dir.get_path_filestat(path, false)).expect("stat").inode
+}
+
+#[test]
+fn forks_are_independent() {
+    let fs = template();
+    let root = fs.root();
+    let fork = fs.fork(clock(), false);
+    let forked = fork.root();
+    assert_eq!(
+        forked.hash_tree(".", true).expect("hash"),
+        root.hash_tree(".", true).expect("hash")
+    );
+    assert_eq!(fork.next_serial(), fs.next_serial());
+    for path in &[".", "etc", "etc/config", "conf.d", "data/big"] {
+        assert_eq!(serial(&forked, path), serial(&root, path), "{}", path);
+    }
+
+    // The contents are shared until written.
+    let big = run(forked.open_file(
+        false,
+        "data/big",
+        OFlags::empty(),
+        true,
+        false,
+        FdFlags::empty(),
+    ))
+    .expect("open");
+    let big = virtfs_file(&*big);
+    assert_eq!(big.owned_size(), 0);
+
+    // The hard link is still one file in the fork, apart from the original.
+    append(&*open_write(&forked, "config"), b"verbose = true\n");
+    assert_eq!(
+        read_file(&forked, "etc/config"),
+        b"verbose = false\nverbose = true\n"
+    );
+    assert_eq!(read_file(&root, "etc/config"), b"verbose = false\n");
+    let stat = run(forked.get_path_filestat("etc/config", false)).expect("stat");
+    assert_eq!(stat.nlink, 2);
+
+    append(&*open_write(&root, "data/big"), b"more");
+    run(forked.unlink_file("conf.d")).expect("unlink");
+    create_file(&forked, "etc/new", b"");
+    assert_eq!(read_file(&forked, "data/big"), vec![1; 1 << 20]);
+    assert_eq!(names(&root), [".", "..", "conf.d", "config", "data", "etc"]);
+    assert_eq!(names(&forked), [".", "..", "config", "data", "etc"]);
+    assert_errkind!(
+        run(root.get_path_filestat("etc/new", false)),
+        ErrorKind::Noent
+    );
+
+    // The fork's own serials carry on from the original's.
+    assert_eq!(serial(&forked, "etc/new"), fs.next_serial());
+}
+
+#[test]
+fn open_handles_stay_with_the_original() {
+    let fs = template();
+    let root = fs.root();
+    let f = open_write(&root, "etc/config");
+    let fork = fs.fork(clock(), false);
+    append(&*f, b"after the fork\n");
+    assert_eq!(
+        read_file(&root, "config"),
+        b"verbose = false\nafter the fork\n"
+    );
+    assert_eq!(read_file(&fork.root(), "config"), b"verbose = false\n");
+
+    // A file unlinked while open isn't in the tree to fork.
+    run(root.unlink_file("etc/config")).expect("unlink");
+    run(root.unlink_file("config")).expect("unlink");
+    let fork = fs.fork(clock(), false);
+    assert_eq!(fork.inode_count(), fs.inode_count() - 1);
+}
+
+#[test]
+fn fresh_serials() {
+    let fs = template();
+    let fork = fs.fork(clock(), true);
+    let forked = fork.root();
+    assert_eq!(serial(&forked, "."), 0);
+    let mut serials: Vec<u64> = ["etc", "etc/config", "conf.d", "data", "data/big"]
+        .iter()
+        .map(|path| serial(&forked, path))
+        .collect();
+    serials.sort_unstable();
+    assert_eq!(serials, [1, 2, 3, 4, 5]);
+    assert_eq!(serial(&forked, "config"), serial(&forked, "etc/config"));
+    assert_eq!(fork.next_serial(), 6);
+    assert_eq!(
+        forked.hash_tree(".", true).expect("hash"),
+        fs.root().hash_tree(".", true).expect("hash")
+    );
+}
@@ -0,0 +1,79 @@
+use crate::{create_file, filesystem, open_write, run};
+use std::io::IoSlice;
+use wasi_common::dir::WasiDir;
+use wasi_virtfs::FsStats;
+
+#[test]
+fn stats_follow_the_tree() {
+    let fs = filesystem();
+    let root = fs.root();
+    assert_eq!(
+        fs.stats(),
+        FsStats {
+            total_bytes_limit: None,
+            bytes_used: 0,
+            inode_limit: None,
+            inodes_used: 1,
+            orphaned_bytes: 0,
+            largest_file: 0,
+        }
+    );
+    fs.set_byte_limit(Some(1 << 20));
+    fs.set_inode_limit(Some(100));
+
+    run(root.create_dir("dir")).expect("mkdir");
+    create_file(&root, "dir/small", &[1; 100]);
+    let big = open_write(&root, "big").expect("open");
+    run(big.write_vectored(&[IoSlice::new(&[2; 5_000])])).expect("write");
+    run(root.symlink("big", "link")).expect("symlink");
+    run(root.hard_link("big", &root, "dir/big")).expect("link");
+    let stats = fs.stats();
+    assert_eq!(stats.total_bytes_limit, Some(1 << 20));
+    assert_eq!(stats.inode_limit, Some(100));
+    assert_eq!(stats.bytes_used, 5_100);
+    assert_eq!(stats.inodes_used, 5);
+    assert_eq!(stats.largest_file, 5_000);
+    assert_eq!(stats.orphaned_bytes, 0);
+
+    // A hole counts towards the size.
+    run(big.set_filestat_size(8_000)).expect("extend");
+    assert_eq!(fs.stats().bytes_used, 8_100);
+    assert_eq!(fs.stats().largest_file, 8_000);
+
+    // Truncating the largest file makes the next largest the largest.
+    run(big.set_filestat_size(50)).expect("truncate");
+    let stats = fs.stats();
+    assert_eq!(stats.bytes_used, 150);
+    assert_eq!(stats.largest_file, 100);
+
+    // Unlinked while open, a file still counts, as an orphan.
+    run(big.write_vectored_at(&[IoSlice::new(&[3; 4_096])], 0)).expect("write");
+    run(root.unlink_file("big")).expect("unlink");
+    run(root.unlink_file("dir/big")).expect("unlink");
+    let stats = fs.stats();
+    assert_eq!(stats.bytes_used, 4_196);
+    assert_eq!(stats.inodes_used, 5);
+    assert_eq!(stats.largest_file, 4_096);
+    assert_eq!(stats.orphaned_bytes, 4_096);
+
+    drop(big);
+    run(root.unlink_file("link")).expect("unlink");
+    assert_eq!(
+        fs.stats(),
+        FsStats {
+            total_bytes_limit: Some(1 << 20),
+            bytes_used: 100,
+            inode_limit: Some(100),
+            inodes_used: 3,
+            orphaned_bytes: 0,
+            largest_file: 100,
+        }
+    );
+    assert_eq!(fs.stats().inodes_used, fs.inode_count());
+    assert_eq!(fs.stats().bytes_used, fs.bytes_used());
+
+    fs.remove_dir_all("dir").expect("remove");
+    let stats = fs.stats();
+    assert_eq!((stats.bytes_used, stats.inodes_used), (0, 1));
+    assert_eq!(stats.largest_file, 0);
+}
@@ -0,0 +1,88 @@
+use crate::{create_file, filesystem, read_file, run, Memory};
+use std::path::PathBuf;
+use wasi_cap_std_sync::WasiCtxBuilder;
+use wasi_common::{
+    dir::{DirCaps, WasiDir},
+    file::FileCaps,
+    snapshots::preview_1::{
+        types::{Fd, Lookupflags},
+        wasi_snapshot_preview1::WasiSnapshotPreview1,
+    },
+    ErrorKind,
+};
+
+#[test]
+fn link_within_one_directory_from_a_guest() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("mkdir");
+    create_file(&root, "dir/a", b"shared");
+
+    // Two preopens of the same directory, as separate handles.
+    let mut ctx = WasiCtxBuilder::new().build();
+    for fd in &[3, 4] {
+        let dir = run(root.open_dir(false, "dir")).expect("open dir");
+        ctx.insert_dir(
+            *fd,
+            dir,
+            DirCaps::all(),
+            FileCaps::all(),
+            PathBuf::from("/dir"),
+        );
+    }
+    let mem = Memory::new();
+    let mut link = |src_fd: u32, src: &str, target_fd: u32, target: &str| {
+        run(ctx.path_link(
+            Fd::from(src_fd),
+            Lookupflags::empty(),
+            &mem.str(src),
+            Fd::from(target_fd),
+            &mem.str(target),
+        ))
+    };
+    link(3, "a", 3, "b").expect("link through one handle");
+    link(3, "b", 4, "c").expect("link through two handles");
+    link(4, "./c", 3, "../dir/d").expect("link through dots");
+
+    let stat = |path: &str| run(root.get_path_filestat(path, false)).expect("stat");
+    let a = stat("dir/a");
+    assert_eq!(a.nlink, 4);
+    for name in &["b", "c", "d"] {
+        assert_eq!(stat(&format!("dir/{}", name)).inode, a.inode);
+        assert_eq!(read_file(&root, &format!("dir/{}", name)), b"shared");
+    }
+}
+
+#[test]
+fn no_links_into_another_filesystem() {
+    let fs = filesystem();
+    let other = filesystem();
+    create_file(&fs.root(), "a", &[1; 600]);
+    assert_errkind!(
+        run(fs.root().hard_link("a", &other.root(), "a")),
+        ErrorKind::Xdev
+    );
+    assert_errkind!(
+        run(other.root().get_path_filestat("a", false)),
+        ErrorKind::Noent
+    );
+    assert_eq!(
+        run(fs.root().get_path_filestat("a", false))
+            .expect("stat")
+            .nlink,
+        1
+    );
+    assert_eq!(other.bytes_used(), 0);
+}
+
+#[test]
+fn directories_cant_be_linked() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("mkdir");
+    let link = |src: &str| run(root.hard_link(src, &root, "link"));
+    assert_errkind!(link("dir"), ErrorKind::Perm);
+    assert_errkind!(link("dir/."), ErrorKind::Perm);
+    assert_errkind!(link("missing"), ErrorKind::Noent);
+    assert_errkind!(run(root.get_path_filestat("link", false)), ErrorKind::Noent);
+}
@@ -0,0 +1,129 @@
+use crate::{create_file, filesystem, run};
+use std::sync::Arc;
+use wasi_common::{dir::WasiDir, ErrorKind, SystemTimeSpec};
+use wasi_virtfs::{Dir, Filesystem, StaticWrites};
+
+pub(crate) fn hex(digest: [u8; 32]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The fixture tree, built in one order or another.
+fn fixture(fs: &Arc<Filesystem>, reversed: bool) -> Dir {
+    let root = fs.root();
+    let steps: [&dyn Fn(&Dir); 3] = [
+        &|root| create_file(root, "a.txt", b"hello\n"),
+        &|root| {
+            run(root.create_dir("dir")).expect("mkdir");
+            create_file(root, "dir/b", b"");
+        },
+        &|root| run(root.symlink("a.txt", "link")).expect("symlink"),
+    ];
+    if reversed {
+        steps.iter().rev().for_each(|step| step(&root));
+    } else {
+        steps.iter().for_each(|step| step(&root));
+    }
+    root
+}
+
+const TREE: &str = "47179da89c0dab4bf8b7b8e9e614aeaac1b0f29075fb3eab8e670cb93cea23d4";
+const HELLO: &str = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+
+#[test]
+fn known_digests() {
+    let fs = filesystem();
+    fixture(&fs, false);
+    assert_eq!(hex(fs.hash_file("a.txt").unwrap()), HELLO);
+    assert_eq!(hex(fs.hash_file("link").unwrap()), HELLO);
+    assert_eq!(hex(fs.hash_tree(".").unwrap()), TREE);
+    assert_eq!(
+        hex(fs.hash_tree("dir").unwrap()),
+        "6b6252a2985962fed2bc2e1507f21b0649c961080bcb75b05ee03753f9c84103"
+    );
+    // A file on its own is hashed as a tree of one entry.
+    assert_eq!(
+        hex(fs.hash_tree("a.txt").unwrap()),
+        "fbca78202046ef443ea956a5cdc3e78c5221cbbfe6bef59318b8db165b8f7c79"
+    );
+    assert_eq!(
+        hex(filesystem().hash_tree(".").unwrap()),
+        "019f76127757f5d29dd33fbdca211fdc0ece9b093995fdb2706a5a8805c0e2b9"
+    );
+}
+
+#[test]
+fn insertion_order_doesnt_matter() {
+    let fs = filesystem();
+    fixture(&fs, true);
+    assert_eq!(hex(fs.hash_tree(".").unwrap()), TREE);
+
+    // Nor do the kinds of storage behind the contents.
+    let fs = filesystem();
+    let root = fixture(&fs, false);
+    run(root.unlink_file("a.txt")).expect("unlink");
+    root.put_static_file("a.txt", b"hello\n", StaticWrites::ReadOnly)
+        .expect("put static file");
+    assert_eq!(hex(fs.hash_tree(".").unwrap()), TREE);
+}
+
+#[test]
+fn changes_change_the_digest() {
+    let fs = filesystem();
+    let root = fixture(&fs, false);
+    let before = fs.hash_tree(".").unwrap();
+
+    // Renaming changes the digest, though no contents change, and renaming
+    // back restores it.
+    run(root.rename("dir/b", &root, "dir/c")).expect("rename");
+    let renamed = fs.hash_tree(".").unwrap();
+    assert_ne!(renamed, before);
+    run(root.rename("dir/c", &root, "dir/b")).expect("rename");
+    assert_eq!(fs.hash_tree(".").unwrap(), before);
+
+    run(root.unlink_file("link")).expect("unlink");
+    run(root.symlink("dir", "link")).expect("symlink");
+    assert_ne!(fs.hash_tree(".").unwrap(), before);
+    create_file(&root, "dir/c", b"");
+    assert_ne!(fs.hash_tree(".").unwrap(), renamed);
+}
+
+#[test]
+fn timestamps() {
+    let build = || {
+        let fs = Filesystem::new_deterministic(0);
+        let root = fixture(&fs, false);
+        (fs, root)
+    };
+    let (a, _) = build();
+    let (b, b_root) = build();
+    let a_root = a.root();
+    assert_eq!(
+        a_root.hash_tree(".", true).unwrap(),
+        b_root.hash_tree(".", true).unwrap()
+    );
+    assert_ne!(
+        a_root.hash_tree(".", true).unwrap(),
+        a_root.hash_tree(".", false).unwrap()
+    );
+
+    // Timestamps only count when asked for.
+    run(b_root.set_times("a.txt", None, Some(SystemTimeSpec::SymbolicNow), true))
+        .expect("set times");
+    assert_ne!(
+        a_root.hash_tree(".", true).unwrap(),
+        b_root.hash_tree(".", true).unwrap()
+    );
+    assert_eq!(hex(b.hash_tree(".").unwrap()), TREE);
+}
+
+#[test]
+fn errors() {
+    let fs = filesystem();
+    let root = fixture(&fs, false);
+    run(root.symlink("missing", "dangling")).expect("symlink");
+
+    assert_errkind!(fs.hash_file("dir"), ErrorKind::Isdir);
+    assert_errkind!(fs.hash_file("missing"), ErrorKind::Noent);
+    assert_errkind!(fs.hash_file("dangling"), ErrorKind::Noent);
+    assert_errkind!(fs.hash_tree("missing"), ErrorKind::Noent);
+}
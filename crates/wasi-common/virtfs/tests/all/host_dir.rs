@@ -0,0 +1,189 @@
+use crate::{create_file, filesystem, names_at, read_file, run};
+use std::path::PathBuf;
+use wasi_common::{dir::WasiDir, file::FileType, ErrorKind};
+use wasi_virtfs::{Filesystem, HostDirOptions, HostSymlinks, SkippedEntry};
+
+fn filetype(dir: &dyn WasiDir, path: &str) -> FileType {
+    run(dir.get_path_filestat(path, false))
+        .expect("stat")
+        .filetype
+}
+
+fn skipped(entries: &[SkippedEntry]) -> Vec<&str> {
+    entries
+        .iter()
+        .map(|e| e.path.to_str().expect("UTF-8"))
+        .collect()
+}
+
+fn with_symlinks(symlinks: HostSymlinks) -> HostDirOptions {
+    HostDirOptions {
+        symlinks,
+        ..HostDirOptions::default()
+    }
+}
+
+#[test]
+fn guest_sees_a_snapshot() {
+    let host = cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    host.create_dir_all("etc/empty").expect("mkdir");
+    host.write("etc/hosts", b"127.0.0.1 localhost\n")
+        .expect("write");
+    host.write("top", b"top").expect("write");
+    host.symlink("etc/hosts", "hosts").expect("symlink");
+
+    let (fs, skipped) = Filesystem::from_host_dir(
+        &host,
+        HostDirOptions::default(),
+        wasi_cap_std_sync::clocks_ctx().system,
+        5,
+    )
+    .expect("import");
+    assert_eq!(skipped, []);
+    let root = fs.root();
+    assert_eq!(names_at(&root, "."), [".", "..", "etc", "hosts", "top"]);
+    assert_eq!(names_at(&root, "etc"), [".", "..", "empty", "hosts"]);
+    assert_eq!(
+        run(root.read_link("hosts")).expect("readlink"),
+        PathBuf::from("etc/hosts")
+    );
+    for path in &["etc", "etc/empty", "etc/hosts", "top"] {
+        let guest = run(root.get_path_filestat(path, false)).expect("stat");
+        let host = host.metadata(path).expect("host stat");
+        assert_eq!(
+            guest.mtim,
+            Some(host.modified().expect("mtime").into_std()),
+            "{}",
+            path
+        );
+    }
+
+    // Changes on the host don't show in the guest, nor the other way.
+    host.write("etc/hosts", b"changed").expect("write");
+    host.remove_file("top").expect("remove");
+    host.write("new", b"new").expect("write");
+    host.remove_dir("etc/empty").expect("rmdir");
+    assert_eq!(read_file(&root, "etc/hosts"), b"127.0.0.1 localhost\n");
+    assert_eq!(read_file(&root, "hosts"), b"127.0.0.1 localhost\n");
+    assert_eq!(read_file(&root, "top"), b"top");
+    assert_eq!(names_at(&root, "."), [".", "..", "etc", "hosts", "top"]);
+    assert_eq!(names_at(&root, "etc"), [".", "..", "empty", "hosts"]);
+    run(root.unlink_file("hosts")).expect("unlink");
+    create_file(&root, "guest", b"guest");
+    assert!(host.symlink_metadata("hosts").is_ok());
+    assert!(host.metadata("guest").is_err());
+}
+
+#[test]
+fn symlinks_are_copied_followed_or_skipped() {
+    let host = cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    host.create_dir("sub").expect("mkdir");
+    host.write("sub/file", b"data").expect("write");
+    host.symlink("sub/file", "to_file").expect("symlink");
+    host.symlink("sub", "to_dir").expect("symlink");
+    host.symlink("missing", "dangling").expect("symlink");
+    host.symlink("../outside", "escaping").expect("symlink");
+
+    let fs = filesystem();
+    let skipped_entries = fs
+        .import_host_dir_at(".", &host, with_symlinks(HostSymlinks::Copy))
+        .expect("import");
+    assert_eq!(skipped_entries, []);
+    let root = fs.root();
+    for (link, target) in &[
+        ("to_file", "sub/file"),
+        ("to_dir", "sub"),
+        ("dangling", "missing"),
+        ("escaping", "../outside"),
+    ] {
+        assert_eq!(filetype(&root, link), FileType::SymbolicLink);
+        assert_eq!(
+            run(root.read_link(link)).expect("readlink"),
+            PathBuf::from(target)
+        );
+    }
+
+    let fs = filesystem();
+    let skipped_entries = fs
+        .import_host_dir_at(".", &host, with_symlinks(HostSymlinks::Follow))
+        .expect("import");
+    assert_eq!(skipped(&skipped_entries), ["dangling", "escaping"]);
+    let root = fs.root();
+    assert_eq!(
+        names_at(&root, "."),
+        [".", "..", "sub", "to_dir", "to_file"]
+    );
+    assert_eq!(filetype(&root, "to_file"), FileType::RegularFile);
+    assert_eq!(filetype(&root, "to_dir"), FileType::Directory);
+    assert_eq!(read_file(&root, "to_dir/file"), b"data");
+    run(root.unlink_file("to_dir/file")).expect("unlink");
+    assert_eq!(read_file(&root, "sub/file"), b"data");
+
+    let fs = filesystem();
+    let skipped_entries = fs
+        .import_host_dir_at(".", &host, with_symlinks(HostSymlinks::Skip))
+        .expect("import");
+    assert_eq!(
+        skipped(&skipped_entries),
+        ["dangling", "escaping", "to_dir", "to_file"]
+    );
+    assert_eq!(names_at(&fs.root(), "."), [".", "..", "sub"]);
+}
+
+#[test]
+fn following_a_symlink_cycle_fails() {
+    let host = cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    host.create_dir("a").expect("mkdir");
+    host.symlink("..", "a/up").expect("symlink");
+    let fs = filesystem();
+    assert_errkind!(
+        fs.import_host_dir_at(".", &host, with_symlinks(HostSymlinks::Follow)),
+        ErrorKind::Loop
+    );
+}
+
+#[test]
+fn imports_stop_at_max_bytes() {
+    let host = cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    host.write("a", b"1234").expect("write");
+    host.create_dir("b").expect("mkdir");
+    host.write("b/c", b"5678").expect("write");
+    host.write("empty", b"").expect("write");
+    let limit = |max_bytes| HostDirOptions {
+        max_bytes: Some(max_bytes),
+        ..HostDirOptions::default()
+    };
+
+    let fs = filesystem();
+    fs.import_host_dir_at(".", &host, limit(8)).expect("import");
+    assert_eq!(read_file(&fs.root(), "b/c"), b"5678");
+
+    let fs = filesystem();
+    assert_errkind!(
+        fs.import_host_dir_at(".", &host, limit(7)),
+        ErrorKind::Nospc
+    );
+    // What came before is left in place.
+    assert_eq!(read_file(&fs.root(), "a"), b"1234");
+    assert_eq!(names_at(&fs.root(), "b"), [".", ".."]);
+}
+
+#[cfg(unix)]
+#[test]
+fn special_files_are_skipped() {
+    use std::os::unix::net::UnixListener;
+
+    let path = std::env::temp_dir().join(format!("virtfs-host-dir-{}", std::process::id()));
+    std::fs::create_dir(&path).expect("mkdir");
+    let _listener = UnixListener::bind(path.join("socket")).expect("bind");
+    std::fs::write(path.join("file"), b"data").expect("write");
+    let host = cap_std::fs::Dir::open_ambient_dir(&path, cap_std::ambient_authority())
+        .expect("open host dir");
+
+    let fs = filesystem();
+    let skipped_entries = fs.import_host_dir_at(".", &host, HostDirOptions::default());
+    std::fs::remove_dir_all(&path).expect("clean up");
+    let skipped_entries = skipped_entries.expect("import");
+    assert_eq!(skipped(&skipped_entries), ["socket"]);
+    assert_eq!(names_at(&fs.root(), "."), [".", "..", "file"]);
+}
@@ -0,0 +1,224 @@
+use crate::{create_file, filesystem, names_at, read_file, run};
+use std::time::UNIX_EPOCH;
+use tar::{EntryType, Header};
+use wasi_common::{dir::WasiDir, file::FileType, ErrorKind};
+use wasi_virtfs::{Filesystem, TarOptions};
+
+/// An archive builder for entries of every kind, each with an mtim of
+/// `mtime` seconds.
+struct Archive(tar::Builder<Vec<u8>>);
+
+impl Archive {
+    fn new() -> Self {
+        Archive(tar::Builder::new(Vec::new()))
+    }
+
+    fn header(kind: EntryType, size: u64, mtime: u64) -> Header {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(kind);
+        header.set_size(size);
+        header.set_mode(0o755);
+        header.set_mtime(mtime);
+        header
+    }
+
+    fn dir(mut self, path: &str, mtime: u64) -> Self {
+        let mut header = Self::header(EntryType::Directory, 0, mtime);
+        self.0
+            .append_data(&mut header, path, &[][..])
+            .expect("append");
+        self
+    }
+
+    fn file(mut self, path: &str, contents: &[u8], mtime: u64) -> Self {
+        let mut header = Self::header(EntryType::Regular, contents.len() as u64, mtime);
+        self.0
+            .append_data(&mut header, path, contents)
+            .expect("append");
+        self
+    }
+
+    fn link(mut self, kind: EntryType, path: &str, target: &str) -> Self {
+        let mut header = Self::header(kind, 0, 0);
+        self.0
+            .append_link(&mut header, path, target)
+            .expect("append");
+        self
+    }
+
+    fn special(mut self, kind: EntryType, path: &str) -> Self {
+        let mut header = Self::header(kind, 0, 0);
+        self.0
+            .append_data(&mut header, path, &[][..])
+            .expect("append");
+        self
+    }
+
+    /// An entry with `path` written as it is, which the builder would
+    /// otherwise refuse.
+    fn raw(mut self, path: &[u8], contents: &[u8]) -> Self {
+        let mut header = Self::header(EntryType::Regular, contents.len() as u64, 0);
+        header.as_old_mut().name[..path.len()].copy_from_slice(path);
+        header.set_cksum();
+        self.0.append(&header, contents).expect("append");
+        self
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.0.into_inner().expect("finish archive")
+    }
+}
+
+fn mtim_secs(dir: &dyn WasiDir, path: &str) -> u64 {
+    let stat = run(dir.get_path_filestat(path, false)).expect("stat");
+    stat.mtim
+        .expect("mtim")
+        .duration_since(UNIX_EPOCH)
+        .expect("after the epoch")
+        .as_secs()
+}
+
+#[test]
+fn imports_a_rootfs() {
+    let long_name = format!("usr/share/{}", "x".repeat(150));
+    let archive = Archive::new()
+        .dir("etc/", 1_000)
+        .file("etc/hostname", b"sandbox\n", 2_000)
+        .file("usr/bin/tool", b"#!/bin/sh\necho hi\n", 3_000)
+        .link(EntryType::Symlink, "usr/bin/alias", "tool")
+        .link(EntryType::Link, "usr/bin/hardlink", "usr/bin/tool")
+        .file(&long_name, b"long", 4_000)
+        .dir("usr/", 5_000)
+        .dir("./empty", 6_000)
+        .finish();
+    let fs = Filesystem::from_tar(&archive[..], wasi_cap_std_sync::clocks_ctx().system, 3)
+        .expect("import");
+    let root = fs.root();
+
+    assert_eq!(names_at(&root, "."), [".", "..", "empty", "etc", "usr"]);
+    assert_eq!(
+        names_at(&root, "usr/bin"),
+        [".", "..", "alias", "hardlink", "tool"]
+    );
+    assert_eq!(
+        run(root.read_link("usr/bin/alias")).expect("readlink"),
+        std::path::PathBuf::from("tool")
+    );
+    let tool = run(root.get_path_filestat("usr/bin/tool", false)).expect("stat");
+    let hardlink = run(root.get_path_filestat("usr/bin/hardlink", false)).expect("stat");
+    assert_eq!(tool.inode, hardlink.inode);
+    assert_eq!(tool.nlink, 2);
+    assert_eq!(
+        run(root.get_path_filestat("usr/bin/alias", false))
+            .expect("stat")
+            .filetype,
+        FileType::SymbolicLink
+    );
+
+    // Directories keep their mtims, however many entries came after them.
+    assert_eq!(mtim_secs(&root, "etc"), 1_000);
+    assert_eq!(mtim_secs(&root, "usr"), 5_000);
+    assert_eq!(mtim_secs(&root, "empty"), 6_000);
+    assert_eq!(mtim_secs(&root, "etc/hostname"), 2_000);
+    assert_eq!(mtim_secs(&root, &long_name), 4_000);
+
+    // A guest reads the files through the same calls as these.
+    assert_eq!(read_file(&root, "etc/hostname"), b"sandbox\n");
+    assert_eq!(read_file(&root, "usr/bin/alias"), b"#!/bin/sh\necho hi\n");
+    assert_eq!(read_file(&root, &long_name), b"long");
+    let tool = run(root.open_file(
+        false,
+        "usr/bin/tool",
+        wasi_common::file::OFlags::empty(),
+        false,
+        true,
+        wasi_common::file::FdFlags::APPEND,
+    ))
+    .expect("open for writing");
+    run(tool.write_vectored(&[std::io::IoSlice::new(b"echo bye\n")])).expect("write");
+    assert_eq!(
+        read_file(&root, "usr/bin/hardlink"),
+        b"#!/bin/sh\necho hi\necho bye\n"
+    );
+}
+
+#[test]
+fn unsupported_entries_fail_unless_skipped() {
+    let archive = Archive::new()
+        .file("before", b"", 0)
+        .special(EntryType::Char, "dev/tty")
+        .special(EntryType::Fifo, "pipe")
+        .file("after", b"", 0)
+        .finish();
+    let fs = filesystem();
+    assert_errkind!(
+        fs.import_tar_at(".", &archive[..], TarOptions::default()),
+        ErrorKind::Notsup
+    );
+    assert_eq!(names_at(&fs.root(), "."), [".", "..", "before"]);
+
+    let fs = filesystem();
+    let options = TarOptions {
+        skip_unsupported: true,
+    };
+    fs.import_tar_at(".", &archive[..], options)
+        .expect("import");
+    assert_eq!(names_at(&fs.root(), "."), [".", "..", "after", "before"]);
+}
+
+#[test]
+fn entries_stay_inside_the_directory() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("sub")).expect("mkdir");
+    create_file(&root, "kept", b"original");
+    create_file(&root, "sub/existing", b"original");
+    let import = |archive: Vec<u8>| fs.import_tar_at("sub", &archive[..], TarOptions::default());
+
+    // A leading slash is relative to the directory, as with `tar`.
+    import(Archive::new().raw(b"/kept", b"inside").finish()).expect("import");
+    assert_eq!(read_file(&root, "sub/kept"), b"inside");
+
+    assert_errkind!(
+        import(Archive::new().raw(b"../kept", b"escaped").finish()),
+        ErrorKind::Perm
+    );
+    assert_errkind!(
+        import(Archive::new().raw(b"a/../../kept", b"escaped").finish()),
+        ErrorKind::Perm
+    );
+    assert_errkind!(
+        import(
+            Archive::new()
+                .link(EntryType::Link, "linked", "../kept")
+                .finish()
+        ),
+        ErrorKind::Perm
+    );
+    // Nor can a symlink: `..` from the directory is the directory itself,
+    // as at a preopen.
+    import(
+        Archive::new()
+            .link(EntryType::Symlink, "up", "..")
+            .file("up/fresh", b"inside", 0)
+            .finish(),
+    )
+    .expect("import");
+    assert_eq!(read_file(&root, "sub/fresh"), b"inside");
+    assert_errkind!(
+        import(Archive::new().file("existing", b"clobbered", 0).finish()),
+        ErrorKind::Exist
+    );
+
+    assert_eq!(read_file(&root, "kept"), b"original");
+    assert_eq!(read_file(&root, "sub/existing"), b"original");
+    assert_eq!(names_at(&root, "."), [".", "..", "kept", "sub"]);
+    assert_eq!(
+        names_at(&root, "sub"),
+        [".", "..", "existing", "fresh", "kept", "up"]
+    );
+    assert_errkind!(
+        fs.import_tar_at("kept", &Archive::new().finish()[..], TarOptions::default()),
+        ErrorKind::Notdir
+    );
+}
@@ -0,0 +1,124 @@
+use crate::hash::hex;
+use crate::{filesystem, names_at, read_file, run};
+use std::io::{Cursor, Write};
+use std::time::UNIX_EPOCH;
+use wasi_common::{dir::WasiDir, file::FileType, ErrorKind};
+use wasi_virtfs::{Filesystem, ZipOptions};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+/// An archive made with Python's `zipfile`: a directory entry for
+/// `assets/`, `assets/readme.txt` stored as it is, `assets/data/big.bin`
+/// deflated with no entry for its directory, and `assets/latest`, a symlink
+/// to `data/big.bin` by Info-ZIP's convention.
+const ASSETS: &[u8] = include_bytes!("../fixtures/assets.zip");
+
+const README_SHA256: &str = "8d0416f1e4129c12cf9c38c79ab5009ae9e73cbdb6a41e87d94e9e5455a89243";
+const BIG_SHA256: &str = "6b4e3f3d1dee15db72c519f5962bad253dfc7d4183d7e30e656259ce1eeab007";
+
+fn stat(dir: &dyn WasiDir, path: &str) -> wasi_common::file::Filestat {
+    run(dir.get_path_filestat(path, false)).expect("stat")
+}
+
+fn mtim_secs(dir: &dyn WasiDir, path: &str) -> u64 {
+    stat(dir, path)
+        .mtim
+        .expect("mtim")
+        .duration_since(UNIX_EPOCH)
+        .expect("after the epoch")
+        .as_secs()
+}
+
+/// A zip archive of files named `names`, each holding its own name.
+fn archive(names: &[&str]) -> Vec<u8> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for name in names {
+        zip.start_file(
+            *name,
+            FileOptions::default().compression_method(CompressionMethod::Stored),
+        )
+        .expect("start file");
+        zip.write_all(name.as_bytes()).expect("write");
+    }
+    zip.finish().expect("finish").into_inner()
+}
+
+#[test]
+fn imports_the_fixture() {
+    let fs = Filesystem::from_zip(
+        Cursor::new(ASSETS),
+        wasi_cap_std_sync::clocks_ctx().system,
+        0,
+    )
+    .expect("import");
+    let root = fs.root();
+    assert_eq!(
+        names_at(&root, "assets"),
+        [".", "..", "data", "latest", "readme.txt"]
+    );
+    assert_eq!(stat(&root, "assets/data").filetype, FileType::Directory);
+    assert_eq!(stat(&root, "assets/readme.txt").size, 40);
+    assert_eq!(stat(&root, "assets/data/big.bin").size, 100_000);
+    assert_eq!(
+        hex(fs.hash_file("assets/readme.txt").unwrap()),
+        README_SHA256
+    );
+    assert_eq!(
+        hex(fs.hash_file("assets/data/big.bin").unwrap()),
+        BIG_SHA256
+    );
+
+    // Without the option, the symlink is a file holding its target.
+    assert_eq!(stat(&root, "assets/latest").filetype, FileType::RegularFile);
+    assert_eq!(read_file(&root, "assets/latest"), b"data/big.bin");
+
+    // 2021-06-01 12:00:00 and so on, as UTC.
+    assert_eq!(mtim_secs(&root, "assets"), 1_622_548_800);
+    assert_eq!(mtim_secs(&root, "assets/readme.txt"), 1_622_622_610);
+    assert_eq!(mtim_secs(&root, "assets/data/big.bin"), 1_622_764_798);
+}
+
+#[test]
+fn unix_symlinks_by_option() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("import")).expect("mkdir");
+    let options = ZipOptions {
+        unix_symlinks: true,
+    };
+    fs.import_zip_at("import", Cursor::new(ASSETS), options)
+        .expect("import");
+    assert_eq!(
+        stat(&root, "import/assets/latest").filetype,
+        FileType::SymbolicLink
+    );
+    assert_eq!(
+        run(root.read_link("import/assets/latest")).expect("readlink"),
+        std::path::PathBuf::from("data/big.bin")
+    );
+    assert_eq!(
+        hex(fs.hash_file("import/assets/latest").unwrap()),
+        BIG_SHA256
+    );
+}
+
+#[test]
+fn hostile_paths_are_rejected() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("sub")).expect("mkdir");
+    let import = |names: &[&str]| {
+        fs.import_zip_at("sub", Cursor::new(archive(names)), ZipOptions::default())
+    };
+    for name in &["../escaped", "a/../../escaped", "C:/escaped", "c:escaped"] {
+        assert_errkind!(import(&[*name]), ErrorKind::Perm);
+    }
+    assert_eq!(names_at(&root, "."), [".", "..", "sub"]);
+    assert_eq!(names_at(&root, "sub"), [".", ".."]);
+
+    // A leading slash is relative to the directory.
+    import(&["/inside", "//deeper/inside"]).expect("import");
+    assert_eq!(read_file(&root, "sub/inside"), b"/inside");
+    assert_eq!(read_file(&root, "sub/deeper/inside"), b"//deeper/inside");
+    assert_errkind!(import(&["inside"]), ErrorKind::Exist);
+    assert_errkind!(import(&["dup", "dup"]), ErrorKind::Exist);
+}
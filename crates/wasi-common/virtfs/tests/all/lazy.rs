@@ -0,0 +1,218 @@
+use crate::{capacity, filesystem, open_read, open_write, pread, read_file, run};
+use std::io::{self, IoSlice, IoSliceMut, Write};
+use std::sync::{Arc, Mutex};
+use wasi_common::{file::Advice, ErrorKind};
+use wasi_virtfs::{LazyContents, ReadSeekContents, StaticWrites};
+
+const GIB: u64 = 1 << 30;
+// Files are stored, and copied on write, in chunks of this size.
+const CHUNK: u64 = 64 * 1024;
+
+/// Generated contents of any size, recording the ranges read and hinted.
+struct Recorder {
+    size: u64,
+    reads: Mutex<Vec<(u64, usize)>>,
+    hints: Mutex<Vec<(u64, u64)>>,
+}
+
+impl Recorder {
+    fn new(size: u64) -> Arc<Self> {
+        Arc::new(Recorder {
+            size,
+            reads: Mutex::new(Vec::new()),
+            hints: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn byte(offset: u64) -> u8 {
+        (offset % 251) as u8
+    }
+
+    /// The bytes read since the last call, as one range, which the reads
+    /// must have covered exactly once.
+    fn take_range(&self) -> Option<(u64, u64)> {
+        let mut reads = std::mem::take(&mut *self.reads.lock().unwrap());
+        reads.sort();
+        let (start, _) = *reads.first()?;
+        let mut end = start;
+        for (offset, len) in reads {
+            assert_eq!(offset, end, "reads are contiguous and don't overlap");
+            end += len as u64;
+        }
+        Some((start, end))
+    }
+}
+
+impl LazyContents for Recorder {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let len = (buf.len() as u64).min(self.size.saturating_sub(offset)) as usize;
+        for (i, byte) in buf[..len].iter_mut().enumerate() {
+            *byte = Self::byte(offset + i as u64);
+        }
+        self.reads.lock().unwrap().push((offset, len));
+        Ok(len)
+    }
+
+    fn will_need(&self, offset: u64, len: u64) {
+        self.hints.lock().unwrap().push((offset, len));
+    }
+}
+
+fn expected(offset: u64, len: usize) -> Vec<u8> {
+    (offset..offset + len as u64).map(Recorder::byte).collect()
+}
+
+#[test]
+fn reads_only_requested_ranges() {
+    let fs = filesystem();
+    let root = fs.root();
+    let source = Recorder::new(4 * GIB);
+    root.put_lazy_file("big", source.clone(), StaticWrites::ReadOnly)
+        .expect("put lazy file");
+    assert_eq!(source.take_range(), None, "nothing is read up front");
+
+    let f = open_read(&root, false, "big").expect("open");
+    assert_eq!(run(f.get_filestat()).unwrap().size, 4 * GIB);
+    assert_eq!(run(f.num_ready_bytes()).unwrap(), 4 * GIB);
+
+    // Straddling a chunk boundary.
+    let offset = 3 * GIB + CHUNK - 50;
+    assert_eq!(pread(&*f, 100, offset), expected(offset, 100));
+    assert_eq!(source.take_range(), Some((offset, offset + 100)));
+
+    // Through the end of the file.
+    let offset = 4 * GIB - 10;
+    assert_eq!(pread(&*f, 100, offset), expected(offset, 10));
+    assert_eq!(source.take_range(), Some((offset, 4 * GIB)));
+    assert_eq!(pread(&*f, 100, 4 * GIB), b"");
+    assert_eq!(source.take_range(), None);
+
+    // Sequential reads.
+    run(f.seek(std::io::SeekFrom::Start(GIB))).expect("seek");
+    let mut buf = [0; 10];
+    let n = run(f.read_vectored(&mut [IoSliceMut::new(&mut buf)])).expect("read");
+    assert_eq!(n, 10);
+    assert_eq!(&buf[..], &expected(GIB, 10)[..]);
+    assert_eq!(source.take_range(), Some((GIB, GIB + 10)));
+
+    assert_eq!(capacity(&*f), 0);
+}
+
+#[test]
+fn host_file_source() {
+    let fs = filesystem();
+    let root = fs.root();
+    let tempdir = cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let data: Vec<u8> = (0..3 * CHUNK).map(Recorder::byte).collect();
+    let mut host_file = tempdir.create("data").expect("create host file");
+    host_file.write_all(&data).expect("write host file");
+    let host_file = tempdir.open("data").expect("open host file");
+
+    let source = ReadSeekContents::new(host_file).expect("source");
+    assert_eq!(source.size(), 3 * CHUNK);
+    root.put_lazy_file("data", Arc::new(source), StaticWrites::ReadOnly)
+        .expect("put lazy file");
+    assert_eq!(read_file(&root, "data"), data);
+    let f = open_read(&root, false, "data").expect("open");
+    assert_eq!(
+        pread(&*f, 20, CHUNK - 10),
+        &data[CHUNK as usize - 10..][..20]
+    );
+}
+
+#[test]
+fn writes() {
+    let fs = filesystem();
+    let root = fs.root();
+    let source = Recorder::new(4 * CHUNK);
+    root.put_lazy_file("read-only", source.clone(), StaticWrites::ReadOnly)
+        .expect("put lazy file");
+    assert_errkind!(open_write(&root, "read-only"), ErrorKind::Rofs);
+
+    root.put_lazy_file("file", source.clone(), StaticWrites::CopyOnWrite)
+        .expect("put lazy file");
+    let f = open_write(&root, "file").expect("open for writing");
+    // Writing to a chunk reads all of it from the source first, and it's
+    // never read from the source again.
+    run(f.write_vectored_at(&[IoSlice::new(b"xyz")], CHUNK + 5)).expect("pwrite");
+    assert_eq!(source.take_range(), Some((CHUNK, 2 * CHUNK)));
+    assert_eq!(capacity(&*f), CHUNK);
+    let mut want = expected(CHUNK, 10);
+    want[5..8].copy_from_slice(b"xyz");
+    assert_eq!(pread(&*f, 10, CHUNK), want);
+    assert_eq!(source.take_range(), None);
+
+    // Appending past the end doesn't read anything.
+    run(f.write_vectored_at(&[IoSlice::new(b"tail")], 4 * CHUNK)).expect("pwrite");
+    assert_eq!(source.take_range(), None);
+    assert_eq!(run(f.get_filestat()).unwrap().size, 4 * CHUNK + 4);
+
+    // Truncated data is never read again, even after growing.
+    run(f.set_filestat_size(CHUNK / 2)).expect("shrink");
+    run(f.set_filestat_size(4 * CHUNK)).expect("grow");
+    let contents = read_file(&root, "file");
+    assert_eq!(source.take_range(), Some((0, CHUNK / 2)));
+    assert_eq!(
+        &contents[..CHUNK as usize / 2],
+        &expected(0, CHUNK as usize / 2)[..]
+    );
+    assert!(contents[CHUNK as usize / 2..].iter().all(|b| *b == 0));
+}
+
+#[test]
+fn will_need_is_forwarded() {
+    let fs = filesystem();
+    let root = fs.root();
+    let source = Recorder::new(GIB);
+    root.put_lazy_file("file", source.clone(), StaticWrites::ReadOnly)
+        .expect("put lazy file");
+    let f = open_read(&root, false, "file").expect("open");
+
+    run(f.advise(100, 4096, Advice::WillNeed)).expect("advise");
+    run(f.advise(GIB - 10, 4096, Advice::WillNeed)).expect("advise");
+    run(f.advise(GIB / 2, 0, Advice::WillNeed)).expect("advise");
+    run(f.advise(2 * GIB, 0, Advice::WillNeed)).expect("advise");
+    run(f.advise(0, 0, Advice::Sequential)).expect("advise");
+    assert_eq!(
+        *source.hints.lock().unwrap(),
+        vec![(100, 4096), (GIB - 10, 10), (GIB / 2, GIB / 2)]
+    );
+    assert_eq!(source.take_range(), None);
+}
+
+#[test]
+fn source_errors_reach_the_guest() {
+    struct Failing;
+    impl LazyContents for Failing {
+        fn size(&self) -> u64 {
+            100
+        }
+        fn read_at(&self, _buf: &mut [u8], _offset: u64) -> io::Result<usize> {
+            Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "source unavailable",
+            ))
+        }
+    }
+
+    let fs = filesystem();
+    let root = fs.root();
+    root.put_lazy_file("file", Arc::new(Failing), StaticWrites::CopyOnWrite)
+        .expect("put lazy file");
+    let f = open_write(&root, "file").expect("open");
+    let mut buf = [0; 10];
+    assert!(run(f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)).is_err());
+    assert!(run(f.write_vectored_at(&[IoSlice::new(b"x")], 0)).is_err());
+    assert_eq!(capacity(&*f), 0);
+    // Nothing stays counted for a write which would have grown the file.
+    let used = fs.bytes_used();
+    assert!(run(f.write_vectored_at(&[IoSlice::new(b"0123456789")], 95)).is_err());
+    assert_eq!(fs.bytes_used(), used);
+    assert_eq!(run(f.get_filestat()).expect("stat").size, 100);
+    // A chunk past the source's data doesn't need reading from it.
+    run(f.write_vectored_at(&[IoSlice::new(b"x")], CHUNK)).expect("pwrite past the end");
+}
@@ -0,0 +1,296 @@
+use crate::{capacity, create_file, filesystem, read_file, run, sorted_names};
+use std::io::IoSlice;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags, WasiFile},
+    Error, ErrorKind,
+};
+use wasi_virtfs::{DEFAULT_MAX_FILE_SIZE, DEFAULT_NAME_MAX, DEFAULT_PATH_MAX};
+
+#[test]
+fn name_max() {
+    let fs = filesystem();
+    let root = fs.root();
+    assert_eq!(fs.name_max(), DEFAULT_NAME_MAX);
+    create_file(&root, "file", b"contents");
+
+    let at_limit = "a".repeat(DEFAULT_NAME_MAX);
+    let over = "b".repeat(DEFAULT_NAME_MAX + 1);
+
+    create_file(&root, &at_limit, b"");
+    run(root.unlink_file(&at_limit)).expect("unlink");
+    run(root.create_dir(&at_limit)).expect("create dir at NAME_MAX");
+    run(root.symlink("file", &format!("{}/link", at_limit))).expect("symlink at NAME_MAX");
+    run(root.hard_link("file", &root, &format!("{}/{}", at_limit, at_limit)))
+        .expect("hard link at NAME_MAX");
+    let before = sorted_names(&root);
+
+    assert_errkind!(
+        run(root.open_file(false, &over, OFlags::CREATE, false, true, FdFlags::empty())),
+        ErrorKind::Nametoolong
+    );
+    assert_errkind!(run(root.create_dir(&over)), ErrorKind::Nametoolong);
+    assert_errkind!(
+        run(root.create_dir(&format!("{}/x", over))),
+        ErrorKind::Nametoolong
+    );
+    assert_errkind!(run(root.symlink("file", &over)), ErrorKind::Nametoolong);
+    assert_errkind!(
+        run(root.rename("file", &root, &over)),
+        ErrorKind::Nametoolong
+    );
+    assert_errkind!(
+        run(root.hard_link("file", &root, &over)),
+        ErrorKind::Nametoolong
+    );
+    assert_eq!(sorted_names(&root), before, "failed calls leave no trace");
+}
+
+#[test]
+fn path_max() {
+    let fs = filesystem();
+    let root = fs.root();
+    assert_eq!(fs.path_max(), DEFAULT_PATH_MAX);
+
+    // "./" components keep every name short while making the path long.
+    let padding = "./".repeat((DEFAULT_PATH_MAX - 2) / 2);
+    let at_limit = format!("{}ab", padding);
+    assert_eq!(at_limit.len(), DEFAULT_PATH_MAX);
+    let over = format!("{}abc", padding);
+
+    create_file(&root, &at_limit, b"contents");
+    run(root.rename(&at_limit, &root, "moved")).expect("rename from PATH_MAX");
+    run(root.create_dir(&at_limit)).expect("create dir at PATH_MAX");
+    let before = sorted_names(&root);
+
+    assert_errkind!(
+        run(root.open_file(false, &over, OFlags::CREATE, false, true, FdFlags::empty())),
+        ErrorKind::Nametoolong
+    );
+    assert_errkind!(run(root.create_dir(&over)), ErrorKind::Nametoolong);
+    assert_errkind!(run(root.symlink("moved", &over)), ErrorKind::Nametoolong);
+    assert_errkind!(run(root.symlink(&over, "link")), ErrorKind::Nametoolong);
+    assert_errkind!(
+        run(root.rename("moved", &root, &over)),
+        ErrorKind::Nametoolong
+    );
+    assert_errkind!(
+        run(root.hard_link("moved", &root, &over)),
+        ErrorKind::Nametoolong
+    );
+    assert_eq!(sorted_names(&root), before, "failed calls leave no trace");
+}
+
+#[test]
+fn configured_limits() {
+    let fs = filesystem();
+    let root = fs.root();
+    fs.set_name_max(4);
+    fs.set_path_max(9);
+
+    run(root.create_dir("abcd")).expect("create dir at name_max");
+    assert_errkind!(run(root.create_dir("abcde")), ErrorKind::Nametoolong);
+    run(root.create_dir("abcd/efgh")).expect("create dir at path_max");
+    assert_errkind!(run(root.create_dir("abcd/./efg")), ErrorKind::Nametoolong);
+
+    fs.set_name_max(DEFAULT_NAME_MAX);
+    fs.set_path_max(DEFAULT_PATH_MAX);
+    run(root.create_dir("abcde")).expect("limits can be raised again");
+}
+
+#[test]
+fn max_depth_cuts_off_nested_mkdir() {
+    let fs = filesystem();
+    fs.set_max_depth(100);
+    assert_eq!(fs.max_depth(), 100);
+
+    // Walk down one level at a time, the way a guest loop would, so that
+    // path length limits don't get in the way.
+    let mut dir: Box<dyn WasiDir> = Box::new(fs.root());
+    let mut created = 0;
+    for _ in 0..10_000 {
+        match run(dir.create_dir("d")) {
+            Ok(()) => created += 1,
+            Err(e) => {
+                assert!(
+                    matches!(e.downcast_ref::<ErrorKind>(), Some(ErrorKind::Nospc)),
+                    "expected ENOSPC, got {:?}",
+                    e
+                );
+                break;
+            }
+        }
+        dir = run(dir.open_dir(false, "d")).expect("open d");
+    }
+    assert_eq!(created, 100);
+    // Other kinds of entries can still be created at the bottom.
+    create_file(&*dir, "file", b"contents");
+}
+
+#[test]
+fn max_depth_rename() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("x")).expect("create x");
+    run(root.create_dir("x/y")).expect("create x/y");
+    run(root.create_dir("a")).expect("create a");
+    run(root.create_dir("a/b")).expect("create a/b");
+    run(root.create_dir("a/b/c")).expect("create a/b/c");
+    fs.set_max_depth(3);
+
+    // a/b/c is already at the limit, so a can't go any deeper.
+    assert_errkind!(run(root.rename("a", &root, "x/a")), ErrorKind::Nospc);
+    assert_errkind!(run(root.rename("a/b", &root, "x/y/b")), ErrorKind::Nospc);
+    assert_errkind!(fs.exchange("a", "x/y"), ErrorKind::Nospc);
+    assert_errkind!(run(root.create_dir("a/b/c/d")), ErrorKind::Nospc);
+    run(root.get_path_filestat("a/b/c", false)).expect("a is where it was");
+
+    run(root.rename("a/b", &root, "x/b")).expect("rename a/b to the same depth");
+    run(root.rename("a", &root, "renamed")).expect("rename within a directory");
+
+    // Tightening the limit leaves existing directories alone.
+    fs.set_max_depth(1);
+    run(root.get_path_filestat("x/b/c", false)).expect("x/b/c is untouched");
+    assert_errkind!(run(root.create_dir("x/z")), ErrorKind::Nospc);
+}
+
+#[test]
+fn max_file_size() {
+    let fs = filesystem();
+    let root = fs.root();
+    assert_eq!(fs.max_file_size(), DEFAULT_MAX_FILE_SIZE);
+    create_file(&root, "file", b"");
+    let f = run(root.open_file(false, "file", OFlags::empty(), true, true, FdFlags::empty()))
+        .expect("open file");
+
+    // Absurd sizes fail before anything is allocated.
+    for (offset, len) in &[(0, 1 << 40), (1 << 40, 1), (0, DEFAULT_MAX_FILE_SIZE + 1)] {
+        assert_errkind!(run(f.allocate(*offset, *len)), ErrorKind::Fbig);
+    }
+    assert_errkind!(run(f.set_filestat_size(1 << 40)), ErrorKind::Fbig);
+    assert_errkind!(
+        run(f.write_vectored_at(&[IoSlice::new(b"x")], 1 << 40)),
+        ErrorKind::Fbig
+    );
+    assert_errkind!(
+        run(f.write_vectored_at(&[IoSlice::new(b"x")], DEFAULT_MAX_FILE_SIZE)),
+        ErrorKind::Fbig
+    );
+    assert_eq!(run(f.get_filestat()).unwrap().size, 0);
+    assert_eq!(capacity(&*f), 0);
+
+    // A smaller limit applies the same way, and growing right up to it is
+    // fine.
+    fs.set_max_file_size(16);
+    run(f.allocate(0, 8)).expect("allocate within the limit");
+    assert_errkind!(run(f.allocate(8, 9)), ErrorKind::Fbig);
+    run(f.set_filestat_size(16)).expect("grow to the limit");
+    assert_errkind!(run(f.set_filestat_size(17)), ErrorKind::Fbig);
+    run(f.write_vectored_at(&[IoSlice::new(b"end")], 13)).expect("write up to the limit");
+    assert_errkind!(
+        run(f.write_vectored_at(&[IoSlice::new(b"x")], 16)),
+        ErrorKind::Fbig
+    );
+    assert_eq!(read_file(&root, "file").len(), 16);
+}
+
+fn write_at(f: &dyn WasiFile, data: &[u8], offset: u64) -> Result<u64, Error> {
+    run(f.write_vectored_at(&[IoSlice::new(data)], offset))
+}
+
+#[test]
+fn writes_cut_short_at_max_file_size() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = run(root.open_file(false, "file", OFlags::empty(), true, true, FdFlags::APPEND))
+        .expect("open file");
+    fs.set_max_file_size(10);
+
+    assert_eq!(write_at(&*f, b"0123456", 0).expect("pwrite"), 7);
+    // The write which crosses the limit writes what fits...
+    let bufs = [IoSlice::new(b"ab"), IoSlice::new(b""), IoSlice::new(b"cd")];
+    assert_eq!(run(f.write_vectored(&bufs)).expect("append"), 3);
+    assert_eq!(read_file(&root, "file"), b"0123456abc");
+    // ...and the next one fails.
+    assert_errkind!(
+        run(f.write_vectored(&[IoSlice::new(b"d")])),
+        ErrorKind::Fbig
+    );
+    assert_errkind!(write_at(&*f, b"d", 10), ErrorKind::Fbig);
+    assert_errkind!(write_at(&*f, b"d", 20), ErrorKind::Fbig);
+
+    // Overwriting within the file is unaffected.
+    assert_eq!(write_at(&*f, b"xyz", 7).expect("pwrite"), 3);
+    assert_eq!(read_file(&root, "file"), b"0123456xyz");
+}
+
+#[test]
+fn appends_check_max_file_size_before_growing() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = run(root.open_file(false, "file", OFlags::empty(), true, true, FdFlags::APPEND))
+        .expect("open file");
+    // Files are stored in chunks of 64KiB, so this ends just into a second.
+    let limit = 64 * 1024 + 10;
+    fs.set_max_file_size(limit);
+
+    let line = [b'x'; 100];
+    let mut written = 0;
+    while written < limit {
+        written += run(f.write_vectored(&[IoSlice::new(&line)])).expect("append");
+    }
+    assert_eq!(written, limit, "the last append is cut short at the limit");
+    let held = capacity(&*f);
+    assert_errkind!(
+        run(f.write_vectored(&[IoSlice::new(&line)])),
+        ErrorKind::Fbig
+    );
+    assert_eq!(
+        capacity(&*f),
+        held,
+        "nothing allocated for the failed append"
+    );
+    assert_eq!(run(f.get_filestat()).unwrap().size, limit);
+}
+
+#[test]
+fn lowering_max_file_size() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "big", b"0123456789");
+    create_file(&root, "small", b"01");
+    let big = run(root.open_file(false, "big", OFlags::empty(), true, true, FdFlags::empty()))
+        .expect("open big");
+    fs.set_max_file_size(4);
+
+    // A file already over the new limit keeps its contents and can be
+    // rewritten in place or shrunk, but not grown.
+    assert_eq!(read_file(&root, "big"), b"0123456789");
+    assert_eq!(write_at(&*big, b"abcdef", 6).expect("rewrite"), 4);
+    assert_eq!(read_file(&root, "big"), b"012345abcd");
+    assert_errkind!(write_at(&*big, b"e", 10), ErrorKind::Fbig);
+    assert_errkind!(run(big.allocate(0, 11)), ErrorKind::Fbig);
+    run(big.allocate(0, 10)).expect("allocate within the file");
+    run(big.set_filestat_size(8)).expect("shrink");
+    assert_errkind!(run(big.set_filestat_size(9)), ErrorKind::Fbig);
+
+    // Other files are held to the new limit.
+    let small = run(root.open_file(
+        false,
+        "small",
+        OFlags::empty(),
+        true,
+        true,
+        FdFlags::empty(),
+    ))
+    .expect("open small");
+    assert_eq!(write_at(&*small, b"2345", 2).expect("pwrite"), 2);
+    assert_eq!(read_file(&root, "small"), b"0123");
+
+    // Raising the limit again lets them grow.
+    fs.set_max_file_size(1024);
+    assert_eq!(write_at(&*small, b"45", 4).expect("pwrite"), 2);
+    run(big.set_filestat_size(20)).expect("grow");
+}
@@ -0,0 +1,148 @@
+use crate::{create_file, filesystem, open_read, run, virtfs_file};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Wake, Waker};
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags},
+    ErrorKind,
+};
+use wasi_virtfs::LockKind;
+
+/// Counts the times it's woken.
+#[derive(Default)]
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn poll(future: &mut Pin<Box<dyn Future<Output = ()> + '_>>, waker: &Waker) -> bool {
+    future
+        .as_mut()
+        .poll(&mut Context::from_waker(waker))
+        .is_ready()
+}
+
+#[test]
+fn handles_contend() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "db", b"");
+    let a = open_read(&root, false, "db").expect("open");
+    let b = open_read(&root, false, "db").expect("open");
+    let (a, b) = (virtfs_file(&*a), virtfs_file(&*b));
+
+    a.try_lock(LockKind::Shared).expect("shared lock");
+    b.try_lock(LockKind::Shared).expect("another shared lock");
+    assert_errkind!(b.try_lock(LockKind::Exclusive), ErrorKind::Again);
+    assert_eq!(b.lock_held(), Some(LockKind::Shared), "b keeps its lock");
+    a.unlock();
+    assert_eq!(a.lock_held(), None);
+    b.try_lock(LockKind::Exclusive).expect("upgrade");
+    assert_errkind!(a.try_lock(LockKind::Shared), ErrorKind::Again);
+    assert_errkind!(a.try_lock(LockKind::Exclusive), ErrorKind::Again);
+
+    // Locking again, or unlocking without a lock, is harmless.
+    b.try_lock(LockKind::Exclusive).expect("relock");
+    a.unlock();
+    assert_eq!(b.lock_held(), Some(LockKind::Exclusive));
+
+    // Locks belong to the file, not to the name it's opened by.
+    run(root.rename("db", &root, "renamed")).expect("rename");
+    let c = open_read(&root, false, "renamed").expect("open");
+    assert_errkind!(
+        virtfs_file(&*c).try_lock(LockKind::Shared),
+        ErrorKind::Again
+    );
+    create_file(&root, "db", b"");
+    let d = open_read(&root, false, "db").expect("open");
+    virtfs_file(&*d)
+        .try_lock(LockKind::Exclusive)
+        .expect("lock a different file");
+}
+
+#[test]
+fn released_on_drop() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "lockfile", b"");
+    let open = || {
+        run(root.open_file(
+            false,
+            "lockfile",
+            OFlags::empty(),
+            false,
+            false,
+            FdFlags::empty(),
+        ))
+        .expect("open")
+    };
+    let a = open();
+    virtfs_file(&*a)
+        .try_lock(LockKind::Exclusive)
+        .expect("lock a handle opened for neither reading nor writing");
+    let b = open();
+    assert_errkind!(
+        virtfs_file(&*b).try_lock(LockKind::Shared),
+        ErrorKind::Again
+    );
+    drop(a);
+    virtfs_file(&*b)
+        .try_lock(LockKind::Exclusive)
+        .expect("lock after the holder is dropped");
+
+    // Even after the file is unlinked.
+    run(root.unlink_file("lockfile")).expect("unlink");
+    drop(b);
+}
+
+#[test]
+fn waiting_for_a_lock() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let a = open_read(&root, false, "file").expect("open");
+    let b = open_read(&root, false, "file").expect("open");
+    let c = open_read(&root, false, "file").expect("open");
+    let (b, c) = (virtfs_file(&*b), virtfs_file(&*c));
+    virtfs_file(&*a)
+        .try_lock(LockKind::Exclusive)
+        .expect("lock");
+
+    let woken = Arc::new(CountingWaker::default());
+    let waker = Waker::from(woken.clone());
+    let mut shared_b: Pin<Box<dyn Future<Output = ()>>> = Box::pin(b.lock(LockKind::Shared));
+    let mut shared_c: Pin<Box<dyn Future<Output = ()>>> = Box::pin(c.lock(LockKind::Shared));
+    assert!(!poll(&mut shared_b, &waker));
+    assert!(!poll(&mut shared_b, &waker));
+    assert!(!poll(&mut shared_c, &waker));
+    assert_eq!(woken.0.load(Ordering::SeqCst), 0);
+
+    // Downgrading lets both shared locks through.
+    virtfs_file(&*a)
+        .try_lock(LockKind::Shared)
+        .expect("downgrade");
+    assert_eq!(woken.0.load(Ordering::SeqCst), 1, "woken once");
+    assert!(poll(&mut shared_b, &waker));
+    assert!(poll(&mut shared_c, &waker));
+    assert_eq!(b.lock_held(), Some(LockKind::Shared));
+
+    // An exclusive lock waits for every shared lock to go.
+    let mut exclusive: Pin<Box<dyn Future<Output = ()>>> = Box::pin(b.lock(LockKind::Exclusive));
+    assert!(!poll(&mut exclusive, &waker));
+    drop(a);
+    assert!(!poll(&mut exclusive, &waker));
+    c.unlock();
+    assert!(poll(&mut exclusive, &waker));
+    assert_eq!(b.lock_held(), Some(LockKind::Exclusive));
+
+    // Without contention, no executor that can wait is needed.
+    c.unlock();
+    b.unlock();
+    run(c.lock(LockKind::Exclusive));
+}
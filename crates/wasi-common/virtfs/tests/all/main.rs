@@ -0,0 +1,277 @@
+/// Assert that a `Result` failed with the given `wasi_common::ErrorKind`.
+macro_rules! assert_errkind {
+    ($result:expr, $kind:pat) => {
+        match $result {
+            Ok(_) => panic!("expected {}, got Ok", stringify!($kind)),
+            Err(e) => assert!(
+                matches!(e.downcast_ref::<wasi_common::ErrorKind>(), Some($kind)),
+                "expected {}, got {:?}",
+                stringify!($kind),
+                e
+            ),
+        }
+    };
+}
+
+mod advise;
+mod builder;
+mod canonicalize;
+mod caps;
+mod case_insensitive;
+mod chunks;
+mod clock;
+mod clone;
+mod conformance;
+mod copy;
+mod create_dir_all;
+mod device;
+mod dynamic;
+mod export_tar;
+mod fifo;
+mod file;
+mod fork;
+mod fs_stats;
+mod hard_link;
+mod hash;
+mod host_dir;
+mod import_tar;
+mod import_zip;
+mod lazy;
+mod limits;
+mod lock;
+mod mount;
+mod names;
+mod normalization;
+mod open;
+mod paths;
+mod quota;
+mod readdir;
+mod readonly;
+mod remove_dir;
+mod remove_dir_all;
+mod rename;
+#[cfg(feature = "serde")]
+mod serialize;
+mod serials;
+mod set_times;
+mod sparse;
+mod stat;
+mod static_files;
+mod symlink;
+mod sync;
+mod times;
+mod tmpfile;
+
+use cap_std::time::{Duration, SystemTime};
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use wasi_common::{
+    clocks::WasiSystemClock,
+    dir::{ReaddirCursor, ReaddirEntity, WasiDir},
+    file::{FdFlags, OFlags, WasiFile},
+    Error,
+};
+use wasi_virtfs::{File, Filesystem};
+use wiggle::{borrow::BorrowChecker, BorrowHandle, GuestError, GuestPtr, Region};
+
+pub(crate) use wiggle::run_in_dummy_executor as run;
+
+pub(crate) fn filesystem() -> Arc<Filesystem> {
+    Filesystem::new(wasi_cap_std_sync::clocks_ctx().system, 0)
+}
+
+/// A clock which advances by one second every time it is read, so that
+/// every timestamp taken by the filesystem is distinct.
+struct TickClock(AtomicU64);
+
+impl WasiSystemClock for TickClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+    fn now(&self, _precision: Duration) -> SystemTime {
+        let secs = self.0.fetch_add(1, Ordering::Relaxed);
+        SystemTime::from_std(std::time::UNIX_EPOCH + Duration::from_secs(secs))
+    }
+}
+
+pub(crate) fn ticking_filesystem() -> Arc<Filesystem> {
+    Filesystem::new(Box::new(TickClock(AtomicU64::new(1))), 0)
+}
+
+/// The virtfs `File` behind `f`.
+pub(crate) fn virtfs_file(f: &dyn WasiFile) -> &File {
+    f.as_any().downcast_ref::<File>().expect("virtfs file")
+}
+
+/// The memory a virtfs file holds for its contents.
+pub(crate) fn capacity(f: &dyn WasiFile) -> u64 {
+    virtfs_file(f).capacity()
+}
+
+pub(crate) fn create_file(dir: &dyn WasiDir, path: &str, contents: &[u8]) {
+    let f = run(dir.open_file(
+        false,
+        path,
+        OFlags::CREATE | OFlags::EXCLUSIVE,
+        false,
+        true,
+        FdFlags::empty(),
+    ))
+    .expect("create file");
+    run(f.write_vectored(&[std::io::IoSlice::new(contents)])).expect("write contents");
+}
+
+pub(crate) fn readdir(dir: &dyn WasiDir) -> Vec<ReaddirEntity> {
+    run(dir.readdir(ReaddirCursor::from(0)))
+        .expect("readdir")
+        .collect::<Result<_, _>>()
+        .expect("readdir entries")
+}
+
+/// The names `dir` lists, in the order it lists them.
+pub(crate) fn names(dir: &dyn WasiDir) -> Vec<String> {
+    readdir(dir).into_iter().map(|e| e.name).collect()
+}
+
+/// The names the directory at `path` lists, in the order it lists them.
+pub(crate) fn names_at(dir: &dyn WasiDir, path: &str) -> Vec<String> {
+    names(&*run(dir.open_dir(false, path)).expect("open dir"))
+}
+
+/// The names `dir` lists, sorted.
+pub(crate) fn sorted_names(dir: &dyn WasiDir) -> Vec<String> {
+    let mut names = names(dir);
+    names.sort();
+    names
+}
+
+/// Open an existing file for reading.
+pub(crate) fn open_read(
+    dir: &dyn WasiDir,
+    symlink_follow: bool,
+    path: &str,
+) -> Result<Box<dyn WasiFile>, Error> {
+    run(dir.open_file(
+        symlink_follow,
+        path,
+        OFlags::empty(),
+        true,
+        false,
+        FdFlags::empty(),
+    ))
+}
+
+/// Open a file for reading and writing, creating it if it's missing.
+pub(crate) fn open_write(dir: &dyn WasiDir, path: &str) -> Result<Box<dyn WasiFile>, Error> {
+    run(dir.open_file(false, path, OFlags::CREATE, true, true, FdFlags::empty()))
+}
+
+/// Up to `len` bytes from `offset`. The buffer starts out as 0xff, so that
+/// zeros in what's returned were read.
+pub(crate) fn pread(f: &dyn WasiFile, len: usize, offset: u64) -> Vec<u8> {
+    let mut buf = vec![0xff; len];
+    let n =
+        run(f.read_vectored_at(&mut [std::io::IoSliceMut::new(&mut buf)], offset)).expect("pread");
+    buf.truncate(n as usize);
+    buf
+}
+
+/// Open the existing file at `path` with the access and flags given.
+pub(crate) fn open(
+    dir: &dyn WasiDir,
+    path: &str,
+    read: bool,
+    write: bool,
+    fdflags: FdFlags,
+) -> Box<dyn WasiFile> {
+    run(dir.open_file(false, path, OFlags::empty(), read, write, fdflags)).expect("open")
+}
+
+/// Write what fits of `data` at the file's position.
+pub(crate) fn write(f: &dyn WasiFile, data: &[u8]) -> Result<u64, Error> {
+    run(f.write_vectored(&[std::io::IoSlice::new(data)]))
+}
+
+/// Write all of `data` at the file's position.
+pub(crate) fn write_all(f: &dyn WasiFile, data: &[u8]) {
+    assert_eq!(write(f, data).expect("write"), data.len() as u64);
+}
+
+/// Up to `len` bytes from the file's position.
+pub(crate) fn read(f: &dyn WasiFile, len: usize) -> Result<Vec<u8>, Error> {
+    let mut buf = vec![0; len];
+    let n = run(f.read_vectored(&mut [std::io::IoSliceMut::new(&mut buf)]))?;
+    buf.truncate(n as usize);
+    Ok(buf)
+}
+
+pub(crate) fn read_file(dir: &dyn WasiDir, path: &str) -> Vec<u8> {
+    let f = open_read(dir, true, path).expect("open file");
+    let size = run(f.get_filestat()).expect("stat file").size;
+    let mut contents = vec![0; size as usize];
+    let nread = run(f.read_vectored(&mut [std::io::IoSliceMut::new(&mut contents)]))
+        .expect("read contents");
+    assert_eq!(nread, size, "short read");
+    contents
+}
+
+/// Guest memory holding the strings passed to a `WasiCtx`, for calling it
+/// as a guest would.
+pub(crate) struct Memory {
+    bytes: UnsafeCell<Box<[u8]>>,
+    used: AtomicU32,
+    bc: BorrowChecker,
+}
+
+// Bytes are only written by `Memory::str`, to a part of memory no pointer
+// has been handed out for yet.
+unsafe impl Send for Memory {}
+unsafe impl Sync for Memory {}
+
+impl Memory {
+    pub(crate) fn new() -> Self {
+        Memory {
+            bytes: UnsafeCell::new(vec![0; 64 * 1024].into_boxed_slice()),
+            used: AtomicU32::new(0),
+            bc: BorrowChecker::new(),
+        }
+    }
+
+    /// A pointer to a copy of `s` in guest memory.
+    pub(crate) fn str(&self, s: &str) -> GuestPtr<str> {
+        let len = s.len() as u32;
+        let offset = self.used.fetch_add(len, Ordering::SeqCst);
+        let bytes = unsafe { &mut *self.bytes.get() };
+        bytes[offset as usize..][..s.len()].copy_from_slice(s.as_bytes());
+        GuestPtr::new(self, (offset, len))
+    }
+}
+
+unsafe impl wiggle::GuestMemory for Memory {
+    fn base(&self) -> (*mut u8, u32) {
+        let bytes = unsafe { &mut *self.bytes.get() };
+        (bytes.as_mut_ptr(), bytes.len() as u32)
+    }
+    fn has_outstanding_borrows(&self) -> bool {
+        self.bc.has_outstanding_borrows()
+    }
+    fn is_shared_borrowed(&self, r: Region) -> bool {
+        self.bc.is_shared_borrowed(r)
+    }
+    fn is_mut_borrowed(&self, r: Region) -> bool {
+        self.bc.is_mut_borrowed(r)
+    }
+    fn shared_borrow(&self, r: Region) -> Result<BorrowHandle, GuestError> {
+        self.bc.shared_borrow(r)
+    }
+    fn mut_borrow(&self, r: Region) -> Result<BorrowHandle, GuestError> {
+        self.bc.mut_borrow(r)
+    }
+    fn shared_unborrow(&self, h: BorrowHandle) {
+        self.bc.shared_unborrow(h)
+    }
+    fn mut_unborrow(&self, h: BorrowHandle) {
+        self.bc.mut_unborrow(h)
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{create_file, filesystem, names, read_file, readdir, run};
+use std::sync::Arc;
+use wasi_cap_std_sync::dir::Dir as HostDir;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, FileType, OFlags},
+    ErrorKind, SystemTimeSpec,
+};
+use wasi_virtfs::{Filesystem, StaticWrites};
+
+/// A filesystem with the root of another, holding `data/a.txt`, mounted at
+/// `mnt`.
+fn mounted() -> (Arc<Filesystem>, Arc<Filesystem>) {
+    let other = filesystem();
+    other.create_dir_all("data").expect("mkdir");
+    create_file(&other.root(), "data/a.txt", b"mounted");
+    let fs = filesystem();
+    fs.create_dir_all("top").expect("mkdir");
+    create_file(&fs.root(), "top/b.txt", b"top");
+    fs.mount("mnt", Box::new(other.root())).expect("mount");
+    (fs, other)
+}
+
+#[test]
+fn open_readdir_and_stat_through_a_mount() {
+    let (fs, other) = mounted();
+    let root = fs.root();
+
+    assert_eq!(read_file(&root, "mnt/data/a.txt"), b"mounted");
+    create_file(&root, "mnt/data/new", b"new");
+    assert_eq!(read_file(&other.root(), "data/new"), b"new");
+    run(root.create_dir("mnt/sub")).expect("mkdir");
+    run(root.symlink("../data/a.txt", "mnt/sub/link")).expect("symlink");
+    assert_eq!(read_file(&root, "mnt/sub/link"), b"mounted");
+
+    // The parent lists the mount point as a directory.
+    let entries = readdir(&root);
+    let mnt = entries
+        .iter()
+        .find(|e| e.name == "mnt")
+        .expect("mnt listed");
+    assert_eq!(mnt.filetype, FileType::Directory);
+    let mnt = run(root.open_dir(false, "mnt")).expect("open mount point");
+    assert_eq!(names(&*mnt), [".", "..", "data", "sub"]);
+    assert_eq!(read_file(&*mnt, "data/a.txt"), b"mounted");
+    let data = run(root.open_dir(false, "mnt/data/")).expect("open");
+    assert_eq!(names(&*data), [".", "..", "a.txt", "new"]);
+    run(root.open_file(
+        false,
+        "mnt",
+        OFlags::DIRECTORY,
+        true,
+        false,
+        FdFlags::empty(),
+    ))
+    .expect("open mount point as a file");
+
+    // Stats are the mounted filesystem's.
+    let stat = run(root.get_path_filestat("mnt", false)).expect("stat");
+    assert_eq!(stat.filetype, FileType::Directory);
+    assert_eq!(stat.device_id, other.device_id());
+    assert_eq!(stat.inode, 0);
+    let stat = run(root.get_path_filestat("mnt/data/a.txt", false)).expect("stat");
+    assert_eq!(stat.size, 7);
+    assert_eq!(stat.device_id, other.device_id());
+    assert_eq!(
+        run(data.get_filestat()).expect("stat").device_id,
+        other.device_id()
+    );
+
+    // A symlink outside the mount can lead into it.
+    run(root.symlink("mnt/data", "data")).expect("symlink");
+    assert_eq!(read_file(&root, "data/a.txt"), b"mounted");
+    assert_eq!(
+        run(root.read_link("mnt/sub/link")).expect("readlink"),
+        std::path::PathBuf::from("../data/a.txt")
+    );
+
+    let now = || Some(SystemTimeSpec::SymbolicNow);
+    run(root.set_times("mnt/data/a.txt", now(), now(), false)).expect("set times");
+    run(root.unlink_file("mnt/data/new")).expect("unlink");
+    run(root.unlink_file("mnt/sub/link")).expect("unlink");
+    run(root.remove_dir("mnt/sub")).expect("rmdir");
+    assert_eq!(names(&other.root()), [".", "..", "data"]);
+
+    // `..` at the top of the mount stays there.
+    assert_eq!(read_file(&root, "mnt/../data/a.txt"), b"mounted");
+    assert_errkind!(
+        run(root.get_path_filestat("mnt/../top/b.txt", false)),
+        ErrorKind::Noent
+    );
+}
+
+#[test]
+fn nothing_crosses_the_mount_point() {
+    let (fs, other) = mounted();
+    let root = fs.root();
+    let rename = |from: &str, to: &str| run(root.rename(from, &root, to));
+    let link = |from: &str, to: &str| run(root.hard_link(from, &root, to));
+
+    assert_errkind!(rename("top/b.txt", "mnt/b.txt"), ErrorKind::Xdev);
+    assert_errkind!(rename("mnt/data/a.txt", "top/a.txt"), ErrorKind::Xdev);
+    assert_errkind!(rename("top", "mnt/top"), ErrorKind::Xdev);
+    assert_errkind!(link("top/b.txt", "mnt/b.txt"), ErrorKind::Xdev);
+    assert_errkind!(link("mnt/data/a.txt", "top/a.txt"), ErrorKind::Xdev);
+    let data = run(root.open_dir(false, "mnt/data")).expect("open");
+    assert_errkind!(
+        run(root.rename("top/b.txt", &*data, "b.txt")),
+        ErrorKind::Xdev
+    );
+    assert_errkind!(
+        run(data.rename("a.txt", &root, "top/a.txt")),
+        ErrorKind::Xdev
+    );
+    assert_errkind!(
+        run(root.hard_link("top/b.txt", &*data, "b.txt")),
+        ErrorKind::Xdev
+    );
+
+    // Within the mount, both work.
+    rename("mnt/data/a.txt", "mnt/a.txt").expect("rename");
+    link("mnt/a.txt", "mnt/data/a.txt").expect("link");
+    let mnt = run(root.open_dir(false, "mnt")).expect("open");
+    run(mnt.rename("a.txt", &*data, "b.txt")).expect("rename");
+    assert_eq!(names(&*data), [".", "..", "a.txt", "b.txt"]);
+    assert_eq!(read_file(&other.root(), "data/b.txt"), b"mounted");
+
+    // The mount point itself stays put.
+    run(root.create_dir("empty")).expect("mkdir");
+    assert_errkind!(rename("mnt", "moved"), ErrorKind::Busy);
+    assert_errkind!(rename("empty", "mnt"), ErrorKind::Busy);
+    assert_errkind!(root.exchange("mnt", &root, "empty"), ErrorKind::Busy);
+    assert_errkind!(root.exchange("top", &root, "mnt"), ErrorKind::Busy);
+    assert_errkind!(fs.exchange("top/b.txt", "mnt"), ErrorKind::Busy);
+    assert_errkind!(run(root.remove_dir("mnt")), ErrorKind::Busy);
+    assert_errkind!(run(root.unlink_file("mnt")), ErrorKind::Isdir);
+    assert_errkind!(link("mnt", "link"), ErrorKind::Perm);
+    assert_errkind!(fs.remove_dir_all("mnt"), ErrorKind::Busy);
+    // Nor does one further down a tree being removed.
+    fs.mount("top/inner", Box::new(filesystem().root()))
+        .expect("mount");
+    assert_errkind!(fs.remove_dir_all("top"), ErrorKind::Busy);
+    assert_eq!(read_file(&root, "top/b.txt"), b"top");
+    fs.unmount("top/inner").expect("unmount");
+    assert_eq!(fs.remove_dir_all("top").expect("remove"), 2);
+
+    // So do the host's own methods.
+    assert_errkind!(
+        root.put_static_file("mnt/c.txt", b"", StaticWrites::ReadOnly),
+        ErrorKind::Xdev
+    );
+    assert_errkind!(fs.create_dir_all("mnt/dir"), ErrorKind::Xdev);
+    assert_errkind!(
+        fs.mount("mnt", Box::new(filesystem().root())),
+        ErrorKind::Exist
+    );
+}
+
+#[test]
+fn unmount_waits_for_handles() {
+    let (fs, _other) = mounted();
+    let root = fs.root();
+    let file = run(root.open_file(
+        false,
+        "mnt/data/a.txt",
+        OFlags::empty(),
+        true,
+        false,
+        FdFlags::empty(),
+    ))
+    .expect("open");
+    assert_errkind!(fs.unmount("mnt"), ErrorKind::Busy);
+    drop(file);
+    let dir = run(root.open_dir(false, "mnt")).expect("open");
+    let inner = run(dir.open_dir(false, "data")).expect("open");
+    drop(dir);
+    assert_errkind!(fs.unmount("mnt"), ErrorKind::Busy);
+    drop(inner);
+
+    let unmounted = fs.unmount("mnt").expect("unmount");
+    assert_eq!(read_file(&*unmounted, "data/a.txt"), b"mounted");
+    assert_eq!(names(&root), [".", "..", "top"]);
+    assert_errkind!(run(root.get_path_filestat("mnt", false)), ErrorKind::Noent);
+    assert_errkind!(fs.unmount("mnt"), ErrorKind::Noent);
+    assert_errkind!(fs.unmount("top"), ErrorKind::Inval);
+
+    // The same directory can be mounted again, elsewhere.
+    fs.mount("top/again", unmounted).expect("mount");
+    assert_eq!(read_file(&root, "top/again/data/a.txt"), b"mounted");
+}
+
+#[test]
+fn mounted_host_directory() {
+    let tempdir = cap_tempfile::tempdir(cap_tempfile::ambient_authority()).expect("create tempdir");
+    let host = HostDir::from_cap_std(tempdir.open_dir(".").expect("open tempdir"));
+    let fs = filesystem();
+    let root = fs.root();
+    fs.mount("host", Box::new(host)).expect("mount");
+
+    create_file(&root, "host/greeting", b"hello");
+    assert_eq!(tempdir.read("greeting").expect("read on host"), b"hello");
+    assert_eq!(read_file(&root, "host/greeting"), b"hello");
+    run(root.create_dir("host/dir")).expect("mkdir");
+    assert!(tempdir.is_dir("dir"));
+
+    let host = run(root.open_dir(false, "host")).expect("open");
+    let mut listed = names(&*host);
+    listed.sort();
+    assert_eq!(listed, [".", "..", "dir", "greeting"]);
+    let stat = run(root.get_path_filestat("host", false)).expect("stat");
+    assert_eq!(stat.filetype, FileType::Directory);
+    assert_ne!(stat.device_id, fs.device_id());
+    let stat = run(root.get_path_filestat("host/greeting", false)).expect("stat");
+    assert_eq!(stat.filetype, FileType::RegularFile);
+    assert_eq!(stat.size, 5);
+
+    create_file(&root, "local", b"");
+    assert_errkind!(
+        run(root.rename("local", &root, "host/local")),
+        ErrorKind::Xdev
+    );
+    run(root.rename("host/greeting", &root, "host/dir/greeting")).expect("rename");
+    assert_eq!(read_file(&*host, "dir/greeting"), b"hello");
+    drop(host);
+    fs.unmount("host").expect("unmount");
+}
@@ -0,0 +1,63 @@
+use crate::{create_file, filesystem, run, sorted_names};
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags},
+    ErrorKind,
+};
+use wasi_virtfs::validate_name;
+
+#[test]
+fn validate() {
+    for name in &["a", "...", ".hidden", "a b", "ünïcödé", "x.."] {
+        validate_name(name).expect(name);
+    }
+    assert_errkind!(validate_name(""), ErrorKind::Inval);
+    assert_errkind!(validate_name("."), ErrorKind::Inval);
+    assert_errkind!(validate_name(".."), ErrorKind::Inval);
+    assert_errkind!(validate_name("a/b"), ErrorKind::Inval);
+    assert_errkind!(validate_name("/"), ErrorKind::Inval);
+    assert_errkind!(validate_name("a\0b"), ErrorKind::Ilseq);
+    assert_errkind!(validate_name("\0"), ErrorKind::Ilseq);
+}
+
+#[test]
+fn nul_in_created_names() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    run(root.create_dir("dir")).expect("create dir");
+    let before = sorted_names(&root);
+
+    assert_errkind!(run(root.create_dir("a\0b")), ErrorKind::Ilseq);
+    assert_errkind!(run(root.create_dir("dir/\0")), ErrorKind::Ilseq);
+    assert_errkind!(
+        run(root.open_file(false, "a\0b", OFlags::CREATE, false, true, FdFlags::empty())),
+        ErrorKind::Ilseq
+    );
+    assert_errkind!(run(root.symlink("file", "a\0b")), ErrorKind::Ilseq);
+    assert_errkind!(run(root.rename("file", &root, "a\0b")), ErrorKind::Ilseq);
+    assert_errkind!(run(root.hard_link("file", &root, "a\0b")), ErrorKind::Ilseq);
+    assert_eq!(sorted_names(&root), before);
+    assert_eq!(
+        sorted_names(&*run(root.open_dir(false, "dir")).unwrap()),
+        vec![".", ".."]
+    );
+}
+
+#[test]
+fn dot_names_are_never_created() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    run(root.create_dir("dir")).expect("create dir");
+    let before = sorted_names(&root);
+
+    assert_errkind!(run(root.rename("file", &root, ".")), ErrorKind::Inval);
+    assert_errkind!(run(root.rename("file", &root, "dir/..")), ErrorKind::Inval);
+    assert_errkind!(run(root.rename("dir", &root, "./")), ErrorKind::Inval);
+    assert_errkind!(run(root.create_dir(".")), ErrorKind::Exist);
+    assert_errkind!(run(root.create_dir("dir/..")), ErrorKind::Exist);
+    assert_errkind!(run(root.symlink("file", "..")), ErrorKind::Exist);
+    assert_errkind!(run(root.hard_link("file", &root, ".")), ErrorKind::Exist);
+    assert_eq!(sorted_names(&root), before);
+}
@@ -0,0 +1,108 @@
+use crate::{create_file, filesystem, read_file, run, sorted_names};
+use std::sync::Arc;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags},
+    ErrorKind,
+};
+use wasi_virtfs::Filesystem;
+
+/// `café` with a precomposed `é`, as in NFC.
+const COMPOSED: &str = "caf\u{e9}";
+/// `café` with `e` followed by a combining acute accent, as in NFD.
+const DECOMPOSED: &str = "cafe\u{301}";
+
+fn normalizing() -> Arc<Filesystem> {
+    let fs = filesystem();
+    fs.set_normalize_names(true);
+    fs
+}
+
+#[test]
+fn either_form_finds_the_entry() {
+    let fs = normalizing();
+    assert!(fs.normalizes_names());
+    let root = fs.root();
+    create_file(&root, DECOMPOSED, b"contents");
+    run(root.create_dir(&format!("{}.d", COMPOSED))).expect("create dir");
+
+    assert_eq!(read_file(&root, COMPOSED), b"contents");
+    assert_eq!(read_file(&root, DECOMPOSED), b"contents");
+    run(root.open_dir(false, &format!("{}.d", DECOMPOSED))).expect("open dir");
+    create_file(&root, &format!("{}.d/inner", DECOMPOSED), b"inner");
+    assert_eq!(read_file(&root, &format!("{}.d/inner", COMPOSED)), b"inner");
+}
+
+#[test]
+fn readdir_reports_nfc() {
+    let fs = normalizing();
+    let root = fs.root();
+    create_file(&root, DECOMPOSED, b"contents");
+    run(root.symlink("target", &format!("{}.link", DECOMPOSED))).expect("create symlink");
+
+    assert_eq!(
+        sorted_names(&root),
+        vec![
+            ".".to_owned(),
+            "..".to_owned(),
+            COMPOSED.to_owned(),
+            format!("{}.link", COMPOSED)
+        ]
+    );
+
+    run(root.rename(DECOMPOSED, &root, &format!("{}.moved", DECOMPOSED))).expect("rename");
+    assert!(sorted_names(&root).contains(&format!("{}.moved", COMPOSED)));
+}
+
+#[test]
+fn forms_collide() {
+    let fs = normalizing();
+    let root = fs.root();
+    create_file(&root, COMPOSED, b"contents");
+
+    assert_errkind!(run(root.create_dir(DECOMPOSED)), ErrorKind::Exist);
+    assert_errkind!(
+        run(root.open_file(
+            false,
+            DECOMPOSED,
+            OFlags::CREATE | OFlags::EXCLUSIVE,
+            false,
+            true,
+            FdFlags::empty()
+        )),
+        ErrorKind::Exist
+    );
+    assert_errkind!(run(root.symlink("target", DECOMPOSED)), ErrorKind::Exist);
+    assert_errkind!(
+        run(root.hard_link(COMPOSED, &root, DECOMPOSED)),
+        ErrorKind::Exist
+    );
+    assert_eq!(sorted_names(&root), vec![".", "..", COMPOSED]);
+
+    run(root.unlink_file(DECOMPOSED)).expect("unlink");
+    assert_eq!(sorted_names(&root), vec![".", ".."]);
+}
+
+#[test]
+fn combined_with_case_insensitivity() {
+    let fs = Filesystem::new_case_insensitive(wasi_cap_std_sync::clocks_ctx().system, 0);
+    fs.set_normalize_names(true);
+    let root = fs.root();
+    create_file(&root, "Cafe\u{301}", b"contents");
+
+    assert_eq!(read_file(&root, "CAF\u{c9}"), b"contents");
+    assert_eq!(sorted_names(&root), vec![".", "..", "Caf\u{e9}"]);
+}
+
+#[test]
+fn byte_exact_by_default() {
+    let fs = filesystem();
+    assert!(!fs.normalizes_names());
+    let root = fs.root();
+    create_file(&root, COMPOSED, b"composed");
+    create_file(&root, DECOMPOSED, b"decomposed");
+
+    assert_eq!(read_file(&root, COMPOSED), b"composed");
+    assert_eq!(read_file(&root, DECOMPOSED), b"decomposed");
+    assert_eq!(sorted_names(&root), vec![".", "..", DECOMPOSED, COMPOSED]);
+}
@@ -0,0 +1,431 @@
+use crate::{create_file, filesystem, open_read, read_file, run, ticking_filesystem};
+use cap_std::time::{Duration, SystemTime};
+use std::{
+    sync::{Arc, Barrier},
+    thread,
+};
+use wasi_common::{
+    clocks::WasiSystemClock,
+    dir::WasiDir,
+    file::{FdFlags, FileType, OFlags, WasiFile},
+    ErrorKind, SystemTimeSpec,
+};
+use wasi_virtfs::{validate_oflags, Filesystem};
+
+#[test]
+fn open_directory_read_only() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+    create_file(&root, "dir/file", b"contents");
+    let dir_stat = run(root.get_path_filestat("dir", false)).expect("stat dir");
+
+    for path in &["dir", "dir/", "dir/.", "."] {
+        let f = open_read(&root, false, path).expect("open directory as a file");
+        assert_eq!(run(f.get_filetype()).unwrap(), FileType::Directory);
+        let stat = run(f.get_filestat()).expect("fd_filestat_get");
+        assert_eq!(stat.filetype, FileType::Directory);
+        if *path != "." {
+            assert_eq!(stat.inode, dir_stat.inode);
+        }
+    }
+
+    let f = open_read(&root, false, "dir").unwrap();
+    let mut buf = [0; 4];
+    assert_errkind!(
+        run(f.read_vectored(&mut [std::io::IoSliceMut::new(&mut buf)])),
+        ErrorKind::Isdir
+    );
+    assert_errkind!(
+        run(f.write_vectored(&[std::io::IoSlice::new(b"data")])),
+        ErrorKind::Isdir
+    );
+    assert_errkind!(run(f.set_filestat_size(0)), ErrorKind::Isdir);
+}
+
+#[test]
+fn open_directory_for_write() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+
+    for path in &["dir", "dir/", "."] {
+        for read in &[false, true] {
+            assert_errkind!(
+                run(root.open_file(false, path, OFlags::empty(), *read, true, FdFlags::empty())),
+                ErrorKind::Isdir
+            );
+        }
+    }
+    run(root.get_path_filestat("dir", false)).expect("dir is still there");
+}
+
+/// Write `contents` to `path` the way `fopen(path, "w")` does.
+fn rewrite(dir: &dyn WasiDir, path: &str, contents: &[u8]) {
+    let f = run(dir.open_file(
+        false,
+        path,
+        OFlags::CREATE | OFlags::TRUNCATE,
+        false,
+        true,
+        FdFlags::empty(),
+    ))
+    .expect("open with CREATE | TRUNCATE");
+    run(f.write_vectored(&[std::io::IoSlice::new(contents)])).expect("write contents");
+}
+
+#[test]
+fn truncate_on_rewrite() {
+    let fs = filesystem();
+    let root = fs.root();
+    rewrite(&root, "config.toml", b"verbose = true\nthreads = 16\n");
+    rewrite(&root, "config.toml", b"threads = 4\n");
+    assert_eq!(read_file(&root, "config.toml"), b"threads = 4\n");
+    rewrite(&root, "config.toml", b"");
+    assert_eq!(read_file(&root, "config.toml"), b"");
+}
+
+#[test]
+fn truncate_updates_times() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    let before = run(root.get_path_filestat("file", false)).unwrap();
+
+    let f = run(root.open_file(
+        false,
+        "file",
+        OFlags::TRUNCATE,
+        false,
+        true,
+        FdFlags::empty(),
+    ))
+    .expect("open with TRUNCATE");
+    let after = run(f.get_filestat()).unwrap();
+    assert_eq!(after.size, 0);
+    assert!(after.mtim > before.mtim);
+    assert!(after.ctim > before.ctim);
+}
+
+#[test]
+fn truncate_fresh_file() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    let f = run(root.open_file(
+        false,
+        "new",
+        OFlags::CREATE | OFlags::TRUNCATE,
+        false,
+        true,
+        FdFlags::empty(),
+    ))
+    .expect("create with TRUNCATE");
+    let stat = run(f.get_filestat()).unwrap();
+    assert_eq!(stat.size, 0);
+    // Nothing was truncated, so the times are those of the creation.
+    assert_eq!(stat.mtim, stat.ctim);
+    assert_eq!(stat.atim, stat.mtim);
+}
+
+#[test]
+fn truncate_requires_write() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+
+    for oflags in &[OFlags::TRUNCATE, OFlags::TRUNCATE | OFlags::CREATE] {
+        assert_errkind!(
+            run(root.open_file(false, "file", *oflags, true, false, FdFlags::empty())),
+            ErrorKind::Inval
+        );
+    }
+    assert_errkind!(
+        run(root.open_file(
+            false,
+            "new",
+            OFlags::CREATE | OFlags::TRUNCATE,
+            true,
+            false,
+            FdFlags::empty()
+        )),
+        ErrorKind::Inval
+    );
+    assert_eq!(read_file(&root, "file"), b"contents");
+    assert_errkind!(open_read(&root, false, "new"), ErrorKind::Noent);
+}
+
+fn open_directory_flag(
+    dir: &dyn WasiDir,
+    follow: bool,
+    path: &str,
+    oflags: OFlags,
+    write: bool,
+) -> Result<Box<dyn WasiFile>, wasi_common::Error> {
+    run(dir.open_file(
+        follow,
+        path,
+        OFlags::DIRECTORY | oflags,
+        true,
+        write,
+        FdFlags::empty(),
+    ))
+}
+
+#[test]
+fn directory_flag() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+    create_file(&root, "file", b"contents");
+    run(root.symlink("dir", "dir_link")).expect("create dir_link");
+    let dir_stat = run(root.get_path_filestat("dir", false)).unwrap();
+
+    for (follow, path) in &[(false, "dir"), (false, "dir/"), (true, "dir_link")] {
+        let f = open_directory_flag(&root, *follow, path, OFlags::empty(), false)
+            .expect("open with DIRECTORY");
+        assert_eq!(run(f.get_filetype()).unwrap(), FileType::Directory);
+        assert_eq!(run(f.get_filestat()).unwrap().inode, dir_stat.inode);
+    }
+
+    for path in &["file", "file/", "dir/../file"] {
+        assert_errkind!(
+            open_directory_flag(&root, false, path, OFlags::empty(), false),
+            ErrorKind::Notdir
+        );
+    }
+    assert_errkind!(
+        open_directory_flag(&root, false, "dir_link", OFlags::empty(), false),
+        ErrorKind::Notdir
+    );
+    assert_errkind!(
+        open_directory_flag(&root, false, "missing", OFlags::empty(), false),
+        ErrorKind::Noent
+    );
+    assert_errkind!(
+        open_directory_flag(&root, false, "dir", OFlags::empty(), true),
+        ErrorKind::Isdir
+    );
+}
+
+#[test]
+fn directory_flag_with_create() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+
+    for path in &["dir", "missing"] {
+        for oflags in &[OFlags::CREATE, OFlags::CREATE | OFlags::EXCLUSIVE] {
+            assert_errkind!(
+                open_directory_flag(&root, false, path, *oflags, false),
+                ErrorKind::Inval
+            );
+        }
+    }
+    assert_errkind!(open_read(&root, false, "missing"), ErrorKind::Noent);
+}
+
+#[test]
+fn oflags_validation() {
+    const CREATE: OFlags = OFlags::CREATE;
+    const DIRECTORY: OFlags = OFlags::DIRECTORY;
+    const EXCLUSIVE: OFlags = OFlags::EXCLUSIVE;
+    const TRUNCATE: OFlags = OFlags::TRUNCATE;
+    const NONE: OFlags = OFlags::empty();
+    #[rustfmt::skip]
+    let cases: &[(OFlags, bool, bool)] = &[
+        // (oflags, write, valid)
+        (NONE, false, true),
+        (NONE, true, true),
+        (CREATE, false, true),
+        (CREATE | EXCLUSIVE, false, true),
+        (EXCLUSIVE, false, true),
+        (EXCLUSIVE, true, true),
+        (TRUNCATE, true, true),
+        (TRUNCATE, false, false),
+        (CREATE | TRUNCATE, true, true),
+        (CREATE | TRUNCATE, false, false),
+        (DIRECTORY, false, true),
+        (DIRECTORY, true, true),
+        (DIRECTORY | EXCLUSIVE, false, true),
+        (DIRECTORY | CREATE, false, false),
+        (DIRECTORY | CREATE | EXCLUSIVE, false, false),
+        (DIRECTORY | TRUNCATE, true, false),
+        (DIRECTORY | TRUNCATE, false, false),
+    ];
+    for (oflags, write, valid) in cases {
+        let result = validate_oflags(*oflags, *write);
+        if *valid {
+            assert!(result.is_ok(), "{:?} write={}: {:?}", oflags, write, result);
+        } else {
+            assert_errkind!(result, ErrorKind::Inval);
+        }
+    }
+    // Every flag is covered.
+    let all = cases.iter().fold(NONE, |all, (oflags, _, _)| all | *oflags);
+    assert_eq!(all, OFlags::all());
+}
+
+#[test]
+fn create_never_shadows_a_directory() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("foo")).expect("create foo");
+    create_file(&root, "foo/bar.txt", b"contents");
+
+    for (read, write) in &[(true, false), (false, true), (true, true)] {
+        for path in &["foo", "foo/", "foo/.", "."] {
+            assert_errkind!(
+                run(root.open_file(false, path, OFlags::CREATE, *read, *write, FdFlags::empty())),
+                ErrorKind::Isdir
+            );
+            assert_errkind!(
+                run(root.open_file(
+                    false,
+                    path,
+                    OFlags::CREATE | OFlags::EXCLUSIVE,
+                    *read,
+                    *write,
+                    FdFlags::empty()
+                )),
+                ErrorKind::Exist
+            );
+        }
+    }
+    let stat = run(root.get_path_filestat("foo", false)).expect("stat foo");
+    assert_eq!(stat.filetype, FileType::Directory);
+    assert_eq!(read_file(&root, "foo/bar.txt"), b"contents");
+}
+
+fn open_with_access(dir: &dyn WasiDir, path: &str, read: bool, write: bool) -> Box<dyn WasiFile> {
+    run(dir.open_file(false, path, OFlags::empty(), read, write, FdFlags::APPEND))
+        .expect("open file")
+}
+
+fn assert_read_errkind(f: &dyn WasiFile, readable: bool) {
+    let mut buf = [0; 4];
+    let results = vec![
+        run(f.read_vectored(&mut [std::io::IoSliceMut::new(&mut buf)])),
+        run(f.read_vectored_at(&mut [std::io::IoSliceMut::new(&mut buf)], 0)),
+        run(f.peek(&mut buf)),
+        run(f.num_ready_bytes()),
+    ];
+    for result in results {
+        if readable {
+            result.expect("read");
+        } else {
+            assert_errkind!(result, ErrorKind::Badf);
+        }
+    }
+}
+
+fn assert_write_errkind(f: &dyn WasiFile, writable: bool) {
+    let results = vec![
+        run(f.write_vectored(&[std::io::IoSlice::new(b"data")])),
+        run(f.write_vectored_at(&[std::io::IoSlice::new(b"data")], 0)),
+        run(f.set_filestat_size(8)).map(|()| 0),
+        run(f.allocate(0, 16)).map(|()| 0),
+    ];
+    for result in results {
+        if writable {
+            result.expect("write");
+        } else {
+            assert_errkind!(result, ErrorKind::Badf);
+        }
+    }
+}
+
+#[test]
+fn stat_only_open() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+
+    let f = open_with_access(&root, "file", false, false);
+    assert_read_errkind(&*f, false);
+    assert_write_errkind(&*f, false);
+
+    assert_eq!(run(f.get_filetype()).unwrap(), FileType::RegularFile);
+    assert_eq!(run(f.get_fdflags()).unwrap(), FdFlags::APPEND);
+    let stat = run(f.get_filestat()).expect("get_filestat");
+    assert_eq!(stat.size, 8);
+    run(f.set_times(Some(SystemTimeSpec::SymbolicNow), None)).expect("set_times");
+    assert!(run(f.get_filestat()).unwrap().atim > stat.atim);
+    assert_eq!(read_file(&root, "file"), b"contents");
+}
+
+#[test]
+fn access_modes() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+
+    for (read, write) in &[(true, false), (false, true), (true, true)] {
+        let f = open_with_access(&root, "file", *read, *write);
+        assert_read_errkind(&*f, *read);
+        assert_write_errkind(&*f, *write);
+    }
+}
+
+/// A clock which lets other threads run whenever it's read, to widen
+/// the gaps between a filesystem's steps.
+struct YieldingClock;
+
+impl WasiSystemClock for YieldingClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+    fn now(&self, _precision: Duration) -> SystemTime {
+        thread::yield_now();
+        SystemTime::from_std(std::time::UNIX_EPOCH)
+    }
+}
+
+#[test]
+fn racing_creates_of_one_name() {
+    const ROUNDS: usize = 200;
+    let fs = Filesystem::new(Box::new(YieldingClock), 0);
+    let inodes = fs.inodes_used();
+    let barrier = Arc::new(Barrier::new(2));
+    // One thread makes directories, the other files, of the same names.
+    let spawn = |as_dir: bool| {
+        let (root, barrier) = (fs.root(), barrier.clone());
+        thread::spawn(move || {
+            (0..ROUNDS)
+                .map(|i| {
+                    let name = format!("name{}", i);
+                    barrier.wait();
+                    if as_dir {
+                        run(root.create_dir(&name))
+                    } else {
+                        run(root.open_file(
+                            false,
+                            &name,
+                            OFlags::CREATE | OFlags::EXCLUSIVE,
+                            true,
+                            true,
+                            FdFlags::empty(),
+                        ))
+                        .map(drop)
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+    };
+    let (dirs, files) = (spawn(true), spawn(false));
+    let (dirs, files) = (dirs.join().unwrap(), files.join().unwrap());
+
+    // Each time, exactly one wins, and the other sees what it made.
+    for (i, (dir, file)) in dirs.into_iter().zip(files).enumerate() {
+        let stat = run(fs.root().get_path_filestat(&format!("name{}", i), false));
+        let filetype = stat.expect("stat").filetype;
+        if dir.is_ok() {
+            assert_errkind!(file, ErrorKind::Exist);
+            assert_eq!(filetype, FileType::Directory);
+        } else {
+            assert_errkind!(dir, ErrorKind::Exist);
+            file.expect("create");
+            assert_eq!(filetype, FileType::RegularFile);
+        }
+    }
+    assert_eq!(fs.inodes_used(), inodes + ROUNDS as u64);
+}
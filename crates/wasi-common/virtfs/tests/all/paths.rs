@@ -0,0 +1,433 @@
+use crate::{create_file, filesystem, open_read, read_file, readdir, run};
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags},
+    ErrorKind,
+};
+
+fn serial(dir: &dyn WasiDir) -> u64 {
+    run(dir.get_filestat()).expect("stat dir").inode
+}
+
+#[test]
+fn dotdot_in_middle() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("a")).expect("create a");
+    run(root.create_dir("b")).expect("create b");
+    create_file(&root, "b/file", b"contents");
+
+    assert_eq!(read_file(&root, "a/../b/file"), b"contents");
+    create_file(&root, "a/../b/new", b"new");
+    assert_eq!(read_file(&root, "b/new"), b"new");
+}
+
+#[test]
+fn dotdot_clamps_at_preopen() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("a")).expect("create a");
+    run(root.create_dir("a/b")).expect("create a/b");
+    create_file(&root, "a/b/file", b"inner");
+    create_file(&root, "file", b"outer");
+
+    // A handle opened from the preopen can climb back up to it, but no
+    // further.
+    let b = run(root.open_dir(false, "a/b")).expect("open a/b");
+    assert_eq!(read_file(&*b, "../../file"), b"outer");
+    assert_eq!(read_file(&*b, "../../../../file"), b"outer");
+    assert_eq!(read_file(&*b, "../b/file"), b"inner");
+    assert_eq!(read_file(&root, "../file"), b"outer");
+    assert_eq!(read_file(&root, "../../a/b/file"), b"inner");
+}
+
+#[test]
+fn dotdot_final_component() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("a")).expect("create a");
+    run(root.create_dir("a/b")).expect("create a/b");
+    let a = run(root.open_dir(false, "a")).expect("open a");
+    let b = run(root.open_dir(false, "a/b")).expect("open a/b");
+
+    let up = run(root.open_dir(false, "a/b/..")).expect("open a/b/..");
+    assert_eq!(serial(&*up), serial(&*a));
+    let up = run(b.open_dir(false, "..")).expect("open .. from a/b");
+    assert_eq!(serial(&*up), serial(&*a));
+    let up = run(up.open_dir(false, "..")).expect("open .. from a");
+    assert_eq!(serial(&*up), serial(&root));
+    let up = run(up.open_dir(false, "..")).expect("open .. from the root again");
+    assert_eq!(serial(&*up), serial(&root), "clamped at the preopen");
+    let up = run(root.open_dir(false, "..")).expect("open .. at root");
+    assert_eq!(serial(&*up), serial(&root));
+    let here = run(root.open_dir(false, ".")).expect("open . at root");
+    assert_eq!(serial(&*here), serial(&root));
+}
+
+#[test]
+fn dotdot_in_symlink_target() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+    create_file(&root, "file", b"contents");
+    run(root.symlink("../file", "dir/link")).expect("create symlink");
+
+    assert_eq!(read_file(&root, "dir/link"), b"contents");
+}
+
+#[test]
+fn dot_and_repeated_slashes() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("a")).expect("create a");
+    run(root.create_dir("a/b")).expect("create a/b");
+    create_file(&root, "a/b/c", b"contents");
+
+    let plain = run(root.get_path_filestat("a/b/c", false)).expect("stat a/b/c");
+    for path in &["./a//b/./c", "a///b/c", "./././a/b/c", "a/./b/.//c"] {
+        let stat = run(root.get_path_filestat(path, false)).expect(path);
+        assert_eq!(stat.inode, plain.inode, "{}", path);
+        assert_eq!(read_file(&root, path), b"contents", "{}", path);
+    }
+    let dir = run(root.open_dir(false, "a//b//")).expect("open a//b//");
+    assert_eq!(read_file(&*dir, "c"), b"contents");
+}
+
+#[test]
+fn create_with_repeated_slashes() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("x")).expect("create x");
+    run(root.create_dir("x//y")).expect("create x//y");
+
+    let x = run(root.open_dir(false, "x")).expect("open x");
+    let names = readdir(&*x).into_iter().map(|e| e.name).collect::<Vec<_>>();
+    assert_eq!(names, vec![".", "..", "y"]);
+}
+
+#[test]
+fn trailing_slash_on_dir() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+    create_file(&root, "dir/file", b"contents");
+
+    let plain = run(root.get_path_filestat("dir", false)).expect("stat dir");
+    let slash = run(root.get_path_filestat("dir/", false)).expect("stat dir/");
+    assert_eq!(slash.inode, plain.inode);
+    let dir = run(root.open_dir(false, "dir/")).expect("open dir/");
+    assert_eq!(read_file(&*dir, "file"), b"contents");
+
+    run(root.create_dir("new/")).expect("create new/");
+    run(root.rename("new/", &root, "renamed/")).expect("rename new/ to renamed/");
+    run(root.remove_dir("renamed/")).expect("remove renamed/");
+    assert_errkind!(run(root.unlink_file("dir/")), ErrorKind::Isdir);
+}
+
+#[test]
+fn trailing_slash_on_file() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+
+    assert_errkind!(
+        run(root.get_path_filestat("file/", false)),
+        ErrorKind::Notdir
+    );
+    assert_errkind!(open_read(&root, false, "file/"), ErrorKind::Notdir);
+    assert_errkind!(run(root.open_dir(false, "file/")), ErrorKind::Notdir);
+    assert_errkind!(run(root.unlink_file("file/")), ErrorKind::Notdir);
+    assert_errkind!(run(root.remove_dir("file/")), ErrorKind::Notdir);
+    assert_errkind!(run(root.create_dir("file/")), ErrorKind::Exist);
+    assert_errkind!(run(root.rename("file/", &root, "other")), ErrorKind::Notdir);
+    assert_errkind!(run(root.rename("file", &root, "other/")), ErrorKind::Notdir);
+    assert_errkind!(run(root.symlink("target", "file/")), ErrorKind::Notdir);
+    assert_eq!(read_file(&root, "file"), b"contents", "file is untouched");
+}
+
+#[test]
+fn trailing_slash_missing() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+
+    assert_errkind!(
+        run(root.get_path_filestat("missing/", false)),
+        ErrorKind::Noent
+    );
+    assert_errkind!(
+        run(root.open_file(
+            false,
+            "missing/",
+            OFlags::CREATE,
+            false,
+            true,
+            FdFlags::empty()
+        )),
+        ErrorKind::Noent
+    );
+    assert_errkind!(run(root.symlink("file", "missing/")), ErrorKind::Noent);
+    assert_errkind!(
+        run(root.hard_link("file", &root, "missing/")),
+        ErrorKind::Noent
+    );
+}
+
+#[test]
+fn trailing_slash_on_symlink() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+    create_file(&root, "file", b"contents");
+    run(root.symlink("dir", "dir_link")).expect("create dir link");
+    run(root.symlink("file", "file_link")).expect("create file link");
+
+    let dir = run(root.get_path_filestat("dir", false)).expect("stat dir");
+    let followed = run(root.get_path_filestat("dir_link/", true)).expect("stat dir_link/");
+    assert_eq!(followed.inode, dir.inode);
+    assert_errkind!(
+        run(root.get_path_filestat("file_link/", true)),
+        ErrorKind::Notdir
+    );
+    // Without following, the final component is the link itself.
+    assert_errkind!(
+        run(root.get_path_filestat("dir_link/", false)),
+        ErrorKind::Notdir
+    );
+    assert_errkind!(run(root.unlink_file("dir_link/")), ErrorKind::Notdir);
+}
+
+#[test]
+fn empty_path() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+
+    assert_errkind!(open_read(&root, false, ""), ErrorKind::Noent);
+    assert_errkind!(
+        run(root.open_file(false, "", OFlags::CREATE, false, true, FdFlags::empty())),
+        ErrorKind::Noent
+    );
+    assert_errkind!(run(root.open_dir(false, "")), ErrorKind::Noent);
+    assert_errkind!(run(root.create_dir("")), ErrorKind::Noent);
+    assert_errkind!(run(root.get_path_filestat("", false)), ErrorKind::Noent);
+    assert_errkind!(run(root.unlink_file("")), ErrorKind::Noent);
+    assert_errkind!(run(root.remove_dir("")), ErrorKind::Noent);
+    assert_errkind!(run(root.symlink("file", "")), ErrorKind::Noent);
+    assert_errkind!(run(root.rename("file", &root, "")), ErrorKind::Noent);
+    assert_errkind!(run(root.hard_link("file", &root, "")), ErrorKind::Noent);
+
+    run(root.symlink("", "empty_link")).expect("create symlink with empty target");
+    assert_errkind!(open_read(&root, true, "empty_link"), ErrorKind::Noent);
+
+    let names = readdir(&root)
+        .into_iter()
+        .map(|e| e.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names.len(), 4, "no empty-named entry: {:?}", names);
+}
+
+#[test]
+fn dot_slash_names_the_directory() {
+    let fs = filesystem();
+    let root = fs.root();
+
+    let stat = run(root.get_path_filestat("./", false)).expect("stat ./");
+    assert_eq!(stat.inode, serial(&root));
+    assert_errkind!(run(root.create_dir("./")), ErrorKind::Exist);
+    assert_errkind!(
+        run(root.open_file(false, "./", OFlags::CREATE, false, true, FdFlags::empty())),
+        ErrorKind::Isdir
+    );
+    assert_errkind!(run(root.symlink("target", "./")), ErrorKind::Exist);
+
+    let names = readdir(&root)
+        .into_iter()
+        .map(|e| e.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec![".", ".."], "nothing was created");
+}
+
+#[test]
+fn very_long_paths() {
+    let fs = filesystem();
+    let root = fs.root();
+    // Exercise the resolver rather than the up-front length check.
+    fs.set_path_max(usize::MAX);
+    run(root.create_dir("a")).expect("create a");
+    create_file(&root, "file", b"contents");
+    const N: usize = 100_000;
+
+    assert_errkind!(
+        run(root.get_path_filestat(&"a/".repeat(N), false)),
+        ErrorKind::Noent
+    );
+    assert_errkind!(
+        run(root.get_path_filestat(&"missing/".repeat(N), false)),
+        ErrorKind::Noent
+    );
+    assert_errkind!(
+        open_read(&root, false, &"file/".repeat(N)),
+        ErrorKind::Notdir
+    );
+    assert_eq!(
+        read_file(&root, &format!("{}file", "./".repeat(N))),
+        b"contents"
+    );
+    assert_eq!(
+        read_file(&root, &format!("{}file", "a/../".repeat(N))),
+        b"contents"
+    );
+    assert_eq!(
+        read_file(&root, &format!("{}file", "../".repeat(N))),
+        b"contents"
+    );
+    assert_eq!(
+        read_file(&root, &format!("a{}..{}file", "/".repeat(N), "/".repeat(N))),
+        b"contents"
+    );
+}
+
+/// Throw pseudo-random paths built from a small alphabet of components at
+/// the resolver. Any result is fine, as long as nothing panics or overflows
+/// the stack.
+#[test]
+fn fuzz_paths() {
+    let fs = filesystem();
+    let root = fs.root();
+    fs.set_path_max(usize::MAX);
+    run(root.create_dir("d")).expect("create d");
+    run(root.create_dir("d/e")).expect("create d/e");
+    create_file(&root, "d/file", b"contents");
+    run(root.symlink("d", "up")).expect("create up");
+    run(root.symlink("../d/e", "d/e/back")).expect("create back");
+    run(root.symlink("loop", "loop")).expect("create loop");
+
+    const COMPONENTS: &[&str] = &[
+        "d", "e", "file", "up", "back", "loop", ".", "..", "", "missing",
+    ];
+    // A fixed linear congruential generator keeps the test deterministic.
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = |n: usize| {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (state >> 33) as usize % n
+    };
+    for _ in 0..200 {
+        let len = 1 + next(20_000);
+        let mut path = String::new();
+        for i in 0..len {
+            if i > 0 {
+                path.push('/');
+            }
+            path.push_str(COMPONENTS[next(COMPONENTS.len())]);
+        }
+        let _ = run(root.get_path_filestat(&path, next(2) == 0));
+        let _ = open_read(&root, next(2) == 0, &path);
+        let _ = run(root.open_dir(next(2) == 0, &path));
+    }
+}
+
+#[test]
+fn absolute_paths_rejected_by_default() {
+    let fs = filesystem();
+    let root = fs.root();
+    assert!(!fs.strips_leading_slash());
+    run(root.create_dir("dir")).expect("create dir");
+    create_file(&root, "dir/file", b"contents");
+
+    assert_errkind!(open_read(&root, true, "/dir/file"), ErrorKind::Perm);
+    assert_errkind!(run(root.create_dir("/new")), ErrorKind::Perm);
+    assert_errkind!(run(root.get_path_filestat("/", false)), ErrorKind::Perm);
+    assert_errkind!(run(root.rename("dir", &root, "/moved")), ErrorKind::Perm);
+
+    // Absolute symlink targets are treated the same way.
+    run(root.symlink("/dir/file", "link")).expect("create symlink");
+    assert_errkind!(open_read(&root, true, "link"), ErrorKind::Perm);
+}
+
+#[test]
+fn strip_leading_slash() {
+    let fs = filesystem();
+    let root = fs.root();
+    fs.set_strip_leading_slash(true);
+    run(root.create_dir("dir")).expect("create dir");
+    create_file(&root, "//dir/file", b"contents");
+
+    assert_eq!(read_file(&root, "/dir/file"), b"contents");
+    let stat = run(root.get_path_filestat("/", false)).expect("stat /");
+    assert_eq!(stat.inode, serial(&root));
+    run(root.rename("/dir/file", &root, "/dir/moved")).expect("rename");
+    assert_eq!(read_file(&root, "dir/moved"), b"contents");
+
+    // "/" means the handle the lookup started from, not the filesystem root.
+    let dir = run(root.open_dir(false, "/dir")).expect("open /dir");
+    assert_eq!(read_file(&*dir, "/moved"), b"contents");
+    run(root.symlink("/moved", "dir/link")).expect("create symlink");
+    assert_eq!(read_file(&*dir, "link"), b"contents");
+    assert_errkind!(open_read(&root, true, "dir/link"), ErrorKind::Noent);
+}
+
+#[test]
+fn create_through_dotdot_from_subdirectory() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("a")).expect("create a");
+    run(root.create_dir("a/b")).expect("create a/b");
+
+    let b = run(root.open_dir(false, "a/b")).expect("open a/b");
+    let a = run(b.open_dir(false, "..")).expect("open ..");
+    create_file(&*a, "file", b"contents");
+    assert_eq!(read_file(&root, "a/file"), b"contents");
+    create_file(&*b, "../other", b"other");
+    assert_eq!(read_file(&root, "a/other"), b"other");
+}
+
+#[test]
+fn dotdot_from_orphaned_directory() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("a")).expect("create a");
+    run(root.create_dir("a/b")).expect("create a/b");
+
+    let b = run(root.open_dir(false, "a/b")).expect("open a/b");
+    run(root.remove_dir("a/b")).expect("remove a/b");
+    run(root.remove_dir("a")).expect("remove a");
+    assert_errkind!(run(b.open_dir(false, "..")), ErrorKind::Noent);
+    assert_errkind!(run(b.get_path_filestat("../x", false)), ErrorKind::Noent);
+}
+
+#[test]
+fn open_dir_on_file() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+    create_file(&root, "dir/notes.txt", b"contents");
+
+    for path in &[
+        "dir/notes.txt",
+        "dir/notes.txt/",
+        "dir/notes.txt/sub",
+        "dir/notes.txt/sub/deeper",
+        "./dir/../dir/notes.txt",
+    ] {
+        let err = match run(root.open_dir(false, path)) {
+            Ok(_) => panic!("open_dir({:?}) succeeded", path),
+            Err(e) => e,
+        };
+        assert!(
+            matches!(err.downcast_ref::<ErrorKind>(), Some(ErrorKind::Notdir)),
+            "open_dir({:?}): {:?}",
+            path,
+            err
+        );
+        assert!(
+            format!("{:?}", err).contains("notes.txt"),
+            "open_dir({:?}) names the file: {:?}",
+            path,
+            err
+        );
+    }
+    assert_errkind!(run(root.open_dir(false, "dir/missing")), ErrorKind::Noent);
+}
@@ -0,0 +1,209 @@
+use crate::{capacity, create_file, filesystem, open_write, read_file, run};
+use std::io::IoSlice;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags, WasiFile},
+    ErrorKind,
+};
+use wasi_virtfs::{clock::FixedClock, StaticWrites, TarOptions};
+
+fn write_at(f: &dyn WasiFile, data: &[u8], offset: u64) -> Result<u64, wasi_common::Error> {
+    run(f.write_vectored_at(&[IoSlice::new(data)], offset))
+}
+
+#[test]
+fn writes_stop_at_the_limit() {
+    let fs = filesystem();
+    let root = fs.root();
+    assert_eq!(fs.byte_limit(), None);
+    fs.set_byte_limit(Some(10_000));
+    assert_eq!(fs.byte_limit(), Some(10_000));
+
+    let f = open_write(&root, "a").expect("open");
+    assert_eq!(write_at(&*f, &[1; 6_000], 0).expect("write"), 6_000);
+    let g = open_write(&root, "b").expect("open");
+    // Only what fits is written, and then nothing more.
+    assert_eq!(write_at(&*g, &[2; 6_000], 0).expect("write"), 4_000);
+    assert_eq!(fs.bytes_used(), 10_000);
+    assert_errkind!(write_at(&*g, &[2; 1], 4_000), ErrorKind::Nospc);
+    assert_errkind!(write_at(&*f, &[1; 1], 6_000), ErrorKind::Nospc);
+    // A hole counts, so a write past the end fails rather than being cut
+    // short to nothing.
+    assert_errkind!(write_at(&*f, &[1; 1], 9_000), ErrorKind::Nospc);
+    // Rewriting what's there is still fine.
+    assert_eq!(write_at(&*f, &[3; 6_000], 0).expect("rewrite"), 6_000);
+    assert_eq!(read_file(&root, "b"), vec![2; 4_000]);
+
+    assert_errkind!(run(f.set_filestat_size(6_001)), ErrorKind::Nospc);
+    assert_errkind!(run(f.allocate(0, 6_001)), ErrorKind::Nospc);
+    assert_eq!(run(f.get_filestat()).expect("stat").size, 6_000);
+
+    // The memory held stays bounded by what was written, however often
+    // the guest tries for more.
+    for _ in 0..100 {
+        assert_errkind!(write_at(&*g, &[2; 1 << 16], 4_000), ErrorKind::Nospc);
+    }
+    assert!(capacity(&*f) + capacity(&*g) < 3 * 10_000);
+
+    // Shrinking a file frees room for others.
+    run(f.set_filestat_size(1_000)).expect("truncate");
+    assert_eq!(fs.bytes_used(), 5_000);
+    assert_eq!(write_at(&*g, &[2; 6_000], 4_000).expect("write"), 5_000);
+    assert_eq!(fs.bytes_used(), 10_000);
+}
+
+#[test]
+fn files_count_until_the_last_link_and_handle_go() {
+    let fs = filesystem();
+    let root = fs.root();
+    fs.set_byte_limit(Some(1_000));
+    create_file(&root, "file", &[1; 600]);
+    run(root.hard_link("file", &root, "link")).expect("link");
+    assert_eq!(fs.bytes_used(), 600);
+
+    let f = open_write(&root, "file").expect("open");
+    run(root.unlink_file("file")).expect("unlink");
+    run(root.unlink_file("link")).expect("unlink");
+    assert_eq!(fs.bytes_used(), 600);
+    let g = open_write(&root, "other").expect("open");
+    assert_eq!(write_at(&*g, &[1; 600], 0).expect("write"), 400);
+    drop(f);
+    assert_eq!(fs.bytes_used(), 400);
+    run(root.unlink_file("other")).expect("unlink");
+    drop(g);
+    assert_eq!(fs.bytes_used(), 0);
+    create_file(&root, "other", &[1; 1_000]);
+
+    // Truncating on open frees the file's bytes too.
+    run(root.open_file(
+        false,
+        "other",
+        OFlags::TRUNCATE,
+        false,
+        true,
+        FdFlags::empty(),
+    ))
+    .expect("open");
+    assert_eq!(fs.bytes_used(), 0);
+
+    // A lower limit than what's used leaves the files alone.
+    create_file(&root, "big", &[1; 1_000]);
+    fs.set_byte_limit(Some(500));
+    assert_eq!(read_file(&root, "big"), vec![1; 1_000]);
+    assert_errkind!(
+        write_at(&*open_write(&root, "other").expect("open"), b"x", 0),
+        ErrorKind::Nospc
+    );
+    fs.set_byte_limit(None);
+    create_file(&root, "huge", &vec![1; 100_000]);
+    assert_eq!(fs.bytes_used(), 101_000);
+}
+
+#[test]
+fn copies_forks_and_imports_are_counted() {
+    let fs = filesystem();
+    let root = fs.root();
+    fs.set_byte_limit(Some(1_000));
+    create_file(&root, "file", &[1; 600]);
+    assert_errkind!(fs.copy_file("file", "copy"), ErrorKind::Nospc);
+    assert_errkind!(
+        root.put_static_file("static", &[0; 600], StaticWrites::CopyOnWrite),
+        ErrorKind::Nospc
+    );
+    assert_errkind!(
+        run(root.get_path_filestat("static", false)),
+        ErrorKind::Noent
+    );
+
+    let mut archive = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(600);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive
+        .append_data(&mut header, "imported", &[2; 600][..])
+        .expect("append");
+    let archive = archive.into_inner().expect("archive");
+    assert_errkind!(
+        fs.import_tar_at(".", &archive[..], TarOptions::default()),
+        ErrorKind::Nospc
+    );
+    assert_eq!(fs.bytes_used(), 600);
+
+    // A fork counts its files apart from the original, under the same limit.
+    let fork = fs.fork(
+        Box::new(FixedClock::new(cap_std::time::SystemTime::from_std(
+            std::time::UNIX_EPOCH,
+        ))),
+        false,
+    );
+    assert_eq!(fork.byte_limit(), Some(1_000));
+    assert_eq!(fork.bytes_used(), 600);
+    run(fork.root().unlink_file("file")).expect("unlink");
+    assert_eq!(fork.bytes_used(), 0);
+    assert_eq!(fs.bytes_used(), 600);
+    fork.import_tar_at(".", &archive[..], TarOptions::default())
+        .expect("import");
+    assert_eq!(fork.bytes_used(), 600);
+}
+
+#[test]
+fn creating_stops_at_the_inode_limit() {
+    let fs = filesystem();
+    let root = fs.root();
+    assert_eq!(fs.inode_limit(), None);
+    assert_eq!(fs.inodes_used(), 1);
+    run(root.create_dir("dir")).expect("mkdir");
+    fs.set_inode_limit(Some(100));
+    assert_eq!(fs.inode_limit(), Some(100));
+
+    let mut created = 0;
+    let err = loop {
+        match run(root.open_file(
+            false,
+            &format!("dir/{}", created),
+            OFlags::CREATE,
+            false,
+            true,
+            FdFlags::empty(),
+        )) {
+            Ok(_) => created += 1,
+            Err(e) => break e,
+        }
+    };
+    assert_errkind!(Err::<(), _>(err), ErrorKind::Nospc);
+    assert_eq!(created, 98);
+    assert_eq!(fs.inodes_used(), 100);
+    assert_eq!(fs.inodes_used(), fs.inode_count());
+    assert_errkind!(run(root.create_dir("other")), ErrorKind::Nospc);
+    assert_errkind!(run(root.symlink("dir", "other")), ErrorKind::Nospc);
+    assert_errkind!(
+        root.put_static_file("other", b"", StaticWrites::ReadOnly),
+        ErrorKind::Nospc
+    );
+    // Another link to a file is no new inode.
+    run(root.hard_link("dir/0", &root, "link")).expect("link");
+
+    // Removing inodes frees room, though not while a file is still open.
+    let open = open_write(&root, "dir/1").expect("open");
+    run(root.unlink_file("dir/1")).expect("unlink");
+    run(root.unlink_file("dir/0")).expect("unlink");
+    assert_eq!(fs.inodes_used(), 100);
+    drop(open);
+    assert_eq!(fs.inodes_used(), 99);
+    run(root.create_dir("other")).expect("mkdir");
+    fs.remove_dir_all("dir").expect("remove");
+    assert_eq!(fs.inodes_used(), 3);
+
+    // A fork counts its own inodes.
+    let fork = fs.fork(
+        Box::new(FixedClock::new(cap_std::time::SystemTime::from_std(
+            std::time::UNIX_EPOCH,
+        ))),
+        false,
+    );
+    assert_eq!(fork.inodes_used(), 3);
+    assert_eq!(fork.inode_limit(), Some(100));
+    drop(fs);
+    assert_eq!(fork.inodes_used(), 3);
+}
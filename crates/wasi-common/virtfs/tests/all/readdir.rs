@@ -0,0 +1,260 @@
+use crate::{create_file, filesystem, names, readdir, run};
+use std::collections::HashSet;
+use wasi_common::{
+    dir::{ReaddirCursor, ReaddirEntity, WasiDir},
+    ErrorKind,
+};
+
+/// Names which sort differently by bytes than by any locale, in no order.
+const NAMES: &[&str] = &[
+    "b", "a", "B", "A", "10", "9", "a.txt", "a-txt", "_", "é", "e", "Z", "zz", "~", "a b", "ab",
+    "ä", "日本",
+];
+
+fn populate<'a>(dir: &dyn WasiDir, names: impl Iterator<Item = &'a &'static str>) {
+    for name in names {
+        if *name == "zz" {
+            run(dir.create_dir(name)).expect("mkdir");
+        } else {
+            create_file(dir, name, b"");
+        }
+    }
+}
+
+#[test]
+fn entries_are_listed_in_byte_order() {
+    let a = filesystem();
+    let b = filesystem();
+    populate(&a.root(), NAMES.iter());
+    populate(&b.root(), NAMES.iter().rev());
+
+    let listed = names(&a.root());
+    let mut sorted = listed[2..].to_vec();
+    sorted.sort();
+    assert_eq!(listed[..2], [".", ".."]);
+    assert_eq!(listed[2..], sorted[..]);
+    assert_eq!(
+        listed[2..8],
+        ["10", "9", "A", "B", "Z", "_"],
+        "uppercase sorts before lowercase"
+    );
+    assert_eq!(listed, names(&b.root()), "the same on every filesystem");
+    assert_eq!(listed, names(&a.root()), "and every time");
+
+    // Entries added later take their place in the order.
+    create_file(&a.root(), "aa", b"");
+    let relisted = names(&a.root());
+    let aa = relisted.iter().position(|n| n == "aa").unwrap();
+    assert_eq!(relisted[aa - 1..aa + 2], ["a.txt", "aa", "ab"]);
+}
+
+/// List `dir` the way a guest with a buffer too small for more than
+/// `batch` entries would, resuming from the last cookie each time, and
+/// calling `between` after each batch with the names in it.
+fn list_in_batches(
+    dir: &dyn WasiDir,
+    batch: usize,
+    mut between: impl FnMut(&[String]),
+) -> Vec<String> {
+    let mut listed = Vec::new();
+    let mut cookie = ReaddirCursor::from(0);
+    loop {
+        let entries: Vec<ReaddirEntity> = run(dir.readdir(cookie))
+            .expect("readdir")
+            .take(batch)
+            .collect::<Result<_, _>>()
+            .expect("readdir entries");
+        let last = match entries.last() {
+            Some(last) => last.next,
+            None => return listed,
+        };
+        let names: Vec<String> = entries.into_iter().map(|e| e.name).collect();
+        between(&names);
+        listed.extend(names);
+        cookie = last;
+    }
+}
+
+fn numbered(n: usize) -> Vec<String> {
+    (0..n).map(|i| format!("file{:04}", i)).collect()
+}
+
+#[test]
+fn deleting_entries_while_listing() {
+    let fs = filesystem();
+    let root = fs.root();
+    let files = numbered(50);
+    for name in &files {
+        create_file(&root, name, b"");
+    }
+
+    // Like `rm -r`, deleting each batch as it's listed.
+    let listed = list_in_batches(&root, 3, |names| {
+        for name in names.iter().filter(|n| n.starts_with("file")) {
+            run(root.unlink_file(name)).expect("unlink");
+        }
+    });
+    assert_eq!(listed[..2], [".", ".."]);
+    assert_eq!(listed[2..], files[..], "each file listed once");
+    assert_eq!(names(&root), [".", ".."]);
+
+    // Deleting entries ahead of the listing skips only those.
+    for name in &files {
+        create_file(&root, name, b"");
+    }
+    let mut deleted = Vec::new();
+    let listed = list_in_batches(&root, 4, |names| {
+        if let Some(last) = names.last().filter(|n| n.starts_with("file")) {
+            let i: usize = last["file".len()..].parse().unwrap();
+            for ahead in files.iter().skip(i + 1).step_by(7).take(2) {
+                run(root.unlink_file(ahead)).expect("unlink");
+                deleted.push(ahead.clone());
+            }
+        }
+    });
+    let survivors: Vec<&String> = files.iter().filter(|f| !deleted.contains(f)).collect();
+    assert_eq!(listed[2..].iter().collect::<Vec<_>>(), survivors);
+}
+
+#[test]
+fn creating_entries_while_listing() {
+    let fs = filesystem();
+    let root = fs.root();
+    let files = numbered(30);
+    for name in &files {
+        create_file(&root, name, b"");
+    }
+
+    // Entries created behind the listing aren't listed, and ones created
+    // ahead of it are, once each; none of the others is skipped or
+    // repeated.
+    let mut created = 0;
+    let listed = list_in_batches(&root, 2, |names| {
+        if names.iter().any(|n| n.starts_with("file")) {
+            create_file(&root, &format!("a{:02}", created), b"");
+            create_file(&root, &format!("z{:02}", created), b"");
+            created += 1;
+        }
+    });
+    let mut expected: Vec<String> = files.clone();
+    expected.extend((0..created).map(|i| format!("z{:02}", i)));
+    assert_eq!(listed[2..], expected[..]);
+}
+
+#[test]
+fn resuming_after_a_removed_entry() {
+    let fs = filesystem();
+    let root = fs.root();
+    for name in &["a", "b", "c"] {
+        create_file(&root, name, b"");
+    }
+    let entries = readdir(&root);
+    let after_a = entries[2].next;
+    assert_eq!(entries[2].name, "a");
+
+    // A cookie still means "after a" once "a" is gone, or replaced.
+    run(root.unlink_file("a")).expect("unlink");
+    let rest = |cookie| -> Vec<String> {
+        run(root.readdir(cookie))
+            .expect("readdir")
+            .map(|e| e.expect("entry").name)
+            .collect()
+    };
+    assert_eq!(rest(after_a), ["b", "c"]);
+    create_file(&root, "a", b"");
+    create_file(&root, "aa", b"");
+    assert_eq!(rest(after_a), ["aa", "b", "c"]);
+
+    // Even after many more entries have come and gone.
+    let files = numbered(3000);
+    let listed = list_in_batches(&root, 5, |names| {
+        for name in names.iter().filter(|n| n.starts_with("file")) {
+            run(root.unlink_file(name)).expect("unlink");
+        }
+    });
+    assert_eq!(listed.len(), 2 + 4);
+    for name in &files {
+        create_file(&root, name, b"");
+    }
+    let listed = list_in_batches(&root, 5, |names| {
+        for name in names.iter().filter(|n| n.starts_with("file")) {
+            run(root.unlink_file(name)).expect("unlink");
+        }
+    });
+    assert_eq!(listed.len(), 2 + 4 + files.len());
+    assert_eq!(rest(after_a), ["aa", "b", "c"]);
+}
+
+#[test]
+fn large_directories_list_each_entry_once() {
+    let fs = filesystem();
+    let root = fs.root();
+    let files = numbered(10_000);
+    for name in &files {
+        create_file(&root, name, b"");
+    }
+
+    let whole = names(&root);
+    assert_eq!(whole[2..], files[..]);
+    // Resuming from every cookie in turn, which would take quadratic time
+    // if each resumption walked the directory from the start.
+    let one_at_a_time = list_in_batches(&root, 1, |_| {});
+    assert_eq!(one_at_a_time, whole);
+    let cookies: HashSet<u64> = readdir(&root)
+        .into_iter()
+        .map(|e| u64::from(e.next))
+        .collect();
+    assert_eq!(cookies.len(), whole.len(), "cookies are distinct");
+}
+
+#[test]
+fn orphaned_directories_are_their_own_parent() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("a")).expect("mkdir");
+    run(root.create_dir("a/b")).expect("mkdir");
+    let b = run(root.open_dir(false, "a/b")).expect("open");
+    let serial = run(root.get_path_filestat("a/b", false))
+        .expect("stat")
+        .inode;
+
+    // With its parent gone, nothing is left for `..` to name.
+    run(root.remove_dir("a/b")).expect("rmdir");
+    run(root.remove_dir("a")).expect("rmdir");
+    let entries = readdir(&*b);
+    let listed: Vec<(&str, u64)> = entries.iter().map(|e| (e.name.as_str(), e.inode)).collect();
+    assert_eq!(listed, [(".", serial), ("..", serial)]);
+    assert_errkind!(run(b.get_path_filestat("..", false)), ErrorKind::Noent);
+}
+
+#[test]
+fn cookies_out_of_range_list_nothing() {
+    let fs = filesystem();
+    let root = fs.root();
+    for name in &["a", "b", "c"] {
+        create_file(&root, name, b"");
+    }
+    let entries = readdir(&root);
+    let len = entries.len() as u64;
+    let from = |cookie: u64| -> Vec<String> {
+        run(root.readdir(ReaddirCursor::from(cookie)))
+            .expect("readdir")
+            .map(|e| e.expect("entry").name)
+            .collect()
+    };
+    assert_eq!(from(0), [".", "..", "a", "b", "c"]);
+    assert_eq!(from(1), ["..", "a", "b", "c"]);
+    assert_eq!(from(2), ["a", "b", "c"]);
+    assert_eq!(from(u64::from(entries[2].next)), ["b", "c"]);
+    assert_eq!(from(u64::from(entries[4].next)), Vec::<String>::new());
+
+    for cookie in &[
+        len + 5,
+        u64::from(u32::MAX),
+        // What a valid cookie would be truncated to on a 32-bit host.
+        (1 << 32) + u64::from(entries[2].next),
+        u64::MAX,
+    ] {
+        assert_eq!(from(*cookie), Vec::<String>::new(), "cookie {}", cookie);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::{create_file, filesystem, names, names_at, read_file, readdir, run};
+use std::io::IoSlice;
+use std::sync::Arc;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags, WasiFile},
+    ErrorKind, SystemTimeSpec,
+};
+use wasi_virtfs::{EchoDevice, Filesystem, StaticWrites};
+
+fn open(dir: &dyn WasiDir, path: &str, oflags: OFlags, write: bool) -> Box<dyn WasiFile> {
+    run(dir.open_file(false, path, oflags, true, write, FdFlags::empty())).expect("open")
+}
+
+/// A bundle of assets, made read-only once it's in place, and a handle to
+/// `app.js` opened for writing before it was.
+fn bundle() -> (Arc<Filesystem>, Box<dyn WasiFile>) {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("assets")).expect("mkdir");
+    create_file(&root, "assets/app.js", b"main();");
+    run(root.symlink("assets/app.js", "app")).expect("symlink");
+    run(root.create_dir("empty")).expect("mkdir");
+    root.put_char_device("tty", Arc::new(EchoDevice::new()))
+        .expect("put");
+    let early = open(&root, "assets/app.js", OFlags::empty(), true);
+    assert!(!fs.is_readonly());
+    fs.set_readonly(true);
+    assert!(fs.is_readonly());
+    (fs, early)
+}
+
+#[test]
+fn mutations_fail_with_erofs() {
+    let (fs, early) = bundle();
+    let root = fs.root();
+    let now = || Some(SystemTimeSpec::SymbolicNow);
+    let open_with = |path: &str, oflags: OFlags, write: bool| {
+        run(root.open_file(false, path, oflags, false, write, FdFlags::empty()))
+    };
+
+    assert_errkind!(open_with("new", OFlags::CREATE, true), ErrorKind::Rofs);
+    assert_errkind!(
+        open_with("assets/app.js", OFlags::CREATE, false),
+        ErrorKind::Rofs
+    );
+    assert_errkind!(
+        open_with("assets/app.js", OFlags::TRUNCATE, true),
+        ErrorKind::Rofs
+    );
+    assert_errkind!(
+        open_with("assets/app.js", OFlags::empty(), true),
+        ErrorKind::Rofs
+    );
+    assert_errkind!(run(root.create_dir("new")), ErrorKind::Rofs);
+    assert_errkind!(run(root.unlink_file("assets/app.js")), ErrorKind::Rofs);
+    assert_errkind!(run(root.unlink_file("app")), ErrorKind::Rofs);
+    assert_errkind!(run(root.remove_dir("empty")), ErrorKind::Rofs);
+    assert_errkind!(
+        run(root.rename("assets/app.js", &root, "moved")),
+        ErrorKind::Rofs
+    );
+    assert_errkind!(
+        run(root.hard_link("assets/app.js", &root, "link")),
+        ErrorKind::Rofs
+    );
+    assert_errkind!(run(root.symlink("assets", "link")), ErrorKind::Rofs);
+    assert_errkind!(
+        run(root.set_times("assets/app.js", now(), now(), false)),
+        ErrorKind::Rofs
+    );
+
+    // Handles opened before are kept from writing too.
+    assert_errkind!(
+        run(early.write_vectored(&[IoSlice::new(b"x")])),
+        ErrorKind::Rofs
+    );
+    assert_errkind!(
+        run(early.write_vectored_at(&[IoSlice::new(b"x")], 0)),
+        ErrorKind::Rofs
+    );
+    assert_errkind!(run(early.set_filestat_size(0)), ErrorKind::Rofs);
+    assert_errkind!(run(early.allocate(0, 100)), ErrorKind::Rofs);
+    assert_errkind!(run(early.set_times(now(), now())), ErrorKind::Rofs);
+    let dir = open(&root, "assets", OFlags::DIRECTORY, false);
+    assert_errkind!(run(dir.set_times(now(), now())), ErrorKind::Rofs);
+    let tty = open(&root, "tty", OFlags::empty(), true);
+    assert_errkind!(run(tty.set_times(now(), now())), ErrorKind::Rofs);
+
+    assert_eq!(names(&root), [".", "..", "app", "assets", "empty", "tty"]);
+    assert_eq!(read_file(&root, "assets/app.js"), b"main();");
+}
+
+#[test]
+fn reads_carry_on() {
+    let (fs, early) = bundle();
+    let root = fs.root();
+    assert_eq!(read_file(&root, "app"), b"main();");
+    let mut buf = [0; 4];
+    assert_eq!(
+        run(early.read_vectored_at(&mut [std::io::IoSliceMut::new(&mut buf)], 0)).expect("read"),
+        4
+    );
+    assert_eq!(&buf, b"main");
+    assert_eq!(
+        run(root.get_path_filestat("assets/app.js", false))
+            .expect("stat")
+            .size,
+        7
+    );
+    assert_eq!(
+        run(root.read_link("app")).expect("readlink"),
+        std::path::PathBuf::from("assets/app.js")
+    );
+    assert_eq!(readdir(&root).len(), 6);
+
+    // Devices can still be written to.
+    let tty = open(&root, "tty", OFlags::empty(), true);
+    assert_eq!(
+        run(tty.write_vectored(&[IoSlice::new(b"hi")])).expect("write"),
+        2
+    );
+
+    // The host can still change the tree, and so can the guest once the
+    // filesystem is writable again.
+    root.put_static_file("assets/extra.css", b"", StaticWrites::ReadOnly)
+        .expect("put");
+    fs.set_readonly(false);
+    assert_eq!(
+        run(early.write_vectored_at(&[IoSlice::new(b"MAIN")], 0)).expect("write"),
+        4
+    );
+    create_file(&root, "new", b"");
+}
+
+/// `/app` read-only, with a file linked from the writable `/tmp` as well,
+/// and `/tmp` writable, with a handle to `app/main.js` opened before.
+fn mixed() -> (Arc<Filesystem>, Box<dyn WasiFile>) {
+    let fs = filesystem();
+    let root = fs.root();
+    fs.create_dir_all("app/lib").expect("mkdir");
+    run(root.create_dir("tmp")).expect("mkdir");
+    create_file(&root, "app/main.js", b"main();");
+    create_file(&root, "app/lib/util.js", b"util();");
+    run(root.hard_link("app/lib/util.js", &root, "tmp/util.js")).expect("link");
+    let early = open(&root, "app/main.js", OFlags::empty(), true);
+    fs.set_readonly_at("app", true).expect("set read-only");
+    (fs, early)
+}
+
+#[test]
+fn read_only_subtrees() {
+    let (fs, early) = mixed();
+    let root = fs.root();
+    assert!(!fs.is_readonly());
+    let now = || Some(SystemTimeSpec::SymbolicNow);
+
+    assert_errkind!(
+        run(root.open_file(
+            false,
+            "app/new",
+            OFlags::CREATE,
+            false,
+            true,
+            FdFlags::empty()
+        )),
+        ErrorKind::Rofs
+    );
+    assert_errkind!(
+        run(root.open_file(
+            false,
+            "app/lib/util.js",
+            OFlags::empty(),
+            false,
+            true,
+            FdFlags::empty()
+        )),
+        ErrorKind::Rofs
+    );
+    assert_errkind!(run(root.create_dir("app/lib/new")), ErrorKind::Rofs);
+    assert_errkind!(run(root.unlink_file("app/main.js")), ErrorKind::Rofs);
+    assert_errkind!(run(root.remove_dir("app/lib")), ErrorKind::Rofs);
+    assert_errkind!(run(root.symlink("x", "app/link")), ErrorKind::Rofs);
+    assert_errkind!(
+        run(root.set_times("app", now(), now(), false)),
+        ErrorKind::Rofs
+    );
+    let app = open(&root, "app", OFlags::DIRECTORY, false);
+    assert_errkind!(run(app.set_times(now(), now())), ErrorKind::Rofs);
+
+    // A handle opened before can't write, and neither can one to the same
+    // file by a name outside the subtree.
+    assert_errkind!(
+        run(early.write_vectored_at(&[IoSlice::new(b"x")], 0)),
+        ErrorKind::Rofs
+    );
+    assert_errkind!(run(early.set_filestat_size(0)), ErrorKind::Rofs);
+    assert_errkind!(
+        run(root.open_file(
+            false,
+            "tmp/util.js",
+            OFlags::empty(),
+            false,
+            true,
+            FdFlags::empty()
+        )),
+        ErrorKind::Rofs
+    );
+
+    // The rest of the tree is writable.
+    create_file(&root, "tmp/scratch", b"scratch");
+    run(root.create_dir("tmp/dir")).expect("mkdir");
+    run(root.unlink_file("tmp/scratch")).expect("unlink");
+    assert_eq!(read_file(&root, "app/main.js"), b"main();");
+
+    fs.set_readonly_at("app", false).expect("set writable");
+    run(early.write_vectored_at(&[IoSlice::new(b"MAIN")], 0)).expect("write");
+    run(root.unlink_file("tmp/util.js")).expect("unlink");
+    create_file(&root, "app/new", b"");
+
+    assert_errkind!(fs.set_readonly_at("app/main.js", true), ErrorKind::Notdir);
+    assert_errkind!(fs.set_readonly_at("missing", true), ErrorKind::Noent);
+}
+
+#[test]
+fn nothing_crosses_the_boundary() {
+    let (fs, _early) = mixed();
+    let root = fs.root();
+    create_file(&root, "tmp/scratch", b"");
+    run(root.create_dir("tmp/empty")).expect("mkdir");
+    let rename = |from: &str, to: &str| run(root.rename(from, &root, to));
+
+    // Out of the subtree, in either direction, or within it.
+    assert_errkind!(rename("app/main.js", "tmp/main.js"), ErrorKind::Rofs);
+    assert_errkind!(rename("app/lib", "tmp/lib"), ErrorKind::Rofs);
+    assert_errkind!(rename("tmp/scratch", "app/scratch"), ErrorKind::Rofs);
+    assert_errkind!(rename("tmp/empty", "app/empty"), ErrorKind::Rofs);
+    assert_errkind!(rename("tmp/scratch", "app/main.js"), ErrorKind::Rofs);
+    assert_errkind!(rename("app/main.js", "app/lib/main.js"), ErrorKind::Rofs);
+    // The top of the subtree can't be moved, or replaced, either.
+    assert_errkind!(rename("app", "moved"), ErrorKind::Rofs);
+    fs.set_readonly_at("tmp/empty", true)
+        .expect("set read-only");
+    run(root.create_dir("tmp/dir")).expect("mkdir");
+    assert_errkind!(rename("tmp/dir", "tmp/empty"), ErrorKind::Rofs);
+    assert_errkind!(run(root.remove_dir("tmp/empty")), ErrorKind::Rofs);
+
+    let link = |from: &str, to: &str| run(root.hard_link(from, &root, to));
+    assert_errkind!(link("app/main.js", "tmp/main.js"), ErrorKind::Rofs);
+    assert_errkind!(link("tmp/scratch", "app/scratch"), ErrorKind::Rofs);
+    // Nor can the file linked from outside gain another name.
+    assert_errkind!(link("tmp/util.js", "tmp/other.js"), ErrorKind::Rofs);
+
+    assert_eq!(names_at(&root, "app"), [".", "..", "lib", "main.js"]);
+    assert_eq!(
+        names_at(&root, "tmp"),
+        [".", "..", "dir", "empty", "scratch", "util.js"]
+    );
+}
+
+#[test]
+fn nested_subtrees() {
+    let (fs, _early) = mixed();
+    let root = fs.root();
+    fs.set_readonly_at("app/lib", true).expect("set read-only");
+    fs.set_readonly_at("app", false).expect("set writable");
+    create_file(&root, "app/new", b"");
+    assert_errkind!(run(root.create_dir("app/lib/new")), ErrorKind::Rofs);
+    assert_errkind!(
+        run(root.open_file(
+            false,
+            "tmp/util.js",
+            OFlags::empty(),
+            false,
+            true,
+            FdFlags::empty()
+        )),
+        ErrorKind::Rofs
+    );
+    fs.set_readonly_at("app/lib", false).expect("set writable");
+    create_file(&root, "app/lib/new", b"");
+    open(&root, "tmp/util.js", OFlags::empty(), true);
+}
+
+#[test]
+fn host_additions_are_read_only() {
+    let (fs, _early) = mixed();
+    let root = fs.root();
+    let write = |path: &str| {
+        let f = run(root.open_file(false, path, OFlags::empty(), false, true, FdFlags::empty()))?;
+        run(f.write_vectored_at(&[IoSlice::new(b"x")], 0))
+    };
+    root.put_static_file("app/lib/static.js", b"static();", StaticWrites::CopyOnWrite)
+        .expect("put");
+    assert_errkind!(write("app/lib/static.js"), ErrorKind::Rofs);
+    create_file(&root, "tmp/scratch", b"scratch");
+    fs.copy_file("tmp/scratch", "app/copy").expect("copy");
+    assert_errkind!(write("app/copy"), ErrorKind::Rofs);
+    fs.create_dir_all("app/lib/deep").expect("mkdir");
+
+    // Moved in by the host, even with a handle already open.
+    let moved = open(&root, "tmp/scratch", OFlags::empty(), true);
+    fs.exchange("tmp/scratch", "app/copy").expect("exchange");
+    assert_errkind!(
+        run(moved.write_vectored_at(&[IoSlice::new(b"x")], 0)),
+        ErrorKind::Rofs
+    );
+    run(root.create_dir("tmp/dir")).expect("mkdir");
+    create_file(&root, "tmp/dir/inner", b"");
+    fs.exchange("tmp/dir", "app/lib/deep").expect("exchange");
+    assert_errkind!(write("app/lib/deep/inner"), ErrorKind::Rofs);
+}
+
+#[test]
+fn host_removals_are_writable() {
+    let (fs, _early) = mixed();
+    let root = fs.root();
+    let write = |path: &str| {
+        let f = run(root.open_file(false, path, OFlags::empty(), false, true, FdFlags::empty()))?;
+        run(f.write_vectored_at(&[IoSlice::new(b"x")], 0))
+    };
+    create_file(&root, "tmp/scratch", b"");
+    run(root.create_dir("tmp/dir")).expect("mkdir");
+
+    fs.exchange("app/main.js", "tmp/scratch").expect("exchange");
+    write("tmp/scratch").expect("write");
+    assert_eq!(read_file(&root, "tmp/scratch"), b"xain();");
+    // A subtree moved out, with the file also linked from `/tmp`.
+    fs.exchange("app/lib", "tmp/dir").expect("exchange");
+    write("tmp/dir/util.js").expect("write");
+    write("tmp/util.js").expect("write");
+    // What was moved in the other way is read-only now.
+    assert_errkind!(write("app/main.js"), ErrorKind::Rofs);
+}
@@ -0,0 +1,151 @@
+use crate::{create_file, filesystem, names_at, run, Memory};
+use std::path::PathBuf;
+use std::sync::Arc;
+use wasi_cap_std_sync::WasiCtxBuilder;
+use wasi_common::{
+    dir::{DirCaps, WasiDir},
+    file::FileCaps,
+    snapshots::preview_1::{types::Fd, wasi_snapshot_preview1::WasiSnapshotPreview1},
+    ErrorKind, WasiCtx,
+};
+use wasi_virtfs::Filesystem;
+
+/// The preopen the guest is given, the root of the filesystem.
+const ROOT: u32 = 3;
+/// Another preopen, the directory `dir`.
+const DIR: u32 = 4;
+
+/// A guest with `dir` and `dir/sub` to try removing.
+fn guest() -> (Arc<Filesystem>, WasiCtx) {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("mkdir");
+    run(root.create_dir("dir/sub")).expect("mkdir");
+    let dir = run(root.open_dir(false, "dir")).expect("open dir");
+    let mut ctx = WasiCtxBuilder::new().build();
+    let mut preopen = |fd, dir, path: &str| {
+        ctx.insert_dir(
+            fd,
+            dir,
+            DirCaps::all(),
+            FileCaps::all(),
+            PathBuf::from(path),
+        )
+    };
+    preopen(ROOT, Box::new(root), "/");
+    preopen(DIR, dir, "/dir");
+    (fs, ctx)
+}
+
+#[test]
+fn dot_and_dot_dot_cannot_be_removed() {
+    let (fs, mut ctx) = guest();
+    let mem = Memory::new();
+    for path in &[
+        ".",
+        "..",
+        "./",
+        "dir/.",
+        "dir/..",
+        "dir/sub/..",
+        "dir/./",
+        "dir/..//",
+    ] {
+        assert_errkind!(
+            run(ctx.path_remove_directory(Fd::from(ROOT), &mem.str(path))),
+            ErrorKind::Inval
+        );
+    }
+    for path in &[".", "..", "sub/.."] {
+        assert_errkind!(
+            run(ctx.path_remove_directory(Fd::from(DIR), &mem.str(path))),
+            ErrorKind::Inval
+        );
+    }
+    assert_eq!(names_at(&fs.root(), "."), [".", "..", "dir"]);
+    assert_eq!(names_at(&fs.root(), "dir"), [".", "..", "sub"]);
+
+    // The directories themselves can still be removed by name.
+    run(ctx.path_remove_directory(Fd::from(DIR), &mem.str("sub"))).expect("rmdir");
+    run(ctx.path_remove_directory(Fd::from(ROOT), &mem.str("dir"))).expect("rmdir");
+    assert_eq!(names_at(&fs.root(), "."), [".", ".."]);
+}
+
+#[test]
+fn preopens_cannot_be_removed() {
+    let (fs, mut ctx) = guest();
+    let mem = Memory::new();
+    fs.set_strip_leading_slash(true);
+    for fd in &[ROOT, DIR] {
+        for path in &["/", "//"] {
+            assert_errkind!(
+                run(ctx.path_remove_directory(Fd::from(*fd), &mem.str(path))),
+                ErrorKind::Perm
+            );
+        }
+    }
+    assert_eq!(names_at(&fs.root(), "."), [".", "..", "dir"]);
+    assert_eq!(names_at(&fs.root(), "dir"), [".", "..", "sub"]);
+}
+
+#[test]
+fn dots_and_preopens_cannot_be_renamed() {
+    let (fs, mut ctx) = guest();
+    let mem = Memory::new();
+    let rename = |ctx: &mut WasiCtx, src: &str, dest: &str| {
+        run(ctx.path_rename(
+            Fd::from(ROOT),
+            &mem.str(src),
+            Fd::from(ROOT),
+            &mem.str(dest),
+        ))
+    };
+    for (src, dest) in &[
+        (".", "moved"),
+        ("dir/..", "moved"),
+        ("dir/sub/.", "moved"),
+        ("dir/sub", "."),
+        ("dir/sub", "dir/.."),
+    ] {
+        assert_errkind!(rename(&mut ctx, src, dest), ErrorKind::Inval);
+    }
+    fs.set_strip_leading_slash(true);
+    assert_errkind!(rename(&mut ctx, "/", "moved"), ErrorKind::Perm);
+    assert_errkind!(rename(&mut ctx, "dir/sub", "/"), ErrorKind::Perm);
+    assert_eq!(names_at(&fs.root(), "."), [".", "..", "dir"]);
+    assert_eq!(names_at(&fs.root(), "dir"), [".", "..", "sub"]);
+}
+
+#[test]
+fn trailing_slashes_as_rm_r_sends_them() {
+    let (fs, mut ctx) = guest();
+    let mem = Memory::new();
+    let root = fs.root();
+    create_file(&root, "dir/file", b"");
+    run(root.symlink("sub", "dir/link")).expect("symlink");
+    let rmdir = |ctx: &mut WasiCtx, path: &str| {
+        run(ctx.path_remove_directory(Fd::from(ROOT), &mem.str(path)))
+    };
+    let unlink =
+        |ctx: &mut WasiCtx, path: &str| run(ctx.path_unlink_file(Fd::from(ROOT), &mem.str(path)));
+
+    // A trailing slash says the entry is a directory, so it's only right
+    // for `rmdir`, and never for a symlink, which isn't followed.
+    assert_errkind!(unlink(&mut ctx, "dir/file/"), ErrorKind::Notdir);
+    assert_errkind!(rmdir(&mut ctx, "dir/file/"), ErrorKind::Notdir);
+    assert_errkind!(unlink(&mut ctx, "dir/link/"), ErrorKind::Notdir);
+    assert_errkind!(rmdir(&mut ctx, "dir/link/"), ErrorKind::Notdir);
+    assert_errkind!(unlink(&mut ctx, "dir/sub/"), ErrorKind::Isdir);
+    assert_errkind!(rmdir(&mut ctx, "dir/missing/"), ErrorKind::Noent);
+    assert_errkind!(rmdir(&mut ctx, "dir/"), ErrorKind::Notempty);
+    assert_eq!(
+        names_at(&fs.root(), "dir"),
+        [".", "..", "file", "link", "sub"]
+    );
+
+    unlink(&mut ctx, "dir/file").expect("unlink");
+    unlink(&mut ctx, "dir/link").expect("unlink");
+    rmdir(&mut ctx, "dir/sub/").expect("rmdir with a trailing slash");
+    rmdir(&mut ctx, "dir//").expect("rmdir with two trailing slashes");
+    assert_eq!(names_at(&fs.root(), "."), [".", ".."]);
+}
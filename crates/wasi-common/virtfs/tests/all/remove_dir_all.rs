@@ -0,0 +1,124 @@
+use crate::{create_file, filesystem, names, readdir, run};
+use std::io::{IoSlice, IoSliceMut, SeekFrom};
+use std::sync::Arc;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags},
+    ErrorKind,
+};
+use wasi_virtfs::EchoDevice;
+
+#[test]
+fn removes_a_deep_tree() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "keep", b"");
+    let nlink = |path: &str| {
+        run(root.get_path_filestat(path, false))
+            .expect("stat")
+            .nlink
+    };
+    // 200 levels, each with a file, a symlink, and a device beside the
+    // next level down.
+    let mut path = String::from("scratch");
+    run(root.create_dir(&path)).expect("mkdir");
+    for _ in 0..200 {
+        create_file(&root, &format!("{}/file", path), b"data");
+        run(root.symlink("file", &format!("{}/link", path))).expect("symlink");
+        root.put_char_device(&format!("{}/tty", path), Arc::new(EchoDevice::new()))
+            .expect("put device");
+        path.push_str("/d");
+        run(root.create_dir(&path)).expect("mkdir");
+    }
+
+    assert_eq!(nlink("."), 3);
+    assert_eq!(nlink("scratch"), 3);
+    assert_eq!(nlink(&path), 2);
+
+    assert_eq!(fs.remove_dir_all("scratch").expect("remove"), 1 + 200 * 4);
+    assert_eq!(nlink("."), 2);
+    assert_eq!(names(&root), [".", "..", "keep"]);
+    assert_errkind!(
+        run(root.get_path_filestat("scratch", false)),
+        ErrorKind::Noent
+    );
+    assert_errkind!(fs.remove_dir_all("scratch"), ErrorKind::Noent);
+
+    // An empty directory counts only itself, and a trailing slash is fine.
+    run(root.create_dir("empty")).expect("mkdir");
+    assert_eq!(root.remove_dir_all("empty/").expect("remove"), 1);
+}
+
+#[test]
+fn open_files_and_directories_outlive_the_tree() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("tree")).expect("mkdir");
+    run(root.create_dir("tree/sub")).expect("mkdir");
+    create_file(&root, "tree/sub/open", b"still readable");
+    create_file(&root, "tree/linked", b"");
+    run(root.hard_link("tree/linked", &root, "outside")).expect("link");
+    let f = run(root.open_file(
+        false,
+        "tree/sub/open",
+        OFlags::empty(),
+        true,
+        true,
+        FdFlags::empty(),
+    ))
+    .expect("open");
+    let sub = run(root.open_dir(false, "tree/sub")).expect("open dir");
+
+    assert_eq!(fs.remove_dir_all("tree").expect("remove"), 4);
+
+    // The open file has lost its only link, but reads and writes as before.
+    assert_eq!(run(f.get_filestat()).expect("fstat").nlink, 0);
+    let mut buf = [0; 32];
+    let n = run(f.read_vectored(&mut [IoSliceMut::new(&mut buf)])).expect("read");
+    assert_eq!(&buf[..n as usize], b"still readable");
+    run(f.seek(SeekFrom::Start(0))).expect("seek");
+    run(f.write_vectored(&[IoSlice::new(b"STILL")])).expect("write");
+
+    // A file linked from outside the tree keeps that link.
+    let outside = run(root.get_path_filestat("outside", false)).expect("stat");
+    assert_eq!(outside.nlink, 1);
+
+    // The open directory is empty, and nothing is left above it.
+    let stat = run(sub.get_filestat()).expect("stat");
+    assert_eq!(stat.nlink, 0);
+    let serial = stat.inode;
+    let listed: Vec<(String, u64)> = readdir(&*sub)
+        .into_iter()
+        .map(|e| (e.name, e.inode))
+        .collect();
+    assert_eq!(
+        listed,
+        [(".".to_owned(), serial), ("..".to_owned(), serial)]
+    );
+    assert_errkind!(run(sub.get_path_filestat("..", false)), ErrorKind::Noent);
+}
+
+#[test]
+fn only_directories_below_the_handle_can_be_removed() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("mkdir");
+    create_file(&root, "dir/file", b"");
+    run(root.symlink("dir", "link")).expect("symlink");
+
+    assert_errkind!(fs.remove_dir_all("dir/file"), ErrorKind::Notdir);
+    assert_errkind!(fs.remove_dir_all("link"), ErrorKind::Notdir);
+    assert_errkind!(fs.remove_dir_all("missing"), ErrorKind::Noent);
+    assert_errkind!(fs.remove_dir_all("."), ErrorKind::Inval);
+    assert_errkind!(fs.remove_dir_all("dir/.."), ErrorKind::Inval);
+    fs.set_strip_leading_slash(true);
+    assert_errkind!(fs.remove_dir_all("/"), ErrorKind::Perm);
+    let dir = run(root.open_dir(false, "dir")).expect("open dir");
+    let dir = dir
+        .as_any()
+        .downcast_ref::<wasi_virtfs::Dir>()
+        .expect("virtfs dir");
+    assert_errkind!(dir.remove_dir_all("."), ErrorKind::Inval);
+    assert_eq!(names(&root), [".", "..", "dir", "link"]);
+    assert_eq!(names(dir), [".", "..", "file"]);
+}
@@ -0,0 +1,352 @@
+use crate::{create_file, filesystem, open_read, read_file, readdir, run, ticking_filesystem};
+use wasi_common::{dir::WasiDir, file::FileType, ErrorKind};
+
+#[test]
+fn rename_file() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "a", b"contents");
+    let before = run(root.get_path_filestat("a", false)).expect("stat a");
+
+    run(root.rename("a", &root, "b")).expect("rename");
+    assert_errkind!(run(root.get_path_filestat("a", false)), ErrorKind::Noent);
+    let after = run(root.get_path_filestat("b", false)).expect("stat b");
+    assert_eq!(after.inode, before.inode);
+    assert_eq!(after.nlink, before.nlink);
+    assert_eq!(read_file(&root, "b"), b"contents");
+}
+
+#[test]
+fn rename_dir_and_symlink() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+    create_file(&root, "dir/file", b"contents");
+    run(root.symlink("dir", "link")).expect("create symlink");
+
+    run(root.rename("dir", &root, "moved")).expect("rename dir");
+    assert_eq!(read_file(&root, "moved/file"), b"contents");
+    run(root.rename("link", &root, "moved_link")).expect("rename symlink");
+    let stat = run(root.get_path_filestat("moved_link", false)).expect("stat link");
+    assert_eq!(stat.filetype, FileType::SymbolicLink);
+}
+
+#[test]
+fn rename_in_subdir() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+    create_file(&root, "dir/a", b"contents");
+
+    run(root.rename("dir/a", &root, "dir/b")).expect("rename");
+    assert_eq!(read_file(&root, "dir/b"), b"contents");
+    let names = readdir(&*run(root.open_dir(false, "dir")).expect("open dir"))
+        .into_iter()
+        .map(|e| e.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec![".", "..", "b"]);
+}
+
+#[test]
+fn rename_updates_times() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    create_file(&root, "a", b"");
+    let dir_before = run(root.get_filestat()).expect("stat root");
+    let file_before = run(root.get_path_filestat("a", false)).expect("stat a");
+
+    run(root.rename("a", &root, "b")).expect("rename");
+    let dir_after = run(root.get_filestat()).expect("stat root");
+    let file_after = run(root.get_path_filestat("b", false)).expect("stat b");
+    assert!(dir_after.mtim > dir_before.mtim, "dir mtim updated");
+    assert!(file_after.ctim > file_before.ctim, "file ctim updated");
+    assert_eq!(file_after.mtim, file_before.mtim, "file mtim unchanged");
+}
+
+#[test]
+fn rename_onto_self() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    create_file(&root, "a", b"contents");
+    let before = run(root.get_filestat()).expect("stat root");
+
+    run(root.rename("a", &root, "a")).expect("rename onto self");
+    assert_eq!(read_file(&root, "a"), b"contents");
+    let after = run(root.get_filestat()).expect("stat root");
+    assert_eq!(after.mtim, before.mtim, "no-op rename leaves dir untouched");
+}
+
+#[test]
+fn rename_missing() {
+    let fs = filesystem();
+    let root = fs.root();
+    assert_errkind!(run(root.rename("missing", &root, "b")), ErrorKind::Noent);
+    assert_errkind!(
+        run(root.rename("missing", &root, "missing")),
+        ErrorKind::Noent
+    );
+    assert_errkind!(
+        run(root.rename("dir/missing", &root, "b")),
+        ErrorKind::Noent
+    );
+}
+
+#[test]
+fn rename_across_dirs() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    run(root.create_dir("src")).expect("create src");
+    run(root.create_dir("dest")).expect("create dest");
+    create_file(&root, "src/file", b"contents");
+    let src = run(root.open_dir(false, "src")).expect("open src");
+    let dest = run(root.open_dir(false, "dest")).expect("open dest");
+    let src_before = run(src.get_filestat()).expect("stat src");
+    let dest_before = run(dest.get_filestat()).expect("stat dest");
+
+    run(src.rename("file", &*dest, "moved")).expect("rename");
+    assert_errkind!(run(src.get_path_filestat("file", false)), ErrorKind::Noent);
+    assert_eq!(read_file(&*dest, "moved"), b"contents");
+    assert!(run(src.get_filestat()).expect("stat src").mtim > src_before.mtim);
+    assert!(run(dest.get_filestat()).expect("stat dest").mtim > dest_before.mtim);
+}
+
+#[test]
+fn rename_dir_across_dirs() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("a")).expect("create a");
+    run(root.create_dir("b")).expect("create b");
+    run(root.create_dir("a/sub")).expect("create a/sub");
+
+    run(root.rename("a/sub", &root, "b/sub")).expect("rename");
+    let b = run(root.get_path_filestat("b", false)).expect("stat b");
+    let sub = run(root.open_dir(false, "b/sub")).expect("open b/sub");
+    let dotdot = readdir(&*sub)
+        .into_iter()
+        .find(|e| e.name == "..")
+        .expect("`..` entry");
+    assert_eq!(dotdot.inode, b.inode, "moved dir's parent is updated");
+}
+
+#[test]
+fn rename_between_handles_to_same_dir() {
+    let fs = filesystem();
+    let root = fs.root();
+    let also_root = run(root.open_dir(false, ".")).expect("open .");
+    create_file(&root, "a", b"contents");
+
+    run(root.rename("a", &*also_root, "b")).expect("rename");
+    assert_eq!(read_file(&root, "b"), b"contents");
+}
+
+#[test]
+fn rename_to_other_filesystem() {
+    let fs = filesystem();
+    let root = fs.root();
+    let other = filesystem();
+    let other_root = other.root();
+    create_file(&root, "a", b"contents");
+
+    assert_errkind!(run(root.rename("a", &other_root, "a")), ErrorKind::Xdev);
+    assert_eq!(read_file(&root, "a"), b"contents");
+    assert_errkind!(
+        run(other_root.get_path_filestat("a", false)),
+        ErrorKind::Noent
+    );
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Entry {
+    File,
+    EmptyDir,
+    FullDir,
+}
+
+fn make(dir: &dyn WasiDir, name: &str, entry: Entry) {
+    match entry {
+        Entry::File => create_file(dir, name, name.as_bytes()),
+        Entry::EmptyDir => run(dir.create_dir(name)).expect("create dir"),
+        Entry::FullDir => {
+            run(dir.create_dir(name)).expect("create dir");
+            create_file(dir, &format!("{}/child", name), b"child");
+        }
+    }
+}
+
+#[test]
+fn rename_over_existing() {
+    use Entry::*;
+    let cases: &[(Entry, Entry, Option<ErrorKind>)] = &[
+        (File, File, None),
+        (EmptyDir, EmptyDir, None),
+        (FullDir, EmptyDir, None),
+        (EmptyDir, File, Some(ErrorKind::Notdir)),
+        (File, EmptyDir, Some(ErrorKind::Isdir)),
+        (File, FullDir, Some(ErrorKind::Isdir)),
+        (EmptyDir, FullDir, Some(ErrorKind::Notempty)),
+    ];
+    for &(src, dest, ref expected) in cases {
+        for &same_dir in &[true, false] {
+            let fs = filesystem();
+            let root = fs.root();
+            run(root.create_dir("d")).expect("create d");
+            let dest_path = if same_dir { "dest" } else { "d/dest" };
+            make(&root, "src", src);
+            make(&root, dest_path, dest);
+            let src_stat = run(root.get_path_filestat("src", false)).expect("stat src");
+            let dest_stat = run(root.get_path_filestat(dest_path, false)).expect("stat dest");
+
+            let result = run(root.rename("src", &root, dest_path));
+            let after = run(root.get_path_filestat(dest_path, false)).expect("stat dest");
+            match expected {
+                None => {
+                    result.unwrap_or_else(|e| panic!("{:?} over {:?}: {:?}", src, dest, e));
+                    assert_eq!(after.inode, src_stat.inode, "{:?} over {:?}", src, dest);
+                    assert_errkind!(run(root.get_path_filestat("src", false)), ErrorKind::Noent);
+                }
+                Some(kind) => {
+                    match result {
+                        Err(e) => assert_eq!(
+                            e.downcast_ref::<ErrorKind>().map(std::mem::discriminant),
+                            Some(std::mem::discriminant(kind)),
+                            "{:?} over {:?}: {:?}",
+                            src,
+                            dest,
+                            e
+                        ),
+                        Ok(()) => panic!("{:?} over {:?} should fail", src, dest),
+                    }
+                    assert_eq!(after.inode, dest_stat.inode, "{:?} over {:?}", src, dest);
+                    run(root.get_path_filestat("src", false)).expect("src still exists");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn rename_over_open_file() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "new", b"new contents");
+    create_file(&root, "old", b"old contents");
+    let f = open_read(&root, false, "old").expect("open old");
+
+    run(root.rename("new", &root, "old")).expect("rename");
+    assert_eq!(read_file(&root, "old"), b"new contents");
+
+    let stat = run(f.get_filestat()).expect("stat open file");
+    assert_eq!(stat.nlink, 0, "replaced file has no links left");
+    let mut buf = [0; 12];
+    let nread = run(f.read_vectored(&mut [std::io::IoSliceMut::new(&mut buf)])).expect("read");
+    assert_eq!(&buf[..nread as usize], b"old contents");
+}
+
+#[test]
+fn rename_over_hard_link_to_self() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "a", b"contents");
+    run(root.hard_link("a", &root, "b")).expect("hard link");
+
+    run(root.rename("a", &root, "b")).expect("rename");
+    let a = run(root.get_path_filestat("a", false)).expect("a is untouched");
+    let b = run(root.get_path_filestat("b", false)).expect("b is untouched");
+    assert_eq!(a.inode, b.inode);
+    assert_eq!(a.nlink, 2);
+}
+
+#[test]
+fn rename_dir_into_itself() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("a")).expect("create a");
+    run(root.create_dir("a/b")).expect("create a/b");
+
+    assert_errkind!(run(root.rename("a", &root, "a/b/c")), ErrorKind::Inval);
+    assert_errkind!(run(root.rename("a", &root, "a/c")), ErrorKind::Inval);
+    run(root.get_path_filestat("a/b", false)).expect("tree is unchanged");
+}
+
+#[test]
+fn rename_dir_into_itself_through_handle() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("a")).expect("create a");
+    run(root.create_dir("a/b")).expect("create a/b");
+    let b = run(root.open_dir(false, "a/b")).expect("open a/b");
+    run(root.symlink("a/b", "link")).expect("create symlink");
+
+    assert_errkind!(run(root.rename("a", &*b, "c")), ErrorKind::Inval);
+    assert_errkind!(run(root.rename("a", &root, "link/c")), ErrorKind::Inval);
+    run(root.get_path_filestat("a/b", false)).expect("tree is unchanged");
+}
+
+#[test]
+fn exchange_keeps_open_handles() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("staging")).expect("create staging");
+    create_file(&root, "config.json", b"{\"old\": true}");
+    create_file(&root, "staging/config.json", b"{\"new\": true}");
+    let guest_fd = open_read(&root, false, "config.json").expect("open config");
+
+    fs.exchange("config.json", "staging/config.json")
+        .expect("exchange");
+    assert_eq!(read_file(&root, "config.json"), b"{\"new\": true}");
+    assert_eq!(read_file(&root, "staging/config.json"), b"{\"old\": true}");
+
+    let mut buf = [0; 13];
+    let nread =
+        run(guest_fd.read_vectored(&mut [std::io::IoSliceMut::new(&mut buf)])).expect("read");
+    assert_eq!(&buf[..nread as usize], b"{\"old\": true}");
+}
+
+#[test]
+fn exchange_file_and_dir() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("a")).expect("create a");
+    run(root.create_dir("a/dir")).expect("create a/dir");
+    create_file(&root, "a/dir/child", b"child");
+    create_file(&root, "file", b"contents");
+
+    fs.exchange("a/dir", "file").expect("exchange");
+    assert_eq!(read_file(&root, "a/dir"), b"contents");
+    assert_eq!(read_file(&root, "file/child"), b"child");
+    let root_stat = run(root.get_filestat()).expect("stat root");
+    let moved = run(root.open_dir(false, "file")).expect("open moved dir");
+    let dotdot = readdir(&*moved)
+        .into_iter()
+        .find(|e| e.name == "..")
+        .expect("`..` entry");
+    assert_eq!(
+        dotdot.inode, root_stat.inode,
+        "moved dir's parent is updated"
+    );
+    // The `..` of the directory now links to the root rather than `a`.
+    assert_eq!(root_stat.nlink, 4);
+    let a = run(root.get_path_filestat("a", false)).expect("stat a");
+    assert_eq!(a.nlink, 2);
+}
+
+#[test]
+fn exchange_errors() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("a")).expect("create a");
+    run(root.create_dir("a/b")).expect("create a/b");
+    create_file(&root, "file", b"contents");
+
+    assert_errkind!(fs.exchange("file", "missing"), ErrorKind::Noent);
+    assert_errkind!(fs.exchange("missing", "file"), ErrorKind::Noent);
+    assert_errkind!(fs.exchange("a", "a/b"), ErrorKind::Inval);
+    fs.exchange("file", "file").expect("exchange with self");
+    assert_eq!(read_file(&root, "file"), b"contents");
+
+    let other = filesystem();
+    assert_errkind!(
+        root.exchange("file", &other.root(), "file"),
+        ErrorKind::Xdev
+    );
+}
@@ -0,0 +1,143 @@
+use crate::{create_file, read_file, readdir, run, ticking_filesystem};
+use std::io::IoSlice;
+use std::sync::Arc;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, Filestat, OFlags},
+    ErrorKind,
+};
+use wasi_virtfs::{EchoDevice, Filesystem, SerializedFilesystem, StaticWrites};
+
+fn round_trip(fs: &Filesystem) -> Arc<Filesystem> {
+    let bytes = bincode::serialize(fs).expect("serialize");
+    let data: SerializedFilesystem = bincode::deserialize(&bytes).expect("deserialize");
+    Filesystem::from_serialized(data, wasi_cap_std_sync::clocks_ctx().system, 9).expect("restore")
+}
+
+fn stat(dir: &dyn WasiDir, path: &str) -> Filestat {
+    run(dir.get_path_filestat(path, false)).expect("stat")
+}
+
+#[test]
+fn round_trips_the_tree() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    run(root.create_dir("home")).expect("mkdir");
+    run(root.create_dir("home/empty")).expect("mkdir");
+    create_file(&root, "home/notes", b"remember this");
+    run(root.hard_link("home/notes", &root, "notes-link")).expect("link");
+    run(root.symlink("home/notes", "shortcut")).expect("symlink");
+    root.put_static_file("motd", b"read me", StaticWrites::ReadOnly)
+        .expect("put");
+    create_file(&root, "big", &vec![3; 200_000]);
+    // Dynamic files and devices are the host's to put back.
+    root.put_dynamic_file("generated", Box::new(|| Ok(b"now".to_vec())))
+        .expect("put");
+    root.put_char_device("echo", Arc::new(EchoDevice::new()))
+        .expect("put");
+
+    let copy = round_trip(&fs);
+    let restored = copy.root();
+    assert_eq!(copy.device_id(), 9);
+    assert_eq!(copy.next_serial(), fs.next_serial());
+    assert_eq!(
+        restored.hash_tree("home", true).expect("hash"),
+        root.hash_tree("home", true).expect("hash")
+    );
+    let names: Vec<String> = readdir(&restored).into_iter().map(|e| e.name).collect();
+    assert_eq!(
+        names,
+        [".", "..", "big", "home", "motd", "notes-link", "shortcut"]
+    );
+    for path in &[
+        ".",
+        "home",
+        "home/empty",
+        "home/notes",
+        "shortcut",
+        "motd",
+        "big",
+    ] {
+        let (before, after) = (stat(&root, path), stat(&restored, path));
+        assert_eq!(after.inode, before.inode, "{}", path);
+        assert_eq!(after.nlink, before.nlink, "{}", path);
+        assert_eq!(after.size, before.size, "{}", path);
+        assert_eq!(
+            (after.atim, after.mtim, after.ctim),
+            (before.atim, before.mtim, before.ctim),
+            "{}",
+            path
+        );
+    }
+    assert_eq!(read_file(&restored, "big"), vec![3; 200_000]);
+    assert_eq!(
+        run(restored.read_link("shortcut")).expect("readlink"),
+        std::path::PathBuf::from("home/notes")
+    );
+    assert_errkind!(
+        run(restored.open_file(
+            false,
+            "motd",
+            OFlags::empty(),
+            false,
+            true,
+            FdFlags::empty()
+        )),
+        ErrorKind::Rofs
+    );
+
+    // The two names are still one file.
+    assert_eq!(
+        stat(&restored, "notes-link").inode,
+        stat(&restored, "home/notes").inode
+    );
+    let f = run(restored.open_file(
+        false,
+        "notes-link",
+        OFlags::empty(),
+        false,
+        true,
+        FdFlags::APPEND,
+    ))
+    .expect("open");
+    run(f.write_vectored(&[IoSlice::new(b", and this")])).expect("write");
+    assert_eq!(
+        read_file(&restored, "home/notes"),
+        b"remember this, and this"
+    );
+
+    // New inodes carry on from where the original left off.
+    let next = fs.next_serial();
+    create_file(&restored, "new", b"");
+    assert_eq!(stat(&restored, "new").inode, next);
+}
+
+#[test]
+fn unlinked_files_are_left_out_but_keep_their_serials() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    create_file(&root, "tmp", b"scratch");
+    let _open = run(root.open_file(false, "tmp", OFlags::empty(), true, false, FdFlags::empty()))
+        .expect("open");
+    let serial = stat(&root, "tmp").inode;
+    run(root.unlink_file("tmp")).expect("unlink");
+
+    let copy = round_trip(&fs);
+    assert_eq!(copy.inode_count(), 1);
+    create_file(&copy.root(), "new", b"");
+    assert!(stat(&copy.root(), "new").inode > serial);
+}
+
+#[test]
+fn later_versions_are_refused() {
+    let fs = ticking_filesystem();
+    let mut bytes = bincode::serialize(&*fs).expect("serialize");
+    // bincode writes the version first, as a little-endian u32.
+    assert_eq!(bytes[..4], 1u32.to_le_bytes());
+    bytes[..4].copy_from_slice(&2u32.to_le_bytes());
+    let data: SerializedFilesystem = bincode::deserialize(&bytes).expect("deserialize");
+    assert_errkind!(
+        Filesystem::from_serialized(data, wasi_cap_std_sync::clocks_ctx().system, 0),
+        ErrorKind::Notsup
+    );
+}
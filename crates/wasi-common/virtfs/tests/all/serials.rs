@@ -0,0 +1,107 @@
+use crate::{create_file, filesystem, readdir, run};
+use std::sync::Arc;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags},
+    ErrorKind,
+};
+use wasi_virtfs::{EchoDevice, StaticWrites};
+
+fn stat_inode(dir: &dyn WasiDir, path: &str) -> u64 {
+    run(dir.get_path_filestat(path, false)).expect("stat").inode
+}
+
+#[test]
+fn serials_run_out_rather_than_wrap() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "early", b"");
+    fs.set_next_serial(u64::MAX - 2).expect("skip ahead");
+    create_file(&root, "a", b"");
+    run(root.create_dir("b")).expect("mkdir");
+    assert_eq!(stat_inode(&root, "a"), u64::MAX - 2);
+    assert_eq!(stat_inode(&root, "b"), u64::MAX - 1);
+    assert_eq!(fs.next_serial(), u64::MAX);
+
+    // Nothing more can be created, and nothing is left behind trying.
+    assert_errkind!(
+        run(root.open_file(false, "c", OFlags::CREATE, true, true, FdFlags::empty())),
+        ErrorKind::Nospc
+    );
+    assert_errkind!(run(root.create_dir("c")), ErrorKind::Nospc);
+    assert_errkind!(run(root.symlink("a", "c")), ErrorKind::Nospc);
+    assert_errkind!(
+        root.put_static_file("c", b"", StaticWrites::CopyOnWrite),
+        ErrorKind::Nospc
+    );
+    assert_errkind!(
+        root.put_dynamic_file("c", Box::new(|| Ok(Vec::new()))),
+        ErrorKind::Nospc
+    );
+    assert_errkind!(
+        root.put_char_device("c", Arc::new(EchoDevice::new())),
+        ErrorKind::Nospc
+    );
+    assert_errkind!(fs.copy_file("a", "c"), ErrorKind::Nospc);
+    assert_errkind!(fs.create_dir_all("b/c/d"), ErrorKind::Nospc);
+    let names: Vec<String> = readdir(&root).into_iter().map(|e| e.name).collect();
+    assert_eq!(names, [".", "..", "a", "b", "early"]);
+
+    // Removing inodes doesn't free their serials for reuse.
+    run(root.unlink_file("early")).expect("unlink");
+    assert_errkind!(run(root.create_dir("c")), ErrorKind::Nospc);
+
+    // What's already there still works.
+    run(root.hard_link("a", &root, "b/a")).expect("link");
+    run(root.rename("b", &root, "renamed")).expect("rename");
+    assert_eq!(stat_inode(&root, "renamed/a"), u64::MAX - 2);
+    assert_eq!(fs.inode_count(), 3);
+}
+
+#[test]
+fn serials_only_skip_ahead() {
+    let fs = filesystem();
+    let root = fs.root();
+    assert_eq!(fs.next_serial(), 1);
+    create_file(&root, "a", b"");
+    fs.set_next_serial(1000).expect("skip ahead");
+    fs.set_next_serial(1000).expect("stay put");
+    assert_errkind!(fs.set_next_serial(999), ErrorKind::Inval);
+    assert_errkind!(fs.set_next_serial(1), ErrorKind::Inval);
+    create_file(&root, "b", b"");
+    assert_eq!(stat_inode(&root, "a"), 1);
+    assert_eq!(stat_inode(&root, "b"), 1000);
+    assert_eq!(fs.next_serial(), 1001);
+}
+
+#[test]
+fn inode_count_counts_each_inode_once() {
+    let fs = filesystem();
+    let root = fs.root();
+    assert_eq!(fs.inode_count(), 1, "the root");
+    run(root.create_dir("dir")).expect("mkdir");
+    create_file(&root, "dir/file", b"data");
+    run(root.hard_link("dir/file", &root, "link")).expect("link");
+    run(root.symlink("dir", "sym")).expect("symlink");
+    assert_eq!(fs.inode_count(), 4);
+
+    // An unlinked file counts until it's closed.
+    let f = run(root.open_file(
+        false,
+        "link",
+        OFlags::empty(),
+        true,
+        false,
+        FdFlags::empty(),
+    ))
+    .expect("open");
+    run(root.unlink_file("link")).expect("unlink");
+    assert_eq!(fs.inode_count(), 4);
+    run(root.unlink_file("dir/file")).expect("unlink");
+    assert_eq!(fs.inode_count(), 4);
+    drop(f);
+    assert_eq!(fs.inode_count(), 3);
+    fs.remove_dir_all("dir").expect("remove");
+    run(root.unlink_file("sym")).expect("unlink");
+    assert_eq!(fs.inode_count(), 1);
+}
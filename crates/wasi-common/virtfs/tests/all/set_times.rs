@@ -0,0 +1,176 @@
+use crate::{create_file, filesystem, open_read, run, ticking_filesystem};
+use cap_std::time::{Duration, SystemTime};
+use wasi_common::{dir::WasiDir, file::FileType, ErrorKind, SystemTimeSpec};
+
+fn at(secs: u64) -> SystemTime {
+    SystemTime::from_std(std::time::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+fn std_at(secs: u64) -> Option<std::time::SystemTime> {
+    Some(at(secs).into_std())
+}
+
+#[test]
+fn set_file_times() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+
+    run(root.set_times(
+        "file",
+        Some(SystemTimeSpec::Absolute(at(100))),
+        Some(SystemTimeSpec::Absolute(at(200))),
+        false,
+    ))
+    .expect("set times");
+    let stat = run(root.get_path_filestat("file", false)).expect("stat file");
+    assert_eq!(stat.atim, std_at(100));
+    assert_eq!(stat.mtim, std_at(200));
+
+    run(root.set_times("file", None, Some(SystemTimeSpec::Absolute(at(300))), false))
+        .expect("set mtime only");
+    let stat = run(root.get_path_filestat("file", false)).expect("stat file");
+    assert_eq!(stat.atim, std_at(100), "atime untouched");
+    assert_eq!(stat.mtim, std_at(300));
+}
+
+#[test]
+fn set_dir_times() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+
+    run(root.set_times(
+        "dir",
+        Some(SystemTimeSpec::Absolute(at(100))),
+        Some(SystemTimeSpec::Absolute(at(200))),
+        false,
+    ))
+    .expect("set times");
+    let stat = run(root.get_path_filestat("dir", false)).expect("stat dir");
+    assert_eq!(stat.atim, std_at(100));
+    assert_eq!(stat.mtim, std_at(200));
+
+    // `fd_filestat_set_times` on a directory fd comes through as ".".
+    let dir = run(root.open_dir(false, "dir")).expect("open dir");
+    run(dir.set_times(".", None, Some(SystemTimeSpec::Absolute(at(300))), false))
+        .expect("set times on .");
+    let stat = run(dir.get_filestat()).expect("stat dir");
+    assert_eq!(stat.mtim, std_at(300));
+}
+
+#[test]
+fn set_times_now_uses_fs_clock() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let before = run(root.get_path_filestat("file", false)).expect("stat file");
+
+    run(root.set_times(
+        "file",
+        Some(SystemTimeSpec::SymbolicNow),
+        Some(SystemTimeSpec::SymbolicNow),
+        false,
+    ))
+    .expect("set times");
+    let after = run(root.get_path_filestat("file", false)).expect("stat file");
+    assert!(after.atim > before.atim);
+    assert!(after.mtim > before.mtim);
+    assert_eq!(after.atim, after.mtim, "both set from a single clock read");
+}
+
+#[test]
+fn set_times_bumps_ctim() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let before = run(root.get_path_filestat("file", false)).expect("stat file");
+
+    run(root.set_times("file", None, None, false)).expect("set nothing");
+    let after = run(root.get_path_filestat("file", false)).expect("stat file");
+    assert_eq!(after.ctim, before.ctim, "nothing changed");
+
+    run(root.set_times("file", Some(SystemTimeSpec::Absolute(at(1))), None, false))
+        .expect("set atime");
+    let after = run(root.get_path_filestat("file", false)).expect("stat file");
+    assert!(after.ctim > before.ctim);
+
+    let f = open_read(&root, false, "file").expect("open file");
+    run(f.set_times(None, Some(SystemTimeSpec::Absolute(at(1))))).expect("fd set times");
+    let fd_after = run(f.get_filestat()).expect("stat file");
+    assert!(fd_after.ctim > after.ctim, "fd set_times also bumps ctim");
+}
+
+#[test]
+fn set_times_missing() {
+    let fs = filesystem();
+    let root = fs.root();
+    assert_errkind!(
+        run(root.set_times("missing", Some(SystemTimeSpec::SymbolicNow), None, false)),
+        ErrorKind::Noent
+    );
+    assert_errkind!(
+        run(root.set_times(
+            "missing/file",
+            Some(SystemTimeSpec::SymbolicNow),
+            None,
+            false
+        )),
+        ErrorKind::Noent
+    );
+}
+
+#[test]
+fn set_times_follow() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    run(root.symlink("file", "link")).expect("create symlink");
+    let link_before = run(root.get_path_filestat("link", false)).expect("stat link");
+
+    run(root.set_times("link", None, Some(SystemTimeSpec::Absolute(at(100))), true))
+        .expect("set times through link");
+    let file = run(root.get_path_filestat("file", false)).expect("stat file");
+    assert_eq!(file.mtim, std_at(100), "target updated");
+    let link = run(root.get_path_filestat("link", false)).expect("stat link");
+    assert_eq!(link.mtim, link_before.mtim, "link untouched");
+}
+
+#[test]
+fn set_times_nofollow() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    run(root.symlink("file", "link")).expect("create symlink");
+    let file_before = run(root.get_path_filestat("file", false)).expect("stat file");
+
+    run(root.set_times(
+        "link",
+        Some(SystemTimeSpec::Absolute(at(100))),
+        Some(SystemTimeSpec::Absolute(at(200))),
+        false,
+    ))
+    .expect("set times on link");
+    let link = run(root.get_path_filestat("link", false)).expect("stat link");
+    assert_eq!(link.filetype, FileType::SymbolicLink);
+    assert_eq!(link.atim, std_at(100));
+    assert_eq!(link.mtim, std_at(200));
+    let file = run(root.get_path_filestat("file", false)).expect("stat file");
+    assert_eq!(file.atim, file_before.atim, "target atime untouched");
+    assert_eq!(file.mtim, file_before.mtim, "target mtime untouched");
+    assert_eq!(file.ctim, file_before.ctim, "target ctime untouched");
+}
+
+#[test]
+fn set_times_dangling() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.symlink("missing", "link")).expect("create symlink");
+    let time = || Some(SystemTimeSpec::Absolute(at(100)));
+
+    run(root.set_times("link", time(), time(), false)).expect("set times on dangling link");
+    assert_errkind!(
+        run(root.set_times("link", time(), time(), true)),
+        ErrorKind::Noent
+    );
+}
@@ -0,0 +1,123 @@
+use crate::{capacity, create_file, filesystem, open_write, pread, read_file, run, virtfs_file};
+use std::io::{IoSlice, SeekFrom};
+use wasi_common::file::WasiFile;
+
+const GIB: u64 = 1 << 30;
+const PAGE: usize = 4096;
+// Files are stored in chunks of this size.
+const CHUNK: usize = 64 * 1024;
+
+fn materialized(f: &dyn WasiFile) -> u64 {
+    virtfs_file(f).materialized_size()
+}
+
+fn pwrite(f: &dyn WasiFile, data: &[u8], offset: u64) {
+    let n = run(f.write_vectored_at(&[IoSlice::new(data)], offset)).expect("pwrite");
+    assert_eq!(n as usize, data.len());
+}
+
+#[test]
+fn truncate_up_leaves_a_hole() {
+    let fs = filesystem();
+    fs.set_max_file_size(4 * GIB);
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = open_write(&root, "file").expect("open file");
+
+    run(f.set_filestat_size(2 * GIB)).expect("grow");
+    assert_eq!(run(f.get_filestat()).unwrap().size, 2 * GIB);
+    assert_eq!(materialized(&*f), 0);
+    assert_eq!(capacity(&*f), 0);
+
+    // A few scattered pages, as a database would write.
+    let page = [7; PAGE];
+    for offset in &[0, GIB, 2 * GIB - PAGE as u64] {
+        pwrite(&*f, &page, *offset);
+    }
+    assert_eq!(run(f.get_filestat()).unwrap().size, 2 * GIB);
+    // The last page ends its chunk, so the start of that chunk is stored too.
+    assert_eq!(materialized(&*f), (2 * PAGE + CHUNK) as u64);
+    assert!(capacity(&*f) < 3 * CHUNK as u64);
+
+    // Holes read as zeros, including reads straddling data and a hole.
+    assert_eq!(pread(&*f, PAGE, GIB / 2), vec![0; PAGE]);
+    let straddling = pread(&*f, 2 * PAGE, GIB - PAGE as u64);
+    assert_eq!(&straddling[..PAGE], &[0; PAGE][..]);
+    assert_eq!(&straddling[PAGE..], &page[..]);
+    let straddling = pread(&*f, 2 * PAGE, GIB + PAGE as u64 / 2);
+    assert_eq!(&straddling[..PAGE / 2], &page[..PAGE / 2]);
+    assert!(straddling[PAGE / 2..].iter().all(|b| *b == 0));
+    assert_eq!(pread(&*f, 2 * PAGE, 2 * GIB - PAGE as u64), &page[..]);
+}
+
+#[test]
+fn allocate_leaves_a_hole() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"data");
+    let f = open_write(&root, "file").expect("open file");
+
+    run(f.allocate(0, GIB / 2)).expect("allocate");
+    assert_eq!(run(f.get_filestat()).unwrap().size, GIB / 2);
+    assert_eq!(materialized(&*f), 4);
+    assert_eq!(pread(&*f, 8, 0), b"data\0\0\0\0");
+    assert_eq!(pread(&*f, 8, GIB / 2 - 4), b"\0\0\0\0");
+}
+
+#[test]
+fn write_past_end_leaves_a_hole() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"head");
+    let f = open_write(&root, "file").expect("open file");
+
+    run(f.seek(SeekFrom::Start(GIB / 2))).expect("seek past end");
+    run(f.write_vectored(&[IoSlice::new(b"tail")])).expect("write");
+    assert_eq!(run(f.get_filestat()).unwrap().size, GIB / 2 + 4);
+    assert_eq!(materialized(&*f), 8);
+    assert_eq!(pread(&*f, 8, GIB / 2 - 4), b"\0\0\0\0tail");
+}
+
+#[test]
+fn overlapping_writes() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = open_write(&root, "file").expect("open file");
+
+    pwrite(&*f, b"aaaa", 0);
+    pwrite(&*f, b"bbbb", 8);
+    pwrite(&*f, b"cccc", 16);
+    assert_eq!(read_file(&root, "file"), b"aaaa\0\0\0\0bbbb\0\0\0\0cccc");
+    assert_eq!(materialized(&*f), 20);
+    // Spans the first two writes and the gaps after them.
+    pwrite(&*f, b"xxxxxxxxxxxxxx", 2);
+    assert_eq!(read_file(&root, "file"), b"aaxxxxxxxxxxxxxxcccc");
+    assert_eq!(materialized(&*f), 20);
+    // Entirely within earlier data.
+    pwrite(&*f, b"yy", 6);
+    // Overlaps the end of the data, past a gap left by another write.
+    pwrite(&*f, b"zz", 24);
+    pwrite(&*f, b"------", 18);
+    assert_eq!(read_file(&root, "file"), b"aaxxxxyyxxxxxxxxcc------zz");
+    assert_eq!(materialized(&*f), 26);
+}
+
+#[test]
+fn shrink_then_grow_reads_zeros() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"0123456789");
+    let f = open_write(&root, "file").expect("open file");
+
+    run(f.set_filestat_size(4)).expect("shrink");
+    run(f.set_filestat_size(10)).expect("grow");
+    assert_eq!(read_file(&root, "file"), b"0123\0\0\0\0\0\0");
+    assert_eq!(materialized(&*f), 4);
+
+    pwrite(&*f, b"ab", 20);
+    run(f.set_filestat_size(21)).expect("shrink into written data");
+    run(f.set_filestat_size(22)).expect("grow");
+    assert_eq!(pread(&*f, 3, 19), b"\0a\0");
+    assert_eq!(materialized(&*f), 21);
+}
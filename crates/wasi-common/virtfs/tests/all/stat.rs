@@ -0,0 +1,58 @@
+use crate::{filesystem, readdir, run};
+use wasi_common::{dir::WasiDir, file::FileType};
+
+/// The inode numbers readdir reports for `.` and `..`.
+fn dot_inodes(dir: &dyn WasiDir) -> (u64, u64) {
+    let entries = readdir(dir);
+    assert_eq!(entries[0].name, ".");
+    assert_eq!(entries[1].name, "..");
+    (entries[0].inode, entries[1].inode)
+}
+
+fn stat_inode(dir: &dyn WasiDir, path: &str) -> u64 {
+    let stat = run(dir.get_path_filestat(path, false)).expect("stat");
+    assert_eq!(stat.filetype, FileType::Directory, "{:?}", path);
+    stat.inode
+}
+
+#[test]
+fn stat_dot_entries_of_root() {
+    let fs = filesystem();
+    let root = fs.root();
+    let (dot, dotdot) = dot_inodes(&root);
+    // The root is its own parent.
+    assert_eq!(dot, dotdot);
+    for path in &[".", "..", "./.", "./", "../..", "../."] {
+        assert_eq!(stat_inode(&root, path), dot, "{:?}", path);
+    }
+}
+
+#[test]
+fn stat_dot_entries_of_subdirectory() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("foo")).expect("create foo");
+    run(root.create_dir("foo/bar")).expect("create foo/bar");
+    let foo = run(root.open_dir(false, "foo")).expect("open foo");
+    let (dot, dotdot) = dot_inodes(&*foo);
+
+    assert_eq!(stat_inode(&root, "foo"), dot);
+    assert_eq!(stat_inode(&root, "foo/."), dot);
+    assert_eq!(stat_inode(&root, "foo/bar/.."), dot);
+    assert_eq!(stat_inode(&root, "foo/./bar/../."), dot);
+    assert_eq!(stat_inode(&root, "foo/.."), dotdot);
+    assert_eq!(stat_inode(&*foo, "."), dot);
+    assert_eq!(stat_inode(&*foo, "bar/.."), dot);
+}
+
+#[test]
+fn stat_dot_with_symlink_follow() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("foo")).expect("create foo");
+    let (dot, _) = dot_inodes(&root);
+    let with_follow = run(root.get_path_filestat(".", true)).expect("stat");
+    assert_eq!(with_follow.inode, dot);
+    let stat = run(root.get_path_filestat("foo/..", true)).expect("stat");
+    assert_eq!(stat.inode, dot);
+}
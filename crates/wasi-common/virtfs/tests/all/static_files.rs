@@ -0,0 +1,155 @@
+use crate::{capacity, filesystem, open_read, open_write, pread, read_file, run, virtfs_file};
+use std::io::IoSlice;
+use std::sync::Arc;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags},
+    ErrorKind,
+};
+use wasi_virtfs::StaticWrites;
+
+const MIB: usize = 1 << 20;
+// Files are stored, and copied on write, in chunks of this size.
+const CHUNK: usize = 64 * 1024;
+
+/// Host data which lives for the rest of the process, as embedded data
+/// would.
+fn leak(len: usize) -> &'static [u8] {
+    let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+    Box::leak(data.into_boxed_slice())
+}
+
+#[test]
+fn reads_in_place() {
+    let fs = filesystem();
+    let root = fs.root();
+    let data = leak(16 * MIB + 100);
+    root.put_static_file("weights", data, StaticWrites::ReadOnly)
+        .expect("put static file");
+
+    let f = open_read(&root, false, "weights").expect("open");
+    assert_eq!(run(f.get_filestat()).unwrap().size, data.len() as u64);
+    assert_eq!(run(f.num_ready_bytes()).unwrap(), data.len() as u64);
+    let offset = (3 * CHUNK - 10) as u64;
+    assert_eq!(
+        pread(&*f, 20, offset),
+        &data[3 * CHUNK - 10..3 * CHUNK + 10]
+    );
+    let mut buf = [0; 8];
+    let n = run(f.peek(&mut buf)).expect("peek");
+    assert_eq!(&buf[..n as usize], &data[..8]);
+    assert_eq!(read_file(&root, "weights"), data);
+
+    // Nothing was copied into memory the filesystem holds.
+    assert_eq!(capacity(&*f), 0);
+    assert_eq!(virtfs_file(&*f).owned_size(), 0);
+    assert_eq!(virtfs_file(&*f).materialized_size(), data.len() as u64);
+}
+
+#[test]
+fn read_only_rejects_writes() {
+    let fs = filesystem();
+    let root = fs.root();
+    root.put_static_file("file", b"static data", StaticWrites::ReadOnly)
+        .expect("put static file");
+
+    assert_errkind!(open_write(&root, "file"), ErrorKind::Rofs);
+    assert_errkind!(
+        run(root.open_file(
+            false,
+            "file",
+            OFlags::TRUNCATE,
+            false,
+            true,
+            FdFlags::empty()
+        )),
+        ErrorKind::Rofs
+    );
+    crate::create_file(&root, "other", b"other");
+    assert_errkind!(fs.copy_file("other", "file"), ErrorKind::Rofs);
+    assert_eq!(read_file(&root, "file"), b"static data");
+
+    // A copy is an ordinary file, still sharing the data.
+    fs.copy_file("file", "copy").expect("copy");
+    let copy = open_write(&root, "copy").expect("open copy for writing");
+    assert_eq!(capacity(&*copy), 0);
+    run(copy.write_vectored_at(&[IoSlice::new(b"STATIC")], 0)).expect("pwrite");
+    assert_eq!(read_file(&root, "copy"), b"STATIC data");
+    assert_eq!(read_file(&root, "file"), b"static data");
+
+    // The file is read-only, not the directory it's in.
+    run(root.unlink_file("file")).expect("unlink");
+}
+
+#[test]
+fn copy_on_write() {
+    let fs = filesystem();
+    let root = fs.root();
+    let data = leak(4 * CHUNK);
+    root.put_static_file("file", data, StaticWrites::CopyOnWrite)
+        .expect("put static file");
+
+    let f = open_write(&root, "file").expect("open for writing");
+    let offset = (2 * CHUNK + 5) as u64;
+    run(f.write_vectored_at(&[IoSlice::new(b"xyz")], offset)).expect("pwrite");
+    assert_eq!(capacity(&*f), CHUNK as u64);
+    assert_eq!(virtfs_file(&*f).materialized_size(), data.len() as u64);
+    let mut expected = data.to_vec();
+    expected[2 * CHUNK + 5..2 * CHUNK + 8].copy_from_slice(b"xyz");
+    assert_eq!(read_file(&root, "file"), expected);
+
+    // Truncating into the host's data, then growing again, reads zeros.
+    run(f.set_filestat_size(CHUNK as u64 / 2)).expect("shrink");
+    run(f.set_filestat_size(3 * CHUNK as u64)).expect("grow");
+    let contents = read_file(&root, "file");
+    assert_eq!(&contents[..CHUNK / 2], &data[..CHUNK / 2]);
+    assert!(contents[CHUNK / 2..].iter().all(|b| *b == 0));
+    assert_eq!(capacity(&*f), 0);
+}
+
+#[test]
+fn shared_data_is_released() {
+    let fs = filesystem();
+    let root = fs.root();
+    let data: Arc<[u8]> = Arc::from(&b"shared data"[..]);
+    root.put_shared_file("a", data.clone(), StaticWrites::ReadOnly)
+        .expect("put shared file");
+    root.put_shared_file("b", data.clone(), StaticWrites::CopyOnWrite)
+        .expect("put shared file");
+    assert_eq!(Arc::strong_count(&data), 3);
+    assert_eq!(read_file(&root, "a"), b"shared data");
+
+    let f = open_read(&root, false, "a").expect("open");
+    run(root.unlink_file("a")).expect("unlink");
+    assert_eq!(Arc::strong_count(&data), 3, "an open handle keeps the data");
+    assert_eq!(pread(&*f, 64, 0), b"shared data");
+    drop(f);
+    assert_eq!(Arc::strong_count(&data), 2);
+
+    let b = open_write(&root, "b").expect("open for writing");
+    run(b.set_filestat_size(0)).expect("truncate");
+    assert_eq!(Arc::strong_count(&data), 1);
+}
+
+#[test]
+fn put_errors() {
+    let fs = filesystem();
+    let root = fs.root();
+    root.put_static_file("file", b"data", StaticWrites::ReadOnly)
+        .expect("put static file");
+    run(root.create_dir("dir")).expect("mkdir");
+
+    for path in &["file", "dir", "."] {
+        assert_errkind!(
+            root.put_static_file(path, b"data", StaticWrites::ReadOnly),
+            ErrorKind::Exist
+        );
+    }
+    assert_errkind!(
+        root.put_static_file("missing/file", b"data", StaticWrites::ReadOnly),
+        ErrorKind::Noent
+    );
+    root.put_static_file("dir/file", b"", StaticWrites::ReadOnly)
+        .expect("put empty file");
+    assert_eq!(read_file(&root, "dir/file"), b"");
+}
@@ -0,0 +1,309 @@
+use crate::{create_file, filesystem, open_read, read_file, readdir, run};
+use std::path::PathBuf;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, FileType, OFlags},
+    ErrorKind,
+};
+
+#[test]
+fn read_link() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.symlink("target", "link")).expect("create symlink");
+    assert_eq!(
+        run(root.read_link("link")).expect("read link"),
+        PathBuf::from("target")
+    );
+}
+
+#[test]
+fn symlink_over_existing_name() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    run(root.create_dir("dir")).expect("create dir");
+    run(root.symlink("target", "link")).expect("create symlink");
+
+    assert_errkind!(run(root.symlink("target", "file")), ErrorKind::Exist);
+    assert_errkind!(run(root.symlink("target", "dir")), ErrorKind::Exist);
+    assert_errkind!(run(root.symlink("elsewhere", "link")), ErrorKind::Exist);
+    assert_eq!(
+        run(root.read_link("link")).expect("read link"),
+        PathBuf::from("target"),
+        "existing link is unchanged"
+    );
+}
+
+#[test]
+fn read_link_on_non_symlink() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    run(root.create_dir("dir")).expect("create dir");
+
+    assert_errkind!(run(root.read_link("file")), ErrorKind::Inval);
+    assert_errkind!(run(root.read_link("dir")), ErrorKind::Inval);
+    assert_errkind!(run(root.read_link("missing")), ErrorKind::Noent);
+}
+
+#[test]
+fn symlink_filestat() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    run(root.symlink("file", "link")).expect("create symlink");
+
+    let file = run(root.get_path_filestat("file", false)).expect("stat file");
+    let link = run(root.get_path_filestat("link", false)).expect("stat link");
+    assert_eq!(link.filetype, FileType::SymbolicLink);
+    assert_eq!(link.size, "file".len() as u64);
+    assert_ne!(link.inode, file.inode, "symlink has its own serial");
+}
+
+#[test]
+fn readdir_shows_symlink() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.symlink("target", "link")).expect("create symlink");
+    let link = run(root.get_path_filestat("link", false)).expect("stat link");
+
+    let entries = readdir(&root);
+    assert_eq!(entries.len(), 3, "., .., link: {:?}", entries);
+    let entry = entries
+        .iter()
+        .find(|e| e.name == "link")
+        .expect("link entry");
+    assert_eq!(entry.filetype, FileType::SymbolicLink);
+    assert_eq!(entry.inode, link.inode);
+}
+
+#[test]
+fn follow_chain() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    run(root.symlink("file", "a")).expect("create a");
+    run(root.symlink("a", "b")).expect("create b");
+    run(root.symlink("b", "c")).expect("create c");
+
+    assert_eq!(read_file(&root, "c"), b"contents");
+}
+
+#[test]
+fn follow_relative_to_containing_dir() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+    create_file(&root, "dir/file", b"inner");
+    create_file(&root, "file", b"outer");
+    run(root.symlink("file", "dir/link")).expect("create symlink");
+
+    assert_eq!(read_file(&root, "dir/link"), b"inner");
+}
+
+#[test]
+fn follow_dir_link_mid_path() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+    run(root.create_dir("dir/sub")).expect("create dir/sub");
+    create_file(&root, "dir/sub/file", b"contents");
+    run(root.symlink("dir", "a")).expect("create a");
+    run(root.symlink("a/sub", "b")).expect("create b");
+
+    assert_eq!(read_file(&root, "a/sub/file"), b"contents");
+    assert_eq!(read_file(&root, "b/file"), b"contents");
+    let dir = run(root.open_dir(false, "a/sub")).expect("open dir through link");
+    assert_eq!(read_file(&*dir, "file"), b"contents");
+
+    create_file(&root, "b/new", b"created");
+    assert_eq!(read_file(&root, "dir/sub/new"), b"created");
+}
+
+#[test]
+fn follow_final_dir_link() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+    create_file(&root, "dir/file", b"contents");
+    run(root.symlink("dir", "link")).expect("create symlink");
+
+    let dir = run(root.open_dir(true, "link")).expect("open dir through link");
+    assert_eq!(read_file(&*dir, "file"), b"contents");
+}
+
+#[test]
+fn dangling() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.symlink("missing", "link")).expect("create symlink");
+
+    assert_errkind!(open_read(&root, true, "link"), ErrorKind::Noent);
+    assert_errkind!(run(root.open_dir(true, "link")), ErrorKind::Noent);
+    assert_errkind!(open_read(&root, true, "link/file"), ErrorKind::Noent);
+    let stat = run(root.get_path_filestat("link", false)).expect("stat dangling link");
+    assert_eq!(stat.filetype, FileType::SymbolicLink);
+}
+
+#[test]
+fn self_loop() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.symlink("a", "a")).expect("create symlink");
+
+    assert_errkind!(open_read(&root, true, "a"), ErrorKind::Loop);
+    assert_errkind!(run(root.open_dir(true, "a")), ErrorKind::Loop);
+    assert_errkind!(open_read(&root, false, "a/file"), ErrorKind::Loop);
+}
+
+#[test]
+fn mutual_loop() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.symlink("b", "a")).expect("create a");
+    run(root.symlink("a", "b")).expect("create b");
+
+    assert_errkind!(open_read(&root, true, "a"), ErrorKind::Loop);
+    assert_errkind!(open_read(&root, false, "b/file"), ErrorKind::Loop);
+}
+
+#[test]
+fn long_chain_hits_limit() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    run(root.symlink("file", "link0")).expect("create link0");
+    for i in 1..40 {
+        run(root.symlink(&format!("link{}", i - 1), &format!("link{}", i))).expect("create link");
+    }
+
+    // 32 traversals are allowed, the 33rd is not.
+    assert_eq!(read_file(&root, "link31"), b"contents");
+    assert_errkind!(open_read(&root, true, "link32"), ErrorKind::Loop);
+}
+
+#[test]
+fn nofollow_open() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    run(root.create_dir("dir")).expect("create dir");
+    run(root.symlink("file", "file_link")).expect("create file link");
+    run(root.symlink("dir", "dir_link")).expect("create dir link");
+    run(root.symlink("missing", "dangling")).expect("create dangling link");
+
+    assert_errkind!(open_read(&root, false, "file_link"), ErrorKind::Loop);
+    assert_errkind!(open_read(&root, false, "dir_link"), ErrorKind::Loop);
+    assert_errkind!(open_read(&root, false, "dangling"), ErrorKind::Loop);
+    assert_eq!(read_file(&root, "file_link"), b"contents");
+}
+
+#[test]
+fn nofollow_create() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.symlink("missing", "link")).expect("create symlink");
+
+    let create = |oflags| run(root.open_file(false, "link", oflags, false, true, FdFlags::empty()));
+    assert_errkind!(create(OFlags::CREATE), ErrorKind::Loop);
+    assert_errkind!(create(OFlags::CREATE | OFlags::EXCLUSIVE), ErrorKind::Exist);
+    let stat = run(root.get_path_filestat("link", false)).expect("stat link");
+    assert_eq!(
+        stat.filetype,
+        FileType::SymbolicLink,
+        "link was not replaced"
+    );
+}
+
+#[test]
+fn filestat_follow() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    run(root.create_dir("dir")).expect("create dir");
+    run(root.symlink("file", "file_link")).expect("create file link");
+    run(root.symlink("dir", "dir_link")).expect("create dir link");
+    run(root.symlink("missing", "dangling")).expect("create dangling link");
+
+    let file = run(root.get_path_filestat("file", false)).expect("stat file");
+    let followed = run(root.get_path_filestat("file_link", true)).expect("stat file link");
+    assert_eq!(followed.filetype, FileType::RegularFile);
+    assert_eq!(followed.inode, file.inode);
+    assert_eq!(followed.size, b"contents".len() as u64);
+
+    let dir = run(root.get_path_filestat("dir", false)).expect("stat dir");
+    let followed = run(root.get_path_filestat("dir_link", true)).expect("stat dir link");
+    assert_eq!(followed.filetype, FileType::Directory);
+    assert_eq!(followed.inode, dir.inode);
+
+    assert_errkind!(
+        run(root.get_path_filestat("dangling", true)),
+        ErrorKind::Noent
+    );
+}
+
+#[test]
+fn filestat_nofollow() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    run(root.create_dir("dir")).expect("create dir");
+    run(root.symlink("file", "file_link")).expect("create file link");
+    run(root.symlink("dir", "dir_link")).expect("create dir link");
+    run(root.symlink("missing", "dangling")).expect("create dangling link");
+
+    for (link, target) in &[
+        ("file_link", "file"),
+        ("dir_link", "dir"),
+        ("dangling", "missing"),
+    ] {
+        let stat = run(root.get_path_filestat(link, false)).expect("stat link");
+        assert_eq!(stat.filetype, FileType::SymbolicLink, "{}", link);
+        assert_eq!(stat.size, target.len() as u64, "{}", link);
+        if let Ok(target) = run(root.get_path_filestat(target, false)) {
+            assert_ne!(stat.inode, target.inode, "{}", link);
+        }
+    }
+}
+
+#[test]
+fn unlink_symlink() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    run(root.symlink("file", "link")).expect("create symlink");
+    let before = run(root.get_path_filestat("file", false)).expect("stat file");
+
+    run(root.unlink_file("link")).expect("unlink symlink");
+    assert_errkind!(run(root.get_path_filestat("link", false)), ErrorKind::Noent);
+    let after = run(root.get_path_filestat("file", false)).expect("stat file");
+    assert_eq!(after.nlink, before.nlink);
+    assert_eq!(read_file(&root, "file"), b"contents");
+}
+
+#[test]
+fn unlink_dir_symlink() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+    create_file(&root, "dir/file", b"contents");
+    run(root.symlink("dir", "link")).expect("create symlink");
+
+    run(root.unlink_file("link")).expect("unlink symlink");
+    assert_eq!(read_file(&root, "dir/file"), b"contents");
+}
+
+#[test]
+fn remove_dir_on_symlink() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("dir")).expect("create dir");
+    run(root.symlink("dir", "link")).expect("create symlink");
+
+    assert_errkind!(run(root.remove_dir("link")), ErrorKind::Notdir);
+    let stat = run(root.get_path_filestat("dir", false)).expect("stat dir");
+    assert_eq!(stat.filetype, FileType::Directory);
+    let stat = run(root.get_path_filestat("link", false)).expect("stat link");
+    assert_eq!(stat.filetype, FileType::SymbolicLink);
+}
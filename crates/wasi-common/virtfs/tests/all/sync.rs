@@ -0,0 +1,129 @@
+use crate::{create_file, filesystem, open_read, open_write, run};
+use std::io::IoSlice;
+use std::sync::{Arc, Mutex};
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags},
+    Error, ErrorExt, ErrorKind,
+};
+use wasi_virtfs::{Filesystem, SyncKind};
+
+/// What a handler saw of one sync.
+#[derive(Debug, PartialEq)]
+struct Synced {
+    kind: SyncKind,
+    path: Option<String>,
+    contents: Vec<u8>,
+}
+
+/// Set a handler which records every sync, and return the record.
+fn record(fs: &Filesystem) -> Arc<Mutex<Vec<Synced>>> {
+    let record = Arc::new(Mutex::new(Vec::new()));
+    let recorder = record.clone();
+    fs.set_sync_handler(move |event| {
+        recorder.lock().unwrap().push(Synced {
+            kind: event.kind(),
+            path: event.path().map(String::from),
+            contents: event.to_vec().expect("contents"),
+        });
+        Ok(())
+    });
+    record
+}
+
+fn synced(kind: SyncKind, path: Option<&str>, contents: &[u8]) -> Synced {
+    Synced {
+        kind,
+        path: path.map(String::from),
+        contents: contents.to_vec(),
+    }
+}
+
+#[test]
+fn handler_sees_each_sync() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("out")).expect("mkdir");
+    let record = record(&fs);
+
+    let f = open_write(&root, "out/result.json").expect("open");
+    run(f.write_vectored(&[IoSlice::new(b"{}")])).expect("write");
+    assert!(record.lock().unwrap().is_empty(), "writes alone don't sync");
+    run(f.sync()).expect("sync");
+    run(f.write_vectored(&[IoSlice::new(b"\n")])).expect("write");
+    run(f.datasync()).expect("datasync");
+
+    // Read-only handles can sync too, and the path follows renames.
+    run(root.rename("out/result.json", &root, "final.json")).expect("rename");
+    let r = open_read(&root, false, "final.json").expect("open");
+    run(r.sync()).expect("sync");
+
+    assert_eq!(
+        *record.lock().unwrap(),
+        vec![
+            synced(SyncKind::Sync, Some("/out/result.json"), b"{}"),
+            synced(SyncKind::DataSync, Some("/out/result.json"), b"{}\n"),
+            synced(SyncKind::Sync, Some("/final.json"), b"{}\n"),
+        ]
+    );
+
+    // Directories aren't synced through it.
+    let dir = run(root.open_file(
+        false,
+        "out",
+        OFlags::DIRECTORY,
+        true,
+        false,
+        FdFlags::empty(),
+    ))
+    .expect("open dir as a file");
+    run(dir.sync()).expect("sync dir");
+    assert_eq!(record.lock().unwrap().len(), 3);
+}
+
+#[test]
+fn paths_of_linked_and_unlinked_files() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("b")).expect("mkdir");
+    create_file(&root, "b/file", b"data");
+    run(root.hard_link("b/file", &root, "c")).expect("link");
+    run(root.create_dir("a")).expect("mkdir");
+    run(root.hard_link("b/file", &root, "a/z")).expect("link");
+    let record = record(&fs);
+    let f = open_read(&root, false, "c").expect("open");
+
+    run(f.sync()).expect("sync");
+    run(root.unlink_file("a/z")).expect("unlink");
+    run(f.sync()).expect("sync");
+    run(root.unlink_file("b/file")).expect("unlink");
+    run(root.unlink_file("c")).expect("unlink");
+    run(f.sync()).expect("sync");
+
+    let paths: Vec<_> = record.lock().unwrap().drain(..).map(|s| s.path).collect();
+    assert_eq!(
+        paths,
+        vec![Some("/a/z".to_string()), Some("/b/file".to_string()), None]
+    );
+}
+
+#[test]
+fn handler_errors_fail_the_sync() {
+    let fs = filesystem();
+    let root = fs.root();
+    let f = open_write(&root, "file").expect("open");
+    run(f.sync()).expect("sync without a handler");
+
+    fs.set_sync_handler(|_| Err(Error::not_supported().context("bucket unavailable")));
+    assert_errkind!(run(f.sync()), ErrorKind::Io);
+    assert_errkind!(run(f.datasync()), ErrorKind::Io);
+
+    // A handler can replace itself, or be cleared.
+    let fs2 = fs.clone();
+    fs.set_sync_handler(move |_| {
+        fs2.clear_sync_handler();
+        Err(Error::io())
+    });
+    assert_errkind!(run(f.sync()), ErrorKind::Io);
+    run(f.sync()).expect("sync after the handler is cleared");
+}
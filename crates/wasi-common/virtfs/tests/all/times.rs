@@ -0,0 +1,272 @@
+use crate::{create_file, open_read, open_write, run, ticking_filesystem};
+use cap_std::time::{Duration, SystemTime};
+use std::io::{IoSlice, IoSliceMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use wasi_common::{clocks::WasiSystemClock, dir::WasiDir, file::Filestat, SystemTimeSpec};
+use wasi_virtfs::{AtimeUpdates, Filesystem};
+
+fn stat(dir: &dyn WasiDir, path: &str) -> Filestat {
+    run(dir.get_path_filestat(path, false)).expect("stat")
+}
+
+#[test]
+fn ctim_on_set_filestat_size() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    let f = open_write(&root, "file").expect("open");
+    let before = stat(&root, "file");
+
+    run(f.set_filestat_size(4)).expect("truncate");
+    let truncated = stat(&root, "file");
+    assert!(truncated.ctim > before.ctim);
+    assert!(truncated.mtim > before.mtim);
+    assert_eq!(truncated.mtim, truncated.ctim);
+    assert_eq!(truncated.atim, before.atim);
+
+    run(f.set_filestat_size(16)).expect("extend");
+    let extended = stat(&root, "file");
+    assert!(extended.ctim > truncated.ctim);
+    assert!(extended.mtim > truncated.mtim);
+    assert_eq!(extended.atim, before.atim);
+
+    // Setting the size it already has changes nothing.
+    run(f.set_filestat_size(16)).expect("same size");
+    assert_eq!(stat(&root, "file"), extended);
+}
+
+#[test]
+fn ctim_on_allocate() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    let f = open_write(&root, "file").expect("open");
+    let before = stat(&root, "file");
+
+    // Allocating within the file changes nothing.
+    run(f.allocate(0, 8)).expect("allocate within");
+    assert_eq!(stat(&root, "file"), before);
+
+    run(f.allocate(8, 8)).expect("allocate beyond the end");
+    let after = stat(&root, "file");
+    assert_eq!(after.size, 16);
+    assert!(after.ctim > before.ctim);
+    assert!(after.mtim > before.mtim);
+    assert_eq!(after.atim, before.atim);
+}
+
+#[test]
+fn ctim_on_link_count_changes() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    let before = stat(&root, "file");
+
+    run(root.hard_link("file", &root, "link")).expect("hard_link");
+    let linked = stat(&root, "file");
+    assert_eq!(linked.nlink, 2);
+    assert!(linked.ctim > before.ctim);
+    assert_eq!(linked.mtim, before.mtim);
+    assert_eq!(linked.atim, before.atim);
+
+    run(root.unlink_file("link")).expect("unlink");
+    let unlinked = stat(&root, "file");
+    assert_eq!(unlinked.nlink, 1);
+    assert!(unlinked.ctim > linked.ctim);
+    assert_eq!(unlinked.mtim, before.mtim);
+    assert_eq!(unlinked.atim, before.atim);
+}
+
+#[test]
+fn links_update_the_directory_gaining_or_losing_them() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    run(root.create_dir("src")).expect("mkdir");
+    run(root.create_dir("dest")).expect("mkdir");
+    create_file(&root, "src/file", b"contents");
+    let (src, dest, file) = (
+        stat(&root, "src"),
+        stat(&root, "dest"),
+        stat(&root, "src/file"),
+    );
+
+    // The file's ctim and the new entry's directory's mtim are both the
+    // time of the link; nothing else moves.
+    run(root.hard_link("src/file", &root, "dest/link")).expect("hard_link");
+    let linked = stat(&root, "src/file");
+    assert!(linked.ctim > file.ctim);
+    assert_eq!((linked.atim, linked.mtim), (file.atim, file.mtim));
+    let dest_linked = stat(&root, "dest");
+    assert_eq!(dest_linked.mtim, linked.ctim);
+    assert_eq!((dest_linked.atim, dest_linked.ctim), (dest.atim, dest.ctim));
+    assert_eq!(stat(&root, "src"), src);
+
+    run(root.unlink_file("dest/link")).expect("unlink");
+    let unlinked = stat(&root, "src/file");
+    assert!(unlinked.ctim > linked.ctim);
+    assert_eq!((unlinked.atim, unlinked.mtim), (file.atim, file.mtim));
+    let dest_unlinked = stat(&root, "dest");
+    assert_eq!(dest_unlinked.mtim, unlinked.ctim);
+    assert_eq!(
+        (dest_unlinked.atim, dest_unlinked.ctim),
+        (dest.atim, dest.ctim)
+    );
+    assert_eq!(stat(&root, "src"), src);
+}
+
+#[test]
+fn writes_update_mtim() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = open_write(&root, "file").expect("open");
+    let before = stat(&root, "file");
+
+    run(f.write_vectored(&[IoSlice::new(b"contents")])).expect("write");
+    let written = run(f.get_filestat()).unwrap();
+    assert!(written.mtim > before.mtim);
+    assert_eq!(written.ctim, written.mtim);
+    assert_eq!(written.atim, before.atim);
+
+    run(f.write_vectored_at(&[IoSlice::new(b"C")], 0)).expect("pwrite");
+    let pwritten = run(f.get_filestat()).unwrap();
+    assert!(pwritten.mtim > written.mtim);
+    assert_eq!(pwritten.ctim, pwritten.mtim);
+
+    // Writing nothing changes nothing.
+    run(f.write_vectored(&[IoSlice::new(b"")])).expect("empty write");
+    assert_eq!(run(f.get_filestat()).unwrap(), pwritten);
+}
+
+#[test]
+fn reads_update_atim() {
+    let fs = ticking_filesystem();
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    let f = open_write(&root, "file").expect("open");
+    let mut buf = [0; 4];
+
+    let mut last = run(f.get_filestat()).unwrap();
+    for read in &["read", "pread", "peek"] {
+        match *read {
+            "read" => run(f.read_vectored(&mut [IoSliceMut::new(&mut buf)])),
+            "pread" => run(f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 2)),
+            _ => run(f.peek(&mut buf)),
+        }
+        .expect(read);
+        let now = run(f.get_filestat()).unwrap();
+        assert!(now.atim > last.atim, "{} updates atim", read);
+        assert_eq!(now.mtim, last.mtim, "{} leaves mtim", read);
+        assert_eq!(now.ctim, last.ctim, "{} leaves ctim", read);
+        last = now;
+    }
+
+    // Writing after reading puts mtim after atim, and reading again puts
+    // atim after that.
+    run(f.write_vectored(&[IoSlice::new(b"more")])).expect("write");
+    let written = run(f.get_filestat()).unwrap();
+    assert!(written.mtim > written.atim);
+    run(f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)).expect("read");
+    let read = run(f.get_filestat()).unwrap();
+    assert!(read.atim > read.mtim);
+}
+
+#[test]
+fn atime_updates_can_be_disabled() {
+    let fs = ticking_filesystem();
+    assert_eq!(fs.atime_updates(), AtimeUpdates::Strict);
+    fs.set_atime_updates(AtimeUpdates::Never);
+    assert_eq!(fs.atime_updates(), AtimeUpdates::Never);
+    let root = fs.root();
+    create_file(&root, "file", b"contents");
+    let before = stat(&root, "file");
+
+    let f = open_read(&root, false, "file").expect("open");
+    let mut buf = [0; 4];
+    run(f.read_vectored(&mut [IoSliceMut::new(&mut buf)])).expect("read");
+    run(f.read_vectored_at(&mut [IoSliceMut::new(&mut buf)], 0)).expect("pread");
+    run(f.peek(&mut buf)).expect("peek");
+    assert_eq!(stat(&root, "file"), before);
+
+    // mtim still moves on writes.
+    let f = open_write(&root, "file").expect("open");
+    run(f.write_vectored(&[IoSlice::new(b"new")])).expect("write");
+    assert!(stat(&root, "file").mtim > before.mtim);
+}
+
+/// A clock which advances by a millisecond every time it is read.
+struct MilliTickClock(AtomicU64);
+
+impl WasiSystemClock for MilliTickClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_millis(1)
+    }
+    fn now(&self, _precision: Duration) -> SystemTime {
+        let millis = self.0.fetch_add(1, Ordering::Relaxed);
+        SystemTime::from_std(std::time::UNIX_EPOCH + Duration::from_millis(millis))
+    }
+}
+
+fn at_millis(millis: u64) -> SystemTime {
+    SystemTime::from_std(std::time::UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+#[test]
+fn coarse_granularity() {
+    let fs = Filesystem::new(Box::new(MilliTickClock(AtomicU64::new(10_000))), 0);
+    assert_eq!(fs.timestamp_granularity(), Duration::from_nanos(1));
+    fs.set_timestamp_granularity(Duration::from_secs(1));
+    assert_eq!(fs.timestamp_granularity(), Duration::from_secs(1));
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = open_write(&root, "file").expect("open");
+
+    run(f.write_vectored(&[IoSlice::new(b"one")])).expect("write");
+    let first = run(f.get_filestat()).unwrap();
+    run(f.write_vectored(&[IoSlice::new(b"two")])).expect("write");
+    let second = run(f.get_filestat()).unwrap();
+    assert_eq!(first.mtim, second.mtim);
+    assert_eq!(second.mtim, Some(at_millis(10_000).into_std()));
+
+    // Absolute times are truncated too.
+    run(f.set_times(
+        Some(SystemTimeSpec::Absolute(at_millis(100_999))),
+        Some(SystemTimeSpec::Absolute(at_millis(200_001))),
+    ))
+    .expect("set_times");
+    let truncated = run(f.get_filestat()).unwrap();
+    assert_eq!(truncated.atim, Some(at_millis(100_000).into_std()));
+    assert_eq!(truncated.mtim, Some(at_millis(200_000).into_std()));
+
+    fs.set_timestamp_granularity(Duration::from_secs(2));
+    run(root.set_times(
+        "file",
+        Some(SystemTimeSpec::Absolute(at_millis(101_500))),
+        None,
+        false,
+    ))
+    .expect("set_times");
+    assert_eq!(
+        stat(&root, "file").atim,
+        Some(at_millis(100_000).into_std())
+    );
+}
+
+#[test]
+fn full_precision_by_default() {
+    let fs = Filesystem::new(Box::new(MilliTickClock(AtomicU64::new(10_000))), 0);
+    let root = fs.root();
+    create_file(&root, "file", b"");
+    let f = open_write(&root, "file").expect("open");
+
+    run(f.write_vectored(&[IoSlice::new(b"one")])).expect("write");
+    let first = run(f.get_filestat()).unwrap();
+    run(f.write_vectored(&[IoSlice::new(b"two")])).expect("write");
+    let second = run(f.get_filestat()).unwrap();
+    assert!(second.mtim > first.mtim);
+    run(f.set_times(Some(SystemTimeSpec::Absolute(at_millis(100_999))), None)).expect("set_times");
+    assert_eq!(
+        run(f.get_filestat()).unwrap().atim,
+        Some(at_millis(100_999).into_std())
+    );
+}
@@ -0,0 +1,101 @@
+use crate::{create_file, filesystem, readdir, run, virtfs_file};
+use std::io::{IoSlice, IoSliceMut, SeekFrom};
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags, WasiFile},
+    ErrorKind,
+};
+
+fn open(dir: &dyn WasiDir, path: &str, oflags: OFlags) -> Box<dyn WasiFile> {
+    run(dir.open_file(false, path, oflags, true, true, FdFlags::empty())).expect("open")
+}
+
+#[test]
+fn unlinked_files_live_until_closed() {
+    let fs = filesystem();
+    let root = fs.root();
+    run(root.create_dir("tmp")).expect("mkdir");
+
+    // Create, open a second time, and unlink straight away.
+    let f = open(&root, "tmp/scratch", OFlags::CREATE | OFlags::EXCLUSIVE);
+    let other = open(&root, "tmp/scratch", OFlags::empty());
+    run(root.unlink_file("tmp/scratch")).expect("unlink");
+    let names: Vec<String> = readdir(&*run(root.open_dir(false, "tmp")).unwrap())
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    assert_eq!(names, [".", ".."]);
+    assert_errkind!(
+        run(root.get_path_filestat("tmp/scratch", false)),
+        ErrorKind::Noent
+    );
+    assert_eq!(run(f.get_filestat()).expect("fstat").nlink, 0);
+    assert_eq!(run(other.get_filestat()).expect("fstat").nlink, 0);
+    assert_eq!(fs.orphaned_bytes(), 0, "nothing written yet");
+
+    // Keep writing, and read it back through the other handle.
+    let block = [7; 4096];
+    for _ in 0..4 {
+        run(f.write_vectored(&[IoSlice::new(&block)])).expect("write");
+    }
+    assert_eq!(run(f.get_filestat()).expect("fstat").size, 4 * 4096);
+    assert_eq!(fs.orphaned_bytes(), virtfs_file(&*f).owned_size());
+    assert!(fs.orphaned_bytes() >= 4 * 4096);
+    let mut buf = [0; 4096];
+    run(other.seek(SeekFrom::Start(3 * 4096))).expect("seek");
+    let n = run(other.read_vectored(&mut [IoSliceMut::new(&mut buf)])).expect("read");
+    assert_eq!(&buf[..n as usize], &block[..]);
+
+    // Clones share a description, so the file lives until every handle is
+    // closed.
+    let clone = virtfs_file(&*f).try_clone().expect("clone");
+    drop(f);
+    drop(other);
+    assert!(fs.orphaned_bytes() >= 4 * 4096);
+    assert_eq!(run(clone.get_filestat()).expect("fstat").nlink, 0);
+    drop(clone);
+    assert_eq!(fs.orphaned_bytes(), 0);
+}
+
+#[test]
+fn only_files_with_no_names_left_are_orphans() {
+    let fs = filesystem();
+    let root = fs.root();
+    create_file(&root, "linked", b"still named");
+    create_file(&root, "replaced", b"renamed over");
+    create_file(&root, "closed", b"never open");
+    create_file(&root, "new", b"");
+    run(root.hard_link("linked", &root, "other")).expect("link");
+    let linked = open(&root, "linked", OFlags::empty());
+    let replaced = open(&root, "replaced", OFlags::empty());
+
+    // Losing one of two names leaves a file reachable.
+    run(root.unlink_file("linked")).expect("unlink");
+    assert_eq!(run(linked.get_filestat()).expect("fstat").nlink, 1);
+    // A file that isn't open is simply gone.
+    run(root.unlink_file("closed")).expect("unlink");
+    assert_eq!(fs.orphaned_bytes(), 0);
+
+    // Renaming over an open file orphans it.
+    run(root.rename("new", &root, "replaced")).expect("rename");
+    assert_eq!(run(replaced.get_filestat()).expect("fstat").nlink, 0);
+    assert_eq!(fs.orphaned_bytes(), virtfs_file(&*replaced).owned_size());
+    assert!(fs.orphaned_bytes() > 0);
+
+    // As does removing the tree it's in.
+    run(root.create_dir("tree")).expect("mkdir");
+    create_file(&root, "tree/deep", b"0123456789");
+    let deep = open(&root, "tree/deep", OFlags::empty());
+    let before = fs.orphaned_bytes();
+    fs.remove_dir_all("tree").expect("remove");
+    assert_eq!(
+        fs.orphaned_bytes(),
+        before + virtfs_file(&*deep).owned_size()
+    );
+
+    drop(replaced);
+    assert_eq!(fs.orphaned_bytes(), virtfs_file(&*deep).owned_size());
+    drop(deep);
+    drop(linked);
+    assert_eq!(fs.orphaned_bytes(), 0);
+}
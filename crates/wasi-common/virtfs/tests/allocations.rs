@@ -0,0 +1,74 @@
+//! Counting the allocations made listing a large directory. This swaps out
+//! the global allocator, so it lives in a test binary of its own.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use wasi_common::{
+    dir::{ReaddirCursor, WasiDir},
+    file::{FdFlags, OFlags},
+};
+use wasi_virtfs::Filesystem;
+use wiggle::run_in_dummy_executor as run;
+
+const ENTRIES: usize = 50_000;
+
+/// An allocator which, while counting, counts every allocation.
+struct Counter;
+
+static COUNTING: AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counter {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.load(Ordering::SeqCst) {
+            ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counter = Counter;
+
+/// The number of allocations made while `f` runs. Only one test may count
+/// at a time, so there's only the one test below.
+fn allocations(f: impl FnOnce()) -> usize {
+    ALLOCATIONS.store(0, Ordering::SeqCst);
+    COUNTING.store(true, Ordering::SeqCst);
+    f();
+    COUNTING.store(false, Ordering::SeqCst);
+    ALLOCATIONS.load(Ordering::SeqCst)
+}
+
+fn list(dir: &dyn WasiDir) {
+    let mut listed = 0;
+    for entry in run(dir.readdir(ReaddirCursor::from(0))).expect("readdir") {
+        entry.expect("entry");
+        listed += 1;
+    }
+    assert_eq!(listed, ENTRIES + 2);
+}
+
+#[test]
+fn readdir_allocations() {
+    let fs = Filesystem::new(wasi_cap_std_sync::clocks_ctx().system, 0);
+    let root = fs.root();
+    for i in 0..ENTRIES {
+        let name = format!("file{:05}", i);
+        run(root.open_file(false, &name, OFlags::CREATE, false, true, FdFlags::empty()))
+            .expect("create");
+    }
+
+    // Each entry handed to wasi-common needs a `String` of its own, and
+    // nothing more than that should be allocated per entry, whether or not
+    // the entry has been listed before.
+    let first = allocations(|| list(&root));
+    let second = allocations(|| list(&root));
+    for n in &[first, second] {
+        assert!(*n < ENTRIES + ENTRIES / 10, "{} allocations", n);
+    }
+}
@@ -0,0 +1,139 @@
+//! Scrubbing file contents from memory. These tests watch every block of
+//! memory freed for a secret written to a file, so they swap out the global
+//! allocator, and live in a test binary of their own.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::IoSlice;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use wasi_common::{
+    dir::WasiDir,
+    file::{FdFlags, OFlags, WasiFile},
+};
+use wasi_virtfs::Filesystem;
+use wiggle::run_in_dummy_executor as run;
+
+const SECRET: &[u8] = b"correct horse battery staple";
+// Files are stored, and copied on write, in chunks of this size.
+const CHUNK: u64 = 64 * 1024;
+
+/// An allocator which, while watching, counts the blocks freed with the
+/// secret still in them. Blocks are zeroed when allocated, so that every
+/// byte looked at has been written.
+struct Watcher;
+
+static WATCHING: AtomicBool = AtomicBool::new(false);
+static LEAKS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Watcher {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if WATCHING.load(Ordering::SeqCst) {
+            let block = std::slice::from_raw_parts(ptr, layout.size());
+            if block.windows(SECRET.len()).any(|w| w == SECRET) {
+                LEAKS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Watcher = Watcher;
+
+/// The number of blocks freed with the secret in them while `f` runs. Only
+/// one test may watch at a time, so there's only the one test below.
+fn leaks(f: impl FnOnce()) -> usize {
+    LEAKS.store(0, Ordering::SeqCst);
+    WATCHING.store(true, Ordering::SeqCst);
+    f();
+    WATCHING.store(false, Ordering::SeqCst);
+    LEAKS.load(Ordering::SeqCst)
+}
+
+fn filesystem(zeroize: bool) -> Arc<Filesystem> {
+    let fs = Filesystem::new(wasi_cap_std_sync::clocks_ctx().system, 0);
+    fs.set_zeroize_contents(zeroize);
+    fs
+}
+
+fn open(dir: &dyn WasiDir, path: &str, oflags: OFlags) -> Box<dyn WasiFile> {
+    run(dir.open_file(false, path, oflags, true, true, FdFlags::empty())).expect("open file")
+}
+
+/// A file holding the secret in two chunks, written a little at a time so
+/// that the chunks grow.
+fn secret_file(dir: &dyn WasiDir, path: &str) -> Box<dyn WasiFile> {
+    let f = open(dir, path, OFlags::CREATE);
+    for offset in &[0, 100, 1000, CHUNK - 10, CHUNK + 100] {
+        run(f.write_vectored_at(&[IoSlice::new(SECRET)], *offset)).expect("pwrite");
+    }
+    f
+}
+
+#[test]
+fn scrubbed() {
+    // The check can see a secret left behind.
+    assert!(leaks(freed_without_zeroization) > 0);
+    for (name, case) in &[
+        ("unlink", unlink as fn()),
+        ("unlinked while open", unlinked_while_open),
+        ("truncate", truncate),
+        ("copies", copies),
+    ] {
+        assert_eq!(leaks(case), 0, "{} left the secret in freed memory", name);
+    }
+}
+
+fn freed_without_zeroization() {
+    let fs = filesystem(false);
+    let root = fs.root();
+    drop(secret_file(&root, "secret"));
+    run(root.unlink_file("secret")).expect("unlink");
+}
+
+fn unlink() {
+    let fs = filesystem(true);
+    let root = fs.root();
+    drop(secret_file(&root, "secret"));
+    run(root.unlink_file("secret")).expect("unlink");
+}
+
+fn unlinked_while_open() {
+    let fs = filesystem(true);
+    let root = fs.root();
+    let f = secret_file(&root, "secret");
+    run(root.unlink_file("secret")).expect("unlink");
+    run(f.write_vectored_at(&[IoSlice::new(SECRET)], 2 * CHUNK)).expect("pwrite");
+    drop(f);
+}
+
+fn truncate() {
+    let fs = filesystem(true);
+    let root = fs.root();
+    let f = secret_file(&root, "secret");
+    run(f.set_filestat_size(CHUNK - 5)).expect("shrink");
+    run(f.set_filestat_size(50)).expect("shrink");
+    run(f.set_filestat_size(0)).expect("truncate");
+    drop(secret_file(&root, "other"));
+    drop(open(&root, "other", OFlags::TRUNCATE));
+    // Dropping the filesystem would scrub whatever the truncations missed.
+    std::mem::forget((fs, root, f));
+}
+
+fn copies() {
+    let fs = filesystem(true);
+    let root = fs.root();
+    drop(secret_file(&root, "secret"));
+    fs.copy_file("secret", "copy").expect("copy");
+    let copy = open(&root, "copy", OFlags::empty());
+    run(copy.write_vectored_at(&[IoSlice::new(b"x")], 5)).expect("pwrite");
+    run(root.unlink_file("secret")).expect("unlink");
+    drop(secret_file(&root, "other"));
+    fs.copy_file("other", "copy").expect("copy over");
+    drop(copy);
+    drop(fs);
+}